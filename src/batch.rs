@@ -0,0 +1,266 @@
+/// Sample manifests and run summaries for `json2maf batch`.
+///
+/// A manifest is a tab-separated file with a header row naming its
+/// columns: `sample_id`, `input` and `output` are required, `normal_id` is
+/// optional (and may be empty per row). Blank lines and lines starting
+/// with `#` are ignored.
+use crate::sample::validate_barcode;
+use crate::types::FilterStats;
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::Path;
+
+/// One sample of a batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Tumor_Sample_Barcode for the sample's output
+    pub sample_id: String,
+    /// Nirvana JSON path
+    pub input: String,
+    /// Matched_Norm_Sample_Barcode, when the sample has a matched normal
+    pub normal_id: Option<String>,
+    pub output: String,
+}
+
+pub fn load_manifest(path: &Path) -> Result<Vec<ManifestEntry>> {
+    let contents =
+        std::fs::read_to_string(path).with_context(|| format!("Failed to read manifest {}", path.display()))?;
+    parse_manifest(&contents).with_context(|| format!("in {}", path.display()))
+}
+
+pub fn parse_manifest(contents: &str) -> Result<Vec<ManifestEntry>> {
+    let mut lines = contents
+        .lines()
+        .enumerate()
+        .map(|(n, line)| (n + 1, line.trim_end_matches('\r')))
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'));
+
+    let (_, header) = lines.next().context("manifest is empty")?;
+    let columns: Vec<&str> = header.split('\t').map(str::trim).collect();
+    for column in &columns {
+        if !["sample_id", "input", "normal_id", "output"].contains(column) {
+            anyhow::bail!(
+                "unknown manifest column '{}' (expected sample_id, input, normal_id and output)",
+                column
+            );
+        }
+    }
+    let index = |name: &str| columns.iter().position(|c| *c == name);
+    let (Some(sample_col), Some(input_col), Some(output_col)) = (index("sample_id"), index("input"), index("output"))
+    else {
+        anyhow::bail!("manifest header must name the sample_id, input and output columns");
+    };
+    let normal_col = index("normal_id");
+
+    let mut entries = Vec::new();
+    let mut sample_ids = HashSet::new();
+    let mut outputs = HashSet::new();
+    for (n, line) in lines {
+        let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+        if fields.len() != columns.len() {
+            anyhow::bail!("line {}: expected {} columns, got {}", n, columns.len(), fields.len());
+        }
+        let field = |col: usize, name: &str| match fields[col] {
+            "" => anyhow::bail!("line {}: {} is empty", n, name),
+            value => Ok(value.to_string()),
+        };
+        let entry = ManifestEntry {
+            sample_id: field(sample_col, "sample_id")?,
+            input: field(input_col, "input")?,
+            normal_id: normal_col.map(|col| fields[col]).filter(|v| !v.is_empty()).map(str::to_string),
+            output: field(output_col, "output")?,
+        };
+        for barcode in std::iter::once(&entry.sample_id).chain(&entry.normal_id) {
+            validate_barcode(barcode).with_context(|| format!("line {}", n))?;
+        }
+        // The sample id names its statistics report, SAMPLE_ID.stats.txt
+        if entry.sample_id.contains(['/', '\\']) || entry.sample_id.contains("..") {
+            anyhow::bail!(
+                "line {}: sample_id '{}' can't be used as a file name (it contains '/', '\\' or '..')",
+                n,
+                entry.sample_id
+            );
+        }
+        if !sample_ids.insert(entry.sample_id.clone()) {
+            anyhow::bail!("line {}: sample {} is listed twice", n, entry.sample_id);
+        }
+        if !outputs.insert(entry.output.clone()) {
+            anyhow::bail!("line {}: output {} is used twice", n, entry.output);
+        }
+        entries.push(entry);
+    }
+    if entries.is_empty() {
+        anyhow::bail!("manifest lists no samples");
+    }
+    Ok(entries)
+}
+
+/// How one sample of a batch went.
+#[derive(Debug)]
+pub struct SampleOutcome {
+    pub entry: ManifestEntry,
+    /// The sample's statistics, or the error that stopped it
+    pub result: Result<FilterStats, String>,
+    pub seconds: f64,
+}
+
+pub const SUMMARY_COLUMNS: &[&str] = &["sample_id", "status", "variants", "included", "excluded", "seconds", "output", "error"];
+
+impl SampleOutcome {
+    /// `excluded` counts every variant not included, whichever stage
+    /// rejected it, so that it adds up with `included` to `variants`.
+    fn summary_fields(&self) -> Vec<String> {
+        let (status, variants, included, excluded, error) = match &self.result {
+            Ok(stats) => (
+                "ok",
+                stats.evaluated.to_string(),
+                stats.included.to_string(),
+                (stats.evaluated - stats.included).to_string(),
+                String::new(),
+            ),
+            Err(error) => ("failed", String::new(), String::new(), String::new(), error.replace(['\t', '\n'], " ")),
+        };
+        vec![
+            self.entry.sample_id.clone(),
+            status.to_string(),
+            variants,
+            included,
+            excluded,
+            format!("{:.1}", self.seconds),
+            self.entry.output.clone(),
+            error,
+        ]
+    }
+}
+
+/// Writes the batch summary as TSV, one row per sample in manifest order.
+pub fn write_summary<W: Write>(out: &mut W, outcomes: &[SampleOutcome]) -> Result<()> {
+    writeln!(out, "{}", SUMMARY_COLUMNS.join("\t"))?;
+    for outcome in outcomes {
+        writeln!(out, "{}", outcome.summary_fields().join("\t"))?;
+    }
+    Ok(())
+}
+
+/// The batch summary as an aligned text table, for the terminal.
+pub fn format_summary_table(outcomes: &[SampleOutcome]) -> String {
+    let rows: Vec<Vec<String>> = std::iter::once(SUMMARY_COLUMNS.iter().map(|c| c.to_string()).collect())
+        .chain(outcomes.iter().map(SampleOutcome::summary_fields))
+        .collect();
+    let widths: Vec<usize> = (0..SUMMARY_COLUMNS.len())
+        .map(|i| rows.iter().map(|r| r[i].chars().count()).max().unwrap_or(0))
+        .collect();
+    rows.iter()
+        .map(|row| {
+            let cells: Vec<String> = row.iter().zip(&widths).map(|(cell, w)| format!("{:<w$}", cell, w = w)).collect();
+            format!("{}\n", cells.join("  ").trim_end())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifest() {
+        let entries = parse_manifest(
+            "# plate 7\nsample_id\tinput\tnormal_id\toutput\n\
+             S1\ts1.json.gz\tN1\tout/S1.maf\nS2\ts2.json.gz\t\tout/S2.maf\n",
+        )
+        .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].normal_id.as_deref(), Some("N1"));
+        assert_eq!(entries[1].normal_id, None);
+        assert_eq!(entries[1].output, "out/S2.maf");
+
+        let without_normal = parse_manifest("output\tsample_id\tinput\nS1.maf\tS1\tS1.json.gz\n").unwrap();
+        assert_eq!(without_normal[0].sample_id, "S1");
+
+        assert!(parse_manifest("").is_err());
+        assert!(parse_manifest("sample_id\tinput\toutput\n").is_err());
+        assert!(parse_manifest("sample_id\tinput\n").is_err());
+        assert!(parse_manifest("sample_id\tinput\toutput\tbam\n").is_err());
+        assert!(parse_manifest("sample_id\tinput\toutput\nS1\ta\n").is_err());
+        assert!(parse_manifest("sample_id\tinput\toutput\nS1\ta\tx\nS1\tb\ty\n").is_err());
+        assert!(parse_manifest("sample_id\tinput\toutput\nS1\ta\tx\nS2\tb\tx\n").is_err());
+        assert!(parse_manifest("sample_id\tinput\toutput\n\ta\tx\n").is_err());
+
+        // Sample ids become report file names
+        for id in ["../S1", "plate/S1", "S1\\x", ".."] {
+            let manifest = format!("sample_id\tinput\toutput\nS0\ta\tx\n{}\tb\ty\n", id);
+            let error = parse_manifest(&manifest).unwrap_err().to_string();
+            assert!(error.starts_with("line 3: sample_id"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_write_summary() {
+        let entry = |id: &str| ManifestEntry {
+            sample_id: id.to_string(),
+            input: format!("{}.json.gz", id),
+            normal_id: None,
+            output: format!("{}.maf", id),
+        };
+        let outcomes = vec![
+            SampleOutcome {
+                entry: entry("S1"),
+                result: Ok(FilterStats {
+                    evaluated: 10,
+                    included: 3,
+                    excluded: 5,
+                    ..FilterStats::default()
+                }),
+                seconds: 1.5,
+            },
+            SampleOutcome {
+                entry: entry("S2"),
+                result: Err("Input file does not exist:\tS2.json.gz".to_string()),
+                seconds: 0.0,
+            },
+        ];
+
+        let mut out = Vec::new();
+        write_summary(&mut out, &outcomes).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], SUMMARY_COLUMNS.join("\t"));
+        assert_eq!(lines[1], "S1\tok\t10\t3\t7\t1.5\tS1.maf\t");
+        assert_eq!(lines[2], "S2\tfailed\t\t\t\t0.0\tS2.maf\tInput file does not exist: S2.json.gz");
+
+        let table = format_summary_table(&outcomes);
+        assert!(table.starts_with("sample_id  status"));
+        assert_eq!(table.lines().count(), 3);
+    }
+
+    #[test]
+    fn test_summary_counts_quality_rejections() {
+        use crate::filters::Pipeline;
+        use crate::types::{FilterConfig, VariantPosition};
+
+        let config = FilterConfig::default();
+        let pipeline = Pipeline::from_config(&config).unwrap();
+        let mut stats = FilterStats::default();
+        let low_depth = VariantPosition {
+            total_depth: Some(1),
+            variant_frequencies: Some(vec![0.5]),
+            ..VariantPosition::test_default()
+        };
+        let outcome = pipeline.run(&low_depth, &config, &mut stats);
+        assert!(matches!(outcome.verdict, crate::filters::Verdict::Rejected { stage: "quality", .. }));
+        assert_eq!(stats.excluded, 0);
+
+        let outcome = SampleOutcome {
+            entry: ManifestEntry {
+                sample_id: "S1".to_string(),
+                input: "S1.json.gz".to_string(),
+                normal_id: None,
+                output: "S1.maf".to_string(),
+            },
+            result: Ok(stats),
+            seconds: 0.0,
+        };
+        assert_eq!(outcome.summary_fields()[2..5], ["1", "0", "1"]);
+    }
+}
@@ -0,0 +1,121 @@
+/// BGZF (blocked gzip) output, as written by `bgzip`.
+///
+/// A BGZF file is a series of gzip members of at most 64 KiB each, with the
+/// block size stored in a `BC` extra field, followed by an empty end-of-file
+/// block. Any gzip reader can decompress it, and `tabix` can index it.
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+use std::io::{self, Write};
+
+/// Uncompressed bytes per block. bgzip uses 0xff00 so that even
+/// incompressible data fits the 64 KiB block limit after deflate.
+const BLOCK_DATA_SIZE: usize = 0xff00;
+
+/// The empty block that marks the end of a BGZF file.
+const EOF_BLOCK: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+pub struct BgzfWriter<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+    compression: Compression,
+}
+
+impl<W: Write> BgzfWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            buffer: Vec::with_capacity(BLOCK_DATA_SIZE),
+            compression: Compression::default(),
+        }
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let mut encoder = DeflateEncoder::new(Vec::with_capacity(self.buffer.len()), self.compression);
+        encoder.write_all(&self.buffer)?;
+        let compressed = encoder.finish()?;
+
+        let mut crc = Crc::new();
+        crc.update(&self.buffer);
+
+        // 18-byte header + data + CRC32 + ISIZE; BSIZE is the total minus one
+        let block_size = 18 + compressed.len() + 8;
+        let bsize = u16::try_from(block_size - 1)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "BGZF block exceeds 64 KiB"))?;
+
+        let mut header = [
+            0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00, 0x00,
+            0x00,
+        ];
+        header[16..18].copy_from_slice(&bsize.to_le_bytes());
+
+        self.inner.write_all(&header)?;
+        self.inner.write_all(&compressed)?;
+        self.inner.write_all(&crc.sum().to_le_bytes())?;
+        self.inner.write_all(&(self.buffer.len() as u32).to_le_bytes())?;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// The underlying writer. Call `flush` first to end the current block.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Writes the last block and the end-of-file marker, returning the
+    /// underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_block()?;
+        self.inner.write_all(&EOF_BLOCK)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(BLOCK_DATA_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..n]);
+        if self.buffer.len() == BLOCK_DATA_SIZE {
+            self.write_block()?;
+        }
+        Ok(n)
+    }
+
+    /// Completes the current block, so everything written so far can be
+    /// decompressed from the output.
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::MultiGzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn test_bgzf_round_trip() {
+        let data: Vec<u8> = (0..200_000u32).flat_map(|i| format!("{}\t", i).into_bytes()).collect();
+
+        let mut writer = BgzfWriter::new(Vec::new());
+        writer.write_all(&data).unwrap();
+        let compressed = writer.finish().unwrap();
+
+        assert!(compressed.ends_with(&EOF_BLOCK));
+        // Every block carries the BC extra field
+        assert_eq!(&compressed[12..14], b"BC");
+
+        let mut decoded = Vec::new();
+        MultiGzDecoder::new(&compressed[..]).read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+    }
+}
@@ -1,6 +1,8 @@
 use anyhow::{Context, Result};
 use clap::Parser;
-use json2maf::sv::{parse_sv_nirvana_streaming, sv_position_to_record, SVType, SVWriter};
+use json2maf::output::{FileOptions, OutputCompression};
+use json2maf::sv::{parse_sv_nirvana_streaming, sv_position_to_record_with, SVType, SVWriter};
+use json2maf::transcript::{PreferredTranscripts, TranscriptCriterion, TranscriptPolicy, TranscriptSource};
 use std::path::Path;
 
 #[derive(Parser, Debug)]
@@ -12,15 +14,51 @@ struct Args {
     #[arg(short, long)]
     input: String,
 
-    /// Output TSV file path
+    /// Output TSV file path (BGZF-compressed when it ends in .gz)
     #[arg(short, long)]
     output: String,
 
+    /// Output compression: auto, none, gzip or bgzf [default: auto]
+    #[arg(long, value_name = "METHOD", default_value_t = OutputCompression::Auto)]
+    compress: OutputCompression,
+
+    /// Keep the temporary output file if the run fails
+    #[arg(long)]
+    keep_temp: bool,
+
+    /// Transcript selection chain, comma-separated (same steps as json2maf)
+    /// [default: preferred,mane_select,source,has_hgvsc]
+    #[arg(long, value_delimiter = ',')]
+    transcript_policy: Option<Vec<TranscriptCriterion>>,
+
+    /// Transcript source used by the 'source' step: refseq or ensembl
+    #[arg(long)]
+    transcript_source: Option<TranscriptSource>,
+
+    /// File of GENE<TAB>ACCESSION lines with the lab's approved transcripts
+    #[arg(long, value_name = "FILE")]
+    preferred_transcripts: Option<String>,
+
     /// Verbose output
     #[arg(short, long)]
     verbose: bool,
 }
 
+impl Args {
+    fn transcript_policy(&self) -> Result<TranscriptPolicy> {
+        let mut policy = TranscriptPolicy::sv_default();
+        if let Some(criteria) = &self.transcript_policy {
+            json2maf::transcript::validate_policy(criteria)?;
+            policy.criteria = criteria.clone();
+        }
+        policy.source = self.transcript_source;
+        if let Some(path) = &self.preferred_transcripts {
+            policy.preferred = PreferredTranscripts::load(Path::new(path))?;
+        }
+        Ok(policy)
+    }
+}
+
 fn main() -> Result<()> {
     env_logger::Builder::from_default_env()
         .filter_level(log::LevelFilter::Warn)
@@ -37,7 +75,13 @@ fn main() -> Result<()> {
         println!("Output: {}", args.output);
     }
 
-    let mut writer = SVWriter::new(&args.output).context("Failed to create output file")?;
+    let policy = args.transcript_policy()?;
+    let file_options = FileOptions {
+        compression: args.compress,
+        keep_temp: args.keep_temp,
+        ..FileOptions::default()
+    };
+    let mut writer = SVWriter::with_options(&args.output, file_options).context("Failed to create output file")?;
 
     let mut total = 0usize;
     let mut del_count = 0usize;
@@ -54,12 +98,12 @@ fn main() -> Result<()> {
             symbolic += 1;
         }
 
-        let record = sv_position_to_record(&pos);
+        let record = sv_position_to_record_with(&pos, &policy);
         writer.write_record(&record)
     })
     .context("Failed to parse SV JSON")?;
 
-    writer.flush()?;
+    writer.finish()?;
 
     if args.verbose {
         println!(
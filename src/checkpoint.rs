@@ -0,0 +1,237 @@
+/// Checkpoints for resuming an interrupted conversion.
+///
+/// A checkpointed run writes its outputs to `.NAME.partial` files (see
+/// `output`) and, every so often between batches, records in
+/// `OUTPUT.checkpoint` how many positions it has consumed, how long each
+/// partial file was at that point and the statistics so far. A rerun that
+/// finds the checkpoint skips that many positions, truncates the partial
+/// files to the recorded lengths and appends from there. The checkpoint
+/// also fingerprints the input (its size, modification time and a SHA-256
+/// of its first and last bytes) and records a digest of the run settings,
+/// and a rerun with a different input or settings is refused rather than
+/// producing a mixed output.
+///
+/// Once every position is written, a last checkpoint marked `finished` is
+/// saved before the outputs are committed, and it is only removed after
+/// all of them are. A rerun that finds it commits the outputs that are
+/// still partial and leaves the committed ones alone.
+use crate::output::hex_digest;
+use crate::types::FilterStats;
+use crate::writer::SinkState;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Version of the checkpoint layout; checkpoints of other versions are
+/// refused.
+pub const CHECKPOINT_VERSION: u32 = 1;
+
+/// Bytes hashed at each end of the input, so fingerprinting doesn't read a
+/// whole WGS file on every run. A single-stream gzip input ends with the
+/// CRC of all its contents; for BGZF the size and modification time have
+/// to catch changes in the middle.
+const FINGERPRINT_SPAN: u64 = 16 << 20;
+
+/// The checkpoint file of an output.
+pub fn checkpoint_path(output: &Path) -> PathBuf {
+    let mut name = output.as_os_str().to_os_string();
+    name.push(".checkpoint");
+    PathBuf::from(name)
+}
+
+/// SHA-256 of `text`, for recording run settings.
+pub fn settings_digest(text: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(text.as_bytes());
+    hex_digest(hasher)
+}
+
+/// Identifies an input file's contents.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputFingerprint {
+    pub size: u64,
+    /// Modification time, in nanoseconds since the Unix epoch
+    pub modified: u64,
+    /// SHA-256 of the first and last `FINGERPRINT_SPAN` bytes (the whole
+    /// file, if it is shorter than both)
+    pub sha256: String,
+}
+
+impl InputFingerprint {
+    /// Fingerprints `path`, reading at most `2 * FINGERPRINT_SPAN` bytes.
+    pub fn of(path: &Path) -> Result<Self> {
+        let context = || format!("Failed to fingerprint input file: {}", path.display());
+        let mut file = File::open(path).with_context(context)?;
+        let metadata = file.metadata().with_context(context)?;
+        let modified = metadata
+            .modified()
+            .with_context(context)?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+
+        let size = metadata.len();
+        let mut hasher = Sha256::new();
+        if size <= 2 * FINGERPRINT_SPAN {
+            hash_bytes(&mut hasher, &mut file).with_context(context)?;
+        } else {
+            hash_bytes(&mut hasher, (&mut file).take(FINGERPRINT_SPAN)).with_context(context)?;
+            file.seek(SeekFrom::Start(size - FINGERPRINT_SPAN)).with_context(context)?;
+            hash_bytes(&mut hasher, &mut file).with_context(context)?;
+        }
+
+        Ok(Self {
+            size,
+            modified,
+            sha256: hex_digest(hasher),
+        })
+    }
+}
+
+fn hash_bytes(hasher: &mut Sha256, mut reader: impl Read) -> io::Result<()> {
+    let mut buffer = vec![0; 1 << 20];
+    loop {
+        let n = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        hasher.update(&buffer[..n]);
+    }
+}
+
+/// Progress of a checkpointed run, as of the end of a batch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub version: u32,
+    pub input: InputFingerprint,
+    /// `settings_digest` of the settings the output was written with
+    pub settings: String,
+    /// Positions read from the input, whether or not they became variants
+    pub positions: u64,
+    /// Variants filtered
+    pub variants: u64,
+    pub output: SinkState,
+    /// Length of the rejected-variant report, when one is written
+    pub rejected: Option<u64>,
+    pub stats: FilterStats,
+    /// Every position has been written and the outputs are being
+    /// committed; some may already be in place
+    #[serde(default)]
+    pub finished: bool,
+}
+
+impl Checkpoint {
+    /// Reads the checkpoint at `path`, if there is one.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read checkpoint {}", path.display())),
+        };
+        let checkpoint: Checkpoint = serde_json::from_str(&text)
+            .with_context(|| format!("Corrupt checkpoint {}; delete it to start over", path.display()))?;
+        Ok(Some(checkpoint))
+    }
+
+    /// Replaces the checkpoint at `path`. The new checkpoint is written
+    /// next to it and renamed into place, so a crash leaves either the old
+    /// or the new one.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let context = || format!("Failed to write checkpoint {}", path.display());
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut file = tempfile::NamedTempFile::new_in(dir).with_context(context)?;
+        serde_json::to_writer_pretty(&mut file, self).with_context(context)?;
+        file.write_all(b"\n").with_context(context)?;
+        file.as_file().sync_all().with_context(context)?;
+        file.persist(path).map_err(|e| e.error).with_context(context)?;
+        Ok(())
+    }
+
+    /// Checks that the checkpoint was written by a run of the same input
+    /// with the same settings.
+    pub fn verify(&self, input: &InputFingerprint, settings: &str) -> Result<()> {
+        let mismatch = if self.version != CHECKPOINT_VERSION {
+            Some(format!("it has version {}, not {}", self.version, CHECKPOINT_VERSION))
+        } else if self.input.size != input.size {
+            Some(format!("the input was {} bytes and is now {}", self.input.size, input.size))
+        } else if self.input.modified != input.modified {
+            Some("the input has been modified since".to_string())
+        } else if self.input.sha256 != input.sha256 {
+            Some("the input's checksum has changed".to_string())
+        } else if self.settings != settings {
+            Some("it was written with different settings".to_string())
+        } else {
+            None
+        };
+        match mismatch {
+            Some(reason) => anyhow::bail!("Can't resume from the checkpoint: {}; delete it to start over", reason),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_checkpoint_round_trip() -> Result<()> {
+        let dir = TempDir::new()?;
+        let input = dir.path().join("sample.json.gz");
+        std::fs::write(&input, b"positions")?;
+        let fingerprint = InputFingerprint::of(&input)?;
+        assert_eq!(fingerprint.size, 9);
+
+        let mut stats = FilterStats {
+            included: 4,
+            excluded: 6,
+            ..FilterStats::default()
+        };
+        stats.failed_filter_tags.insert("LowQ".to_string(), 2);
+        stats.stages.entry("quality".into()).or_default().failed = 2;
+        let checkpoint = Checkpoint {
+            version: CHECKPOINT_VERSION,
+            input: fingerprint.clone(),
+            settings: settings_digest("min_depth = 30"),
+            positions: 120,
+            variants: 10,
+            output: SinkState { bytes: 4096, records: 4 },
+            rejected: None,
+            stats,
+            finished: false,
+        };
+
+        let path = checkpoint_path(&dir.path().join("sample.maf"));
+        assert_eq!(Checkpoint::load(&path)?, None);
+        checkpoint.save(&path)?;
+        checkpoint.save(&path)?;
+        let loaded = Checkpoint::load(&path)?.unwrap();
+        assert_eq!(loaded, checkpoint);
+        // Only the input and the checkpoint; no leftover temporary files
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 2);
+
+        loaded.verify(&fingerprint, &settings_digest("min_depth = 30"))?;
+        assert!(loaded.verify(&fingerprint, &settings_digest("min_depth = 20")).is_err());
+
+        // Same size and (as far as the checkpoint can tell) time, but
+        // different contents
+        std::fs::write(&input, b"POSITIONS")?;
+        let changed = InputFingerprint {
+            modified: fingerprint.modified,
+            ..InputFingerprint::of(&input)?
+        };
+        let error = loaded.verify(&changed, &checkpoint.settings).unwrap_err();
+        assert!(error.to_string().contains("checksum"));
+        Ok(())
+    }
+}
@@ -0,0 +1,407 @@
+/// Run configuration files (TOML or YAML) and built-in presets.
+///
+/// A config file has a `[filter]` section with the `FilterConfig` fields,
+/// plus `[output]` and `[runtime]` sections and an optional top-level
+/// `preset` to start from:
+///
+/// ```toml
+/// preset = "somatic-tumor-only"
+///
+/// [filter]
+/// min_total_depth = 50
+/// reject_filters = ["LowQ*"]
+///
+/// [output]
+/// rejected_output = "rejected.tsv"
+/// ```
+///
+/// Settings resolve as built-in defaults, then the preset, then the file,
+/// then command-line flags; each layer only overrides the keys it sets.
+use crate::consequence::VariantClassifier;
+use crate::output::OutputCompression;
+use crate::sample::validate_barcode;
+use crate::types::{FilterConfig, TranscriptOutput};
+use crate::writer::OutputFormat;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RunConfig {
+    /// Preset the configuration was built on, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    pub filter: FilterConfig,
+    pub output: OutputConfig,
+    pub runtime: RuntimeConfig,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    /// Format of the output file (maf, vcf, vcf.gz, jsonl or parquet)
+    pub format: OutputFormat,
+    /// Output compression (auto, none, gzip or bgzf)
+    pub compression: OutputCompression,
+    /// Sort records in karyotypic order
+    pub sort: bool,
+    /// Rejected-variant TSV path
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rejected_output: Option<String>,
+    /// Statistics report path
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<String>,
+    /// Which transcripts become MAF rows
+    pub transcripts: TranscriptOutput,
+    /// Fill ClinVar_All with every ClinVar record
+    pub clinvar_all: bool,
+    /// SO term to Variant_Classification overrides
+    pub variant_classification: BTreeMap<String, String>,
+    /// Tumor_Sample_Barcode (defaults to the reported sample's name)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tumor_barcode: Option<String>,
+    /// Matched_Norm_Sample_Barcode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normal_barcode: Option<String>,
+    /// SAMPLE<TAB>BARCODE file mapping Nirvana sample names to LIMS IDs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_sheet: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuntimeConfig {
+    /// Worker threads (defaults to the number of CPU cores)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threads: Option<usize>,
+    /// Variants held in memory per parallel filtering batch
+    pub batch_size: usize,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self {
+            threads: None,
+            batch_size: 20_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+}
+
+impl std::str::FromStr for ConfigFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "toml" => Ok(ConfigFormat::Toml),
+            "yaml" | "yml" => Ok(ConfigFormat::Yaml),
+            _ => anyhow::bail!("invalid config format '{}' (expected toml or yaml)", s),
+        }
+    }
+}
+
+impl ConfigFormat {
+    /// Format implied by a file extension; TOML unless `.yaml`/`.yml`.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml") => {
+                ConfigFormat::Yaml
+            }
+            _ => ConfigFormat::Toml,
+        }
+    }
+}
+
+/// Built-in presets with a one-line description. These are starting points
+/// for a lab's own SOP configuration, not validated settings.
+pub const PRESETS: &[(&str, &str)] = &[
+    (
+        "germline-clinical",
+        "Germline panels: heterozygous VAF floor, GQ >= 20, strict FILTER, benign excluded, LoF evidence",
+    ),
+    (
+        "somatic-tumor-only",
+        "Tumor-only somatic: low VAF floor, >= 5 alt reads, rare in population, benign excluded, LoF evidence",
+    ),
+    (
+        "liquid-biopsy",
+        "cfDNA: deep coverage, VAF down to 0.2%, >= 5 alt reads",
+    ),
+];
+
+/// Builds the named preset on top of the defaults.
+pub fn preset(name: &str) -> Result<RunConfig> {
+    let defaults = FilterConfig::default();
+    let filter = match name {
+        "germline-clinical" => FilterConfig {
+            fail_missing_filters: true,
+            min_total_depth: 20,
+            min_variant_frequency: 0.2,
+            min_genotype_quality: Some(20.0),
+            max_eas_af: 0.01,
+            enable_lof_rescue: true,
+            exclude_benign: true,
+            ..defaults
+        },
+        "somatic-tumor-only" => FilterConfig {
+            min_total_depth: 30,
+            min_variant_frequency: 0.05,
+            min_alt_reads: Some(5),
+            max_eas_af: 0.001,
+            enable_lof_rescue: true,
+            exclude_benign: true,
+            ..defaults
+        },
+        "liquid-biopsy" => FilterConfig {
+            min_total_depth: 500,
+            min_variant_frequency: 0.002,
+            min_alt_reads: Some(5),
+            max_eas_af: 0.001,
+            exclude_benign: true,
+            ..defaults
+        },
+        _ => anyhow::bail!(
+            "unknown preset '{}' (available: {})",
+            name,
+            PRESETS.iter().map(|(n, _)| *n).collect::<Vec<_>>().join(", ")
+        ),
+    };
+
+    Ok(RunConfig {
+        preset: Some(name.to_string()),
+        filter,
+        ..RunConfig::default()
+    })
+}
+
+impl RunConfig {
+    /// Resolves defaults, preset and config file. `preset_override` (from the
+    /// command line) takes precedence over a `preset` key in the file.
+    pub fn resolve(config_path: Option<&Path>, preset_override: Option<&str>) -> Result<RunConfig> {
+        let file_value = match config_path {
+            Some(path) => Some(read_value(path)?),
+            None => None,
+        };
+
+        let preset_name = preset_override.map(str::to_string).or_else(|| {
+            file_value
+                .as_ref()
+                .and_then(|v| v.get("preset"))
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        });
+
+        let base = match &preset_name {
+            Some(name) => preset(name)?,
+            None => RunConfig::default(),
+        };
+
+        let mut merged = serde_json::to_value(&base).context("Failed to serialize configuration")?;
+        if let Some(value) = file_value {
+            merge_values(&mut merged, value);
+        }
+        if let Some(name) = preset_name {
+            merged["preset"] = Value::String(name);
+        }
+
+        let source = config_path
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|| "preset".to_string());
+        from_sections(merged).with_context(|| format!("Invalid configuration in {}", source))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        self.filter.validate()?;
+        self.output.transcripts.validate()?;
+        VariantClassifier::new(&self.output.variant_classification)
+            .context("Invalid output.variant_classification")?;
+        for barcode in [&self.output.tumor_barcode, &self.output.normal_barcode].into_iter().flatten() {
+            validate_barcode(barcode)?;
+        }
+        if self.runtime.batch_size == 0 {
+            anyhow::bail!("batch_size must be at least 1");
+        }
+        if self.runtime.threads == Some(0) {
+            anyhow::bail!("threads must be at least 1");
+        }
+        Ok(())
+    }
+
+    /// Serializes the configuration, e.g. for `--dump-config`.
+    pub fn render(&self, format: ConfigFormat) -> Result<String> {
+        match format {
+            ConfigFormat::Toml => toml::to_string(self).context("Failed to serialize configuration as TOML"),
+            ConfigFormat::Yaml => serde_norway::to_string(self).context("Failed to serialize configuration as YAML"),
+        }
+    }
+}
+
+fn read_value(path: &Path) -> Result<Value> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+    let value: Value = match ConfigFormat::from_path(path) {
+        ConfigFormat::Toml => toml::from_str(&text)
+            .with_context(|| format!("Failed to parse TOML config: {}", path.display()))?,
+        ConfigFormat::Yaml => serde_norway::from_str(&text)
+            .with_context(|| format!("Failed to parse YAML config: {}", path.display()))?,
+    };
+    if !value.is_object() && !value.is_null() {
+        anyhow::bail!("Config file {} must contain a table of settings", path.display());
+    }
+    Ok(value)
+}
+
+/// Recursively overlays `overlay` onto `base`: tables merge key by key,
+/// everything else (scalars, arrays) replaces.
+fn merge_values(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_values(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        // An empty section (`output:` in YAML) leaves the defaults alone
+        (Value::Object(_), Value::Null) => {}
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Deserializes section by section so errors name the section at fault.
+fn from_sections(value: Value) -> Result<RunConfig> {
+    let Value::Object(mut map) = value else {
+        anyhow::bail!("configuration must be a table");
+    };
+
+    let mut section = |name: &str| map.remove(name).unwrap_or(Value::Null);
+    let filter = section("filter");
+    let output = section("output");
+    let runtime = section("runtime");
+    let preset = section("preset");
+
+    if let Some(unknown) = map.keys().next() {
+        anyhow::bail!(
+            "unknown section '{}' (expected preset, [filter], [output] or [runtime])",
+            unknown
+        );
+    }
+
+    fn parse<T: serde::de::DeserializeOwned + Default>(value: Value, name: &str) -> Result<T> {
+        if value.is_null() {
+            return Ok(T::default());
+        }
+        serde_json::from_value(value).with_context(|| format!("in [{}]", name))
+    }
+
+    Ok(RunConfig {
+        preset: parse(preset, "preset")?,
+        filter: parse(filter, "filter")?,
+        output: parse(output, "output")?,
+        runtime: parse(runtime, "runtime")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::MissingDataPolicy;
+    use std::io::Write;
+
+    fn write_config(dir: &tempfile::TempDir, name: &str, contents: &str) -> std::path::PathBuf {
+        let path = dir.path().join(name);
+        std::fs::File::create(&path)
+            .unwrap()
+            .write_all(contents.as_bytes())
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn test_toml_file_over_preset() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = write_config(
+            &dir,
+            "run.toml",
+            r#"
+preset = "somatic-tumor-only"
+
+[filter]
+min_total_depth = 50
+missing_population_af = "flag"
+min_impact = "MODERATE"
+
+[output]
+rejected_output = "rejected.tsv"
+"#,
+        );
+
+        let config = RunConfig::resolve(Some(&path), None).unwrap();
+        assert_eq!(config.preset.as_deref(), Some("somatic-tumor-only"));
+        assert_eq!(config.filter.min_total_depth, 50);
+        // Untouched preset values survive
+        assert_eq!(config.filter.min_alt_reads, Some(5));
+        assert_eq!(config.filter.missing_population_af, MissingDataPolicy::Flag);
+        assert_eq!(config.filter.min_impact, Some(crate::consequence::Impact::Moderate));
+        assert_eq!(config.output.rejected_output.as_deref(), Some("rejected.tsv"));
+        assert_eq!(config.runtime.batch_size, 20_000);
+    }
+
+    #[test]
+    fn test_yaml_file_and_preset_override() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = write_config(
+            &dir,
+            "run.yaml",
+            "preset: liquid-biopsy\nfilter:\n  min_variant_frequency: 0.001\n",
+        );
+
+        let config = RunConfig::resolve(Some(&path), Some("germline-clinical")).unwrap();
+        assert_eq!(config.preset.as_deref(), Some("germline-clinical"));
+        assert_eq!(config.filter.min_variant_frequency, 0.001);
+        assert_eq!(config.filter.min_total_depth, 20);
+    }
+
+    #[test]
+    fn test_unknown_keys_rejected() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = write_config(&dir, "bad.toml", "[filter]\nmin_dept = 10\n");
+        let err = format!("{:#}", RunConfig::resolve(Some(&path), None).unwrap_err());
+        assert!(err.contains("min_dept"), "{}", err);
+        assert!(err.contains("[filter]"), "{}", err);
+
+        let path = write_config(&dir, "bad2.toml", "[filters]\nmin_total_depth = 10\n");
+        assert!(RunConfig::resolve(Some(&path), None).is_err());
+
+        assert!(RunConfig::resolve(None, Some("exome")).is_err());
+    }
+
+    #[test]
+    fn test_dump_round_trip() {
+        for (name, _) in PRESETS {
+            let config = preset(name).unwrap();
+            config.validate().unwrap();
+            for format in [ConfigFormat::Toml, ConfigFormat::Yaml] {
+                let dir = tempfile::TempDir::new().unwrap();
+                let file = if format == ConfigFormat::Toml { "c.toml" } else { "c.yaml" };
+                let path = write_config(&dir, file, &config.render(format).unwrap());
+                let reloaded = RunConfig::resolve(Some(&path), None).unwrap();
+                assert_eq!(
+                    reloaded.render(ConfigFormat::Toml).unwrap(),
+                    config.render(ConfigFormat::Toml).unwrap()
+                );
+            }
+        }
+    }
+}
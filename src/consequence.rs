@@ -0,0 +1,433 @@
+/// Sequence Ontology consequence terms: severity ranking and VEP impact.
+///
+/// Nirvana reports per-transcript consequences as SO terms but only
+/// sometimes fills `impact`, so the impact of a transcript is derived from
+/// its terms whenever the field is missing. The table below follows the
+/// Ensembl VEP severity order (most severe first); legacy and SnpEff
+/// synonyms share the rank and impact of their SO term, and terms not in
+/// either table rank after every known term and have no impact.
+use crate::types::TranscriptAnnotation;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// VEP putative impact, ordered from least to most severe so that
+/// `impact >= Impact::Moderate` reads naturally.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Impact {
+    Modifier,
+    Low,
+    Moderate,
+    High,
+}
+
+impl Impact {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Impact::Modifier => "MODIFIER",
+            Impact::Low => "LOW",
+            Impact::Moderate => "MODERATE",
+            Impact::High => "HIGH",
+        }
+    }
+}
+
+impl fmt::Display for Impact {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Impact {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_uppercase().as_str() {
+            "HIGH" => Ok(Impact::High),
+            "MODERATE" => Ok(Impact::Moderate),
+            "LOW" => Ok(Impact::Low),
+            "MODIFIER" => Ok(Impact::Modifier),
+            _ => anyhow::bail!("invalid impact '{}' (expected HIGH, MODERATE, LOW or MODIFIER)", s),
+        }
+    }
+}
+
+/// SO terms in VEP severity order (most severe first) with their impact.
+pub const SO_TERMS: &[(&str, Impact)] = &[
+    ("transcript_ablation", Impact::High),
+    ("splice_acceptor_variant", Impact::High),
+    ("splice_donor_variant", Impact::High),
+    ("stop_gained", Impact::High),
+    ("frameshift_variant", Impact::High),
+    ("stop_lost", Impact::High),
+    ("start_lost", Impact::High),
+    ("transcript_amplification", Impact::High),
+    ("exon_loss_variant", Impact::High),
+    ("inframe_insertion", Impact::Moderate),
+    ("inframe_deletion", Impact::Moderate),
+    ("missense_variant", Impact::Moderate),
+    ("protein_altering_variant", Impact::Moderate),
+    ("splice_donor_5th_base_variant", Impact::Low),
+    ("splice_region_variant", Impact::Low),
+    ("splice_donor_region_variant", Impact::Low),
+    ("splice_polypyrimidine_tract_variant", Impact::Low),
+    ("incomplete_terminal_codon_variant", Impact::Low),
+    ("start_retained_variant", Impact::Low),
+    ("stop_retained_variant", Impact::Low),
+    ("synonymous_variant", Impact::Low),
+    ("coding_sequence_variant", Impact::Modifier),
+    ("mature_miRNA_variant", Impact::Modifier),
+    ("5_prime_UTR_variant", Impact::Modifier),
+    ("3_prime_UTR_variant", Impact::Modifier),
+    ("non_coding_transcript_exon_variant", Impact::Modifier),
+    ("intron_variant", Impact::Modifier),
+    ("NMD_transcript_variant", Impact::Modifier),
+    ("non_coding_transcript_variant", Impact::Modifier),
+    ("coding_transcript_variant", Impact::Modifier),
+    ("transcript_variant", Impact::Modifier),
+    ("upstream_gene_variant", Impact::Modifier),
+    ("downstream_gene_variant", Impact::Modifier),
+    ("TFBS_ablation", Impact::Modifier),
+    ("TFBS_amplification", Impact::Modifier),
+    ("TF_binding_site_variant", Impact::Modifier),
+    ("regulatory_region_ablation", Impact::Moderate),
+    ("regulatory_region_amplification", Impact::Modifier),
+    ("feature_elongation", Impact::Modifier),
+    ("regulatory_region_variant", Impact::Modifier),
+    ("feature_truncation", Impact::Modifier),
+    ("intergenic_variant", Impact::Modifier),
+    ("sequence_variant", Impact::Modifier),
+];
+
+/// Legacy and SnpEff synonyms (also mapped in `VARIANT_CLASSIFICATIONS`) and
+/// the SO term whose rank and impact they take.
+pub const SO_SYNONYMS: &[(&str, &str)] = &[
+    ("initiator_codon_variant", "start_lost"),
+    ("disruptive_inframe_insertion", "inframe_insertion"),
+    ("disruptive_inframe_deletion", "inframe_deletion"),
+    ("conservative_missense_variant", "missense_variant"),
+    ("rare_amino_acid_variant", "missense_variant"),
+    ("5_prime_UTR_premature_start_codon_gain_variant", "5_prime_UTR_variant"),
+    ("exon_variant", "non_coding_transcript_exon_variant"),
+    ("non_coding_exon_variant", "non_coding_transcript_exon_variant"),
+    ("intragenic_variant", "intron_variant"),
+    ("nc_transcript_variant", "non_coding_transcript_variant"),
+    ("regulatory_region", "regulatory_region_variant"),
+    ("intergenic_region", "intergenic_variant"),
+];
+
+/// Term used for a variant that overlaps no transcript at all.
+pub const INTERGENIC: &str = "intergenic_variant";
+
+fn lookup(term: &str) -> Option<(usize, Impact)> {
+    let term = SO_SYNONYMS
+        .iter()
+        .find(|(synonym, _)| synonym.eq_ignore_ascii_case(term))
+        .map_or(term, |(_, canonical)| canonical);
+    SO_TERMS
+        .iter()
+        .position(|(t, _)| t.eq_ignore_ascii_case(term))
+        .map(|i| (i, SO_TERMS[i].1))
+}
+
+pub fn is_known_term(term: &str) -> bool {
+    lookup(term).is_some()
+}
+
+/// Impact of a single SO term, or `None` for terms outside the table.
+pub fn consequence_impact(term: &str) -> Option<Impact> {
+    lookup(term).map(|(_, impact)| impact)
+}
+
+/// Severity rank of a term (0 = most severe). Unknown terms rank last.
+pub fn severity_rank(term: &str) -> usize {
+    lookup(term).map(|(rank, _)| rank).unwrap_or(SO_TERMS.len())
+}
+
+/// The most severe of `terms`, or `None` if the list is empty. Ties keep the
+/// earlier term, so unknown terms fall back to list order.
+pub fn most_severe<S: AsRef<str>>(terms: &[S]) -> Option<&str> {
+    terms
+        .iter()
+        .map(|t| t.as_ref())
+        .min_by_key(|t| severity_rank(t))
+}
+
+/// Impact of a transcript: Nirvana's `impact` field when present and
+/// recognized, otherwise the highest impact among its consequence terms.
+pub fn transcript_impact(transcript: &TranscriptAnnotation) -> Option<Impact> {
+    transcript
+        .impact
+        .as_deref()
+        .and_then(|i| i.parse().ok())
+        .or_else(|| {
+            transcript
+                .consequence
+                .iter()
+                .filter_map(|c| consequence_impact(c))
+                .max()
+        })
+}
+
+/// MAF `Variant_Classification` values.
+pub const MAF_CLASSIFICATIONS: &[&str] = &[
+    "Frame_Shift_Del",
+    "Frame_Shift_Ins",
+    "In_Frame_Del",
+    "In_Frame_Ins",
+    "Missense_Mutation",
+    "Nonsense_Mutation",
+    "Silent",
+    "Splice_Site",
+    "Translation_Start_Site",
+    "Nonstop_Mutation",
+    "3'UTR",
+    "3'Flank",
+    "5'UTR",
+    "5'Flank",
+    "IGR",
+    "Intron",
+    "RNA",
+    "Targeted_Region",
+    "De_novo_Start_InFrame",
+    "De_novo_Start_OutOfFrame",
+    "Splice_Region",
+    "Unknown",
+];
+
+/// Classes resolved from the alleles: `Frame_Shift` and `In_Frame` become
+/// `_Ins` when the alternate allele is longer and `_Del` otherwise;
+/// `Protein_Altering` is a frameshift, in-frame indel or (equal lengths)
+/// missense depending on the length change.
+pub const FRAME_SHIFT: &str = "Frame_Shift";
+pub const IN_FRAME: &str = "In_Frame";
+pub const PROTEIN_ALTERING: &str = "Protein_Altering";
+
+/// Classification of a transcript whose most severe term has no mapping.
+pub const DEFAULT_CLASSIFICATION: &str = "Targeted_Region";
+
+/// Classification of a variant that overlaps no transcript.
+pub const NO_TRANSCRIPT_CLASSIFICATION: &str = "IGR";
+
+/// SO term to `Variant_Classification`, following vcf2maf's
+/// `GetVariantClassification`. Legacy and SnpEff synonyms are included
+/// so older annotation sources map the same way.
+pub const VARIANT_CLASSIFICATIONS: &[(&str, &str)] = &[
+    ("transcript_ablation", "Splice_Site"),
+    ("exon_loss_variant", "Splice_Site"),
+    ("splice_acceptor_variant", "Splice_Site"),
+    ("splice_donor_variant", "Splice_Site"),
+    ("stop_gained", "Nonsense_Mutation"),
+    ("frameshift_variant", FRAME_SHIFT),
+    ("protein_altering_variant", PROTEIN_ALTERING),
+    ("stop_lost", "Nonstop_Mutation"),
+    ("start_lost", "Translation_Start_Site"),
+    ("initiator_codon_variant", "Translation_Start_Site"),
+    ("inframe_insertion", "In_Frame_Ins"),
+    ("disruptive_inframe_insertion", "In_Frame_Ins"),
+    ("inframe_deletion", "In_Frame_Del"),
+    ("disruptive_inframe_deletion", "In_Frame_Del"),
+    ("missense_variant", "Missense_Mutation"),
+    ("coding_sequence_variant", "Missense_Mutation"),
+    ("conservative_missense_variant", "Missense_Mutation"),
+    ("rare_amino_acid_variant", "Missense_Mutation"),
+    ("transcript_amplification", "Intron"),
+    ("intron_variant", "Intron"),
+    ("intragenic_variant", "Intron"),
+    ("splice_region_variant", "Splice_Region"),
+    ("splice_donor_5th_base_variant", "Splice_Region"),
+    ("splice_donor_region_variant", "Splice_Region"),
+    ("splice_polypyrimidine_tract_variant", "Splice_Region"),
+    ("incomplete_terminal_codon_variant", "Silent"),
+    ("synonymous_variant", "Silent"),
+    ("stop_retained_variant", "Silent"),
+    ("start_retained_variant", "Silent"),
+    ("NMD_transcript_variant", "Silent"),
+    ("mature_miRNA_variant", "RNA"),
+    ("exon_variant", "RNA"),
+    ("non_coding_exon_variant", "RNA"),
+    ("non_coding_transcript_exon_variant", "RNA"),
+    ("non_coding_transcript_variant", "RNA"),
+    ("nc_transcript_variant", "RNA"),
+    ("5_prime_UTR_variant", "5'UTR"),
+    ("5_prime_UTR_premature_start_codon_gain_variant", "5'UTR"),
+    ("3_prime_UTR_variant", "3'UTR"),
+    ("TF_binding_site_variant", "IGR"),
+    ("regulatory_region_variant", "IGR"),
+    ("regulatory_region", "IGR"),
+    ("intergenic_variant", "IGR"),
+    ("intergenic_region", "IGR"),
+    ("upstream_gene_variant", "5'Flank"),
+    ("downstream_gene_variant", "3'Flank"),
+];
+
+/// Maps a transcript's consequence terms to a MAF `Variant_Classification`
+/// using the built-in table plus per-term overrides from the run config.
+#[derive(Debug, Clone, Default)]
+pub struct VariantClassifier {
+    /// Lowercased SO term to classification
+    overrides: BTreeMap<String, String>,
+}
+
+impl VariantClassifier {
+    /// Builds a classifier with `overrides` (SO term to classification, or
+    /// one of the allele-resolved classes) taking precedence over the table.
+    pub fn new(overrides: &BTreeMap<String, String>) -> anyhow::Result<Self> {
+        for (term, class) in overrides {
+            let valid = MAF_CLASSIFICATIONS.contains(&class.as_str())
+                || [FRAME_SHIFT, IN_FRAME, PROTEIN_ALTERING].contains(&class.as_str());
+            if !valid {
+                anyhow::bail!(
+                    "invalid Variant_Classification '{}' for {} (expected one of {}, or {}, {}, {})",
+                    class,
+                    term,
+                    MAF_CLASSIFICATIONS.join(", "),
+                    FRAME_SHIFT,
+                    IN_FRAME,
+                    PROTEIN_ALTERING
+                );
+            }
+        }
+        Ok(Self {
+            overrides: overrides
+                .iter()
+                .map(|(term, class)| (term.to_lowercase(), class.clone()))
+                .collect(),
+        })
+    }
+
+    /// Class of a single term before allele resolution, or `None` if the
+    /// term has no mapping.
+    pub fn term_class(&self, term: &str) -> Option<&str> {
+        self.overrides
+            .get(&term.to_lowercase())
+            .map(String::as_str)
+            .or_else(|| {
+                VARIANT_CLASSIFICATIONS
+                    .iter()
+                    .find(|(t, _)| t.eq_ignore_ascii_case(term))
+                    .map(|(_, class)| *class)
+            })
+    }
+
+    /// Classifies the most severe of `consequences`. Alleles use Nirvana's
+    /// `-` for an empty allele.
+    pub fn classify<S: AsRef<str>>(&self, consequences: &[S], reference: &str, alternate: &str) -> String {
+        let class = most_severe(consequences)
+            .and_then(|term| self.term_class(term))
+            .unwrap_or(DEFAULT_CLASSIFICATION);
+        resolve_class(class, reference, alternate).to_string()
+    }
+}
+
+fn allele_len(allele: &str) -> usize {
+    if allele == "-" {
+        0
+    } else {
+        allele.len()
+    }
+}
+
+fn resolve_class<'a>(class: &'a str, reference: &str, alternate: &str) -> &'a str {
+    let (ref_len, alt_len) = (allele_len(reference), allele_len(alternate));
+    let insertion = alt_len > ref_len;
+    match class {
+        FRAME_SHIFT if insertion => "Frame_Shift_Ins",
+        FRAME_SHIFT => "Frame_Shift_Del",
+        IN_FRAME if insertion => "In_Frame_Ins",
+        IN_FRAME => "In_Frame_Del",
+        PROTEIN_ALTERING if ref_len == alt_len => "Missense_Mutation",
+        PROTEIN_ALTERING => {
+            let in_frame = ref_len.abs_diff(alt_len) % 3 == 0;
+            resolve_class(if in_frame { IN_FRAME } else { FRAME_SHIFT }, reference, alternate)
+        }
+        _ => class,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_impact_ordering() {
+        assert!(Impact::High > Impact::Moderate);
+        assert!(Impact::Low > Impact::Modifier);
+        assert_eq!("moderate".parse::<Impact>().unwrap(), Impact::Moderate);
+        assert!("severe".parse::<Impact>().is_err());
+    }
+
+    #[test]
+    fn test_most_severe() {
+        let terms = ["splice_region_variant", "missense_variant", "intron_variant"];
+        assert_eq!(most_severe(&terms), Some("missense_variant"));
+        assert_eq!(most_severe(&["novel_term", "intron_variant"]), Some("intron_variant"));
+        assert_eq!(most_severe::<&str>(&[]), None);
+    }
+
+    #[test]
+    fn test_consequence_impact() {
+        assert_eq!(consequence_impact("stop_gained"), Some(Impact::High));
+        assert_eq!(consequence_impact("5_prime_UTR_variant"), Some(Impact::Modifier));
+        assert_eq!(consequence_impact("feature_truncation"), Some(Impact::Modifier));
+        assert!(severity_rank("feature_elongation") > severity_rank("intron_variant"));
+        assert_eq!(consequence_impact("not_a_term"), None);
+    }
+
+    #[test]
+    fn test_synonyms_rank_as_their_term() {
+        assert_eq!(severity_rank("initiator_codon_variant"), severity_rank("start_lost"));
+        assert_eq!(consequence_impact("conservative_missense_variant"), Some(Impact::Moderate));
+        assert_eq!(
+            most_severe(&["intron_variant", "initiator_codon_variant"]),
+            Some("initiator_codon_variant")
+        );
+        assert_eq!(
+            VariantClassifier::default().classify(&["intron_variant", "disruptive_inframe_deletion"], "CAGT", "C"),
+            "In_Frame_Del"
+        );
+
+        // Every classified term has a severity, so none falls back to list order
+        for (term, _) in VARIANT_CLASSIFICATIONS {
+            assert!(is_known_term(term), "{} has no severity rank", term);
+        }
+    }
+
+    #[test]
+    fn test_variant_classification() {
+        let classifier = VariantClassifier::default();
+        assert_eq!(classifier.classify(&["frameshift_variant"], "AT", "A"), "Frame_Shift_Del");
+        assert_eq!(classifier.classify(&["frameshift_variant"], "-", "T"), "Frame_Shift_Ins");
+        assert_eq!(classifier.classify(&["protein_altering_variant"], "A", "ATTT"), "In_Frame_Ins");
+        assert_eq!(classifier.classify(&["protein_altering_variant"], "AG", "TC"), "Missense_Mutation");
+        // The most severe term wins, not the first
+        assert_eq!(
+            classifier.classify(&["splice_region_variant", "missense_variant"], "C", "T"),
+            "Missense_Mutation"
+        );
+        assert_eq!(
+            classifier.classify(&["splice_region_variant", "intron_variant"], "C", "T"),
+            "Splice_Region"
+        );
+        assert_eq!(classifier.classify(&["upstream_gene_variant"], "C", "T"), "5'Flank");
+        assert_eq!(classifier.classify(&["non_coding_transcript_exon_variant"], "C", "T"), "RNA");
+        assert_eq!(classifier.classify(&["stop_retained_variant"], "C", "T"), "Silent");
+        assert_eq!(classifier.classify(&["feature_elongation"], "C", "T"), "Targeted_Region");
+    }
+
+    #[test]
+    fn test_classification_overrides() {
+        let overrides = BTreeMap::from([
+            ("splice_region_variant".to_string(), "Intron".to_string()),
+            ("feature_truncation".to_string(), "Frame_Shift".to_string()),
+        ]);
+        let classifier = VariantClassifier::new(&overrides).unwrap();
+        assert_eq!(classifier.classify(&["splice_region_variant"], "C", "T"), "Intron");
+        assert_eq!(classifier.classify(&["feature_truncation"], "CAG", "C"), "Frame_Shift_Del");
+
+        let invalid = BTreeMap::from([("missense_variant".to_string(), "Missense".to_string())]);
+        assert!(VariantClassifier::new(&invalid).is_err());
+    }
+}
@@ -1,9 +1,120 @@
+use crate::consequence::{VariantClassifier, NO_TRANSCRIPT_CLASSIFICATION};
+use crate::filters::clinvar::review_status_stars;
+use crate::hgvs::hgvsp_short;
+use crate::transcript::is_protein_coding;
 use crate::types::*;
 
-pub fn variant_to_maf(variant: &VariantPosition, decision: &FilterDecision) -> MAFRecord {
-    // Select canonical transcript
-    let transcript = select_canonical_transcript(&variant.transcripts);
+/// How included variants become MAF rows.
+#[derive(Debug, Clone, Default)]
+pub struct MafOptions {
+    pub transcripts: TranscriptOutput,
+    pub classifier: VariantClassifier,
+    pub tumor_sample_barcode: String,
+    pub normal_sample_barcode: String,
+    /// Fill `ClinVar_All` with every ClinVar record
+    pub clinvar_all: bool,
+}
+
+/// Converts an included variant to a MAF row for its selected transcript.
+/// The ClinVar columns show the entry chosen by `clinvar`, the variant's
+/// ClinVar assessment (`None` when the ClinVar stage did not run), whatever
+/// evidence decided the variant's inclusion.
+pub fn variant_to_maf(variant: &VariantPosition, clinvar: Option<&ClinVarAssessment>) -> MAFRecord {
+    let selected = variant.selected_transcript();
+    transcript_to_maf(variant, clinvar, selected, true, &MafOptions::default())
+}
+
+/// Converts an included variant to MAF rows as configured by `options`: the
+/// selected transcript only, or one row per transcript. The selected
+/// transcript's row is always written, even when the source/protein-coding
+/// limits would leave it out.
+pub fn variant_to_maf_rows(
+    variant: &VariantPosition,
+    clinvar: Option<&ClinVarAssessment>,
+    options: &MafOptions,
+) -> Vec<MAFRecord> {
+    let output = &options.transcripts;
+    let selected = variant.selected_transcript();
+    let mut rows = if output.all_transcripts && !variant.transcripts.is_empty() {
+        variant
+            .transcripts
+            .iter()
+            .filter_map(|t| {
+                let is_selected = selected.is_some_and(|s| std::ptr::eq(s, t));
+                let source_ok = match output.source {
+                    Some(source) => source.matches(t),
+                    None => true,
+                };
+                let wanted = source_ok && (!output.protein_coding_only || is_protein_coding(t));
+                (is_selected || wanted)
+                    .then(|| transcript_to_maf(variant, clinvar, Some(t), is_selected, options))
+            })
+            .collect()
+    } else {
+        vec![transcript_to_maf(variant, clinvar, selected, true, options)]
+    };
+
+    if options.clinvar_all {
+        let clinvar_all = format_clinvar_all(&variant.clinvar);
+        for row in &mut rows {
+            row.clinvar_all = clinvar_all.clone();
+        }
+    }
+    if output.all_effects {
+        let all_effects = format_all_effects(&variant.transcripts);
+        for row in &mut rows {
+            row.all_effects = all_effects.clone();
+        }
+    }
+    rows
+}
+
+/// vcf2maf-style effect list: one `SYMBOL,Consequence,HGVSp_Short,Transcript_ID,HGVSc,IMPACT,MANE`
+/// entry per transcript, separated by `;`, with multiple consequences joined by `&`.
+fn format_all_effects(transcripts: &[TranscriptAnnotation]) -> String {
+    transcripts
+        .iter()
+        .map(|t| {
+            let (hgvsc, _, hgvsp_short) = extract_hgvs_notation(Some(t));
+            [
+                t.hgnc.clone().unwrap_or_default(),
+                t.consequence.join("&"),
+                hgvsp_short,
+                t.id.clone().unwrap_or_default(),
+                hgvsc,
+                t.impact.as_deref().map(str::to_uppercase).unwrap_or_default(),
+                if t.is_mane_select == Some(true) { "MANE".to_string() } else { String::new() },
+            ]
+            .join(",")
+        })
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// Every ClinVar record as `ID,Significance,Stars`, separated by `;`, with
+/// multiple significances joined by `/`.
+fn format_clinvar_all(entries: &[ClinVarEntry]) -> String {
+    entries
+        .iter()
+        .map(|e| {
+            format!(
+                "{},{},{}",
+                e.id.as_deref().unwrap_or(""),
+                e.clinical_significance.join("/"),
+                review_status_stars(e.review_status.as_deref().unwrap_or(""))
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(";")
+}
 
+fn transcript_to_maf(
+    variant: &VariantPosition,
+    clinvar: Option<&ClinVarAssessment>,
+    transcript: Option<&TranscriptAnnotation>,
+    is_selected: bool,
+    options: &MafOptions,
+) -> MAFRecord {
     // Extract gene symbol (use hgnc field which contains the gene symbol)
     let hugo_symbol = transcript
         .as_ref()
@@ -12,16 +123,16 @@ pub fn variant_to_maf(variant: &VariantPosition, decision: &FilterDecision) -> M
         .to_string();
 
     // Map variant classification
-    let variant_classification = transcript
-        .as_ref()
-        .map(|t| map_variant_classification(&t.consequence))
-        .unwrap_or_else(|| "".to_string());
+    let variant_classification = match transcript {
+        Some(t) => options.classifier.classify(&t.consequence, &variant.reference_allele, &variant.alternate_allele),
+        None => NO_TRANSCRIPT_CLASSIFICATION.to_string(),
+    };
 
     // Map variant type
     let variant_type = map_variant_type(&variant.variant_type);
 
     // Extract HGVS notation
-    let (hgvsc, hgvsp, hgvsp_short) = extract_hgvs_notation(transcript.as_ref());
+    let (hgvsc, hgvsp, hgvsp_short) = extract_hgvs_notation(transcript);
 
     // Transcript ID
     let transcript_id = transcript
@@ -93,15 +204,10 @@ pub fn variant_to_maf(variant: &VariantPosition, decision: &FilterDecision) -> M
         .unwrap_or("")
         .to_string();
 
-    // ClinVar information
+    // ClinVar information: the entry the assessment selected
+    let selected_clinvar = clinvar.and_then(|a| a.selected_entry.as_ref());
     let (clinvar_id, clinvar_review_status, clinvar_significance, clinvar_disease) =
-        if let Some(entry) = decision
-            .primary_evidence
-            .as_str()
-            .eq("ClinVar")
-            .then(|| variant.clinvar.first())
-            .flatten()
-        {
+        if let Some(entry) = selected_clinvar {
             (
                 entry.id.as_deref().unwrap_or("").to_string(),
                 entry.review_status.as_deref().unwrap_or("").to_string(),
@@ -119,6 +225,12 @@ pub fn variant_to_maf(variant: &VariantPosition, decision: &FilterDecision) -> M
         .map(|s| format!("{:.4}", s))
         .unwrap_or_else(|| "".to_string());
 
+    // Blank when the score came from the gene-level PrimateAI fallback
+    let primate_ai_transcript = variant
+        .primate_ai_3d
+        .and(variant.primate_ai_3d_transcript.clone())
+        .unwrap_or_default();
+
     let dann_score = variant
         .dann_score
         .map(|s| format!("{:.4}", s))
@@ -145,6 +257,24 @@ pub fn variant_to_maf(variant: &VariantPosition, decision: &FilterDecision) -> M
         .map(|v| format!("{:.4}", v))
         .unwrap_or_else(|| "".to_string());
 
+    // Per-sample genotype fields
+    let t_ref_count = variant
+        .ref_read_count()
+        .map(|c| c.to_string())
+        .unwrap_or_default();
+
+    let t_alt_count = variant
+        .alt_read_count()
+        .map(|c| c.to_string())
+        .unwrap_or_default();
+
+    let genotype = variant.genotype.clone().unwrap_or_default();
+
+    let genotype_quality = variant
+        .genotype_quality
+        .map(|gq| gq.to_string())
+        .unwrap_or_default();
+
     MAFRecord {
         hugo_symbol,
         chromosome: variant.chromosome.clone(),
@@ -156,11 +286,13 @@ pub fn variant_to_maf(variant: &VariantPosition, decision: &FilterDecision) -> M
         reference_allele: variant.reference_allele.clone(),
         tumor_seq_allele1: variant.reference_allele.clone(),
         tumor_seq_allele2: variant.alternate_allele.clone(),
-        tumor_sample_barcode: "".to_string(),
+        tumor_sample_barcode: options.tumor_sample_barcode.clone(),
+        matched_norm_sample_barcode: options.normal_sample_barcode.clone(),
         hgvsc,
         hgvsp,
         hgvsp_short,
         transcript_id,
+        is_selected_transcript: if is_selected { "1" } else { "0" }.to_string(),
         exon,
         consequence,
         impact,
@@ -176,54 +308,50 @@ pub fn variant_to_maf(variant: &VariantPosition, decision: &FilterDecision) -> M
         clinvar_review_status,
         clinvar_significance,
         clinvar_disease,
+        clinvar_all: String::new(),
         primate_ai_score,
+        primate_ai_transcript,
         dann_score,
         revel_score,
+        predictive_evidence: String::new(),
         gnomad_af,
         gnomad_eas_af,
         depth,
         vaf,
+        t_ref_count,
+        t_alt_count,
+        genotype,
+        genotype_quality,
+        qc_flags: String::new(),
+        all_effects: String::new(),
     }
 }
 
-fn select_canonical_transcript(
-    transcripts: &[TranscriptAnnotation],
-) -> Option<TranscriptAnnotation> {
-    // Prefer MANE Select transcript
-    if let Some(mane) = transcripts
-        .iter()
-        .find(|t| t.is_mane_select == Some(true))
-    {
-        return Some(mane.clone());
+/// Builds a rejected-variants report row for a variant dropped at `stage`.
+pub fn variant_to_rejected(
+    variant: &VariantPosition,
+    stage: &str,
+    reason: &str,
+    flags: &[String],
+) -> RejectedRecord {
+    RejectedRecord {
+        chromosome: variant.chromosome.clone(),
+        start_position: variant.start,
+        reference_allele: variant.reference_allele.clone(),
+        alternate_allele: variant.alternate_allele.clone(),
+        stage: stage.to_string(),
+        reason: reason.to_string(),
+        qc_flags: flags.join(";"),
     }
-
-    // Otherwise, return first transcript
-    transcripts.first().cloned()
 }
 
-fn map_variant_classification(consequences: &[String]) -> String {
-    // Map SO terms to MAF variant classification
-    for consequence in consequences {
-        let consequence_lower = consequence.to_lowercase();
-        let classification = match consequence_lower.as_str() {
-            s if s.contains("missense") => "Missense_Mutation",
-            s if s.contains("nonsense") || s.contains("stop_gained") => "Nonsense_Mutation",
-            s if s.contains("frameshift") => "Frame_Shift_Del",
-            s if s.contains("splice_acceptor") || s.contains("splice_donor") => "Splice_Site",
-            s if s.contains("inframe_deletion") => "In_Frame_Del",
-            s if s.contains("inframe_insertion") => "In_Frame_Ins",
-            s if s.contains("start_lost") => "Translation_Start_Site",
-            s if s.contains("stop_lost") => "Nonstop_Mutation",
-            s if s.contains("synonymous") => "Silent",
-            s if s.contains("5_prime_utr") => "5'UTR",
-            s if s.contains("3_prime_utr") => "3'UTR",
-            s if s.contains("intron") => "Intron",
-            _ => continue,
-        };
-        return classification.to_string();
-    }
-
-    "".to_string()
+/// Picks a transcript with the default policy (MANE Select, otherwise the
+/// first). Variants carry the transcript chosen by the configured policy;
+/// use `VariantPosition::selected_transcript` for those.
+pub fn select_canonical_transcript(
+    transcripts: &[TranscriptAnnotation],
+) -> Option<&TranscriptAnnotation> {
+    crate::transcript::select_default_index(transcripts).map(|i| &transcripts[i])
 }
 
 fn map_variant_type(variant_type: &str) -> String {
@@ -241,19 +369,8 @@ fn extract_hgvs_notation(transcript: Option<&TranscriptAnnotation>) -> (String,
         let hgvsc = t.hgvsc.as_deref().unwrap_or("").to_string();
         let hgvsp = t.hgvsp.as_deref().unwrap_or("").to_string();
 
-        // Generate short HGVS protein notation (e.g., p.V600E)
-        let hgvsp_short = if let Some(hgvsp_str) = &t.hgvsp {
-            // Extract short form from full HGVS (e.g., "NP_004324.2:p.Val600Glu" -> "p.V600E")
-            if let Some(pos) = hgvsp_str.find(":p.") {
-                shorten_hgvsp(&hgvsp_str[pos + 1..])
-            } else if hgvsp_str.starts_with("p.") {
-                shorten_hgvsp(hgvsp_str)
-            } else {
-                hgvsp_str.to_string()
-            }
-        } else {
-            String::new()
-        };
+        // Short HGVS protein notation (e.g., "NP_004324.2:p.(Val600Glu)" -> "p.V600E")
+        let hgvsp_short = t.hgvsp.as_deref().map(hgvsp_short).unwrap_or_default();
 
         (hgvsc, hgvsp, hgvsp_short)
     } else {
@@ -261,37 +378,8 @@ fn extract_hgvs_notation(transcript: Option<&TranscriptAnnotation>) -> (String,
     }
 }
 
-fn shorten_hgvsp(hgvsp: &str) -> String {
-    // Convert three-letter amino acid codes to single-letter codes
-    hgvsp
-        .replace("Ala", "A")
-        .replace("Arg", "R")
-        .replace("Asn", "N")
-        .replace("Asp", "D")
-        .replace("Cys", "C")
-        .replace("Gln", "Q")
-        .replace("Glu", "E")
-        .replace("Gly", "G")
-        .replace("His", "H")
-        .replace("Ile", "I")
-        .replace("Leu", "L")
-        .replace("Lys", "K")
-        .replace("Met", "M")
-        .replace("Phe", "F")
-        .replace("Pro", "P")
-        .replace("Ser", "S")
-        .replace("Thr", "T")
-        .replace("Trp", "W")
-        .replace("Tyr", "Y")
-        .replace("Val", "V")
-        .replace("Ter", "*")
-}
-
 fn extract_population_frequencies(variant: &VariantPosition) -> (String, String) {
-    let gnomad_exome = variant
-        .population_frequencies
-        .iter()
-        .find(|pf| pf.source == "gnomad-exome");
+    let gnomad_exome = variant.gnomad_exome();
 
     let gnomad_af = gnomad_exome
         .and_then(|pf| pf.all_af)
@@ -312,30 +400,16 @@ mod tests {
 
     #[test]
     fn test_map_variant_classification() {
-        assert_eq!(
-            map_variant_classification(&vec!["missense_variant".to_string()]),
-            "Missense_Mutation"
-        );
-        assert_eq!(
-            map_variant_classification(&vec!["stop_gained".to_string()]),
-            "Nonsense_Mutation"
-        );
-        assert_eq!(
-            map_variant_classification(&vec!["frameshift_variant".to_string()]),
-            "Frame_Shift_Del"
-        );
-        assert_eq!(
-            map_variant_classification(&vec!["splice_donor_variant".to_string()]),
-            "Splice_Site"
-        );
-        assert_eq!(
-            map_variant_classification(&vec!["synonymous_variant".to_string()]),
-            "Silent"
-        );
-        assert_eq!(
-            map_variant_classification(&vec!["inframe_deletion".to_string()]),
-            "In_Frame_Del"
-        );
+        let classify = |terms: &[&str], reference: &str, alternate: &str| {
+            VariantClassifier::default().classify(terms, reference, alternate)
+        };
+        assert_eq!(classify(&["missense_variant"], "A", "G"), "Missense_Mutation");
+        assert_eq!(classify(&["stop_gained"], "C", "T"), "Nonsense_Mutation");
+        assert_eq!(classify(&["frameshift_variant"], "CT", "C"), "Frame_Shift_Del");
+        assert_eq!(classify(&["frameshift_variant"], "C", "CT"), "Frame_Shift_Ins");
+        assert_eq!(classify(&["splice_donor_variant"], "G", "A"), "Splice_Site");
+        assert_eq!(classify(&["synonymous_variant"], "G", "A"), "Silent");
+        assert_eq!(classify(&["inframe_deletion"], "CTTG", "C"), "In_Frame_Del");
     }
 
     #[test]
@@ -348,10 +422,12 @@ mod tests {
 
     #[test]
     fn test_shorten_hgvsp() {
-        assert_eq!(shorten_hgvsp("p.Val600Glu"), "p.V600E");
-        assert_eq!(shorten_hgvsp("p.Arg132His"), "p.R132H");
-        assert_eq!(shorten_hgvsp("p.Gly12Asp"), "p.G12D");
-        assert_eq!(shorten_hgvsp("p.Leu858Arg"), "p.L858R");
+        assert_eq!(hgvsp_short("p.Val600Glu"), "p.V600E");
+        assert_eq!(hgvsp_short("p.Arg132His"), "p.R132H");
+        assert_eq!(hgvsp_short("p.Gly12Asp"), "p.G12D");
+        assert_eq!(hgvsp_short("p.Leu858Arg"), "p.L858R");
+        // Substrings of the notation itself are not amino acids
+        assert_eq!(hgvsp_short("NP_000537.3:p.(Ser1982ArgfsTer22)"), "p.S1982Rfs*22");
     }
 
     #[test]
@@ -373,6 +449,8 @@ mod tests {
             hgvsp: Some("p.Val600Glu".to_string()),
             is_canonical: Some(true),
             is_mane_select: Some(true),
+            is_mane_plus_clinical: None,
+            bio_type: None,
         };
 
         // Create a minimal variant for testing
@@ -380,31 +458,13 @@ mod tests {
             chromosome: "chr7".to_string(),
             start: 140453136,
             end_pos: 140453136,
-            reference_allele: "A".to_string(),
-            alternate_allele: "T".to_string(),
-            variant_type: "SNV".to_string(),
-            filters: vec!["PASS".to_string()],
             total_depth: Some(100),
             variant_frequencies: Some(vec![0.45]),
             transcripts: vec![transcript],
-            clinvar: vec![],
-            cosmic: vec![],
-            population_frequencies: vec![],
-            primate_ai_3d: None,
-            primate_ai: None,
-            dann_score: None,
-            revel_score: None,
-            dbsnp_ids: vec![],
+            ..VariantPosition::test_default()
         };
 
-        let decision = FilterDecision {
-            should_include: true,
-            pathogenicity_class: "Pathogenic".to_string(),
-            primary_evidence: "ClinVar".to_string(),
-            justification: "Test".to_string(),
-        };
-
-        let maf = variant_to_maf(&variant, &decision);
+        let maf = variant_to_maf(&variant, None);
 
         // Verify all new fields are correctly extracted
         assert_eq!(maf.impact, "MODERATE"); // Should be uppercase
@@ -436,37 +496,21 @@ mod tests {
             hgvsp: None,
             is_canonical: None,
             is_mane_select: None,
+            is_mane_plus_clinical: None,
+            bio_type: None,
         };
 
         let variant = VariantPosition {
-            chromosome: "chr1".to_string(),
             start: 12345,
             end_pos: 12345,
-            reference_allele: "A".to_string(),
             alternate_allele: "G".to_string(),
-            variant_type: "SNV".to_string(),
-            filters: vec!["PASS".to_string()],
             total_depth: Some(50),
             variant_frequencies: Some(vec![0.3]),
             transcripts: vec![transcript],
-            clinvar: vec![],
-            cosmic: vec![],
-            population_frequencies: vec![],
-            primate_ai_3d: None,
-            primate_ai: None,
-            dann_score: None,
-            revel_score: None,
-            dbsnp_ids: vec![],
-        };
-
-        let decision = FilterDecision {
-            should_include: false,
-            pathogenicity_class: "Excluded".to_string(),
-            primary_evidence: "None".to_string(),
-            justification: "Test".to_string(),
+            ..VariantPosition::test_default()
         };
 
-        let maf = variant_to_maf(&variant, &decision);
+        let maf = variant_to_maf(&variant, None);
 
         // Verify missing fields result in empty strings
         assert_eq!(maf.impact, "");
@@ -477,4 +521,187 @@ mod tests {
         assert_eq!(maf.cds_position, "");
         assert_eq!(maf.protein_position, "");
     }
+
+    #[test]
+    fn test_all_transcript_rows() {
+        let selected = TranscriptAnnotation {
+            id: Some("NM_004333.6".to_string()),
+            source: Some("RefSeq".to_string()),
+            hgnc: Some("BRAF".to_string()),
+            consequence: vec!["splice_region_variant".to_string(), "intron_variant".to_string()],
+            impact: Some("low".to_string()),
+            amino_acids: None,
+            cdna_pos: None,
+            cds_pos: None,
+            exons: None,
+            codons: None,
+            protein_pos: None,
+            hgvsc: Some("NM_004333.6:c.1742+3A>T".to_string()),
+            hgvsp: None,
+            is_canonical: Some(true),
+            is_mane_select: Some(true),
+            is_mane_plus_clinical: None,
+            bio_type: Some("protein_coding".to_string()),
+        };
+        let isoform = TranscriptAnnotation {
+            id: Some("ENST00000644969.2".to_string()),
+            source: Some("Ensembl".to_string()),
+            consequence: vec!["frameshift_variant".to_string()],
+            impact: Some("high".to_string()),
+            cds_pos: Some("1745".to_string()),
+            hgvsp: Some("ENSP00000496776.1:p.Leu582Ter".to_string()),
+            is_canonical: None,
+            is_mane_select: None,
+            ..selected.clone()
+        };
+        let processed = TranscriptAnnotation {
+            id: Some("ENST00000479537.6".to_string()),
+            source: Some("Ensembl".to_string()),
+            consequence: vec!["non_coding_transcript_exon_variant".to_string()],
+            is_canonical: None,
+            is_mane_select: None,
+            bio_type: Some("processed_transcript".to_string()),
+            ..selected.clone()
+        };
+
+        let variant = VariantPosition {
+            chromosome: "chr7".to_string(),
+            start: 140753336,
+            end_pos: 140753336,
+            total_depth: Some(100),
+            variant_frequencies: Some(vec![0.45]),
+            transcripts: vec![isoform, selected, processed],
+            ..VariantPosition::test_default()
+        };
+        let rows = variant_to_maf_rows(&variant, None, &MafOptions::default());
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].transcript_id, "NM_004333.6");
+        assert_eq!(rows[0].is_selected_transcript, "1");
+        assert_eq!(rows[0].all_effects, "");
+
+        let output = MafOptions {
+            transcripts: TranscriptOutput {
+                all_transcripts: true,
+                ..TranscriptOutput::default()
+            },
+            ..MafOptions::default()
+        };
+        let rows = variant_to_maf_rows(&variant, None, &output);
+        let flags: Vec<(&str, &str)> = rows
+            .iter()
+            .map(|r| (r.transcript_id.as_str(), r.is_selected_transcript.as_str()))
+            .collect();
+        assert_eq!(
+            flags,
+            vec![("ENST00000644969.2", "0"), ("NM_004333.6", "1"), ("ENST00000479537.6", "0")]
+        );
+        assert_eq!(rows[0].hgvsp_short, "p.L582*");
+        assert_eq!(rows[0].variant_classification, "Frame_Shift_Del");
+        assert_eq!(rows[1].variant_classification, "Splice_Region");
+        assert_eq!(rows[2].variant_classification, "RNA");
+
+        // Limits never drop the selected transcript
+        let output = MafOptions {
+            transcripts: TranscriptOutput {
+                all_transcripts: true,
+                source: Some(crate::transcript::TranscriptSource::Ensembl),
+                protein_coding_only: true,
+                all_effects: true,
+            },
+            ..MafOptions::default()
+        };
+        let rows = variant_to_maf_rows(&variant, None, &output);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].transcript_id, "NM_004333.6");
+        assert!(rows[1].all_effects.starts_with(
+            "BRAF,frameshift_variant,p.L582*,ENST00000644969.2,NM_004333.6:c.1742+3A>T,HIGH,;\
+             BRAF,splice_region_variant&intron_variant,,NM_004333.6,NM_004333.6:c.1742+3A>T,LOW,MANE;"
+        ));
+    }
+
+    #[test]
+    fn test_clinvar_columns_use_selected_entry() {
+        let entry = |id: &str, significance: &str, review_status: &str| ClinVarEntry {
+            id: Some(id.to_string()),
+            allele_id: None,
+            clinical_significance: vec![significance.to_string()],
+            review_status: Some(review_status.to_string()),
+            phenotypes: vec!["Lynch syndrome".to_string()],
+            last_evaluated: None,
+        };
+        let variant = VariantPosition {
+            chromosome: "chr3".to_string(),
+            start: 37025749,
+            end_pos: 37025749,
+            reference_allele: "C".to_string(),
+            total_depth: Some(80),
+            variant_frequencies: Some(vec![0.5]),
+            clinvar: vec![
+                entry("RCV000000001.1", "benign", "criteria provided, single submitter"),
+                entry("RCV000000002.1", "uncertain significance", "no assertion criteria provided"),
+                entry("RCV000000003.2", "pathogenic", "reviewed by expert panel"),
+            ],
+            ..VariantPosition::test_default()
+        };
+        let assessment = crate::filters::clinvar::assess_clinvar_pathogenicity(&variant.clinvar);
+        let options = MafOptions {
+            clinvar_all: true,
+            ..MafOptions::default()
+        };
+        let rows = variant_to_maf_rows(&variant, Some(&assessment), &options);
+        assert_eq!(rows[0].clinvar_id, "RCV000000003.2");
+        assert_eq!(rows[0].clinvar_significance, "pathogenic");
+        assert_eq!(rows[0].clinvar_review_status, "reviewed by expert panel");
+        assert_eq!(
+            rows[0].clinvar_all,
+            "RCV000000001.1,benign,1;RCV000000002.1,uncertain significance,0;RCV000000003.2,pathogenic,3"
+        );
+        assert_eq!(rows[0].variant_classification, "IGR");
+
+        // Without a ClinVar assessment there is nothing to report
+        let maf = variant_to_maf(&variant, None);
+        assert_eq!(maf.clinvar_id, "");
+        assert_eq!(maf.clinvar_all, "");
+    }
+
+    #[test]
+    fn test_clinvar_columns_without_clinvar_evidence() {
+        use crate::filters::pipeline::{Pipeline, Verdict};
+
+        // A VUS in ClinVar, included on its predictive scores
+        let variant = VariantPosition {
+            chromosome: "chr17".to_string(),
+            start: 7674220,
+            end_pos: 7674220,
+            reference_allele: "C".to_string(),
+            total_depth: Some(80),
+            variant_frequencies: Some(vec![0.5]),
+            clinvar: vec![ClinVarEntry {
+                id: Some("RCV000000004.1".to_string()),
+                allele_id: None,
+                clinical_significance: vec!["uncertain significance".to_string()],
+                review_status: Some("criteria provided, single submitter".to_string()),
+                phenotypes: vec!["Li-Fraumeni syndrome".to_string()],
+                last_evaluated: None,
+            }],
+            primate_ai_3d: Some(0.92),
+            dann_score: Some(0.99),
+            revel_score: Some(0.88),
+            ..VariantPosition::test_default()
+        };
+
+        let config = FilterConfig::default();
+        let pipeline = Pipeline::from_config(&config).unwrap();
+        let outcome = pipeline.run(&variant, &config, &mut FilterStats::default());
+        match &outcome.verdict {
+            Verdict::Included(decision) => assert_eq!(decision.primary_evidence, "Predictive"),
+            Verdict::Rejected { reason, .. } => panic!("variant should be included: {}", reason.message),
+        }
+
+        let rows = variant_to_maf_rows(&variant, outcome.context.clinvar.as_ref(), &MafOptions::default());
+        assert_eq!(rows[0].clinvar_id, "RCV000000004.1");
+        assert_eq!(rows[0].clinvar_significance, "uncertain significance");
+        assert_eq!(rows[0].clinvar_review_status, "criteria provided, single submitter");
+        assert_eq!(rows[0].clinvar_disease, "Li-Fraumeni syndrome");
+    }
 }
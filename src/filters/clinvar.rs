@@ -27,9 +27,11 @@ pub fn assess_clinvar_pathogenicity(entries: &[ClinVarEntry]) -> ClinVarAssessme
         .collect();
 
     // Check for benign classification first
+    let mut selected_benign = None;
     let (is_benign, is_likely_benign) = if !benign_entries.is_empty() {
-        let selected_benign = resolve_conflicting_entries(&benign_entries);
-        let sig_lower = selected_benign
+        let selected = resolve_conflicting_entries(&benign_entries);
+        selected_benign = Some(selected);
+        let sig_lower = selected
             .clinical_significance
             .join(", ")
             .to_lowercase();
@@ -47,14 +49,17 @@ pub fn assess_clinvar_pathogenicity(entries: &[ClinVarEntry]) -> ClinVarAssessme
         (false, false)
     };
 
-    // If no pathogenic entries
+    // If no pathogenic entries, report the benign entry, or else the
+    // best-reviewed entry of any significance
     if pathogenic_entries.is_empty() {
+        let all_entries: Vec<_> = entries.iter().collect();
+        let selected = selected_benign.unwrap_or_else(|| resolve_conflicting_entries(&all_entries));
         return ClinVarAssessment {
             is_pathogenic: false,
             is_likely_pathogenic: false,
             is_benign,
             is_likely_benign,
-            selected_entry: None,
+            selected_entry: Some(selected.clone()),
             confidence_level: "none".to_string(),
             reason: "No pathogenic or likely pathogenic ClinVar entries".to_string(),
         };
@@ -193,6 +198,17 @@ pub fn resolve_conflicting_entries<'a>(entries: &'a [&'a ClinVarEntry]) -> &'a C
     sorted[0]
 }
 
+/// ClinVar review stars (0-4) for a review status, from its priority.
+pub fn review_status_stars(status: &str) -> u8 {
+    match get_review_status_priority(status) {
+        1 => 4,
+        2 => 3,
+        3 => 2,
+        4 | 5 => 1,
+        _ => 0,
+    }
+}
+
 fn get_confidence_level(review_status: &str) -> String {
     let priority = get_review_status_priority(review_status);
 
@@ -266,4 +282,16 @@ mod tests {
             3
         );
     }
+
+    #[test]
+    fn test_review_status_stars() {
+        assert_eq!(review_status_stars("practice guideline"), 4);
+        assert_eq!(review_status_stars("reviewed by expert panel"), 3);
+        assert_eq!(review_status_stars("criteria provided, multiple submitters, no conflicts"), 2);
+        assert_eq!(review_status_stars("criteria provided, conflicting interpretations"), 1);
+        assert_eq!(review_status_stars("criteria provided, single submitter"), 1);
+        assert_eq!(review_status_stars("no assertion criteria provided"), 0);
+        assert_eq!(review_status_stars("no assertion provided"), 0);
+        assert_eq!(review_status_stars(""), 0);
+    }
 }
@@ -0,0 +1,217 @@
+use crate::consequence::{most_severe, transcript_impact, Impact, INTERGENIC};
+use crate::types::*;
+
+pub fn apply_consequence_filters(
+    variant: &VariantPosition,
+    config: &FilterConfig,
+) -> ConsequenceFilterResult {
+    if !has_consequence_filters(config) {
+        return ConsequenceFilterResult {
+            passes_consequence: true,
+            failure_reason: None,
+            failed_impact: false,
+            consequence: None,
+        };
+    }
+
+    let candidates: Vec<&TranscriptAnnotation> = match config.consequence_scope {
+        ConsequenceScope::SelectedTranscript => variant.selected_transcript().into_iter().collect(),
+        ConsequenceScope::AnyTranscript => variant.transcripts.iter().collect(),
+    };
+
+    // Intergenic variants carry no transcripts; evaluate them as a single
+    // pseudo-transcript so they can be included or excluded by name.
+    if candidates.is_empty() {
+        let terms = [INTERGENIC.to_string()];
+        let result = check_transcript(&terms, Some(Impact::Modifier), config);
+        return into_result(result, INTERGENIC);
+    }
+
+    let mut first_failure = None;
+    for transcript in candidates {
+        let term = most_severe(&transcript.consequence).unwrap_or("");
+        match check_transcript(&transcript.consequence, transcript_impact(transcript), config) {
+            Ok(()) => return into_result(Ok(()), term),
+            Err(failure) => {
+                first_failure.get_or_insert((failure, term));
+            }
+        }
+    }
+
+    let (failure, term) = first_failure.expect("at least one candidate transcript");
+    into_result(Err(failure), term)
+}
+
+fn has_consequence_filters(config: &FilterConfig) -> bool {
+    !config.include_consequences.is_empty()
+        || !config.exclude_consequences.is_empty()
+        || config.min_impact.is_some()
+}
+
+enum ConsequenceFailure {
+    NotIncluded(String),
+    Excluded(String),
+    LowImpact(String),
+}
+
+/// Checks one transcript's terms. Include passes if any term is listed;
+/// exclude only rejects when every term is excluded, so excluding
+/// `splice_region_variant` does not drop a `missense_variant` that also
+/// touches a splice region.
+fn check_transcript(
+    terms: &[String],
+    impact: Option<Impact>,
+    config: &FilterConfig,
+) -> Result<(), ConsequenceFailure> {
+    let listed = |list: &[String], term: &String| list.iter().any(|c| c.eq_ignore_ascii_case(term));
+
+    if !config.include_consequences.is_empty()
+        && !terms.iter().any(|t| listed(&config.include_consequences, t))
+    {
+        return Err(ConsequenceFailure::NotIncluded(terms.join(",")));
+    }
+
+    if !config.exclude_consequences.is_empty()
+        && !terms.is_empty()
+        && terms.iter().all(|t| listed(&config.exclude_consequences, t))
+    {
+        return Err(ConsequenceFailure::Excluded(terms.join(",")));
+    }
+
+    if let Some(min_impact) = config.min_impact {
+        if !matches!(impact, Some(i) if i >= min_impact) {
+            let found = impact.map(|i| i.as_str()).unwrap_or("unknown");
+            return Err(ConsequenceFailure::LowImpact(format!(
+                "Impact below minimum ({} < {})",
+                found, min_impact
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn into_result(result: Result<(), ConsequenceFailure>, term: &str) -> ConsequenceFilterResult {
+    let (reason, failed_impact) = match result {
+        Ok(()) => {
+            return ConsequenceFilterResult {
+                passes_consequence: true,
+                failure_reason: None,
+                failed_impact: false,
+                consequence: Some(term.to_string()),
+            }
+        }
+        Err(ConsequenceFailure::NotIncluded(terms)) => {
+            (format!("Consequence not included ({})", terms), false)
+        }
+        Err(ConsequenceFailure::Excluded(terms)) => {
+            (format!("Consequence excluded ({})", terms), false)
+        }
+        Err(ConsequenceFailure::LowImpact(reason)) => (reason, true),
+    };
+
+    ConsequenceFilterResult {
+        passes_consequence: false,
+        failure_reason: Some(reason),
+        failed_impact,
+        consequence: Some(term.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_filters_configured() {
+        let variant = create_test_variant(vec![create_transcript(&["intron_variant"], false)]);
+        let result = apply_consequence_filters(&variant, &FilterConfig::default());
+        assert!(result.passes_consequence);
+    }
+
+    #[test]
+    fn test_exclude_consequences() {
+        let config = FilterConfig {
+            exclude_consequences: vec!["intron_variant".to_string(), "splice_region_variant".to_string()],
+            ..FilterConfig::default()
+        };
+
+        let variant = create_test_variant(vec![create_transcript(&["intron_variant"], false)]);
+        let result = apply_consequence_filters(&variant, &config);
+        assert!(!result.passes_consequence);
+        assert_eq!(result.consequence.as_deref(), Some("intron_variant"));
+
+        // Only partially excluded: kept
+        let variant = create_test_variant(vec![create_transcript(
+            &["missense_variant", "splice_region_variant"],
+            false,
+        )]);
+        assert!(apply_consequence_filters(&variant, &config).passes_consequence);
+    }
+
+    #[test]
+    fn test_min_impact_and_scope() {
+        let config = FilterConfig {
+            min_impact: Some(Impact::Moderate),
+            ..FilterConfig::default()
+        };
+
+        // MANE transcript is synonymous, another isoform is missense
+        let variant = create_test_variant(vec![
+            create_transcript(&["missense_variant"], false),
+            create_transcript(&["synonymous_variant"], true),
+        ]);
+        let result = apply_consequence_filters(&variant, &config);
+        assert!(!result.passes_consequence);
+        assert!(result.failed_impact);
+
+        let config = FilterConfig {
+            consequence_scope: ConsequenceScope::AnyTranscript,
+            ..config
+        };
+        assert!(apply_consequence_filters(&variant, &config).passes_consequence);
+    }
+
+    #[test]
+    fn test_intergenic_variant() {
+        let config = FilterConfig {
+            include_consequences: vec!["missense_variant".to_string()],
+            ..FilterConfig::default()
+        };
+        let variant = create_test_variant(vec![]);
+        let result = apply_consequence_filters(&variant, &config);
+        assert!(!result.passes_consequence);
+        assert_eq!(result.consequence.as_deref(), Some(INTERGENIC));
+    }
+
+    fn create_transcript(consequences: &[&str], mane: bool) -> TranscriptAnnotation {
+        TranscriptAnnotation {
+            id: Some("NM_000001.1".to_string()),
+            source: Some("RefSeq".to_string()),
+            hgnc: Some("GENE1".to_string()),
+            consequence: consequences.iter().map(|c| c.to_string()).collect(),
+            impact: None,
+            amino_acids: None,
+            cdna_pos: None,
+            cds_pos: None,
+            exons: None,
+            codons: None,
+            protein_pos: None,
+            hgvsc: None,
+            hgvsp: None,
+            is_canonical: None,
+            is_mane_select: Some(mane),
+            is_mane_plus_clinical: None,
+            bio_type: None,
+        }
+    }
+
+    fn create_test_variant(transcripts: Vec<TranscriptAnnotation>) -> VariantPosition {
+        VariantPosition {
+            total_depth: Some(50),
+            variant_frequencies: Some(vec![0.05]),
+            transcripts,
+            ..VariantPosition::test_default()
+        }
+    }
+}
@@ -5,13 +5,20 @@ pub fn make_filter_decision(
     clinvar_assessment: &ClinVarAssessment,
     predictive_assessment: &PredictiveAssessment,
 ) -> FilterDecision {
-    make_filter_decision_with_config(_variant, clinvar_assessment, predictive_assessment, false)
+    make_filter_decision_with_config(
+        _variant,
+        clinvar_assessment,
+        predictive_assessment,
+        &LofAssessment::default(),
+        false,
+    )
 }
 
 pub fn make_filter_decision_with_config(
     _variant: &VariantPosition,
     clinvar_assessment: &ClinVarAssessment,
     predictive_assessment: &PredictiveAssessment,
+    lof_assessment: &LofAssessment,
     exclude_benign: bool,
 ) -> FilterDecision {
     // Priority 1: ClinVar Pathogenic (takes precedence over benign)
@@ -59,22 +66,43 @@ pub fn make_filter_decision_with_config(
         };
     }
 
-    // Priority 3: Predictive scores suggest pathogenic
+    // Priority 3: High-confidence loss-of-function in the selected transcript.
+    // Truncating variants rarely have missense predictors, so this is the
+    // only non-ClinVar evidence most of them can get.
+    if lof_assessment.is_high_confidence {
+        return FilterDecision {
+            should_include: true,
+            pathogenicity_class: "Likely pathogenic".to_string(),
+            primary_evidence: "LoF".to_string(),
+            justification: lof_assessment.reason.clone(),
+        };
+    }
+
+    // Priority 4: Predictive scores suggest pathogenic
     if predictive_assessment.suggests_pathogenic {
-        let score_names: Vec<String> = predictive_assessment
+        let mut score_names: Vec<String> = predictive_assessment
             .contributing_scores
             .keys()
             .cloned()
             .collect();
+        score_names.sort();
+
+        let basis = match predictive_assessment.model {
+            PredictiveModel::Votes => format!("confidence: {:.2}", predictive_assessment.confidence),
+            PredictiveModel::Weighted => format!("weight: {:.2}", predictive_assessment.total_weight),
+            PredictiveModel::Calibrated => {
+                format!("evidence: {}", predictive_assessment.evidence_strength.acmg_code())
+            }
+        };
 
         return FilterDecision {
             should_include: true,
             pathogenicity_class: "Likely pathogenic".to_string(),
             primary_evidence: "Predictive".to_string(),
             justification: format!(
-                "Supported by predictive scores: {} (confidence: {:.2})",
+                "Supported by predictive scores: {} ({})",
                 score_names.join(", "),
-                predictive_assessment.confidence
+                basis
             ),
         };
     }
@@ -128,7 +156,7 @@ mod tests {
             },
             confidence: 0.7,
             support_count: 2,
-            has_primate_ai_support: false,
+            ..PredictiveAssessment::default()
         };
 
         let decision = make_filter_decision(&variant, &clinvar, &predictive);
@@ -136,6 +164,32 @@ mod tests {
         assert!(decision.should_include);
         assert_eq!(decision.pathogenicity_class, "Likely pathogenic");
         assert_eq!(decision.primary_evidence, "Predictive");
+        assert_eq!(
+            decision.justification,
+            "Supported by predictive scores: DANN, REVEL (confidence: 0.70)"
+        );
+    }
+
+    #[test]
+    fn test_calibrated_predictive_justification() {
+        let variant = create_test_variant();
+        let clinvar = create_empty_clinvar();
+        let predictive = PredictiveAssessment {
+            suggests_pathogenic: true,
+            contributing_scores: [("REVEL".to_string(), 0.95)].into_iter().collect(),
+            support_count: 1,
+            model: PredictiveModel::Calibrated,
+            evidence_strength: EvidenceStrength::PathogenicStrong,
+            ..PredictiveAssessment::default()
+        };
+
+        let decision = make_filter_decision(&variant, &clinvar, &predictive);
+
+        assert!(decision.should_include);
+        assert_eq!(
+            decision.justification,
+            "Supported by predictive scores: REVEL (evidence: PP3_Strong)"
+        );
     }
 
     #[test]
@@ -150,26 +204,32 @@ mod tests {
         assert_eq!(decision.pathogenicity_class, "Excluded");
     }
 
+    #[test]
+    fn test_lof_decision() {
+        let variant = create_test_variant();
+        let clinvar = create_empty_clinvar();
+        let predictive = create_empty_predictive();
+        let lof = LofAssessment {
+            is_high_confidence: true,
+            consequence: Some("stop_gained".to_string()),
+            transcript_id: Some("NM_000546.6".to_string()),
+            failed_checks: vec![],
+            unchecked: vec![],
+            reason: "High-confidence LoF: stop_gained in NM_000546.6".to_string(),
+        };
+
+        let decision = make_filter_decision_with_config(&variant, &clinvar, &predictive, &lof, false);
+
+        assert!(decision.should_include);
+        assert_eq!(decision.pathogenicity_class, "Likely pathogenic");
+        assert_eq!(decision.primary_evidence, "LoF");
+    }
+
     fn create_test_variant() -> VariantPosition {
         VariantPosition {
-            chromosome: "chr1".to_string(),
-            start: 100,
-            end_pos: 100,
-            reference_allele: "A".to_string(),
-            alternate_allele: "T".to_string(),
-            variant_type: "SNV".to_string(),
-            filters: vec!["PASS".to_string()],
             total_depth: Some(50),
             variant_frequencies: Some(vec![0.05]),
-            transcripts: vec![],
-            clinvar: vec![],
-            cosmic: vec![],
-            population_frequencies: vec![],
-            primate_ai_3d: None,
-            primate_ai: None,
-            dann_score: None,
-            revel_score: None,
-            dbsnp_ids: vec![],
+            ..VariantPosition::test_default()
         }
     }
 
@@ -186,13 +246,7 @@ mod tests {
     }
 
     fn create_empty_predictive() -> PredictiveAssessment {
-        PredictiveAssessment {
-            suggests_pathogenic: false,
-            contributing_scores: HashMap::new(),
-            confidence: 0.0,
-            support_count: 0,
-            has_primate_ai_support: false,
-        }
+        PredictiveAssessment::default()
     }
 
     #[test]
@@ -210,7 +264,7 @@ mod tests {
         };
         let predictive = create_empty_predictive();
 
-        let decision = make_filter_decision_with_config(&variant, &clinvar, &predictive, true);
+        let decision = make_filter_decision_with_config(&variant, &clinvar, &predictive, &LofAssessment::default(), true);
 
         assert!(decision.should_include); // Should still be included
         assert_eq!(decision.pathogenicity_class, "Pathogenic");
@@ -232,7 +286,7 @@ mod tests {
         };
         let predictive = create_empty_predictive();
 
-        let decision = make_filter_decision_with_config(&variant, &clinvar, &predictive, true);
+        let decision = make_filter_decision_with_config(&variant, &clinvar, &predictive, &LofAssessment::default(), true);
 
         assert!(!decision.should_include); // Should be excluded
         assert_eq!(decision.pathogenicity_class, "Excluded (Benign)");
@@ -254,7 +308,7 @@ mod tests {
         };
         let predictive = create_empty_predictive();
 
-        let decision = make_filter_decision_with_config(&variant, &clinvar, &predictive, false);
+        let decision = make_filter_decision_with_config(&variant, &clinvar, &predictive, &LofAssessment::default(), false);
 
         assert!(!decision.should_include); // Excluded for insufficient evidence, not benign
         assert_eq!(decision.pathogenicity_class, "Excluded");
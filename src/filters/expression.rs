@@ -0,0 +1,1129 @@
+//! Filter expressions for custom include/exclude rules.
+//!
+//! A rule is a boolean expression over named variant fields:
+//!
+//! ```text
+//! revel >= 0.7 && gnomad_exome.eas_af < 0.005 && consequence ~ "missense"
+//! gene in ["TTN", "MUC16"]
+//! !clinvar.benign && (lof.high_confidence || predictive.support >= 2)
+//! ```
+//!
+//! Rules are parsed and type-checked once, when the pipeline is built, so a
+//! misspelled field or an operator applied to the wrong kind of field is
+//! reported before any variant is read. Evaluation walks the compiled tree
+//! without allocating.
+//!
+//! Missing values follow three-valued (SQL-style) logic: a comparison
+//! against a missing value (no REVEL score, no transcript, no gnomAD entry)
+//! is unknown, as are `mane_select` without a transcript and `loh` without
+//! an LOH call; `!` of unknown is unknown, `false && unknown` is false and
+//! `true || unknown` is true. A rule matches only when it is true, so an
+//! unknown rule neither includes nor excludes a variant. `dann < 0.5` and
+//! `!(dann >= 0.5)` therefore both skip a variant without a DANN score.
+use crate::consequence::transcript_impact;
+use crate::types::*;
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("{message} (column {column})")]
+pub struct ExpressionError {
+    pub message: String,
+    /// 1-based character column in the rule text
+    pub column: usize,
+}
+
+/// Everything a rule can refer to for one variant.
+pub struct EvalContext<'a> {
+    pub variant: &'a VariantPosition,
+    pub clinvar: &'a ClinVarAssessment,
+    pub predictive: &'a PredictiveAssessment,
+    pub lof: &'a LofAssessment,
+}
+
+/// A parsed, type-checked rule.
+#[derive(Debug, Clone)]
+pub struct Expression {
+    source: String,
+    root: Node,
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self, ExpressionError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            end: source.chars().count() + 1,
+        };
+        let root = parser.parse_or()?;
+        if let Some(token) = parser.peek() {
+            return Err(parser.error_at(token.column, format!("unexpected {}", token.kind)));
+        }
+        Ok(Expression {
+            source: source.to_string(),
+            root,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether the rule matches: true, not false or unknown.
+    pub fn evaluate(&self, ctx: &EvalContext) -> bool {
+        self.root.evaluate(ctx) == Some(true)
+    }
+}
+
+/// Compiled `include_rules` / `exclude_rules` from `FilterConfig`.
+///
+/// Include rules rescue variants the decision engine excluded; exclude rules
+/// veto variants it included. A variant matching both is excluded.
+#[derive(Debug, Clone, Default)]
+pub struct RuleSet {
+    pub include: Vec<Expression>,
+    pub exclude: Vec<Expression>,
+}
+
+impl RuleSet {
+    pub fn from_config(config: &FilterConfig) -> anyhow::Result<Self> {
+        let compile = |rules: &[String], kind: &str| -> anyhow::Result<Vec<Expression>> {
+            rules
+                .iter()
+                .map(|rule| {
+                    Expression::parse(rule)
+                        .map_err(|e| anyhow::anyhow!("invalid {} rule '{}': {}", kind, rule, e))
+                })
+                .collect()
+        };
+
+        Ok(RuleSet {
+            include: compile(&config.include_rules, "include")?,
+            exclude: compile(&config.exclude_rules, "exclude")?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Applies the rules on top of the decision engine's verdict.
+    pub fn apply(&self, ctx: &EvalContext, decision: FilterDecision) -> FilterDecision {
+        if decision.should_include {
+            if let Some(rule) = self.exclude.iter().find(|r| r.evaluate(ctx)) {
+                return FilterDecision {
+                    should_include: false,
+                    pathogenicity_class: "Excluded (Rule)".to_string(),
+                    primary_evidence: "Rule".to_string(),
+                    justification: format!("Matched exclude rule: {}", rule.source()),
+                };
+            }
+        } else if let Some(rule) = self.include.iter().find(|r| r.evaluate(ctx)) {
+            if !self.exclude.iter().any(|r| r.evaluate(ctx)) {
+                return FilterDecision {
+                    should_include: true,
+                    pathogenicity_class: "Custom rule".to_string(),
+                    primary_evidence: "Rule".to_string(),
+                    justification: format!("Matched include rule: {}", rule.source()),
+                };
+            }
+        }
+        decision
+    }
+}
+
+// ============================================================================
+// Fields
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldKind {
+    Number,
+    Text,
+    Bool,
+}
+
+impl fmt::Display for FieldKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FieldKind::Number => "a number",
+            FieldKind::Text => "text",
+            FieldKind::Bool => "a boolean",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    // Numbers
+    Start,
+    End,
+    Depth,
+    Vaf,
+    AltReads,
+    RefReads,
+    GenotypeQuality,
+    StrandBias,
+    SomaticQuality,
+    Revel,
+    Dann,
+    PrimateAi,
+    CosmicCount,
+    PredictiveSupport,
+    PredictiveConfidence,
+    PredictiveWeight,
+    Population(&'static str, PopulationColumn),
+    // Text (single value)
+    Chrom,
+    Ref,
+    Alt,
+    VariantType,
+    Gene,
+    Transcript,
+    Hgvsc,
+    Hgvsp,
+    Impact,
+    Genotype,
+    ClinVarReviewStatus,
+    PredictiveStrength,
+    // Text (list; matches if any element matches)
+    Consequence,
+    Filters,
+    ClinVarSignificance,
+    // Booleans
+    ClinVarPathogenic,
+    ClinVarLikelyPathogenic,
+    ClinVarBenign,
+    ClinVarLikelyBenign,
+    PredictivePathogenic,
+    LofHighConfidence,
+    ManeSelect,
+    Loh,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PopulationColumn {
+    All,
+    Eas,
+    Afr,
+    Amr,
+    Eur,
+}
+
+const FIELDS: &[(&str, Field)] = &[
+    ("start", Field::Start),
+    ("end", Field::End),
+    ("depth", Field::Depth),
+    ("vaf", Field::Vaf),
+    ("alt_reads", Field::AltReads),
+    ("ref_reads", Field::RefReads),
+    ("gq", Field::GenotypeQuality),
+    ("strand_bias", Field::StrandBias),
+    ("somatic_quality", Field::SomaticQuality),
+    ("revel", Field::Revel),
+    ("dann", Field::Dann),
+    ("primate_ai", Field::PrimateAi),
+    ("cosmic_count", Field::CosmicCount),
+    ("predictive.support", Field::PredictiveSupport),
+    ("predictive.confidence", Field::PredictiveConfidence),
+    ("predictive.weight", Field::PredictiveWeight),
+    ("chrom", Field::Chrom),
+    ("ref", Field::Ref),
+    ("alt", Field::Alt),
+    ("variant_type", Field::VariantType),
+    ("gene", Field::Gene),
+    ("transcript", Field::Transcript),
+    ("hgvsc", Field::Hgvsc),
+    ("hgvsp", Field::Hgvsp),
+    ("impact", Field::Impact),
+    ("genotype", Field::Genotype),
+    ("clinvar.review_status", Field::ClinVarReviewStatus),
+    ("predictive.strength", Field::PredictiveStrength),
+    ("consequence", Field::Consequence),
+    ("filters", Field::Filters),
+    ("clinvar.significance", Field::ClinVarSignificance),
+    ("clinvar.pathogenic", Field::ClinVarPathogenic),
+    ("clinvar.likely_pathogenic", Field::ClinVarLikelyPathogenic),
+    ("clinvar.benign", Field::ClinVarBenign),
+    ("clinvar.likely_benign", Field::ClinVarLikelyBenign),
+    ("predictive.pathogenic", Field::PredictivePathogenic),
+    ("lof.high_confidence", Field::LofHighConfidence),
+    ("mane_select", Field::ManeSelect),
+    ("loh", Field::Loh),
+];
+
+/// Population field prefixes and the Nirvana source they read from.
+const POPULATION_SOURCES: &[(&str, &str)] = &[
+    ("gnomad", "gnomad"),
+    ("gnomad_exome", "gnomad-exome"),
+    ("onekg", "oneKg"),
+    ("topmed", "topmed"),
+];
+
+const POPULATION_COLUMNS: &[(&str, PopulationColumn)] = &[
+    ("af", PopulationColumn::All),
+    ("eas_af", PopulationColumn::Eas),
+    ("afr_af", PopulationColumn::Afr),
+    ("amr_af", PopulationColumn::Amr),
+    ("eur_af", PopulationColumn::Eur),
+];
+
+/// All field names, for error messages and documentation.
+pub fn field_names() -> Vec<String> {
+    let mut names: Vec<String> = FIELDS.iter().map(|(name, _)| name.to_string()).collect();
+    for (prefix, _) in POPULATION_SOURCES {
+        for (column, _) in POPULATION_COLUMNS {
+            names.push(format!("{}.{}", prefix, column));
+        }
+    }
+    names
+}
+
+fn lookup_field(name: &str) -> Option<Field> {
+    if let Some((_, field)) = FIELDS.iter().find(|(n, _)| *n == name) {
+        return Some(*field);
+    }
+    let (prefix, column) = name.split_once('.')?;
+    let source = POPULATION_SOURCES.iter().find(|(p, _)| *p == prefix)?.1;
+    let column = POPULATION_COLUMNS.iter().find(|(c, _)| *c == column)?.1;
+    Some(Field::Population(source, column))
+}
+
+impl Field {
+    fn kind(&self) -> FieldKind {
+        match self {
+            Field::Start
+            | Field::End
+            | Field::Depth
+            | Field::Vaf
+            | Field::AltReads
+            | Field::RefReads
+            | Field::GenotypeQuality
+            | Field::StrandBias
+            | Field::SomaticQuality
+            | Field::Revel
+            | Field::Dann
+            | Field::PrimateAi
+            | Field::CosmicCount
+            | Field::PredictiveSupport
+            | Field::PredictiveConfidence
+            | Field::PredictiveWeight
+            | Field::Population(..) => FieldKind::Number,
+            Field::Chrom
+            | Field::Ref
+            | Field::Alt
+            | Field::VariantType
+            | Field::Gene
+            | Field::Transcript
+            | Field::Hgvsc
+            | Field::Hgvsp
+            | Field::Impact
+            | Field::Genotype
+            | Field::ClinVarReviewStatus
+            | Field::PredictiveStrength
+            | Field::Consequence
+            | Field::Filters
+            | Field::ClinVarSignificance => FieldKind::Text,
+            Field::ClinVarPathogenic
+            | Field::ClinVarLikelyPathogenic
+            | Field::ClinVarBenign
+            | Field::ClinVarLikelyBenign
+            | Field::PredictivePathogenic
+            | Field::LofHighConfidence
+            | Field::ManeSelect
+            | Field::Loh => FieldKind::Bool,
+        }
+    }
+
+    fn number(&self, ctx: &EvalContext) -> Option<f64> {
+        let v = ctx.variant;
+        match self {
+            Field::Start => Some(v.start as f64),
+            Field::End => Some(v.end_pos as f64),
+            Field::Depth => v.total_depth.map(f64::from),
+            Field::Vaf => v.variant_frequencies.as_ref()?.first().copied(),
+            Field::AltReads => v.alt_read_count().map(f64::from),
+            Field::RefReads => v.ref_read_count().map(f64::from),
+            Field::GenotypeQuality => v.genotype_quality,
+            Field::StrandBias => v.strand_bias,
+            Field::SomaticQuality => v.somatic_quality,
+            Field::Revel => v.revel_score,
+            Field::Dann => v.dann_score,
+            Field::PrimateAi => v.primate_ai_3d,
+            Field::CosmicCount => {
+                if v.cosmic.is_empty() {
+                    None
+                } else {
+                    Some(v.cosmic.iter().filter_map(|c| c.count).sum::<i32>() as f64)
+                }
+            }
+            Field::PredictiveSupport => Some(ctx.predictive.support_count as f64),
+            Field::PredictiveConfidence => Some(ctx.predictive.confidence),
+            Field::PredictiveWeight => Some(ctx.predictive.total_weight),
+            Field::Population(source, column) => {
+                let pf = v.population_frequencies.iter().find(|pf| pf.source == *source)?;
+                match column {
+                    PopulationColumn::All => pf.all_af,
+                    PopulationColumn::Eas => pf.eas_af,
+                    PopulationColumn::Afr => pf.afr_af,
+                    PopulationColumn::Amr => pf.amr_af,
+                    PopulationColumn::Eur => pf.eur_af,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Tests `pred` against the field's value(s). `None` when the field has
+    /// no value, otherwise whether any value satisfied `pred`.
+    fn any_text(&self, ctx: &EvalContext, pred: impl FnMut(&str) -> bool) -> Option<bool> {
+        let v = ctx.variant;
+        let transcript = || v.selected_transcript();
+        let single = match self {
+            Field::Chrom => Some(v.chromosome.as_str()),
+            Field::Ref => Some(v.reference_allele.as_str()),
+            Field::Alt => Some(v.alternate_allele.as_str()),
+            Field::VariantType => Some(v.variant_type.as_str()),
+            Field::Gene => transcript().and_then(|t| t.hgnc.as_deref()),
+            Field::Transcript => transcript().and_then(|t| t.id.as_deref()),
+            Field::Hgvsc => transcript().and_then(|t| t.hgvsc.as_deref()),
+            Field::Hgvsp => transcript().and_then(|t| t.hgvsp.as_deref()),
+            Field::Impact => transcript().and_then(transcript_impact).map(|i| i.as_str()),
+            Field::Genotype => v.genotype.as_deref(),
+            Field::ClinVarReviewStatus => ctx
+                .clinvar
+                .selected_entry
+                .as_ref()
+                .and_then(|e| e.review_status.as_deref()),
+            // Only meaningful when some calibrated score was present
+            Field::PredictiveStrength => (!ctx.predictive.calibrated_scores.is_empty())
+                .then(|| ctx.predictive.evidence_strength.acmg_code()),
+            Field::Consequence => return any_of(transcript()?.consequence.iter(), pred),
+            Field::Filters => return any_of(v.filters.iter(), pred),
+            Field::ClinVarSignificance => {
+                let entry = ctx.clinvar.selected_entry.as_ref()?;
+                return any_of(entry.clinical_significance.iter(), pred);
+            }
+            _ => None,
+        };
+        single.map(pred)
+    }
+
+    /// `None` when there is no transcript (`mane_select`) or the sample
+    /// has no LOH call (`loh`). Nirvana leaves `isManeSelect` out of
+    /// non-MANE transcripts, so a transcript without it is not MANE Select.
+    fn flag(&self, ctx: &EvalContext) -> Option<bool> {
+        Some(match self {
+            Field::ClinVarPathogenic => ctx.clinvar.is_pathogenic,
+            Field::ClinVarLikelyPathogenic => ctx.clinvar.is_likely_pathogenic,
+            Field::ClinVarBenign => ctx.clinvar.is_benign,
+            Field::ClinVarLikelyBenign => ctx.clinvar.is_likely_benign,
+            Field::PredictivePathogenic => ctx.predictive.suggests_pathogenic,
+            Field::LofHighConfidence => ctx.lof.is_high_confidence,
+            Field::ManeSelect => ctx.variant.selected_transcript()?.is_mane_select.unwrap_or(false),
+            Field::Loh => ctx.variant.is_loss_of_heterozygosity?,
+            _ => false,
+        })
+    }
+}
+
+fn any_of<'a>(
+    mut values: impl Iterator<Item = &'a String>,
+    mut pred: impl FnMut(&str) -> bool,
+) -> Option<bool> {
+    let first = values.next()?;
+    Some(pred(first) || values.any(|v| pred(v)))
+}
+
+fn contains_ignore_case(haystack: &str, needle: &str) -> bool {
+    needle.is_empty()
+        || haystack
+            .as_bytes()
+            .windows(needle.len())
+            .any(|w| w.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+// ============================================================================
+// Compiled tree
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Flag(Field, bool),
+    Number(Field, CmpOp, f64),
+    NumberIn(Field, Vec<f64>),
+    TextEq(Field, String, bool),
+    TextContains(Field, String),
+    TextIn(Field, Vec<String>),
+}
+
+impl Node {
+    /// Three-valued evaluation; `None` is unknown (a missing value).
+    fn evaluate(&self, ctx: &EvalContext) -> Option<bool> {
+        match self {
+            Node::And(a, b) => match a.evaluate(ctx) {
+                Some(false) => Some(false),
+                left => match (left, b.evaluate(ctx)) {
+                    (_, Some(false)) => Some(false),
+                    (Some(true), Some(true)) => Some(true),
+                    _ => None,
+                },
+            },
+            Node::Or(a, b) => match a.evaluate(ctx) {
+                Some(true) => Some(true),
+                left => match (left, b.evaluate(ctx)) {
+                    (_, Some(true)) => Some(true),
+                    (Some(false), Some(false)) => Some(false),
+                    _ => None,
+                },
+            },
+            Node::Not(a) => a.evaluate(ctx).map(|value| !value),
+            Node::Flag(field, expected) => field.flag(ctx).map(|value| value == *expected),
+            Node::Number(field, op, rhs) => field.number(ctx).map(|lhs| match op {
+                CmpOp::Eq => lhs == *rhs,
+                CmpOp::Ne => lhs != *rhs,
+                CmpOp::Lt => lhs < *rhs,
+                CmpOp::Le => lhs <= *rhs,
+                CmpOp::Gt => lhs > *rhs,
+                CmpOp::Ge => lhs >= *rhs,
+            }),
+            Node::NumberIn(field, values) => field.number(ctx).map(|n| values.contains(&n)),
+            Node::TextEq(field, rhs, equal) => field
+                .any_text(ctx, |v| v == rhs)
+                .map(|found| found == *equal),
+            Node::TextContains(field, needle) => field.any_text(ctx, |v| contains_ignore_case(v, needle)),
+            Node::TextIn(field, values) => field.any_text(ctx, |v| values.iter().any(|x| x == v)),
+        }
+    }
+}
+
+// ============================================================================
+// Lexer
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Ident(String),
+    Number(f64),
+    Str(String),
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+    And,
+    Or,
+    Not,
+    Cmp(CmpOp),
+    Tilde,
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::Ident(name) => write!(f, "'{}'", name),
+            TokenKind::Number(n) => write!(f, "number {}", n),
+            TokenKind::Str(s) => write!(f, "string \"{}\"", s),
+            TokenKind::LParen => f.write_str("'('"),
+            TokenKind::RParen => f.write_str("')'"),
+            TokenKind::LBracket => f.write_str("'['"),
+            TokenKind::RBracket => f.write_str("']'"),
+            TokenKind::Comma => f.write_str("','"),
+            TokenKind::And => f.write_str("'&&'"),
+            TokenKind::Or => f.write_str("'||'"),
+            TokenKind::Not => f.write_str("'!'"),
+            TokenKind::Cmp(op) => f.write_str(match op {
+                CmpOp::Eq => "'=='",
+                CmpOp::Ne => "'!='",
+                CmpOp::Lt => "'<'",
+                CmpOp::Le => "'<='",
+                CmpOp::Gt => "'>'",
+                CmpOp::Ge => "'>='",
+            }),
+            TokenKind::Tilde => f.write_str("'~'"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    column: usize,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, ExpressionError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        let error = |message: String| ExpressionError { message, column };
+        let next = chars.get(i + 1).copied();
+
+        let (kind, len) = match c {
+            _ if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => (TokenKind::LParen, 1),
+            ')' => (TokenKind::RParen, 1),
+            '[' => (TokenKind::LBracket, 1),
+            ']' => (TokenKind::RBracket, 1),
+            ',' => (TokenKind::Comma, 1),
+            '~' => (TokenKind::Tilde, 1),
+            '&' if next == Some('&') => (TokenKind::And, 2),
+            '|' if next == Some('|') => (TokenKind::Or, 2),
+            '=' if next == Some('=') => (TokenKind::Cmp(CmpOp::Eq), 2),
+            '!' if next == Some('=') => (TokenKind::Cmp(CmpOp::Ne), 2),
+            '!' => (TokenKind::Not, 1),
+            '<' if next == Some('=') => (TokenKind::Cmp(CmpOp::Le), 2),
+            '<' => (TokenKind::Cmp(CmpOp::Lt), 1),
+            '>' if next == Some('=') => (TokenKind::Cmp(CmpOp::Ge), 2),
+            '>' => (TokenKind::Cmp(CmpOp::Gt), 1),
+            '"' | '\'' => {
+                let mut value = String::new();
+                let mut j = i + 1;
+                loop {
+                    match chars.get(j) {
+                        None => return Err(error("unterminated string".to_string())),
+                        Some('\\') if j + 1 < chars.len() => {
+                            value.push(chars[j + 1]);
+                            j += 2;
+                        }
+                        Some(&q) if q == c => break,
+                        Some(&other) => {
+                            value.push(other);
+                            j += 1;
+                        }
+                    }
+                }
+                (TokenKind::Str(value), j + 1 - i)
+            }
+            _ if c.is_ascii_digit() || c == '.' || (c == '-' && next.is_some_and(|n| n.is_ascii_digit() || n == '.')) => {
+                let mut j = i + 1;
+                while j < chars.len()
+                    && (chars[j].is_ascii_digit()
+                        || chars[j] == '.'
+                        || matches!(chars[j], 'e' | 'E')
+                        || (matches!(chars[j], '-' | '+') && matches!(chars[j - 1], 'e' | 'E')))
+                {
+                    j += 1;
+                }
+                let text: String = chars[i..j].iter().collect();
+                let value = text
+                    .parse::<f64>()
+                    .map_err(|_| error(format!("invalid number '{}'", text)))?;
+                (TokenKind::Number(value), j - i)
+            }
+            _ if c.is_ascii_alphabetic() || c == '_' => {
+                let mut j = i + 1;
+                while j < chars.len() && (chars[j].is_ascii_alphanumeric() || matches!(chars[j], '_' | '.')) {
+                    j += 1;
+                }
+                (TokenKind::Ident(chars[i..j].iter().collect()), j - i)
+            }
+            _ => return Err(error(format!("unexpected character '{}'", c))),
+        };
+
+        tokens.push(Token { kind, column });
+        i += len;
+    }
+
+    Ok(tokens)
+}
+
+// ============================================================================
+// Parser
+// ============================================================================
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Column just past the end of input, for "unexpected end" errors
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token, ExpressionError> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| self.error_at(self.end, "unexpected end of expression".to_string()))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        if self.peek().map(|t| &t.kind) == Some(kind) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error_at(&self, column: usize, message: String) -> ExpressionError {
+        ExpressionError { message, column }
+    }
+
+    fn parse_or(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.parse_and()?;
+        while self.eat(&TokenKind::Or) || self.eat_keyword("or") {
+            node = Node::Or(Box::new(node), Box::new(self.parse_and()?));
+        }
+        Ok(node)
+    }
+
+    fn parse_and(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.parse_unary()?;
+        while self.eat(&TokenKind::And) || self.eat_keyword("and") {
+            node = Node::And(Box::new(node), Box::new(self.parse_unary()?));
+        }
+        Ok(node)
+    }
+
+    fn parse_unary(&mut self) -> Result<Node, ExpressionError> {
+        if self.eat(&TokenKind::Not) || self.eat_keyword("not") {
+            return Ok(Node::Not(Box::new(self.parse_unary()?)));
+        }
+        if self.eat(&TokenKind::LParen) {
+            let node = self.parse_or()?;
+            let token = self.next()?;
+            if token.kind != TokenKind::RParen {
+                return Err(self.error_at(token.column, format!("expected ')' but found {}", token.kind)));
+            }
+            return Ok(node);
+        }
+        self.parse_comparison()
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        self.eat(&TokenKind::Ident(keyword.to_string()))
+    }
+
+    fn parse_comparison(&mut self) -> Result<Node, ExpressionError> {
+        let token = self.next()?;
+        let name = match token.kind {
+            TokenKind::Ident(name) => name,
+            other => {
+                return Err(self.error_at(token.column, format!("expected a field name but found {}", other)))
+            }
+        };
+        let field = lookup_field(&name).ok_or_else(|| {
+            let message = match suggest(&name) {
+                Some(s) => format!("unknown field '{}' (did you mean '{}'?)", name, s),
+                None => format!("unknown field '{}'", name),
+            };
+            self.error_at(token.column, message)
+        })?;
+        let kind = field.kind();
+
+        let op_token = match self.peek() {
+            Some(t) if matches!(t.kind, TokenKind::Cmp(_) | TokenKind::Tilde) || t.kind == TokenKind::Ident("in".into()) => {
+                self.next()?
+            }
+            _ => {
+                // Bare field: only booleans are predicates on their own
+                if kind == FieldKind::Bool {
+                    return Ok(Node::Flag(field, true));
+                }
+                return Err(self.error_at(
+                    token.column,
+                    format!("field '{}' is {}; compare it with an operator", name, kind),
+                ));
+            }
+        };
+
+        let mismatch = |op: &TokenKind, this: &Self| {
+            this.error_at(
+                op_token.column,
+                format!("operator {} cannot be applied to '{}', which is {}", op, name, kind),
+            )
+        };
+
+        match (&op_token.kind, kind) {
+            (TokenKind::Ident(_), FieldKind::Number) => {
+                let values = self.parse_list(|t| match t {
+                    TokenKind::Number(n) => Some(*n),
+                    _ => None,
+                }, "number")?;
+                Ok(Node::NumberIn(field, values))
+            }
+            (TokenKind::Ident(_), FieldKind::Text) => {
+                let values = self.parse_list(|t| match t {
+                    TokenKind::Str(s) => Some(s.clone()),
+                    _ => None,
+                }, "string")?;
+                Ok(Node::TextIn(field, values))
+            }
+            (TokenKind::Tilde, FieldKind::Text) => {
+                let value = self.expect_string(&name)?;
+                Ok(Node::TextContains(field, value))
+            }
+            (TokenKind::Cmp(op), FieldKind::Number) => {
+                let value = self.expect_literal(&name, "a number", |t| match t {
+                    TokenKind::Number(n) => Some(*n),
+                    _ => None,
+                })?;
+                Ok(Node::Number(field, *op, value))
+            }
+            (TokenKind::Cmp(op @ (CmpOp::Eq | CmpOp::Ne)), FieldKind::Text) => {
+                let value = self.expect_string(&name)?;
+                Ok(Node::TextEq(field, value, *op == CmpOp::Eq))
+            }
+            (TokenKind::Cmp(op @ (CmpOp::Eq | CmpOp::Ne)), FieldKind::Bool) => {
+                let value = self.expect_literal(&name, "true or false", |t| match t {
+                    TokenKind::Ident(s) if s == "true" => Some(true),
+                    TokenKind::Ident(s) if s == "false" => Some(false),
+                    _ => None,
+                })?;
+                Ok(Node::Flag(field, value == (*op == CmpOp::Eq)))
+            }
+            (op, _) => Err(mismatch(op, self)),
+        }
+    }
+
+    fn expect_string(&mut self, name: &str) -> Result<String, ExpressionError> {
+        self.expect_literal(name, "a string", |t| match t {
+            TokenKind::Str(s) => Some(s.clone()),
+            _ => None,
+        })
+    }
+
+    fn expect_literal<T>(
+        &mut self,
+        name: &str,
+        expected: &str,
+        extract: impl Fn(&TokenKind) -> Option<T>,
+    ) -> Result<T, ExpressionError> {
+        let token = self.next()?;
+        extract(&token.kind).ok_or_else(|| {
+            self.error_at(
+                token.column,
+                format!("'{}' must be compared with {}, found {}", name, expected, token.kind),
+            )
+        })
+    }
+
+    fn parse_list<T>(
+        &mut self,
+        extract: impl Fn(&TokenKind) -> Option<T>,
+        expected: &str,
+    ) -> Result<Vec<T>, ExpressionError> {
+        let open = self.next()?;
+        if open.kind != TokenKind::LBracket {
+            return Err(self.error_at(open.column, format!("expected '[' after 'in' but found {}", open.kind)));
+        }
+        let mut values = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token.kind == TokenKind::RBracket && values.is_empty() {
+                break;
+            }
+            let value = extract(&token.kind).ok_or_else(|| {
+                self.error_at(token.column, format!("expected a {} in list but found {}", expected, token.kind))
+            })?;
+            values.push(value);
+
+            let sep = self.next()?;
+            match sep.kind {
+                TokenKind::Comma => continue,
+                TokenKind::RBracket => break,
+                other => {
+                    return Err(self.error_at(sep.column, format!("expected ',' or ']' but found {}", other)))
+                }
+            }
+        }
+        Ok(values)
+    }
+}
+
+/// Closest known field name within a small edit distance.
+fn suggest(name: &str) -> Option<String> {
+    field_names()
+        .into_iter()
+        .map(|candidate| (edit_distance(name, &candidate), candidate))
+        .filter(|(distance, _)| *distance <= 3)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        prev = curr;
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_evaluate() {
+        let variant = create_test_variant();
+        let (clinvar, predictive, lof) = empty_assessments();
+        let ctx = EvalContext {
+            variant: &variant,
+            clinvar: &clinvar,
+            predictive: &predictive,
+            lof: &lof,
+        };
+
+        let eval = |src: &str| Expression::parse(src).unwrap().evaluate(&ctx);
+        assert!(eval(r#"revel >= 0.7 && gnomad_exome.eas_af < 0.005 && consequence ~ "missense""#));
+        assert!(eval(r#"gene in ["TTN", "BRAF"]"#));
+        assert!(!eval(r#"gene in ["TTN", "MUC16"]"#));
+        assert!(eval(r#"!(dann > 0.5) || depth == 50"#));
+        assert!(eval(r#"impact == "MODERATE" and not clinvar.benign"#));
+        assert!(eval("clinvar.pathogenic == false"));
+    }
+
+    #[test]
+    fn test_precedence() {
+        let variant = create_test_variant();
+        let (clinvar, predictive, lof) = empty_assessments();
+        let ctx = EvalContext {
+            variant: &variant,
+            clinvar: &clinvar,
+            predictive: &predictive,
+            lof: &lof,
+        };
+
+        let eval = |src: &str| Expression::parse(src).unwrap().evaluate(&ctx);
+        // && binds tighter than ||: true || (false && false)
+        assert!(eval("depth == 50 || depth == 1 && depth == 2"));
+        // (true || false) && false
+        assert!(!eval("(depth == 50 || depth == 1) && depth == 2"));
+        // ! binds tighter than &&: (!false) && true
+        assert!(eval("!clinvar.benign && depth == 50"));
+        assert!(!eval("!(clinvar.benign || depth == 50)"));
+        // Keywords parse the same as the symbols
+        assert!(eval("depth == 50 or depth == 1 and depth == 2"));
+        assert!(eval("not not (depth == 50)"));
+    }
+
+    #[test]
+    fn test_missing_values_are_unknown() {
+        let variant = create_test_variant();
+        let (clinvar, predictive, lof) = empty_assessments();
+        let ctx = EvalContext {
+            variant: &variant,
+            clinvar: &clinvar,
+            predictive: &predictive,
+            lof: &lof,
+        };
+
+        let value = |src: &str| Expression::parse(src).unwrap().root.evaluate(&ctx);
+        // No DANN score, no gnomAD genome entry and no ClinVar entry
+        assert_eq!(value("dann < 0.5"), None);
+        assert_eq!(value("dann != 0.5"), None);
+        assert_eq!(value("dann in [0.5]"), None);
+        assert_eq!(value("gnomad.af < 0.01"), None);
+        assert_eq!(value(r#"clinvar.significance ~ "benign""#), None);
+        assert_eq!(value(r#"clinvar.review_status != "practice guideline""#), None);
+
+        // `!` of unknown stays unknown, so neither form matches
+        assert_eq!(value("!(dann >= 0.5)"), None);
+        assert!(!Expression::parse("!(dann >= 0.5)").unwrap().evaluate(&ctx));
+
+        // A known operand can decide && and ||
+        assert_eq!(value("dann < 0.5 && depth == 1"), Some(false));
+        assert_eq!(value("depth == 1 && dann < 0.5"), Some(false));
+        assert_eq!(value("dann < 0.5 && depth == 50"), None);
+        assert_eq!(value("dann < 0.5 || depth == 50"), Some(true));
+        assert_eq!(value("depth == 50 || dann < 0.5"), Some(true));
+        assert_eq!(value("dann < 0.5 || depth == 1"), None);
+
+        // Flags without a transcript or an LOH call are unknown too
+        let bare = VariantPosition {
+            transcripts: vec![],
+            is_loss_of_heterozygosity: None,
+            ..variant.clone()
+        };
+        let ctx = EvalContext { variant: &bare, ..ctx };
+        let value = |src: &str| Expression::parse(src).unwrap().root.evaluate(&ctx);
+        assert_eq!(value("not mane_select"), None);
+        assert_eq!(value("mane_select == false"), None);
+        assert_eq!(value("!loh"), None);
+        assert_eq!(value("not mane_select || depth == 50"), Some(true));
+    }
+
+    #[test]
+    fn test_type_errors() {
+        let err = Expression::parse("revl >= 0.7").unwrap_err();
+        assert_eq!(err.column, 1);
+        assert!(err.message.contains("did you mean 'revel'"));
+
+        let err = Expression::parse(r#"revel ~ "high""#).unwrap_err();
+        assert_eq!(err.column, 7);
+        assert!(err.message.contains("cannot be applied to 'revel', which is a number"));
+
+        let err = Expression::parse(r#"gene >= "TTN""#).unwrap_err();
+        assert_eq!(err.column, 6);
+        assert!(err.message.contains("which is text"));
+
+        let err = Expression::parse(r#"revel >= "0.7""#).unwrap_err();
+        assert_eq!(err.column, 10);
+        assert!(err.message.contains("must be compared with a number"));
+
+        let err = Expression::parse("mane_select == 1").unwrap_err();
+        assert!(err.message.contains("true or false"));
+
+        let err = Expression::parse("revel").unwrap_err();
+        assert_eq!(err.column, 1);
+        assert!(err.message.contains("compare it with an operator"));
+
+        let err = Expression::parse(r#"gene in ["TTN", 3]"#).unwrap_err();
+        assert_eq!(err.column, 17);
+        assert!(err.message.contains("expected a string in list"));
+    }
+
+    #[test]
+    fn test_parse_error_positions() {
+        let error = |src: &str| Expression::parse(src).unwrap_err();
+
+        // Unexpected end points just past the input
+        let err = error("revel >= 0.7 &&");
+        assert_eq!((err.column, err.message.as_str()), (16, "unexpected end of expression"));
+
+        let err = error(r#"gene == "TTN"#);
+        assert_eq!((err.column, err.message.as_str()), (9, "unterminated string"));
+
+        let err = error("(depth > 10 && vaf > 0.1");
+        assert_eq!(err.column, 25);
+
+        let err = error("(depth > 10] && vaf > 0.1");
+        assert_eq!(err.column, 12);
+        assert!(err.message.contains("expected ')'"));
+
+        let err = error("depth > 10 vaf > 0.1");
+        assert_eq!((err.column, err.message.as_str()), (12, "unexpected 'vaf'"));
+
+        let err = error("depth > 10 # comment");
+        assert_eq!(err.column, 12);
+
+        // Columns count characters, not bytes
+        let err = error(r#"gene == "é" && révél > 1"#);
+        assert_eq!(err.column, 17);
+        assert_eq!(err.to_string(), "unexpected character 'é' (column 17)");
+    }
+
+    #[test]
+    fn test_rule_set_rescue_and_veto() {
+        let variant = create_test_variant();
+        let (clinvar, predictive, lof) = empty_assessments();
+        let ctx = EvalContext {
+            variant: &variant,
+            clinvar: &clinvar,
+            predictive: &predictive,
+            lof: &lof,
+        };
+        let excluded = FilterDecision {
+            should_include: false,
+            pathogenicity_class: "Excluded".to_string(),
+            primary_evidence: "None".to_string(),
+            justification: "Insufficient evidence for pathogenicity".to_string(),
+        };
+
+        let config = FilterConfig {
+            include_rules: vec!["revel >= 0.7".to_string()],
+            ..FilterConfig::default()
+        };
+        let rules = RuleSet::from_config(&config).unwrap();
+        let decision = rules.apply(&ctx, excluded.clone());
+        assert!(decision.should_include);
+        assert_eq!(decision.primary_evidence, "Rule");
+
+        let config = FilterConfig {
+            include_rules: vec!["revel >= 0.7".to_string()],
+            exclude_rules: vec![r#"gene == "BRAF""#.to_string()],
+            ..FilterConfig::default()
+        };
+        let rules = RuleSet::from_config(&config).unwrap();
+        assert!(!rules.apply(&ctx, excluded).should_include);
+    }
+
+    fn empty_assessments() -> (ClinVarAssessment, PredictiveAssessment, LofAssessment) {
+        (
+            ClinVarAssessment {
+                is_pathogenic: false,
+                is_likely_pathogenic: false,
+                is_benign: false,
+                is_likely_benign: false,
+                selected_entry: None,
+                confidence_level: "none".to_string(),
+                reason: "No ClinVar entries".to_string(),
+            },
+            PredictiveAssessment::default(),
+            LofAssessment::default(),
+        )
+    }
+
+    fn create_test_variant() -> VariantPosition {
+        VariantPosition {
+            chromosome: "chr7".to_string(),
+            start: 140753336,
+            end_pos: 140753336,
+            total_depth: Some(50),
+            variant_frequencies: Some(vec![0.25]),
+            transcripts: vec![TranscriptAnnotation {
+                id: Some("NM_004333.6".to_string()),
+                source: Some("RefSeq".to_string()),
+                hgnc: Some("BRAF".to_string()),
+                consequence: vec!["missense_variant".to_string()],
+                impact: None,
+                amino_acids: Some("V/E".to_string()),
+                cdna_pos: None,
+                cds_pos: None,
+                exons: None,
+                codons: None,
+                protein_pos: None,
+                hgvsc: None,
+                hgvsp: None,
+                is_canonical: Some(true),
+                is_mane_select: Some(true),
+                is_mane_plus_clinical: None,
+                bio_type: None,
+            }],
+            population_frequencies: vec![PopulationFrequency {
+                source: "gnomad-exome".to_string(),
+                all_af: Some(0.0001),
+                eas_af: Some(0.0),
+                afr_af: None,
+                amr_af: None,
+                eur_af: None,
+            }],
+            revel_score: Some(0.93),
+            ..VariantPosition::test_default()
+        }
+    }
+}
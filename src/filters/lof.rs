@@ -0,0 +1,318 @@
+use crate::types::*;
+
+/// QC flag for a high-confidence LoF call that skipped some checks for lack
+/// of annotation (see `LofAssessment::unchecked`).
+pub const FLAG_LOF_UNCHECKED: &str = "lof_unchecked";
+
+/// Consequences treated as putative loss-of-function.
+const LOF_CONSEQUENCES: &[&str] = &[
+    "stop_gained",
+    "frameshift_variant",
+    "splice_donor_variant",
+    "splice_acceptor_variant",
+];
+
+/// Assesses the selected transcript for high-confidence loss-of-function,
+/// using LOFTEE-style sanity checks:
+/// - truncation not in the last `lof_max_cds_fraction` of the CDS (END_TRUNC)
+/// - premature stop not in the terminal exon, where it escapes NMD
+/// - splice variants hit the canonical ±1/±2 intronic positions
+///
+/// Nirvana reports exon numbers but not exon boundaries, so the 50-bp rule
+/// for the penultimate exon cannot be evaluated; only the terminal-exon part
+/// of the NMD check is applied. Likewise the end-truncation check only runs
+/// when the CDS or protein position carries a total length ("600/766"), and
+/// the splice-position check only when `hgvsc` has an intronic offset.
+/// Checks skipped for these reasons are listed in `unchecked`, so that a
+/// high-confidence call resting on them can be told apart.
+pub fn assess_loss_of_function(variant: &VariantPosition, config: &FilterConfig) -> LofAssessment {
+    if !config.enable_lof_rescue {
+        return LofAssessment::default();
+    }
+
+    let transcript = match variant.selected_transcript() {
+        Some(t) => t,
+        None => return LofAssessment::default(),
+    };
+
+    let consequence = match LOF_CONSEQUENCES
+        .iter()
+        .find(|lof| transcript.consequence.iter().any(|c| c == *lof))
+    {
+        Some(c) => c.to_string(),
+        None => return LofAssessment::default(),
+    };
+
+    let is_splice = consequence.starts_with("splice_");
+    let mut failed_checks = Vec::new();
+    let mut unchecked = Vec::new();
+
+    if !is_splice {
+        match coding_position_fraction(transcript) {
+            Some(fraction) if fraction > config.lof_max_cds_fraction => {
+                failed_checks.push(format!(
+                    "END_TRUNC ({:.1}% of CDS)",
+                    fraction * 100.0
+                ));
+            }
+            Some(_) => {}
+            None => unchecked.push("END_TRUNC (no CDS length)".to_string()),
+        }
+
+        match parse_ordinal(transcript.exons.as_deref()) {
+            Some((exon, total)) if total > 1 && exon == total => {
+                failed_checks.push("NMD_ESCAPE (terminal exon)".to_string());
+            }
+            // Escapes NMD within 50 bp of the last exon junction, which
+            // can't be located without exon boundaries
+            Some((exon, total)) if total > 1 && exon == total - 1 => {
+                unchecked.push("NMD_50BP (penultimate exon)".to_string());
+            }
+            Some(_) => {}
+            None => unchecked.push("NMD_ESCAPE (no exon number)".to_string()),
+        }
+    } else {
+        match transcript.hgvsc.as_deref().map(intronic_offset) {
+            Some(Some(offset)) if offset.abs() > 2 => {
+                failed_checks.push(format!("NON_CAN_SPLICE (offset {:+})", offset));
+            }
+            Some(Some(_)) => {}
+            // An exonic position says nothing about the distance to the
+            // intron boundary
+            Some(None) => unchecked.push("NON_CAN_SPLICE (exonic HGVSc)".to_string()),
+            None => unchecked.push("NON_CAN_SPLICE (no HGVSc)".to_string()),
+        }
+    }
+
+    let transcript_id = transcript.id.clone();
+    let mut reason = if failed_checks.is_empty() {
+        format!(
+            "High-confidence LoF: {} in {}",
+            consequence,
+            transcript_id.as_deref().unwrap_or("selected transcript")
+        )
+    } else {
+        format!("Low-confidence LoF: {} ({})", consequence, failed_checks.join(", "))
+    };
+    if !unchecked.is_empty() {
+        reason.push_str(&format!("; not checked: {}", unchecked.join(", ")));
+    }
+
+    LofAssessment {
+        is_high_confidence: failed_checks.is_empty(),
+        consequence: Some(consequence),
+        transcript_id,
+        failed_checks,
+        unchecked,
+        reason,
+    }
+}
+
+/// Relative position of the variant in the coding sequence, from
+/// `cdsPos`/`proteinPos` values of the form "start[-end]/length".
+fn coding_position_fraction(transcript: &TranscriptAnnotation) -> Option<f64> {
+    [transcript.cds_pos.as_deref(), transcript.protein_pos.as_deref()]
+        .into_iter()
+        .flatten()
+        .find_map(|pos| {
+            let (start, length) = pos.split_once('/')?;
+            let start: f64 = start.split('-').next()?.trim().parse().ok()?;
+            let length: f64 = length.trim().parse().ok()?;
+            (length > 0.0).then(|| start / length)
+        })
+}
+
+/// Parses "n/total" exon or intron ordinals.
+fn parse_ordinal(value: Option<&str>) -> Option<(u32, u32)> {
+    let (n, total) = value?.split_once('/')?;
+    // Multi-exon spans ("3-4/10") are judged by their last exon
+    let n = n.rsplit('-').next()?.trim().parse().ok()?;
+    Some((n, total.trim().parse().ok()?))
+}
+
+/// Intronic offset of an HGVS c. position, e.g. `c.1234+5G>A` -> 5,
+/// `c.1235-2A>G` -> -2. `None` for exonic positions.
+fn intronic_offset(hgvsc: &str) -> Option<i32> {
+    let coding = &hgvsc[hgvsc.find("c.")? + 2..];
+    let bytes = coding.as_bytes();
+    // Skip the anchor position (which may itself start with '-' or '*')
+    let mut i = 0;
+    while i < bytes.len() && matches!(bytes[i], b'-' | b'*') {
+        i += 1;
+    }
+    while i < bytes.len() && bytes[i].is_ascii_digit() {
+        i += 1;
+    }
+    let sign = match bytes.get(i) {
+        Some(b'+') => 1,
+        Some(b'-') => -1,
+        _ => return None,
+    };
+    let digits: String = coding[i + 1..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    digits.parse::<i32>().ok().map(|d| sign * d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_high_confidence_stop_gained() {
+        let variant = create_test_variant(create_transcript(
+            "stop_gained",
+            Some("300/1200"),
+            Some("5/18"),
+            Some("NM_000001.1:c.300C>T"),
+        ));
+        let assessment = assess_loss_of_function(&variant, &lof_config());
+        assert!(assessment.is_high_confidence);
+        assert_eq!(assessment.consequence.as_deref(), Some("stop_gained"));
+    }
+
+    #[test]
+    fn test_disabled_by_default() {
+        let variant = create_test_variant(create_transcript("stop_gained", None, None, None));
+        let assessment = assess_loss_of_function(&variant, &FilterConfig::default());
+        assert!(!assessment.is_high_confidence);
+        assert!(assessment.consequence.is_none());
+    }
+
+    #[test]
+    fn test_end_truncation_and_terminal_exon() {
+        let variant = create_test_variant(create_transcript(
+            "frameshift_variant",
+            Some("1180/1200"),
+            Some("12/18"),
+            None,
+        ));
+        let assessment = assess_loss_of_function(&variant, &lof_config());
+        assert!(!assessment.is_high_confidence);
+        assert!(assessment.failed_checks[0].starts_with("END_TRUNC"));
+
+        let variant = create_test_variant(create_transcript(
+            "stop_gained",
+            Some("600/1200"),
+            Some("18/18"),
+            None,
+        ));
+        let assessment = assess_loss_of_function(&variant, &lof_config());
+        assert!(!assessment.is_high_confidence);
+        assert!(assessment.failed_checks[0].starts_with("NMD_ESCAPE"));
+    }
+
+    #[test]
+    fn test_unchecked_penultimate_exon() {
+        let variant = create_test_variant(create_transcript(
+            "stop_gained",
+            Some("1000/1200"),
+            Some("17/18"),
+            None,
+        ));
+        let assessment = assess_loss_of_function(&variant, &lof_config());
+        assert!(assessment.is_high_confidence);
+        assert_eq!(assessment.unchecked, vec!["NMD_50BP (penultimate exon)"]);
+        assert!(assessment.reason.ends_with("not checked: NMD_50BP (penultimate exon)"));
+
+        // Without a CDS length or exon number neither check can run
+        let variant = create_test_variant(create_transcript("frameshift_variant", None, None, None));
+        let assessment = assess_loss_of_function(&variant, &lof_config());
+        assert!(assessment.is_high_confidence);
+        assert_eq!(assessment.unchecked.len(), 2);
+    }
+
+    #[test]
+    fn test_splice_site_offsets() {
+        assert_eq!(intronic_offset("NM_000001.1:c.1234+1G>A"), Some(1));
+        assert_eq!(intronic_offset("c.1235-2A>G"), Some(-2));
+        assert_eq!(intronic_offset("c.-15+5G>A"), Some(5));
+        assert_eq!(intronic_offset("c.1234G>A"), None);
+
+        let variant = create_test_variant(create_transcript(
+            "splice_donor_variant",
+            None,
+            None,
+            Some("NM_000001.1:c.1234+5G>A"),
+        ));
+        let assessment = assess_loss_of_function(&variant, &lof_config());
+        assert!(!assessment.is_high_confidence);
+
+        let variant = create_test_variant(create_transcript(
+            "splice_acceptor_variant",
+            None,
+            None,
+            Some("NM_000001.1:c.1235-1G>A"),
+        ));
+        let assessment = assess_loss_of_function(&variant, &lof_config());
+        assert!(assessment.is_high_confidence);
+        assert!(assessment.unchecked.is_empty());
+    }
+
+    #[test]
+    fn test_unchecked_splice_position() {
+        let variant = create_test_variant(create_transcript("splice_donor_variant", None, None, None));
+        let assessment = assess_loss_of_function(&variant, &lof_config());
+        assert!(assessment.is_high_confidence);
+        assert_eq!(assessment.unchecked, vec!["NON_CAN_SPLICE (no HGVSc)"]);
+
+        let variant = create_test_variant(create_transcript(
+            "splice_acceptor_variant",
+            None,
+            None,
+            Some("NM_000001.1:c.1234G>A"),
+        ));
+        let assessment = assess_loss_of_function(&variant, &lof_config());
+        assert!(assessment.is_high_confidence);
+        assert_eq!(assessment.unchecked, vec!["NON_CAN_SPLICE (exonic HGVSc)"]);
+        assert!(assessment.reason.ends_with("not checked: NON_CAN_SPLICE (exonic HGVSc)"));
+    }
+
+    fn lof_config() -> FilterConfig {
+        FilterConfig {
+            enable_lof_rescue: true,
+            ..FilterConfig::default()
+        }
+    }
+
+    fn create_transcript(
+        consequence: &str,
+        cds_pos: Option<&str>,
+        exons: Option<&str>,
+        hgvsc: Option<&str>,
+    ) -> TranscriptAnnotation {
+        TranscriptAnnotation {
+            id: Some("NM_000001.1".to_string()),
+            source: Some("RefSeq".to_string()),
+            hgnc: Some("TP53".to_string()),
+            consequence: vec![consequence.to_string()],
+            impact: None,
+            amino_acids: None,
+            cdna_pos: None,
+            cds_pos: cds_pos.map(str::to_string),
+            exons: exons.map(str::to_string),
+            codons: None,
+            protein_pos: None,
+            hgvsc: hgvsc.map(str::to_string),
+            hgvsp: None,
+            is_canonical: Some(true),
+            is_mane_select: Some(true),
+            is_mane_plus_clinical: None,
+            bio_type: None,
+        }
+    }
+
+    fn create_test_variant(transcript: TranscriptAnnotation) -> VariantPosition {
+        VariantPosition {
+            chromosome: "chr17".to_string(),
+            start: 7675088,
+            end_pos: 7675088,
+            reference_allele: "C".to_string(),
+            total_depth: Some(50),
+            variant_frequencies: Some(vec![0.25]),
+            transcripts: vec![transcript],
+            ..VariantPosition::test_default()
+        }
+    }
+}
@@ -1,9 +1,17 @@
 pub mod clinvar;
+pub mod consequence;
 pub mod decision;
+pub mod expression;
+pub mod lof;
+pub mod pipeline;
 pub mod predictive;
 pub mod quality;
 
 pub use clinvar::*;
+pub use consequence::*;
 pub use decision::*;
+pub use expression::{EvalContext, Expression, ExpressionError, RuleSet};
+pub use lof::*;
+pub use pipeline::*;
 pub use predictive::*;
 pub use quality::*;
@@ -0,0 +1,543 @@
+//! Composable filter pipeline.
+//!
+//! A [`Pipeline`] runs a sequence of [`FilterStage`]s over each variant.
+//! Every stage sees a shared [`VariantContext`] holding the results of the
+//! stages before it, and returns pass, annotate (pass with QC flags) or fail
+//! with a typed [`Reason`]. The first failure stops the pipeline.
+//!
+//! The built-in stages are selected and ordered by `FilterConfig::stages`;
+//! library users can insert their own stages anywhere:
+//!
+//! ```
+//! use json2maf::*;
+//!
+//! struct Blocklist(Vec<String>);
+//!
+//! impl FilterStage for Blocklist {
+//!     fn name(&self) -> &'static str {
+//!         "blocklist"
+//!     }
+//!
+//!     fn evaluate(&self, ctx: &mut VariantContext) -> StageOutcome {
+//!         let key = format!("{}:{}", ctx.variant.chromosome, ctx.variant.start);
+//!         if self.0.contains(&key) {
+//!             StageOutcome::Fail(Reason::new(
+//!                 ReasonCode::Custom("blocklisted".to_string()),
+//!                 format!("{} is on the lab blocklist", key),
+//!             ))
+//!         } else {
+//!             StageOutcome::Pass
+//!         }
+//!     }
+//! }
+//!
+//! let config = FilterConfig::default();
+//! let mut pipeline = Pipeline::from_config(&config).unwrap();
+//! pipeline
+//!     .insert_after(QUALITY_STAGE, Box::new(Blocklist(vec!["chr1:12345".to_string()])))
+//!     .unwrap();
+//! ```
+use crate::filters::clinvar::assess_clinvar_pathogenicity;
+use crate::filters::consequence::apply_consequence_filters;
+use crate::filters::decision::make_filter_decision_with_config;
+use crate::filters::expression::{EvalContext, RuleSet};
+use crate::filters::lof::{assess_loss_of_function, FLAG_LOF_UNCHECKED};
+use crate::filters::predictive::assess_predictive_scores;
+use crate::filters::quality::{apply_quality_filters, FLAG_MISSING_POPULATION_AF, FLAG_MISSING_SAMPLE_DATA};
+use crate::types::*;
+use std::borrow::Cow;
+
+pub const QUALITY_STAGE: &str = "quality";
+pub const CONSEQUENCE_STAGE: &str = "consequence";
+pub const CLINVAR_STAGE: &str = "clinvar";
+pub const PREDICTIVE_STAGE: &str = "predictive";
+pub const LOF_STAGE: &str = "lof";
+pub const DECISION_STAGE: &str = "decision";
+
+/// Built-in stages in their default order.
+pub const DEFAULT_STAGES: &[&str] = &[
+    QUALITY_STAGE,
+    CONSEQUENCE_STAGE,
+    CLINVAR_STAGE,
+    PREDICTIVE_STAGE,
+    LOF_STAGE,
+    DECISION_STAGE,
+];
+
+/// State shared by the stages while one variant moves through the pipeline.
+/// Built-in stages store their results here for later stages to use.
+pub struct VariantContext<'a> {
+    pub variant: &'a VariantPosition,
+    pub config: &'a FilterConfig,
+    /// QC flags reported in the output
+    pub flags: Vec<String>,
+    pub quality: Option<QualityFilterResult>,
+    pub consequence: Option<ConsequenceFilterResult>,
+    pub clinvar: Option<ClinVarAssessment>,
+    pub predictive: Option<PredictiveAssessment>,
+    pub lof: Option<LofAssessment>,
+    pub decision: Option<FilterDecision>,
+}
+
+impl<'a> VariantContext<'a> {
+    pub fn new(variant: &'a VariantPosition, config: &'a FilterConfig) -> Self {
+        VariantContext {
+            variant,
+            config,
+            flags: Vec::new(),
+            quality: None,
+            consequence: None,
+            clinvar: None,
+            predictive: None,
+            lof: None,
+            decision: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StageOutcome {
+    Pass,
+    /// Pass, recording these QC flags on the variant
+    Annotate(Vec<String>),
+    Fail(Reason),
+}
+
+pub trait FilterStage: Send + Sync {
+    /// Stable name used in statistics and the rejected-variant report.
+    fn name(&self) -> &'static str;
+
+    fn evaluate(&self, ctx: &mut VariantContext) -> StageOutcome;
+}
+
+#[derive(Debug, Clone)]
+pub enum Verdict {
+    Included(FilterDecision),
+    Rejected { stage: &'static str, reason: Reason },
+}
+
+pub struct PipelineOutcome<'a> {
+    pub verdict: Verdict,
+    pub context: VariantContext<'a>,
+}
+
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Box<dyn FilterStage>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds the built-in stages named in `config.stages`, in that order.
+    pub fn from_config(config: &FilterConfig) -> anyhow::Result<Self> {
+        validate_stage_order(&config.stages)?;
+
+        let mut pipeline = Pipeline::new();
+        for name in &config.stages {
+            let stage: Box<dyn FilterStage> = match name.as_str() {
+                QUALITY_STAGE => Box::new(QualityStage),
+                CONSEQUENCE_STAGE => Box::new(ConsequenceStage),
+                CLINVAR_STAGE => Box::new(ClinVarStage),
+                PREDICTIVE_STAGE => Box::new(PredictiveStage),
+                LOF_STAGE => Box::new(LofStage),
+                DECISION_STAGE => Box::new(DecisionStage {
+                    rules: RuleSet::from_config(config)?,
+                }),
+                other => unreachable!("stage '{}' passed validation", other),
+            };
+            pipeline.stages.push(stage);
+        }
+        Ok(pipeline)
+    }
+
+    pub fn push(&mut self, stage: Box<dyn FilterStage>) -> &mut Self {
+        self.stages.push(stage);
+        self
+    }
+
+    pub fn insert_before(&mut self, name: &str, stage: Box<dyn FilterStage>) -> anyhow::Result<()> {
+        let index = self.position(name)?;
+        self.stages.insert(index, stage);
+        Ok(())
+    }
+
+    pub fn insert_after(&mut self, name: &str, stage: Box<dyn FilterStage>) -> anyhow::Result<()> {
+        let index = self.position(name)?;
+        self.stages.insert(index + 1, stage);
+        Ok(())
+    }
+
+    fn position(&self, name: &str) -> anyhow::Result<usize> {
+        self.stages
+            .iter()
+            .position(|s| s.name() == name)
+            .ok_or_else(|| anyhow::anyhow!("pipeline has no stage named '{}'", name))
+    }
+
+    pub fn stage_names(&self) -> Vec<&'static str> {
+        self.stages.iter().map(|s| s.name()).collect()
+    }
+
+    /// Runs every stage on `variant`, recording per-stage and per-reason
+    /// counts in `stats`. A variant that passes every stage is included with
+    /// the decision stage's verdict. `from_config` always adds a decision
+    /// stage; a pipeline assembled by hand without one includes such
+    /// variants as "Unclassified".
+    pub fn run<'a>(
+        &self,
+        variant: &'a VariantPosition,
+        config: &'a FilterConfig,
+        stats: &mut FilterStats,
+    ) -> PipelineOutcome<'a> {
+        let mut ctx = VariantContext::new(variant, config);
+        let mut rejection = None;
+        stats.evaluated += 1;
+
+        for stage in &self.stages {
+            let outcome = stage.evaluate(&mut ctx);
+            let stage_stats = stats.stages.entry(Cow::Borrowed(stage.name())).or_default();
+            stage_stats.evaluated += 1;
+
+            match outcome {
+                StageOutcome::Pass => stage_stats.passed += 1,
+                StageOutcome::Annotate(flags) => {
+                    stage_stats.passed += 1;
+                    stage_stats.annotated += 1;
+                    ctx.flags.extend(flags);
+                }
+                StageOutcome::Fail(reason) => {
+                    stage_stats.failed += 1;
+                    *stage_stats.reasons.entry(reason.code.to_string()).or_insert(0) += 1;
+                    rejection = Some((stage.name(), reason));
+                    break;
+                }
+            }
+        }
+
+        record_observations(&ctx, stats);
+
+        let verdict = match rejection {
+            Some((stage, reason)) => {
+                stats.record_rejection(&reason);
+                Verdict::Rejected { stage, reason }
+            }
+            None => {
+                let decision = ctx.decision.clone().unwrap_or_else(|| FilterDecision {
+                    should_include: true,
+                    pathogenicity_class: "Unclassified".to_string(),
+                    primary_evidence: "None".to_string(),
+                    justification: "Passed all pipeline stages".to_string(),
+                });
+                stats.record_inclusion(&decision, ctx.predictive.as_ref());
+                Verdict::Included(decision)
+            }
+        };
+
+        PipelineOutcome { verdict, context: ctx }
+    }
+}
+
+/// Counts what the built-in stages observed regardless of the verdict.
+fn record_observations(ctx: &VariantContext, stats: &mut FilterStats) {
+    if let Some(quality) = &ctx.quality {
+        if quality.passes_quality {
+            stats.passed_quality += 1;
+        }
+        for flag in &quality.missing_data {
+            match flag.as_str() {
+                FLAG_MISSING_SAMPLE_DATA => stats.missing_sample_data += 1,
+                FLAG_MISSING_POPULATION_AF => stats.missing_population_af += 1,
+                _ => {}
+            }
+        }
+    }
+    if let Some(lof) = &ctx.lof {
+        if lof.consequence.is_some() && !lof.is_high_confidence {
+            stats.low_confidence_lof += 1;
+        }
+    }
+}
+
+/// Checks a configured stage list: only built-in names, no repeats, a
+/// decision stage, and the assessment stages ahead of the decision that
+/// consumes them.
+pub fn validate_stage_order(stages: &[String]) -> anyhow::Result<()> {
+    for (i, name) in stages.iter().enumerate() {
+        if !DEFAULT_STAGES.contains(&name.as_str()) {
+            anyhow::bail!(
+                "unknown pipeline stage '{}' (expected one of: {})",
+                name,
+                DEFAULT_STAGES.join(", ")
+            );
+        }
+        if stages[..i].contains(name) {
+            anyhow::bail!("pipeline stage '{}' is listed more than once", name);
+        }
+    }
+
+    // Without a decision every variant that passed the other stages would
+    // be included unclassified
+    let decision = stages.iter().position(|s| s == DECISION_STAGE).ok_or_else(|| {
+        anyhow::anyhow!("pipeline stages must include '{}'", DECISION_STAGE)
+    })?;
+    for input in [CLINVAR_STAGE, PREDICTIVE_STAGE, LOF_STAGE] {
+        if stages[decision..].iter().any(|s| s == input) {
+            anyhow::bail!("pipeline stage '{}' must come before '{}'", input, DECISION_STAGE);
+        }
+    }
+
+    Ok(())
+}
+
+// ============================================================================
+// Built-in stages
+// ============================================================================
+
+/// VCF FILTER, depth/VAF, per-sample and population frequency checks.
+pub struct QualityStage;
+
+impl FilterStage for QualityStage {
+    fn name(&self) -> &'static str {
+        QUALITY_STAGE
+    }
+
+    fn evaluate(&self, ctx: &mut VariantContext) -> StageOutcome {
+        let result = apply_quality_filters(ctx.variant, ctx.config);
+        let outcome = if result.passes_quality {
+            if result.flags.is_empty() {
+                StageOutcome::Pass
+            } else {
+                StageOutcome::Annotate(result.flags.clone())
+            }
+        } else {
+            // Flags are reported on rejected variants too
+            ctx.flags.extend(result.flags.iter().cloned());
+            StageOutcome::Fail(
+                Reason::new(
+                    result.failure_code.clone().unwrap_or(ReasonCode::VcfFilter),
+                    result.failure_reason.clone().unwrap_or_default(),
+                )
+                .with_tags(result.failed_filters.clone()),
+            )
+        };
+        ctx.quality = Some(result);
+        outcome
+    }
+}
+
+/// Consequence and impact filtering on the selected transcript.
+pub struct ConsequenceStage;
+
+impl FilterStage for ConsequenceStage {
+    fn name(&self) -> &'static str {
+        CONSEQUENCE_STAGE
+    }
+
+    fn evaluate(&self, ctx: &mut VariantContext) -> StageOutcome {
+        let result = apply_consequence_filters(ctx.variant, ctx.config);
+        let outcome = if result.passes_consequence {
+            StageOutcome::Pass
+        } else {
+            let code = if result.failed_impact {
+                ReasonCode::LowImpact
+            } else {
+                ReasonCode::Consequence
+            };
+            StageOutcome::Fail(
+                Reason::new(code, result.failure_reason.clone().unwrap_or_default())
+                    .with_tags(result.consequence.iter().cloned().collect()),
+            )
+        };
+        ctx.consequence = Some(result);
+        outcome
+    }
+}
+
+pub struct ClinVarStage;
+
+impl FilterStage for ClinVarStage {
+    fn name(&self) -> &'static str {
+        CLINVAR_STAGE
+    }
+
+    fn evaluate(&self, ctx: &mut VariantContext) -> StageOutcome {
+        ctx.clinvar = Some(assess_clinvar_pathogenicity(&ctx.variant.clinvar));
+        StageOutcome::Pass
+    }
+}
+
+pub struct PredictiveStage;
+
+impl FilterStage for PredictiveStage {
+    fn name(&self) -> &'static str {
+        PREDICTIVE_STAGE
+    }
+
+    fn evaluate(&self, ctx: &mut VariantContext) -> StageOutcome {
+        ctx.predictive = Some(assess_predictive_scores(ctx.variant, ctx.config));
+        StageOutcome::Pass
+    }
+}
+
+/// Loss-of-function assessment (a no-op unless `enable_lof_rescue` is set).
+pub struct LofStage;
+
+impl FilterStage for LofStage {
+    fn name(&self) -> &'static str {
+        LOF_STAGE
+    }
+
+    fn evaluate(&self, ctx: &mut VariantContext) -> StageOutcome {
+        let lof = assess_loss_of_function(ctx.variant, ctx.config);
+        let outcome = if lof.is_high_confidence && !lof.unchecked.is_empty() {
+            StageOutcome::Annotate(vec![FLAG_LOF_UNCHECKED.to_string()])
+        } else {
+            StageOutcome::Pass
+        };
+        ctx.lof = Some(lof);
+        outcome
+    }
+}
+
+/// Hierarchical decision plus custom include/exclude rules. Assessment
+/// stages left out of the pipeline count as providing no evidence.
+pub struct DecisionStage {
+    pub rules: RuleSet,
+}
+
+impl FilterStage for DecisionStage {
+    fn name(&self) -> &'static str {
+        DECISION_STAGE
+    }
+
+    fn evaluate(&self, ctx: &mut VariantContext) -> StageOutcome {
+        let clinvar = ctx.clinvar.get_or_insert_with(|| assess_clinvar_pathogenicity(&[]));
+        let predictive = ctx.predictive.get_or_insert_with(PredictiveAssessment::default);
+        let lof = ctx.lof.get_or_insert_with(LofAssessment::default);
+
+        let decision = make_filter_decision_with_config(
+            ctx.variant,
+            clinvar,
+            predictive,
+            lof,
+            ctx.config.exclude_benign,
+        );
+
+        let decision = if self.rules.is_empty() {
+            decision
+        } else {
+            let eval_ctx = EvalContext {
+                variant: ctx.variant,
+                clinvar,
+                predictive,
+                lof,
+            };
+            self.rules.apply(&eval_ctx, decision)
+        };
+
+        let outcome = if decision.should_include {
+            StageOutcome::Pass
+        } else {
+            let code = if decision.primary_evidence == "Rule" {
+                ReasonCode::Rule
+            } else if decision.pathogenicity_class.contains("Benign") {
+                ReasonCode::Benign
+            } else {
+                ReasonCode::InsufficientEvidence
+            };
+            StageOutcome::Fail(Reason::new(code, decision.justification.clone()))
+        };
+        ctx.decision = Some(decision);
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct AlwaysFail;
+
+    impl FilterStage for AlwaysFail {
+        fn name(&self) -> &'static str {
+            "always_fail"
+        }
+
+        fn evaluate(&self, _ctx: &mut VariantContext) -> StageOutcome {
+            StageOutcome::Fail(Reason::new(ReasonCode::Custom("blocked".to_string()), "Blocked"))
+        }
+    }
+
+    #[test]
+    fn test_default_pipeline_order() {
+        let pipeline = Pipeline::from_config(&FilterConfig::default()).unwrap();
+        assert_eq!(pipeline.stage_names(), DEFAULT_STAGES);
+    }
+
+    #[test]
+    fn test_stage_order_validation() {
+        let stages = |names: &[&str]| names.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert!(validate_stage_order(&stages(&["consequence", "quality", "clinvar", "decision"])).is_ok());
+        assert!(validate_stage_order(&stages(&["quality", "qualty"])).is_err());
+        assert!(validate_stage_order(&stages(&["quality", "quality"])).is_err());
+        assert!(validate_stage_order(&stages(&["decision", "clinvar"])).is_err());
+        assert!(validate_stage_order(&stages(&["quality", "clinvar", "predictive"])).is_err());
+        assert!(validate_stage_order(&stages(&["decision"])).is_ok());
+    }
+
+    #[test]
+    fn test_custom_stage_rejects_and_counts() {
+        let config = FilterConfig::default();
+        let mut pipeline = Pipeline::from_config(&config).unwrap();
+        pipeline.insert_before(QUALITY_STAGE, Box::new(AlwaysFail)).unwrap();
+        assert!(pipeline.insert_after("missing", Box::new(AlwaysFail)).is_err());
+
+        let variant = create_test_variant();
+        let mut stats = FilterStats::default();
+        let outcome = pipeline.run(&variant, &config, &mut stats);
+
+        match outcome.verdict {
+            Verdict::Rejected { stage, reason } => {
+                assert_eq!(stage, "always_fail");
+                assert_eq!(reason.code.as_str(), "blocked");
+            }
+            Verdict::Included(_) => panic!("variant should be rejected"),
+        }
+        assert_eq!(stats.stages["always_fail"].failed, 1);
+        assert!(!stats.stages.contains_key(QUALITY_STAGE));
+        assert_eq!(stats.excluded, 1);
+    }
+
+    #[test]
+    fn test_quality_failure_typed_reason() {
+        let config = FilterConfig::default();
+        let pipeline = Pipeline::from_config(&config).unwrap();
+        let mut variant = create_test_variant();
+        variant.total_depth = Some(10);
+
+        let mut stats = FilterStats::default();
+        let outcome = pipeline.run(&variant, &config, &mut stats);
+        match outcome.verdict {
+            Verdict::Rejected { stage, reason } => {
+                assert_eq!(stage, QUALITY_STAGE);
+                assert_eq!(reason.code, ReasonCode::LowDepth);
+            }
+            Verdict::Included(_) => panic!("variant should be rejected"),
+        }
+        assert_eq!(stats.failed_depth, 1);
+        assert_eq!(stats.stages[QUALITY_STAGE].reasons["low_depth"], 1);
+    }
+
+    fn create_test_variant() -> VariantPosition {
+        VariantPosition {
+            start: 12345,
+            end_pos: 12345,
+            total_depth: Some(50),
+            variant_frequencies: Some(vec![0.25]),
+            ..VariantPosition::test_default()
+        }
+    }
+}
@@ -1,49 +1,84 @@
 use crate::types::*;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+
+/// Scores that can carry a weight in the `weighted` model.
+pub const WEIGHTED_SOURCES: &[&str] = &["PrimateAI-3D", "REVEL", "DANN", "COSMIC"];
+
+/// Scores that can be given evidence thresholds in the `calibrated` model.
+pub const CALIBRATED_SOURCES: &[&str] = &["PrimateAI-3D", "REVEL", "DANN"];
 
 pub fn assess_predictive_scores(
     variant: &VariantPosition,
     config: &FilterConfig,
 ) -> PredictiveAssessment {
-    let mut contributing = HashMap::new();
-    let mut support_count = 0;
-
-    // Check PrimateAI-3D
-    if let Some(primate_ai_3d) = get_primate_ai_score(variant) {
-        if primate_ai_3d >= config.min_primate_ai_score {
-            contributing.insert("PrimateAI-3D".to_string(), primate_ai_3d);
-            support_count += 1;
-        }
-    }
+    let scores = [
+        ("PrimateAI-3D", get_primate_ai_score(variant), config.min_primate_ai_score),
+        ("REVEL", get_revel_score(variant), config.min_revel_score),
+        ("DANN", get_dann_score(variant), config.min_dann_score),
+    ];
 
-    // Check REVEL
-    if let Some(revel) = get_revel_score(variant) {
-        if revel >= config.min_revel_score {
-            contributing.insert("REVEL".to_string(), revel);
-            support_count += 1;
+    // Threshold crossings, shared by the votes and weighted models
+    let mut crossing = HashMap::new();
+    for (name, score, threshold) in scores {
+        if let Some(score) = score {
+            if score >= threshold {
+                crossing.insert(name.to_string(), score);
+            }
         }
     }
 
-    // Check DANN
-    if let Some(dann) = get_dann_score(variant) {
-        if dann >= config.min_dann_score {
-            contributing.insert("DANN".to_string(), dann);
-            support_count += 1;
-        }
-    }
-
-    // Check COSMIC (presence indicates positive evidence)
+    // COSMIC (presence indicates positive evidence)
     if is_in_cosmic(variant) {
-        contributing.insert("COSMIC".to_string(), 1.0);
-        support_count += 1;
+        crossing.insert("COSMIC".to_string(), 1.0);
     }
 
-    // Determine if should be suggested as likely pathogenic
+    let total_weight: f64 = crossing
+        .keys()
+        .map(|name| config.score_weights.get(name).copied().unwrap_or(0.0))
+        .sum();
+
+    // Calibrated evidence is reported under every model. PrimateAI-3D
+    // thresholds do not apply to the legacy PrimateAI score (a different
+    // scale) that `get_primate_ai_score` falls back on, so it stays uncalibrated.
+    let calibration_inputs = [
+        ("PrimateAI-3D", variant.primate_ai_3d),
+        ("REVEL", get_revel_score(variant)),
+        ("DANN", get_dann_score(variant)),
+    ];
+    let calibrated_scores: BTreeMap<String, EvidenceStrength> = calibration_inputs
+        .iter()
+        .filter_map(|(name, score)| {
+            let calibration = config.score_calibrations.get(*name)?;
+            Some((name.to_string(), calibration.strength((*score)?)))
+        })
+        .collect();
+    let evidence_strength = combine_evidence(&calibrated_scores);
+
+    let (suggests_pathogenic, contributing) = match config.predictive_model {
+        PredictiveModel::Votes => {
+            let suggests = crossing.contains_key("PrimateAI-3D") || crossing.len() >= 2;
+            (suggests, crossing)
+        }
+        PredictiveModel::Weighted => (total_weight >= config.min_predictive_weight, crossing),
+        PredictiveModel::Calibrated => {
+            let contributing = calibration_inputs
+                .iter()
+                .filter(|(name, _)| calibrated_scores.get(*name).is_some_and(|s| s.is_pathogenic()))
+                .filter_map(|(name, score)| Some((name.to_string(), (*score)?)))
+                .collect();
+            (evidence_strength >= config.min_evidence_strength, contributing)
+        }
+    };
+
+    let support_count = contributing.len();
     let has_primate_ai_3d = contributing.contains_key("PrimateAI-3D");
-    let suggests_pathogenic = has_primate_ai_3d || support_count >= 2;
 
-    // Calculate confidence score
-    let confidence = calculate_confidence(&contributing, has_primate_ai_3d, support_count);
+    let confidence = match config.predictive_model {
+        PredictiveModel::Calibrated => posterior_probability(evidence_strength),
+        PredictiveModel::Votes | PredictiveModel::Weighted => {
+            calculate_confidence(&contributing, has_primate_ai_3d, support_count)
+        }
+    };
 
     PredictiveAssessment {
         suggests_pathogenic,
@@ -51,9 +86,48 @@ pub fn assess_predictive_scores(
         confidence,
         support_count,
         has_primate_ai_support: has_primate_ai_3d,
+        model: config.predictive_model,
+        total_weight,
+        calibrated_scores,
+        evidence_strength,
+    }
+}
+
+/// Combines per-tool strengths the way ClinGen recommends applying PP3/BP4:
+/// once, at the strongest level reached. Tools pointing in opposite
+/// directions cancel out to indeterminate.
+fn combine_evidence(calibrated: &BTreeMap<String, EvidenceStrength>) -> EvidenceStrength {
+    let strongest_pathogenic = calibrated.values().copied().filter(|s| s.is_pathogenic()).max();
+    let strongest_benign = calibrated.values().copied().filter(|s| s.is_benign()).min();
+
+    match (strongest_pathogenic, strongest_benign) {
+        (Some(p), None) => p,
+        (None, Some(b)) => b,
+        _ => EvidenceStrength::Indeterminate,
     }
 }
 
+/// Posterior probability of pathogenicity after applying `strength`, under the
+/// ClinGen Bayesian adaptation of the ACMG/AMP rules (Tavtigian et al. 2018):
+/// prior 0.1, and odds of pathogenicity of 350^(1/8) for supporting, ^(2/8)
+/// for moderate and ^(4/8) for strong evidence (inverted for benign).
+fn posterior_probability(strength: EvidenceStrength) -> f64 {
+    const PRIOR: f64 = 0.1;
+    let exponent = match strength {
+        EvidenceStrength::BenignStrong => -4.0,
+        EvidenceStrength::BenignModerate => -2.0,
+        EvidenceStrength::BenignSupporting => -1.0,
+        EvidenceStrength::Indeterminate => 0.0,
+        EvidenceStrength::PathogenicSupporting => 1.0,
+        EvidenceStrength::PathogenicModerate => 2.0,
+        EvidenceStrength::PathogenicStrong => 4.0,
+    };
+    let odds = 350f64.powf(exponent / 8.0);
+    odds * PRIOR / ((odds - 1.0) * PRIOR + 1.0)
+}
+
+/// Heuristic confidence of the `votes` and `weighted` models, kept so their
+/// output is unchanged; the `calibrated` model uses `posterior_probability`.
 fn calculate_confidence(
     _contributing: &HashMap<String, f64>,
     has_primate_ai: bool,
@@ -141,26 +215,133 @@ mod tests {
         assert_eq!(assessment.support_count, 1);
     }
 
+    #[test]
+    fn test_weighted_model() {
+        let mut variant = create_test_variant();
+        variant.revel_score = Some(0.8);
+
+        let mut config = FilterConfig {
+            predictive_model: PredictiveModel::Weighted,
+            ..FilterConfig::default()
+        };
+        let assessment = assess_predictive_scores(&variant, &config);
+        assert_eq!(assessment.total_weight, 1.0);
+        assert!(!assessment.suggests_pathogenic);
+
+        config.score_weights.insert("REVEL".to_string(), 2.5);
+        let assessment = assess_predictive_scores(&variant, &config);
+        assert!(assessment.suggests_pathogenic);
+        assert!(assessment.contributing_scores.contains_key("REVEL"));
+    }
+
+    #[test]
+    fn test_calibrated_revel_strengths() {
+        let calibration = ScoreCalibration::revel();
+        assert_eq!(calibration.strength(0.95), EvidenceStrength::PathogenicStrong);
+        assert_eq!(calibration.strength(0.80), EvidenceStrength::PathogenicModerate);
+        assert_eq!(calibration.strength(0.65), EvidenceStrength::PathogenicSupporting);
+        assert_eq!(calibration.strength(0.50), EvidenceStrength::Indeterminate);
+        assert_eq!(calibration.strength(0.20), EvidenceStrength::BenignSupporting);
+        assert_eq!(calibration.strength(0.01), EvidenceStrength::BenignStrong);
+
+        let mut variant = create_test_variant();
+        variant.revel_score = Some(0.80);
+        // Uncalibrated scores carry no weight in the calibrated model
+        variant.primate_ai_3d = Some(0.95);
+
+        let mut config = FilterConfig {
+            predictive_model: PredictiveModel::Calibrated,
+            ..FilterConfig::default()
+        };
+        let assessment = assess_predictive_scores(&variant, &config);
+        assert!(assessment.suggests_pathogenic);
+        assert_eq!(assessment.evidence_strength, EvidenceStrength::PathogenicModerate);
+        assert_eq!(assessment.support_count, 1);
+        assert!(!assessment.has_primate_ai_support);
+
+        config.min_evidence_strength = EvidenceStrength::PathogenicStrong;
+        assert!(!assess_predictive_scores(&variant, &config).suggests_pathogenic);
+    }
+
+    #[test]
+    fn test_calibrated_confidence_is_posterior_probability() {
+        let mut variant = create_test_variant();
+        variant.revel_score = Some(0.80);
+
+        let config = FilterConfig {
+            predictive_model: PredictiveModel::Calibrated,
+            ..FilterConfig::default()
+        };
+        let assessment = assess_predictive_scores(&variant, &config);
+        assert!((assessment.confidence - 0.325).abs() < 0.01);
+
+        variant.revel_score = Some(0.95);
+        let strong = assess_predictive_scores(&variant, &config).confidence;
+        assert!(strong > assessment.confidence);
+        assert!((strong - 0.675).abs() < 0.01);
+
+        variant.revel_score = Some(0.01);
+        assert!(assess_predictive_scores(&variant, &config).confidence < 0.1);
+    }
+
+    #[test]
+    fn test_legacy_primate_ai_is_not_calibrated() {
+        let mut variant = create_test_variant();
+        variant.primate_ai = Some(0.95);
+
+        let mut config = FilterConfig {
+            predictive_model: PredictiveModel::Calibrated,
+            ..FilterConfig::default()
+        };
+        config.score_calibrations.insert(
+            "PrimateAI-3D".to_string(),
+            ScoreCalibration {
+                pathogenic_supporting: Some(0.8),
+                ..ScoreCalibration::default()
+            },
+        );
+        let assessment = assess_predictive_scores(&variant, &config);
+        assert!(assessment.calibrated_scores.is_empty());
+        assert!(!assessment.suggests_pathogenic);
+
+        variant.primate_ai_3d = Some(0.9);
+        let assessment = assess_predictive_scores(&variant, &config);
+        assert_eq!(
+            assessment.calibrated_scores["PrimateAI-3D"],
+            EvidenceStrength::PathogenicSupporting
+        );
+        assert!(assessment.suggests_pathogenic);
+    }
+
+    #[test]
+    fn test_conflicting_calibrated_scores() {
+        let mut variant = create_test_variant();
+        variant.revel_score = Some(0.95);
+        variant.dann_score = Some(0.1);
+
+        let mut config = FilterConfig {
+            predictive_model: PredictiveModel::Calibrated,
+            ..FilterConfig::default()
+        };
+        config.score_calibrations.insert(
+            "DANN".to_string(),
+            ScoreCalibration {
+                pathogenic_supporting: Some(0.99),
+                benign_supporting: Some(0.5),
+                ..ScoreCalibration::default()
+            },
+        );
+        let assessment = assess_predictive_scores(&variant, &config);
+        assert_eq!(assessment.calibrated_scores["DANN"], EvidenceStrength::BenignSupporting);
+        assert_eq!(assessment.evidence_strength, EvidenceStrength::Indeterminate);
+        assert!(!assessment.suggests_pathogenic);
+    }
+
     fn create_test_variant() -> VariantPosition {
         VariantPosition {
-            chromosome: "chr1".to_string(),
-            start: 100,
-            end_pos: 100,
-            reference_allele: "A".to_string(),
-            alternate_allele: "T".to_string(),
-            variant_type: "SNV".to_string(),
-            filters: vec!["PASS".to_string()],
             total_depth: Some(50),
             variant_frequencies: Some(vec![0.05]),
-            transcripts: vec![],
-            clinvar: vec![],
-            cosmic: vec![],
-            population_frequencies: vec![],
-            primate_ai_3d: None,
-            primate_ai: None,
-            dann_score: None,
-            revel_score: None,
-            dbsnp_ids: vec![],
+            ..VariantPosition::test_default()
         }
     }
 }
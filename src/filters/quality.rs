@@ -1,83 +1,284 @@
 use crate::types::*;
 
+/// QC flag recorded when depth, VAF or the field of a configured per-sample
+/// threshold is absent from the sample.
+pub const FLAG_MISSING_SAMPLE_DATA: &str = "missing_sample_data";
+/// QC flag recorded when no gnomAD-exome/1000G East Asian AF is available.
+pub const FLAG_MISSING_POPULATION_AF: &str = "missing_population_af";
+
 pub fn apply_quality_filters(
     variant: &VariantPosition,
     config: &FilterConfig,
 ) -> QualityFilterResult {
-    // Check VCF filters field: only accept ["PASS"]
-    if !(variant.filters.len() == 1 && variant.filters[0] == "PASS") {
-        let filters_str = variant.filters.join(", ");
-        return QualityFilterResult {
-            passes_quality: false,
-            failure_reason: Some(format!("Failed VCF filters: [{}]", filters_str)),
-            depth: variant.total_depth,
-            variant_frequency: get_variant_frequency(variant),
-            eas_allele_frequency: None,
-        };
+    let mut flags = Vec::new();
+    let mut missing_data = Vec::new();
+    let fail = |code: ReasonCode,
+                reason: String,
+                failed_filters: Vec<String>,
+                flags: Vec<String>,
+                missing_data: Vec<String>,
+                eas_allele_frequency: Option<f64>| QualityFilterResult {
+        passes_quality: false,
+        failure_reason: Some(reason),
+        failure_code: Some(code),
+        failed_filters,
+        flags,
+        missing_data,
+        depth: variant.total_depth,
+        variant_frequency: get_variant_frequency(variant),
+        eas_allele_frequency,
+    };
+
+    // Check VCF filters field against the accept/reject policy
+    if let Some((reason, failed_filters)) = check_vcf_filters(variant, config) {
+        return fail(ReasonCode::VcfFilter, reason, failed_filters, flags, missing_data, None);
     }
 
     // Check sequencing quality
-    if let Some((false, reason)) = check_sequencing_quality(variant, config) {
-        return QualityFilterResult {
-            passes_quality: false,
-            failure_reason: Some(reason),
-            depth: variant.total_depth,
-            variant_frequency: get_variant_frequency(variant),
-            eas_allele_frequency: None,
-        };
+    if let Some((code, reason)) = check_sequencing_quality(variant, config) {
+        return fail(code, reason, Vec::new(), flags, missing_data, None);
+    }
+
+    // Depth, VAF or a per-sample threshold's field absent: that check
+    // could not be applied
+    let missing_fields = missing_sample_fields(variant, config);
+    if !missing_fields.is_empty() {
+        missing_data.push(FLAG_MISSING_SAMPLE_DATA.to_string());
+        if config.missing_sample_data != MissingDataPolicy::Pass {
+            flags.push(FLAG_MISSING_SAMPLE_DATA.to_string());
+        }
+        if config.missing_sample_data == MissingDataPolicy::Fail {
+            return fail(
+                ReasonCode::MissingSampleData,
+                format!("Missing sample data ({} unavailable)", missing_fields.join("/")),
+                Vec::new(),
+                flags,
+                missing_data,
+                None,
+            );
+        }
+    }
+
+    // Check per-sample genotype-level quality
+    if let Some((code, reason)) = check_sample_quality(variant, config) {
+        return fail(code, reason, Vec::new(), flags, missing_data, None);
     }
 
     // Check population frequency
     let (pop_pass, pop_reason, eas_af) = check_population_frequency(variant, config);
     if !pop_pass {
-        return QualityFilterResult {
-            passes_quality: false,
-            failure_reason: pop_reason,
-            depth: variant.total_depth,
-            variant_frequency: get_variant_frequency(variant),
-            eas_allele_frequency: eas_af,
-        };
+        return fail(
+            ReasonCode::HighPopulationAf,
+            pop_reason.unwrap_or_default(),
+            Vec::new(),
+            flags,
+            missing_data,
+            eas_af,
+        );
+    }
+
+    // No population AF: the frequency filter could not be applied
+    if eas_af.is_none() {
+        missing_data.push(FLAG_MISSING_POPULATION_AF.to_string());
+        if config.missing_population_af != MissingDataPolicy::Pass {
+            flags.push(FLAG_MISSING_POPULATION_AF.to_string());
+        }
+        if config.missing_population_af == MissingDataPolicy::Fail {
+            return fail(
+                ReasonCode::MissingPopulationAf,
+                "Missing population allele frequency".to_string(),
+                Vec::new(),
+                flags,
+                missing_data,
+                None,
+            );
+        }
     }
 
     // All passed
     QualityFilterResult {
         passes_quality: true,
         failure_reason: None,
+        failure_code: None,
+        failed_filters: Vec::new(),
+        flags,
+        missing_data,
         depth: variant.total_depth,
         variant_frequency: get_variant_frequency(variant),
         eas_allele_frequency: eas_af,
     }
 }
 
+/// Evaluates the record-level FILTER tags. A tag fails when it matches any
+/// `reject_filters` pattern or matches none of the `accept_filters`
+/// patterns; reject wins over accept. An empty FILTER list (Nirvana omits
+/// the field for `.`) passes unless `fail_missing_filters` is set, in which
+/// case it is reported as the tag `.`.
+fn check_vcf_filters(
+    variant: &VariantPosition,
+    config: &FilterConfig,
+) -> Option<(String, Vec<String>)> {
+    if variant.filters.is_empty() {
+        if config.fail_missing_filters {
+            return Some(("Missing VCF filters".to_string(), vec![".".to_string()]));
+        }
+        return None;
+    }
+
+    let failed: Vec<String> = variant
+        .filters
+        .iter()
+        .filter(|tag| {
+            config.reject_filters.iter().any(|p| glob_match(p, tag))
+                || !config.accept_filters.iter().any(|p| glob_match(p, tag))
+        })
+        .cloned()
+        .collect();
+
+    if failed.is_empty() {
+        return None;
+    }
+
+    Some((
+        format!("Failed VCF filters: [{}]", variant.filters.join(", ")),
+        failed,
+    ))
+}
+
+/// Minimal glob matcher for FILTER tags: `*` matches any run of characters,
+/// `?` matches exactly one. Matching is case-sensitive, as VCF tags are.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            backtrack = Some((pi, ti));
+            pi += 1;
+        } else if let Some((star_pi, star_ti)) = backtrack {
+            pi = star_pi + 1;
+            ti = star_ti + 1;
+            backtrack = Some((star_pi, star_ti + 1));
+        } else {
+            return false;
+        }
+    }
+
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+/// Checks depth and VAF against their minimums. Each value is only checked
+/// when present; whether an absent value is acceptable is decided separately
+/// by the `missing_sample_data` policy.
 fn check_sequencing_quality(
     variant: &VariantPosition,
     config: &FilterConfig,
-) -> Option<(bool, String)> {
+) -> Option<(ReasonCode, String)> {
     // Check sequencing depth
-    let depth = variant.total_depth?;
-    if depth < config.min_total_depth {
-        return Some((
-            false,
-            format!(
-                "Low sequencing depth ({} < {})",
-                depth, config.min_total_depth
-            ),
-        ));
+    if let Some(depth) = variant.total_depth {
+        if depth < config.min_total_depth {
+            return Some((
+                ReasonCode::LowDepth,
+                format!("Low sequencing depth ({} < {})", depth, config.min_total_depth),
+            ));
+        }
     }
 
     // Check variant frequency
-    let vaf = get_variant_frequency(variant)?;
-    if vaf < config.min_variant_frequency {
-        return Some((
-            false,
-            format!(
-                "Low variant frequency ({:.4} < {})",
-                vaf, config.min_variant_frequency
-            ),
-        ));
+    if let Some(vaf) = get_variant_frequency(variant) {
+        if vaf < config.min_variant_frequency {
+            return Some((
+                ReasonCode::LowVaf,
+                format!("Low variant frequency ({:.4} < {})", vaf, config.min_variant_frequency),
+            ));
+        }
+    }
+
+    None
+}
+
+/// Sample fields that a check needs but the sample lacks: depth and VAF
+/// always, and the field of each configured per-sample threshold. They are
+/// handled by the `missing_sample_data` policy.
+fn missing_sample_fields(variant: &VariantPosition, config: &FilterConfig) -> Vec<&'static str> {
+    let checks = [
+        (true, variant.total_depth.is_none(), "depth"),
+        (true, get_variant_frequency(variant).is_none(), "VAF"),
+        (config.min_alt_reads.is_some(), variant.alt_read_count().is_none(), "alt read count"),
+        (config.min_genotype_quality.is_some(), variant.genotype_quality.is_none(), "genotype quality"),
+        (config.max_strand_bias.is_some(), variant.strand_bias.is_none(), "strand bias"),
+        (config.min_somatic_quality.is_some(), variant.somatic_quality.is_none(), "somatic quality"),
+    ];
+    checks
+        .iter()
+        .filter(|(applies, missing, _)| *applies && *missing)
+        .map(|(_, _, field)| *field)
+        .collect()
+}
+
+/// Applies the optional per-sample thresholds (alt reads, GQ, strand bias,
+/// somatic quality). Each check only runs when its threshold is configured
+/// and the sample carries the field; see `missing_sample_fields`. Returns
+/// the first failure.
+fn check_sample_quality(
+    variant: &VariantPosition,
+    config: &FilterConfig,
+) -> Option<(ReasonCode, String)> {
+
+    if let Some(min_alt) = config.min_alt_reads {
+        match variant.alt_read_count() {
+            Some(alt_reads) if alt_reads < min_alt => {
+                return Some((
+                    ReasonCode::LowAltReads,
+                    format!("Low alt read count ({} < {})", alt_reads, min_alt),
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(min_gq) = config.min_genotype_quality {
+        match variant.genotype_quality {
+            Some(gq) if gq < min_gq => {
+                return Some((
+                    ReasonCode::LowGenotypeQuality,
+                    format!("Low genotype quality ({} < {})", gq, min_gq),
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(max_sb) = config.max_strand_bias {
+        match variant.strand_bias {
+            Some(sb) if sb > max_sb => {
+                return Some((
+                    ReasonCode::HighStrandBias,
+                    format!("High strand bias ({:.4} > {})", sb, max_sb),
+                ));
+            }
+            _ => {}
+        }
     }
 
-    Some((true, String::new()))
+    if let Some(min_sq) = config.min_somatic_quality {
+        match variant.somatic_quality {
+            Some(sq) if sq < min_sq => {
+                return Some((
+                    ReasonCode::LowSomaticQuality,
+                    format!("Low somatic quality ({:.2} < {})", sq, min_sq),
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    None
 }
 
 fn check_population_frequency(
@@ -114,7 +315,7 @@ fn check_population_frequency(
         return (true, None, Some(onekg_af));
     }
 
-    // No population frequency data, consider as pass (conservative strategy)
+    // No population frequency data; the caller applies `missing_population_af`
     (true, None, None)
 }
 
@@ -177,26 +378,226 @@ mod tests {
             .contains("Low variant frequency"));
     }
 
+    #[test]
+    fn test_vcf_filter_policy() {
+        let mut variant = create_test_variant(50, 0.05);
+        variant.filters = vec!["weak_evidence".to_string()];
+
+        let result = apply_quality_filters(&variant, &FilterConfig::default());
+        assert!(!result.passes_quality);
+        assert_eq!(result.failed_filters, vec!["weak_evidence".to_string()]);
+
+        let config = FilterConfig {
+            accept_filters: vec!["PASS".to_string(), "weak_*".to_string()],
+            ..FilterConfig::default()
+        };
+        assert!(apply_quality_filters(&variant, &config).passes_quality);
+
+        let config = FilterConfig {
+            accept_filters: vec!["*".to_string()],
+            reject_filters: vec!["weak_evidence".to_string()],
+            ..FilterConfig::default()
+        };
+        assert!(!apply_quality_filters(&variant, &config).passes_quality);
+    }
+
+    #[test]
+    fn test_missing_vcf_filters() {
+        let mut variant = create_test_variant(50, 0.05);
+        variant.filters = vec![];
+
+        assert!(apply_quality_filters(&variant, &FilterConfig::default()).passes_quality);
+
+        let config = FilterConfig {
+            fail_missing_filters: true,
+            ..FilterConfig::default()
+        };
+        let result = apply_quality_filters(&variant, &config);
+        assert!(!result.passes_quality);
+        assert_eq!(result.failed_filters, vec![".".to_string()]);
+    }
+
+    #[test]
+    fn test_missing_sample_data_policy() {
+        let mut variant = create_test_variant(50, 0.05);
+        variant.total_depth = None;
+
+        // Default policy: pass without a flag on the variant
+        let result = apply_quality_filters(&variant, &FilterConfig::default());
+        assert!(result.passes_quality);
+        assert!(result.flags.is_empty());
+        assert!(result
+            .missing_data
+            .contains(&FLAG_MISSING_SAMPLE_DATA.to_string()));
+
+        let config = FilterConfig {
+            missing_sample_data: MissingDataPolicy::Flag,
+            ..FilterConfig::default()
+        };
+        let result = apply_quality_filters(&variant, &config);
+        assert!(result.passes_quality);
+        assert_eq!(result.flags, vec![FLAG_MISSING_SAMPLE_DATA.to_string()]);
+
+        let config = FilterConfig {
+            missing_sample_data: MissingDataPolicy::Fail,
+            ..FilterConfig::default()
+        };
+        let result = apply_quality_filters(&variant, &config);
+        assert!(!result.passes_quality);
+        assert!(result.failure_reason.unwrap().contains("Missing sample data"));
+    }
+
+    #[test]
+    fn test_vaf_checked_when_depth_missing() {
+        let mut variant = create_test_variant(50, 0.01);
+        variant.total_depth = None;
+        let result = apply_quality_filters(&variant, &FilterConfig::default());
+        assert!(!result.passes_quality);
+        assert!(result.failure_reason.unwrap().contains("Low variant frequency"));
+    }
+
+    #[test]
+    fn test_missing_population_af_policy() {
+        let variant = create_test_variant(50, 0.05);
+        let config = FilterConfig {
+            missing_population_af: MissingDataPolicy::Fail,
+            ..FilterConfig::default()
+        };
+        let result = apply_quality_filters(&variant, &config);
+        assert!(!result.passes_quality);
+        assert_eq!(result.flags, vec![FLAG_MISSING_POPULATION_AF.to_string()]);
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("PASS", "PASS"));
+        assert!(!glob_match("PASS", "pass"));
+        assert!(glob_match("Low*", "LowDP"));
+        assert!(glob_match("*_evidence", "weak_evidence"));
+        assert!(glob_match("Low??", "LowGQ"));
+        assert!(!glob_match("Low??", "LowDPX"));
+        assert!(glob_match("*", ""));
+    }
+
+    #[test]
+    fn test_quality_filter_low_alt_reads() {
+        let mut variant = create_test_variant(50, 0.05);
+        variant.allele_depths = Some(vec![47, 3]);
+        let config = FilterConfig {
+            min_alt_reads: Some(5),
+            ..FilterConfig::default()
+        };
+        let result = apply_quality_filters(&variant, &config);
+        assert!(!result.passes_quality);
+        assert!(result.failure_reason.unwrap().contains("Low alt read count"));
+    }
+
+    #[test]
+    fn test_quality_filter_strand_bias_and_gq() {
+        let mut variant = create_test_variant(50, 0.05);
+        variant.genotype_quality = Some(12.0);
+        variant.strand_bias = Some(8.5);
+
+        // Thresholds not configured: the fields are ignored
+        let result = apply_quality_filters(&variant, &FilterConfig::default());
+        assert!(result.passes_quality);
+
+        let config = FilterConfig {
+            max_strand_bias: Some(3.0),
+            ..FilterConfig::default()
+        };
+        let result = apply_quality_filters(&variant, &config);
+        assert!(result.failure_reason.unwrap().contains("High strand bias"));
+
+        let config = FilterConfig {
+            min_genotype_quality: Some(20.0),
+            ..FilterConfig::default()
+        };
+        let result = apply_quality_filters(&variant, &config);
+        assert!(result.failure_reason.unwrap().contains("Low genotype quality"));
+    }
+
+    #[test]
+    fn test_sample_thresholds_with_missing_fields() {
+        // No allele depths, GQ, strand bias or somatic quality
+        let variant = create_test_variant(50, 0.05);
+        let thresholds = [
+            (
+                FilterConfig {
+                    min_alt_reads: Some(5),
+                    ..FilterConfig::default()
+                },
+                "alt read count",
+            ),
+            (
+                FilterConfig {
+                    min_genotype_quality: Some(20.0),
+                    ..FilterConfig::default()
+                },
+                "genotype quality",
+            ),
+            (
+                FilterConfig {
+                    max_strand_bias: Some(3.0),
+                    ..FilterConfig::default()
+                },
+                "strand bias",
+            ),
+            (
+                FilterConfig {
+                    min_somatic_quality: Some(10.0),
+                    ..FilterConfig::default()
+                },
+                "somatic quality",
+            ),
+        ];
+
+        for (config, field) in thresholds {
+            // By default a field the sample doesn't carry isn't checked
+            let result = apply_quality_filters(&variant, &config);
+            assert!(result.passes_quality);
+            assert!(result.flags.is_empty());
+            assert!(result.missing_data.contains(&FLAG_MISSING_SAMPLE_DATA.to_string()));
+
+            // fail_missing_filters only concerns FILTER
+            let no_filter_policy = FilterConfig {
+                fail_missing_filters: true,
+                ..config.clone()
+            };
+            assert!(apply_quality_filters(&variant, &no_filter_policy).passes_quality);
+
+            let flag = FilterConfig {
+                missing_sample_data: MissingDataPolicy::Flag,
+                ..config.clone()
+            };
+            let result = apply_quality_filters(&variant, &flag);
+            assert!(result.passes_quality);
+            assert_eq!(result.flags, vec![FLAG_MISSING_SAMPLE_DATA.to_string()]);
+
+            let strict = FilterConfig {
+                missing_sample_data: MissingDataPolicy::Fail,
+                ..config
+            };
+            let result = apply_quality_filters(&variant, &strict);
+            assert!(!result.passes_quality);
+            assert_eq!(result.failure_code, Some(ReasonCode::MissingSampleData));
+            assert!(result.failure_reason.unwrap().contains(field));
+        }
+
+        // The germline preset's GQ threshold doesn't reject samples without GQ
+        let germline = crate::config::preset("germline-clinical").unwrap().filter;
+        assert!(apply_quality_filters(&create_test_variant(50, 0.5), &germline).passes_quality);
+
+        // Without thresholds, the same sample has nothing missing
+        let result = apply_quality_filters(&variant, &FilterConfig::default());
+        assert!(!result.missing_data.contains(&FLAG_MISSING_SAMPLE_DATA.to_string()));
+    }
+
     fn create_test_variant(depth: i32, vaf: f64) -> VariantPosition {
         VariantPosition {
-            chromosome: "chr1".to_string(),
-            start: 100,
-            end_pos: 100,
-            reference_allele: "A".to_string(),
-            alternate_allele: "T".to_string(),
-            variant_type: "SNV".to_string(),
-            filters: vec!["PASS".to_string()],
             total_depth: Some(depth),
             variant_frequencies: Some(vec![vaf]),
-            transcripts: vec![],
-            clinvar: vec![],
-            cosmic: vec![],
-            population_frequencies: vec![],
-            primate_ai_3d: None,
-            primate_ai: None,
-            dann_score: None,
-            revel_score: None,
-            dbsnp_ids: vec![],
+            ..VariantPosition::test_default()
         }
     }
 }
@@ -0,0 +1,448 @@
+/// HGVS protein (`p.`) notation: a typed parser and renderers for the full
+/// three-letter form and the single-letter `HGVSp_Short` form.
+///
+/// Nirvana reports `hgvsp` as `NP_004324.2:p.(Val600Glu)`; the accession
+/// and the parentheses marking a predicted consequence are optional on
+/// input. Both three-letter and single-letter amino acids are accepted, so
+/// a short form parses back to the same change.
+use std::fmt;
+use std::str::FromStr;
+
+/// Three-letter and single-letter amino acid codes, including the stop
+/// codon (`Ter`/`*`) and the ambiguity codes used in HGVS.
+const AMINO_ACIDS: &[(&str, u8)] = &[
+    ("Ala", b'A'),
+    ("Arg", b'R'),
+    ("Asn", b'N'),
+    ("Asp", b'D'),
+    ("Cys", b'C'),
+    ("Gln", b'Q'),
+    ("Glu", b'E'),
+    ("Gly", b'G'),
+    ("His", b'H'),
+    ("Ile", b'I'),
+    ("Leu", b'L'),
+    ("Lys", b'K'),
+    ("Met", b'M'),
+    ("Phe", b'F'),
+    ("Pro", b'P'),
+    ("Ser", b'S'),
+    ("Thr", b'T'),
+    ("Trp", b'W'),
+    ("Tyr", b'Y'),
+    ("Val", b'V'),
+    ("Sec", b'U'),
+    ("Pyl", b'O'),
+    ("Asx", b'B'),
+    ("Glx", b'Z'),
+    ("Xaa", b'X'),
+    ("Ter", b'*'),
+];
+
+/// An amino acid, stored as its single-letter code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AminoAcid(u8);
+
+impl AminoAcid {
+    pub const TER: AminoAcid = AminoAcid(b'*');
+
+    pub fn from_three_letter(code: &str) -> Option<Self> {
+        AMINO_ACIDS
+            .iter()
+            .find(|(three, _)| *three == code)
+            .map(|(_, one)| AminoAcid(*one))
+    }
+
+    pub fn from_one_letter(code: char) -> Option<Self> {
+        AMINO_ACIDS
+            .iter()
+            .find(|(_, one)| *one as char == code)
+            .map(|(_, one)| AminoAcid(*one))
+    }
+
+    pub fn one_letter(&self) -> char {
+        self.0 as char
+    }
+
+    pub fn three_letter(&self) -> &'static str {
+        AMINO_ACIDS
+            .iter()
+            .find(|(_, one)| *one == self.0)
+            .map(|(three, _)| *three)
+            .unwrap_or("Xaa")
+    }
+}
+
+/// An amino acid at a protein position, e.g. `Val600`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Residue {
+    pub amino_acid: AminoAcid,
+    pub position: u32,
+}
+
+/// Position of the new stop codon in a frameshift or extension, counted
+/// from the first changed residue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopOffset {
+    At(u32),
+    Unknown,
+}
+
+/// Where an extension adds residues.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtensionEnd {
+    /// N-terminal: a new start this many codons upstream (`Met1ext-5`)
+    Upstream(u32),
+    /// C-terminal: the lost stop is replaced by a new one (`Ter110GlnextTer17`)
+    Stop(StopOffset),
+}
+
+/// The edit described by an HGVS p. expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProteinEdit {
+    /// `Val600Glu`; a nonsense change is a substitution to `Ter`
+    Substitution { residue: Residue, alt: AminoAcid },
+    /// `=` or `Leu12=`
+    Synonymous { residue: Option<Residue> },
+    /// `Lys23_Val25del`
+    Deletion { start: Residue, end: Option<Residue> },
+    /// `Lys23_Val25dup`
+    Duplication { start: Residue, end: Option<Residue> },
+    /// `Lys2_Met3insGlnSerVal`
+    Insertion { start: Residue, end: Residue, inserted: Vec<AminoAcid> },
+    /// `Cys28_Lys29delinsTrp`
+    DelIns { start: Residue, end: Option<Residue>, inserted: Vec<AminoAcid> },
+    /// `Arg97ProfsTer23`, `Arg97fs`
+    Frameshift { residue: Residue, alt: Option<AminoAcid>, stop: Option<StopOffset> },
+    /// `Ter110GlnextTer17`, `Met1ext-5`
+    Extension { residue: Residue, alt: Option<AminoAcid>, end: ExtensionEnd },
+    /// `Met1?`: the residue is affected but the outcome is unknown
+    UnknownChange { residue: Residue },
+    /// `?`
+    Unknown,
+    /// `0`: no protein is produced
+    NoProtein,
+}
+
+/// A parsed HGVS p. expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProteinChange {
+    pub edit: ProteinEdit,
+    /// Written in parentheses: predicted from the DNA change, not observed
+    pub predicted: bool,
+}
+
+#[derive(Clone, Copy)]
+enum Style {
+    ThreeLetter,
+    OneLetter,
+}
+
+impl ProteinChange {
+    /// Three-letter form, e.g. `p.(Val600Glu)`.
+    pub fn to_full(&self) -> String {
+        let body = render_edit(&self.edit, Style::ThreeLetter);
+        if self.predicted {
+            format!("p.({})", body)
+        } else {
+            format!("p.{}", body)
+        }
+    }
+
+    /// Single-letter form without parentheses, e.g. `p.V600E`, as used for
+    /// MAF `HGVSp_Short`.
+    pub fn to_short(&self) -> String {
+        format!("p.{}", render_edit(&self.edit, Style::OneLetter))
+    }
+}
+
+impl fmt::Display for ProteinChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_full())
+    }
+}
+
+impl FromStr for ProteinChange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        parse_hgvsp(s)
+    }
+}
+
+/// Short form of a Nirvana `hgvsp` value. Expressions that don't parse are
+/// returned as given (without the accession) rather than guessed at.
+pub fn hgvsp_short(hgvsp: &str) -> String {
+    match parse_hgvsp(hgvsp) {
+        Ok(change) => change.to_short(),
+        Err(_) => protein_part(hgvsp).to_string(),
+    }
+}
+
+fn protein_part(hgvsp: &str) -> &str {
+    match hgvsp.find(":p.") {
+        Some(pos) => &hgvsp[pos + 1..],
+        None => hgvsp,
+    }
+}
+
+/// Parses `p.` notation, with or without a leading `ACCESSION:`.
+pub fn parse_hgvsp(s: &str) -> anyhow::Result<ProteinChange> {
+    let trimmed = protein_part(s.trim());
+    let body = trimmed
+        .strip_prefix("p.")
+        .ok_or_else(|| anyhow::anyhow!("'{}' is not HGVS protein notation (expected p.)", s))?;
+
+    let (inner, predicted) = match body.strip_prefix('(').and_then(|b| b.strip_suffix(')')) {
+        Some(inner) => (inner, true),
+        None => (body, false),
+    };
+
+    let mut cursor = Cursor { rest: inner };
+    let edit = cursor
+        .edit()
+        .ok_or_else(|| anyhow::anyhow!("invalid HGVS protein notation '{}'", s))?;
+    if !cursor.rest.is_empty() {
+        anyhow::bail!("invalid HGVS protein notation '{}' (unexpected '{}')", s, cursor.rest);
+    }
+    Ok(ProteinChange { edit, predicted })
+}
+
+struct Cursor<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Cursor<'a> {
+    fn eat(&mut self, token: &str) -> bool {
+        match self.rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn amino_acid(&mut self) -> Option<AminoAcid> {
+        if let Some(aa) = self.rest.get(..3).and_then(AminoAcid::from_three_letter) {
+            self.rest = &self.rest[3..];
+            return Some(aa);
+        }
+        let c = self.rest.chars().next()?;
+        if !(c.is_ascii_uppercase() || c == '*') {
+            return None;
+        }
+        let aa = AminoAcid::from_one_letter(c)?;
+        self.rest = &self.rest[1..];
+        Some(aa)
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        let len = self.rest.bytes().take_while(u8::is_ascii_digit).count();
+        let n = self.rest[..len].parse().ok()?;
+        self.rest = &self.rest[len..];
+        Some(n)
+    }
+
+    fn residue(&mut self) -> Option<Residue> {
+        let amino_acid = self.amino_acid()?;
+        let position = self.number().filter(|&p| p > 0)?;
+        Some(Residue { amino_acid, position })
+    }
+
+    fn sequence(&mut self) -> Option<Vec<AminoAcid>> {
+        let mut inserted = Vec::new();
+        while let Some(aa) = self.amino_acid() {
+            inserted.push(aa);
+        }
+        (!inserted.is_empty()).then_some(inserted)
+    }
+
+    /// `Ter23`, `*23`, `Ter?`, `*?`
+    fn stop_offset(&mut self) -> Option<StopOffset> {
+        if !(self.eat("Ter") || self.eat("*")) {
+            return None;
+        }
+        if self.eat("?") {
+            return Some(StopOffset::Unknown);
+        }
+        self.number().map(StopOffset::At)
+    }
+
+    fn edit(&mut self) -> Option<ProteinEdit> {
+        if self.eat("?") {
+            return Some(ProteinEdit::Unknown);
+        }
+        if self.eat("0") {
+            // `p.0?` (no protein predicted) is the same edit
+            self.eat("?");
+            return Some(ProteinEdit::NoProtein);
+        }
+        if self.eat("=") {
+            return Some(ProteinEdit::Synonymous { residue: None });
+        }
+
+        let start = self.residue()?;
+        if self.eat("_") {
+            let end = self.residue()?;
+            return if self.eat("delins") {
+                let inserted = self.sequence()?;
+                Some(ProteinEdit::DelIns { start, end: Some(end), inserted })
+            } else if self.eat("del") {
+                Some(ProteinEdit::Deletion { start, end: Some(end) })
+            } else if self.eat("dup") {
+                Some(ProteinEdit::Duplication { start, end: Some(end) })
+            } else if self.eat("ins") {
+                let inserted = self.sequence()?;
+                Some(ProteinEdit::Insertion { start, end, inserted })
+            } else {
+                None
+            };
+        }
+
+        if self.eat("delins") {
+            let inserted = self.sequence()?;
+            return Some(ProteinEdit::DelIns { start, end: None, inserted });
+        }
+        if self.eat("del") {
+            return Some(ProteinEdit::Deletion { start, end: None });
+        }
+        if self.eat("dup") {
+            return Some(ProteinEdit::Duplication { start, end: None });
+        }
+        if self.eat("=") {
+            return Some(ProteinEdit::Synonymous { residue: Some(start) });
+        }
+        if self.eat("?") {
+            return Some(ProteinEdit::UnknownChange { residue: start });
+        }
+
+        let alt = self.amino_acid();
+        if self.eat("fs") {
+            let stop = self.stop_offset();
+            return Some(ProteinEdit::Frameshift { residue: start, alt, stop });
+        }
+        if self.eat("ext") {
+            let end = if self.eat("-") {
+                ExtensionEnd::Upstream(self.number()?)
+            } else {
+                ExtensionEnd::Stop(self.stop_offset()?)
+            };
+            return Some(ProteinEdit::Extension { residue: start, alt, end });
+        }
+        alt.map(|alt| ProteinEdit::Substitution { residue: start, alt })
+    }
+}
+
+fn render_aa(aa: AminoAcid, style: Style) -> String {
+    match style {
+        Style::ThreeLetter => aa.three_letter().to_string(),
+        Style::OneLetter => aa.one_letter().to_string(),
+    }
+}
+
+fn render_residue(residue: &Residue, style: Style) -> String {
+    format!("{}{}", render_aa(residue.amino_acid, style), residue.position)
+}
+
+fn render_range(start: &Residue, end: Option<&Residue>, style: Style) -> String {
+    match end {
+        Some(end) => format!("{}_{}", render_residue(start, style), render_residue(end, style)),
+        None => render_residue(start, style),
+    }
+}
+
+fn render_sequence(sequence: &[AminoAcid], style: Style) -> String {
+    sequence.iter().map(|aa| render_aa(*aa, style)).collect()
+}
+
+fn render_stop(stop: StopOffset, style: Style) -> String {
+    let ter = render_aa(AminoAcid::TER, style);
+    match stop {
+        StopOffset::At(n) => format!("{}{}", ter, n),
+        StopOffset::Unknown => format!("{}?", ter),
+    }
+}
+
+fn render_edit(edit: &ProteinEdit, style: Style) -> String {
+    let alt_str = |alt: &Option<AminoAcid>| alt.map(|aa| render_aa(aa, style)).unwrap_or_default();
+    match edit {
+        ProteinEdit::Substitution { residue, alt } => {
+            format!("{}{}", render_residue(residue, style), render_aa(*alt, style))
+        }
+        ProteinEdit::Synonymous { residue: None } => "=".to_string(),
+        ProteinEdit::Synonymous { residue: Some(residue) } => format!("{}=", render_residue(residue, style)),
+        ProteinEdit::Deletion { start, end } => format!("{}del", render_range(start, end.as_ref(), style)),
+        ProteinEdit::Duplication { start, end } => format!("{}dup", render_range(start, end.as_ref(), style)),
+        ProteinEdit::Insertion { start, end, inserted } => format!(
+            "{}ins{}",
+            render_range(start, Some(end), style),
+            render_sequence(inserted, style)
+        ),
+        ProteinEdit::DelIns { start, end, inserted } => format!(
+            "{}delins{}",
+            render_range(start, end.as_ref(), style),
+            render_sequence(inserted, style)
+        ),
+        ProteinEdit::Frameshift { residue, alt, stop } => format!(
+            "{}{}fs{}",
+            render_residue(residue, style),
+            alt_str(alt),
+            stop.map(|s| render_stop(s, style)).unwrap_or_default()
+        ),
+        ProteinEdit::Extension { residue, alt, end } => {
+            let end = match end {
+                ExtensionEnd::Upstream(n) => format!("-{}", n),
+                ExtensionEnd::Stop(stop) => render_stop(*stop, style),
+            };
+            format!("{}{}ext{}", render_residue(residue, style), alt_str(alt), end)
+        }
+        ProteinEdit::UnknownChange { residue } => format!("{}?", render_residue(residue, style)),
+        ProteinEdit::Unknown => "?".to_string(),
+        ProteinEdit::NoProtein => "0".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_substitution() {
+        let change = parse_hgvsp("NP_004324.2:p.(Val600Glu)").unwrap();
+        assert!(change.predicted);
+        assert_eq!(
+            change.edit,
+            ProteinEdit::Substitution {
+                residue: Residue { amino_acid: AminoAcid(b'V'), position: 600 },
+                alt: AminoAcid(b'E'),
+            }
+        );
+        assert_eq!(change.to_full(), "p.(Val600Glu)");
+        assert_eq!(change.to_short(), "p.V600E");
+        assert_eq!(hgvsp_short("p.Trp24Ter"), "p.W24*");
+    }
+
+    #[test]
+    fn test_short_forms() {
+        assert_eq!(hgvsp_short("p.(Ser1982ArgfsTer22)"), "p.S1982Rfs*22");
+        assert_eq!(hgvsp_short("p.(Leu1590=)"), "p.L1590=");
+        assert_eq!(hgvsp_short("p.Lys23_Val25del"), "p.K23_V25del");
+        assert_eq!(hgvsp_short("p.Lys2_Met3insGlnSerVal"), "p.K2_M3insQSV");
+        assert_eq!(hgvsp_short("p.Cys28delinsTrpVal"), "p.C28delinsWV");
+        assert_eq!(hgvsp_short("p.Ter110GlnextTer17"), "p.*110Qext*17");
+        assert_eq!(hgvsp_short("p.Met1ext-5"), "p.M1ext-5");
+        assert_eq!(hgvsp_short("p.(Met1?)"), "p.M1?");
+        assert_eq!(hgvsp_short("p.?"), "p.?");
+    }
+
+    #[test]
+    fn test_invalid_notation() {
+        assert!(parse_hgvsp("c.1799T>A").is_err());
+        assert!(parse_hgvsp("p.Val600").is_err());
+        assert!(parse_hgvsp("p.Val0Glu").is_err());
+        assert!(parse_hgvsp("p.Lys2insGln").is_err());
+        // Unparseable values are passed through, not mangled
+        assert_eq!(hgvsp_short("NP_1.1:p.Val600Glu;Lys601Glu"), "p.Val600Glu;Lys601Glu");
+    }
+}
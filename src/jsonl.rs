@@ -0,0 +1,238 @@
+/// JSON Lines output: one object per included variant.
+///
+/// Unlike MAF, nothing is flattened or dropped. Each line holds the whole
+/// `VariantPosition` (every transcript, ClinVar and COSMIC entry, population
+/// frequency and score) and the assessments behind the decision. Nested
+/// Nirvana objects keep Nirvana's field names. Every line carries
+/// `schema_version`, which changes whenever a field is renamed or removed.
+use crate::output::{FileOptions, OutputFile};
+use crate::sort::{ExternalSorter, SortKey};
+use crate::transcript::select_default_index;
+use crate::types::{
+    ClinVarAssessment, FilterDecision, PredictiveAssessment, QualityFilterResult, VariantPosition,
+};
+use crate::writer::{OutputRecord, RecordSink, SinkState};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::io::Write;
+
+/// Version of the JSONL record layout.
+pub const JSONL_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct JsonlRecord<'a> {
+    schema_version: u32,
+    variant: &'a VariantPosition,
+    /// Index into `variant.transcripts` of the reported transcript
+    selected_transcript_index: Option<usize>,
+    decision: &'a FilterDecision,
+    quality: Option<&'a QualityFilterResult>,
+    clinvar: Option<&'a ClinVarAssessment>,
+    predictive: Option<&'a PredictiveAssessment>,
+    qc_flags: &'a [String],
+}
+
+impl<'a> JsonlRecord<'a> {
+    fn new(record: &'a OutputRecord) -> Self {
+        let context = record.context;
+        let variant = context.variant;
+        JsonlRecord {
+            schema_version: JSONL_SCHEMA_VERSION,
+            variant,
+            selected_transcript_index: variant
+                .selected_transcript_index
+                .or_else(|| select_default_index(&variant.transcripts)),
+            decision: record.decision,
+            quality: context.quality.as_ref(),
+            clinvar: context.clinvar.as_ref(),
+            predictive: context.predictive.as_ref(),
+            qc_flags: &context.flags,
+        }
+    }
+}
+
+pub struct JsonlWriter {
+    writer: OutputFile,
+    sorter: Option<ExternalSorter<String>>,
+    records_written: usize,
+}
+
+impl JsonlWriter {
+    pub fn create(output_path: &str, options: FileOptions) -> Result<Self> {
+        Ok(Self {
+            writer: OutputFile::create(output_path, options)?,
+            sorter: None,
+            records_written: 0,
+        })
+    }
+
+    /// Continues a JSONL file checkpointed with `state`.
+    pub fn resume(output_path: &str, state: SinkState, options: FileOptions) -> Result<Self> {
+        Ok(Self {
+            writer: OutputFile::resume(output_path, options, state.bytes)?,
+            sorter: None,
+            records_written: state.records,
+        })
+    }
+
+    /// With a chunk size, lines are written in genomic order on `finish`.
+    pub fn with_sort(mut self, chunk_size: Option<usize>) -> Self {
+        self.sorter = chunk_size.map(|n| ExternalSorter::for_output(n, self.writer.path()));
+        self
+    }
+}
+
+impl RecordSink for JsonlWriter {
+    fn write(&mut self, record: &OutputRecord) -> Result<()> {
+        match self.sorter.as_mut() {
+            Some(sorter) => {
+                let variant = record.variant();
+                let key = SortKey::new(
+                    &variant.chromosome,
+                    variant.start.into(),
+                    &variant.reference_allele,
+                    &variant.alternate_allele,
+                );
+                let line = serde_json::to_string(&JsonlRecord::new(record)).context("Failed to write JSONL record")?;
+                sorter.push(key, line)?;
+            }
+            None => {
+                serde_json::to_writer(&mut self.writer, &JsonlRecord::new(record))
+                    .context("Failed to write JSONL record")?;
+                self.writer.write_all(b"\n").context("Failed to write JSONL record")?;
+            }
+        }
+        self.records_written += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(sorter) = self.sorter.take() {
+            let out = &mut self.writer;
+            sorter.finish(|line| writeln!(out, "{}", line).context("Failed to write JSONL record"))?;
+        }
+        self.writer.commit()
+    }
+
+    fn records_written(&self) -> usize {
+        self.records_written
+    }
+
+    fn checkpoint(&mut self) -> Result<SinkState> {
+        if self.sorter.is_some() {
+            anyhow::bail!("sorted output can't be checkpointed");
+        }
+        Ok(SinkState {
+            bytes: self.writer.checkpoint()?,
+            records: self.records_written,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::variant_to_maf;
+    use crate::filters::{Pipeline, Verdict};
+    use crate::types::{ClinVarEntry, CosmicEntry, FilterConfig, FilterStats, TranscriptAnnotation};
+    use serde_json::Value;
+    use tempfile::TempDir;
+
+    fn transcript(id: &str, is_canonical: bool) -> TranscriptAnnotation {
+        TranscriptAnnotation {
+            id: Some(id.to_string()),
+            source: Some("RefSeq".to_string()),
+            hgnc: Some("TP53".to_string()),
+            consequence: vec!["stop_gained".to_string()],
+            impact: Some("HIGH".to_string()),
+            amino_acids: Some("R/*".to_string()),
+            cdna_pos: None,
+            cds_pos: Some("637".to_string()),
+            exons: Some("6/11".to_string()),
+            codons: None,
+            protein_pos: Some("213".to_string()),
+            hgvsc: Some(format!("{}:c.637C>T", id)),
+            hgvsp: Some(format!("{}:p.(Arg213Ter)", id)),
+            is_canonical: Some(is_canonical),
+            is_mane_select: None,
+            is_mane_plus_clinical: None,
+            bio_type: Some("protein_coding".to_string()),
+        }
+    }
+
+    fn create_test_variant() -> VariantPosition {
+        VariantPosition {
+            chromosome: "chr17".to_string(),
+            start: 7674945,
+            end_pos: 7674945,
+            reference_allele: "G".to_string(),
+            alternate_allele: "A".to_string(),
+            total_depth: Some(80),
+            variant_frequencies: Some(vec![0.4]),
+            genotype: Some("0/1".to_string()),
+            allele_depths: Some(vec![48, 32]),
+            transcripts: vec![transcript("NM_000546.6", true), transcript("NM_001126112.3", false)],
+            clinvar: vec![ClinVarEntry {
+                id: Some("RCV000013144".to_string()),
+                allele_id: None,
+                clinical_significance: vec!["pathogenic".to_string()],
+                review_status: Some("reviewed by expert panel".to_string()),
+                phenotypes: vec!["Li-Fraumeni syndrome".to_string()],
+                last_evaluated: None,
+            }],
+            cosmic: ["COSV52661038", "COSV52675917"]
+                .iter()
+                .map(|id| CosmicEntry {
+                    id: Some(id.to_string()),
+                    gene: Some("TP53".to_string()),
+                    mutation_type: None,
+                    count: None,
+                })
+                .collect(),
+            dbsnp_ids: vec!["rs397516436".to_string(), "rs587782705".to_string()],
+            ..VariantPosition::test_default()
+        }
+    }
+
+    #[test]
+    fn test_write_jsonl() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("out.jsonl");
+        let path = path.to_str().unwrap();
+
+        let variant = create_test_variant();
+        let config = FilterConfig::default();
+        let pipeline = Pipeline::from_config(&config)?;
+        let outcome = pipeline.run(&variant, &config, &mut FilterStats::default());
+        let Verdict::Included(decision) = &outcome.verdict else {
+            panic!("variant should be included");
+        };
+        let rows = vec![variant_to_maf(&variant, outcome.context.clinvar.as_ref())];
+
+        let mut writer = JsonlWriter::create(path, FileOptions::default())?;
+        writer.write(&OutputRecord {
+            context: &outcome.context,
+            decision,
+            rows: &rows,
+        })?;
+        writer.finish()?;
+        assert_eq!(writer.records_written(), 1);
+
+        let contents = std::fs::read_to_string(path)?;
+        assert_eq!(contents.lines().count(), 1);
+        let json: Value = serde_json::from_str(contents.trim_end())?;
+
+        assert_eq!(json["schema_version"], JSONL_SCHEMA_VERSION);
+        assert_eq!(json["selected_transcript_index"], 0);
+        // Everything MAF drops is kept
+        assert_eq!(json["variant"]["transcripts"].as_array().unwrap().len(), 2);
+        assert_eq!(json["variant"]["transcripts"][1]["transcript"], "NM_001126112.3");
+        assert_eq!(json["variant"]["cosmic"].as_array().unwrap().len(), 2);
+        assert_eq!(json["variant"]["dbsnp_ids"][1], "rs587782705");
+        assert_eq!(json["decision"]["primary_evidence"], "ClinVar");
+        assert_eq!(json["clinvar"]["selected_entry"]["id"], "RCV000013144");
+        assert_eq!(json["quality"]["passes_quality"], true);
+        assert!(json["predictive"].is_object());
+        Ok(())
+    }
+}
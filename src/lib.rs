@@ -1,13 +1,39 @@
+pub mod batch;
+pub mod bgzf;
+pub mod checkpoint;
+pub mod config;
+pub mod consequence;
+// The converter's `unwrap_or_else(|| "".to_string())` chains predate this lint
+#[allow(clippy::unwrap_or_default)]
 pub mod converter;
 pub mod filters;
+pub mod hgvs;
+pub mod jsonl;
 mod json_stream;
+pub mod maf_reader;
+pub mod merge;
+pub mod output;
+pub mod parquet_writer;
 pub mod parser;
+pub mod sample;
+pub mod sort;
 pub mod sv;
+pub mod transcript;
 pub mod types;
+pub mod vcf;
 pub mod writer;
 
+pub use checkpoint::{Checkpoint, InputFingerprint};
+pub use config::{ConfigFormat, RunConfig};
+pub use consequence::{Impact, VariantClassifier};
 pub use converter::*;
 pub use filters::*;
+pub use hgvs::{parse_hgvsp, ProteinChange, ProteinEdit};
+pub use maf_reader::{MafReader, MafRow, MafValues};
+pub use output::{FileOptions, OutputCompression, OutputFile};
 pub use parser::*;
+pub use sample::{BarcodeSettings, SampleSheet};
+pub use sort::{ExternalSorter, SortKey};
+pub use transcript::{PreferredTranscripts, TranscriptCriterion, TranscriptPolicy, TranscriptSource};
 pub use types::*;
 pub use writer::*;
@@ -0,0 +1,323 @@
+/// Streaming MAF reader.
+///
+/// Reads plain or gzip/BGZF-compressed MAFs (recognized by the gzip magic
+/// bytes, whatever the extension) and skips `#` lines such as
+/// `#version 2.4`. Any column set is accepted: the `MAF_COLUMNS` present
+/// are read into a `MAFRecord`, with absent ones left empty, and every
+/// other column is kept by name in `MafRow::extra`. Numeric columns are
+/// parsed into `MafValues` as each row is read, so a malformed depth or
+/// score is reported with its line number.
+///
+/// Files written by `MAFWriter` read back to the same `MAFRecord`s.
+use crate::types::{MAFRecord, MAF_COLUMNS};
+use anyhow::{Context, Result};
+use csv::StringRecord;
+use flate2::read::MultiGzDecoder;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Numeric MAF columns. Empty and `.` values are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MafValues {
+    pub depth: Option<i32>,
+    pub vaf: Option<f64>,
+    pub t_ref_count: Option<i32>,
+    pub t_alt_count: Option<i32>,
+    pub genotype_quality: Option<f64>,
+    pub gnomad_af: Option<f64>,
+    pub gnomad_eas_af: Option<f64>,
+    pub primate_ai_score: Option<f64>,
+    pub dann_score: Option<f64>,
+    pub revel_score: Option<f64>,
+}
+
+impl MafValues {
+    pub fn parse(record: &MAFRecord) -> Result<Self> {
+        Ok(Self {
+            depth: number("Depth", &record.depth)?,
+            vaf: number("VAF", &record.vaf)?,
+            t_ref_count: number("t_ref_count", &record.t_ref_count)?,
+            t_alt_count: number("t_alt_count", &record.t_alt_count)?,
+            genotype_quality: number("GQ", &record.genotype_quality)?,
+            gnomad_af: number("gnomAD_AF", &record.gnomad_af)?,
+            gnomad_eas_af: number("gnomAD_EAS_AF", &record.gnomad_eas_af)?,
+            primate_ai_score: number("PrimateAI_Score", &record.primate_ai_score)?,
+            dann_score: number("DANN_Score", &record.dann_score)?,
+            revel_score: number("REVEL_Score", &record.revel_score)?,
+        })
+    }
+}
+
+fn number<T: FromStr>(column: &str, value: &str) -> Result<Option<T>> {
+    match value.trim() {
+        "" | "." => Ok(None),
+        v => v
+            .parse()
+            .map(Some)
+            .map_err(|_| anyhow::anyhow!("invalid {} '{}'", column, value)),
+    }
+}
+
+/// One row read from a MAF.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MafRow {
+    pub record: MAFRecord,
+    pub values: MafValues,
+    /// Columns outside `MAF_COLUMNS`, by name
+    pub extra: BTreeMap<String, String>,
+}
+
+/// Opens `path` as tab-separated text, decompressing gzip/BGZF input and
+/// skipping `#` lines.
+pub(crate) fn open_tsv(path: &Path) -> Result<csv::Reader<Box<dyn Read>>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    tsv_reader(file).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+fn tsv_reader<R: Read + 'static>(reader: R) -> Result<csv::Reader<Box<dyn Read>>> {
+    let mut reader = BufReader::new(reader);
+    let reader: Box<dyn Read> = if reader.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        Box::new(MultiGzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    };
+    Ok(csv::ReaderBuilder::new()
+        .delimiter(b'\t')
+        .comment(Some(b'#'))
+        .from_reader(reader))
+}
+
+/// Reads `MafRow`s one at a time; also an `Iterator` of `Result<MafRow>`.
+pub struct MafReader {
+    reader: csv::Reader<Box<dyn Read>>,
+    headers: StringRecord,
+    /// Index and name of each column outside `MAF_COLUMNS`
+    extra_columns: Vec<(usize, String)>,
+    record: StringRecord,
+}
+
+impl MafReader {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        Self::from_csv(open_tsv(path)?).with_context(|| format!("Failed to read {}", path.display()))
+    }
+
+    /// Reads a MAF from any reader, e.g. stdin.
+    pub fn new<R: Read + 'static>(reader: R) -> Result<Self> {
+        Self::from_csv(tsv_reader(reader)?)
+    }
+
+    fn from_csv(mut reader: csv::Reader<Box<dyn Read>>) -> Result<Self> {
+        let headers = reader.headers().context("Failed to read the MAF header")?.clone();
+        let extra_columns = headers
+            .iter()
+            .enumerate()
+            .filter(|(_, name)| !MAF_COLUMNS.contains(name))
+            .map(|(i, name)| (i, name.to_string()))
+            .collect();
+        Ok(Self {
+            reader,
+            headers,
+            extra_columns,
+            record: StringRecord::new(),
+        })
+    }
+
+    /// The file's columns, in file order.
+    pub fn columns(&self) -> impl Iterator<Item = &str> {
+        self.headers.iter()
+    }
+
+    /// The next row, or `None` at the end of the file.
+    pub fn read_row(&mut self) -> Result<Option<MafRow>> {
+        if !self.reader.read_record(&mut self.record).context("Failed to read MAF row")? {
+            return Ok(None);
+        }
+        let line = self.record.position().map(|p| p.line()).unwrap_or_default();
+        let record: MAFRecord = self
+            .record
+            .deserialize(Some(&self.headers))
+            .with_context(|| format!("Invalid MAF row at line {}", line))?;
+        let values = MafValues::parse(&record).with_context(|| format!("Invalid MAF row at line {}", line))?;
+        let extra = self
+            .extra_columns
+            .iter()
+            .map(|(i, name)| (name.clone(), self.record.get(*i).unwrap_or_default().to_string()))
+            .collect();
+        Ok(Some(MafRow { record, values, extra }))
+    }
+}
+
+impl Iterator for MafReader {
+    type Item = Result<MafRow>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_row().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{FileOptions, OutputCompression};
+    use crate::writer::MAFWriter;
+    use proptest::prelude::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn write_and_read(records: &[MAFRecord], compression: OutputCompression) -> Result<Vec<MafRow>> {
+        let dir = TempDir::new()?;
+        let path = dir.path().join("test.maf");
+        let options = FileOptions {
+            compression,
+            ..FileOptions::default()
+        };
+        let mut writer = MAFWriter::with_options(path.to_str().unwrap(), options)?;
+        for record in records {
+            writer.write_record(record)?;
+        }
+        writer.finish()?;
+        MafReader::open(&path)?.collect()
+    }
+
+    #[test]
+    fn test_maf_columns_match_writer() -> Result<()> {
+        let dir = TempDir::new()?;
+        let path = dir.path().join("test.maf");
+        let mut writer = MAFWriter::new(path.to_str().unwrap())?;
+        writer.write_record(&MAFRecord::default())?;
+        writer.finish()?;
+        let reader = MafReader::open(&path)?;
+        assert_eq!(reader.columns().collect::<Vec<_>>(), MAF_COLUMNS);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_foreign_maf() -> Result<()> {
+        let dir = TempDir::new()?;
+        let path = dir.path().join("other.maf");
+        fs::write(
+            &path,
+            "#version 2.4\n#comment\nHugo_Symbol\tChromosome\tStart_Position\tt_depth\tVAF\tREVEL_Score\n\
+             BRAF\tchr7\t140453136\t250\t0.4500\t.\n",
+        )?;
+        let rows: Vec<MafRow> = MafReader::open(&path)?.collect::<Result<_>>()?;
+        assert_eq!(rows.len(), 1);
+        let row = &rows[0];
+        assert_eq!(row.record.hugo_symbol, "BRAF");
+        assert_eq!(row.record.start_position, 140453136);
+        assert_eq!(row.record.transcript_id, "");
+        assert_eq!(row.values.vaf, Some(0.45));
+        assert_eq!(row.values.revel_score, None);
+        assert_eq!(row.extra.get("t_depth").map(String::as_str), Some("250"));
+
+        fs::write(&path, "Chromosome\tDepth\nchr1\tdeep\n")?;
+        let err = MafReader::open(&path)?.next().unwrap().unwrap_err();
+        assert!(format!("{:#}", err).contains("invalid Depth 'deep'"));
+        Ok(())
+    }
+
+    /// Any text, weighted towards the characters TSV quoting must handle.
+    fn text() -> impl Strategy<Value = String> {
+        prop_oneof!["[\\PC\t\n]{0,8}", "#?[#\" \t\nA]{0,4}"]
+    }
+
+    fn arb_record() -> impl Strategy<Value = MAFRecord> {
+        (proptest::collection::vec(text(), MAF_COLUMNS.len() - 2), any::<i32>(), any::<i32>()).prop_map(
+            |(fields, start_position, end_position)| {
+                let mut fields = fields.into_iter();
+                let mut next = || fields.next().unwrap_or_default();
+                MAFRecord {
+                    hugo_symbol: next(),
+                    chromosome: next(),
+                    start_position,
+                    end_position,
+                    strand: next(),
+                    variant_classification: next(),
+                    variant_type: next(),
+                    reference_allele: next(),
+                    tumor_seq_allele1: next(),
+                    tumor_seq_allele2: next(),
+                    tumor_sample_barcode: next(),
+                    matched_norm_sample_barcode: next(),
+                    hgvsc: next(),
+                    hgvsp: next(),
+                    hgvsp_short: next(),
+                    transcript_id: next(),
+                    is_selected_transcript: next(),
+                    exon: next(),
+                    consequence: next(),
+                    impact: next(),
+                    codons: next(),
+                    amino_acids: next(),
+                    cdna_position: next(),
+                    cds_position: next(),
+                    protein_position: next(),
+                    dbsnp_rs: next(),
+                    dbsnp_val_status: next(),
+                    cosmic_id: next(),
+                    clinvar_id: next(),
+                    clinvar_review_status: next(),
+                    clinvar_significance: next(),
+                    clinvar_disease: next(),
+                    clinvar_all: next(),
+                    primate_ai_score: String::new(),
+                    primate_ai_transcript: next(),
+                    dann_score: String::new(),
+                    revel_score: String::new(),
+                    predictive_evidence: next(),
+                    gnomad_af: String::new(),
+                    gnomad_eas_af: String::new(),
+                    depth: String::new(),
+                    vaf: String::new(),
+                    t_ref_count: String::new(),
+                    t_alt_count: String::new(),
+                    genotype: next(),
+                    genotype_quality: String::new(),
+                    qc_flags: next(),
+                    all_effects: next(),
+                }
+            },
+        )
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn prop_round_trip(records in proptest::collection::vec(arb_record(), 0..4), gzip in any::<bool>()) {
+            let compression = if gzip { OutputCompression::Gzip } else { OutputCompression::None };
+            let rows = write_and_read(&records, compression).unwrap();
+            let read: Vec<MAFRecord> = rows.into_iter().map(|r| r.record).collect();
+            prop_assert_eq!(read, records);
+        }
+
+        #[test]
+        fn prop_numeric_round_trip(
+            depth in proptest::option::of(0..100_000i32),
+            vaf in proptest::option::of(0.0..=1.0f64),
+            gq in proptest::option::of(0..100i32),
+        ) {
+            // Formatted as the converter writes them
+            let record = MAFRecord {
+                depth: depth.map(|d| d.to_string()).unwrap_or_default(),
+                vaf: vaf.map(|v| format!("{:.4}", v)).unwrap_or_default(),
+                revel_score: vaf.map(|v| format!("{:.4}", v)).unwrap_or_default(),
+                genotype_quality: gq.map(|g| f64::from(g).to_string()).unwrap_or_default(),
+                ..MAFRecord::default()
+            };
+            let rows = write_and_read(&[record], OutputCompression::None).unwrap();
+            let values = &rows[0].values;
+            prop_assert_eq!(values.depth, depth);
+            prop_assert_eq!(values.genotype_quality, gq.map(f64::from));
+            prop_assert_eq!(values.vaf.is_some(), vaf.is_some());
+            if let (Some(read), Some(written)) = (values.vaf, vaf) {
+                prop_assert!((read - written).abs() <= 5e-5);
+            }
+            prop_assert_eq!(values.revel_score, values.vaf);
+        }
+    }
+}
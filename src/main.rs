@@ -1,57 +1,308 @@
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use json2maf::batch::{format_summary_table, load_manifest, write_summary, ManifestEntry, SampleOutcome};
+use json2maf::checkpoint::{checkpoint_path, settings_digest, CHECKPOINT_VERSION};
+use json2maf::config::RuntimeConfig;
+use json2maf::merge::{expand_inputs, merge_maf_files, MergeOptions};
+use json2maf::output::is_committed;
 use indicatif::{ProgressBar, ProgressStyle};
 use json2maf::*;
 use rayon::prelude::*;
-use std::fs;
-use std::path::Path;
+use std::cell::RefCell;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
 #[derive(Parser, Debug)]
 #[command(name = "json2maf")]
 #[command(author = "JSON2MAF Contributors")]
 #[command(version = "0.4.0")]
 #[command(about = "Pathogenic variant filtering tool for Nirvana JSON", long_about = None)]
+#[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Input Nirvana JSON.gz file path
-    #[arg(short, long)]
-    input: String,
+    #[arg(short, long, required_unless_present_any = ["dump_config", "list_presets"])]
+    input: Option<String>,
+
+    /// Output file path (MAF unless --output-format says otherwise)
+    #[arg(short, long, required_unless_present_any = ["dump_config", "list_presets"])]
+    output: Option<String>,
 
-    /// Output MAF file path
+    /// Run configuration file (TOML, or YAML with a .yaml/.yml extension).
+    /// Command-line flags override values from the file
     #[arg(short, long)]
-    output: String,
+    config: Option<String>,
+
+    /// Start from a built-in preset (see --list-presets); overrides the
+    /// file's `preset` key
+    #[arg(long)]
+    preset: Option<String>,
+
+    /// Print the fully resolved configuration (toml or yaml) and exit
+    #[arg(long, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "toml")]
+    dump_config: Option<ConfigFormat>,
+
+    /// List the built-in presets and exit
+    #[arg(long)]
+    list_presets: bool,
+
+    /// VCF FILTER tags to accept (comma-separated glob patterns) [default: PASS]
+    #[arg(long, value_delimiter = ',')]
+    accept_filters: Option<Vec<String>>,
+
+    /// VCF FILTER tags to reject even if accepted (comma-separated glob patterns)
+    #[arg(long, value_delimiter = ',')]
+    reject_filters: Option<Vec<String>>,
+
+    /// Treat variants with no FILTER value as failing
+    #[arg(long, overrides_with = "no_fail_missing_filters")]
+    fail_missing_filters: bool,
+
+    /// Count variants without a FILTER value as PASS, and pass samples missing a threshold's field
+    #[arg(long, overrides_with = "fail_missing_filters")]
+    no_fail_missing_filters: bool,
+
+    /// Minimum sequencing depth [default: 30]
+    #[arg(long)]
+    min_depth: Option<i32>,
+
+    /// Minimum variant allele frequency VAF [default: 0.03]
+    #[arg(long)]
+    min_vaf: Option<f64>,
+
+    /// How to treat variants without depth/VAF sample data, or without the
+    /// field a configured per-sample threshold needs: pass, fail or flag
+    /// [default: pass]
+    #[arg(long)]
+    missing_sample_data: Option<MissingDataPolicy>,
+
+    /// Minimum reads supporting the alternate allele (from alleleDepths)
+    #[arg(long, overrides_with = "no_min_alt_reads")]
+    min_alt_reads: Option<i32>,
+
+    /// Turn off the alternate read count threshold
+    #[arg(long, overrides_with = "min_alt_reads")]
+    no_min_alt_reads: bool,
+
+    /// Minimum genotype quality (GQ)
+    #[arg(long, overrides_with = "no_min_gq")]
+    min_gq: Option<f64>,
+
+    /// Turn off the genotype quality threshold
+    #[arg(long, overrides_with = "min_gq")]
+    no_min_gq: bool,
+
+    /// Maximum strand bias score
+    #[arg(long, overrides_with = "no_max_strand_bias")]
+    max_strand_bias: Option<f64>,
+
+    /// Turn off the strand bias threshold
+    #[arg(long, overrides_with = "max_strand_bias")]
+    no_max_strand_bias: bool,
+
+    /// Minimum somatic quality score
+    #[arg(long, overrides_with = "no_min_somatic_quality")]
+    min_somatic_quality: Option<f64>,
+
+    /// Turn off the somatic quality threshold
+    #[arg(long, overrides_with = "min_somatic_quality")]
+    no_min_somatic_quality: bool,
+
+    /// Maximum East Asian population allele frequency [default: 0.01]
+    #[arg(long)]
+    max_eas_af: Option<f64>,
+
+    /// How to treat variants without population allele frequency: pass, fail or flag [default: pass]
+    #[arg(long)]
+    missing_population_af: Option<MissingDataPolicy>,
+
+    /// Only keep variants with one of these SO consequences (comma-separated)
+    #[arg(long, value_delimiter = ',')]
+    include_consequences: Option<Vec<String>>,
+
+    /// Drop variants whose consequences are all in this list (comma-separated)
+    #[arg(long, value_delimiter = ',')]
+    exclude_consequences: Option<Vec<String>>,
+
+    /// Minimum VEP impact: HIGH, MODERATE, LOW or MODIFIER
+    #[arg(long, overrides_with = "no_min_impact")]
+    min_impact: Option<Impact>,
+
+    /// Turn off the impact threshold
+    #[arg(long, overrides_with = "min_impact")]
+    no_min_impact: bool,
+
+    /// Apply consequence/impact filters to any transcript instead of the selected one
+    #[arg(long, overrides_with = "no_any_transcript")]
+    any_transcript: bool,
+
+    /// Apply consequence/impact filters to the selected transcript only
+    #[arg(long, overrides_with = "any_transcript")]
+    no_any_transcript: bool,
+
+    /// Transcript selection chain, comma-separated: preferred, mane_select,
+    /// mane_plus_clinical, canonical, source, most_severe, longest_cds,
+    /// has_hgvsc [default: preferred,mane_select,source]
+    #[arg(long, value_delimiter = ',')]
+    transcript_policy: Option<Vec<TranscriptCriterion>>,
+
+    /// Transcript source used by the 'source' step: refseq or ensembl
+    #[arg(long)]
+    transcript_source: Option<TranscriptSource>,
+
+    /// File of GENE<TAB>ACCESSION lines with the lab's approved transcripts
+    #[arg(long, value_name = "FILE")]
+    preferred_transcripts: Option<String>,
+
+    /// REVEL score threshold [default: 0.75]
+    #[arg(long)]
+    min_revel: Option<f64>,
+
+    /// PrimateAI-3D score threshold [default: 0.8]
+    #[arg(long)]
+    min_primate_ai: Option<f64>,
+
+    /// DANN score threshold [default: 0.96]
+    #[arg(long)]
+    min_dann: Option<f64>,
+
+    /// Which transcript's PrimateAI-3D score to use: selected (the output
+    /// transcript, or its gene) or max (across all transcripts) [default: selected]
+    #[arg(long)]
+    score_transcript: Option<ScoreTranscriptPolicy>,
 
-    /// Minimum sequencing depth
-    #[arg(long, default_value_t = 30)]
-    min_depth: i32,
+    /// How predictive scores are combined: votes, weighted or calibrated [default: votes]
+    #[arg(long)]
+    predictive_model: Option<PredictiveModel>,
+
+    /// Weight of a score for the weighted model, as NAME=WEIGHT with NAME one
+    /// of PrimateAI-3D, REVEL, DANN, COSMIC (repeatable)
+    #[arg(long = "score-weight", value_name = "NAME=WEIGHT", value_parser = parse_score_weight)]
+    score_weights: Vec<(String, f64)>,
 
-    /// Minimum variant allele frequency VAF
-    #[arg(long, default_value_t = 0.03)]
-    min_vaf: f64,
+    /// Total weight needed to suggest pathogenicity in the weighted model [default: 2]
+    #[arg(long)]
+    min_predictive_weight: Option<f64>,
 
-    /// Maximum East Asian population allele frequency
-    #[arg(long, default_value_t = 0.01)]
-    max_eas_af: f64,
+    /// Weakest PP3 strength that suggests pathogenicity in the calibrated
+    /// model: supporting, moderate or strong [default: supporting]
+    #[arg(long)]
+    min_evidence_strength: Option<EvidenceStrength>,
 
-    /// REVEL score threshold
-    #[arg(long, default_value_t = 0.75)]
-    min_revel: f64,
+    /// Include high-confidence loss-of-function variants (stop_gained,
+    /// frameshift, canonical splice) in the selected transcript as evidence
+    #[arg(long, overrides_with = "no_lof_rescue")]
+    lof_rescue: bool,
 
-    /// PrimateAI-3D score threshold
-    #[arg(long, default_value_t = 0.8)]
-    min_primate_ai: f64,
+    /// Don't use loss-of-function as evidence
+    #[arg(long, overrides_with = "lof_rescue")]
+    no_lof_rescue: bool,
 
-    /// DANN score threshold
-    #[arg(long, default_value_t = 0.96)]
-    min_dann: f64,
+    /// Truncations past this fraction of the CDS are not rescued as LoF [default: 0.95]
+    #[arg(long)]
+    lof_max_cds_fraction: Option<f64>,
 
-    /// Keep temporary files
+    /// Keep the temporary output files if the run fails (outputs are written
+    /// to hidden temporary files and renamed into place on success)
     #[arg(long)]
     keep_temp: bool,
 
+    /// Output compression: auto (BGZF for paths ending in .gz), none, gzip
+    /// or bgzf [default: auto]
+    #[arg(long, value_name = "METHOD")]
+    compress: Option<OutputCompression>,
+
+    /// Save progress to OUTPUT.checkpoint as the run goes, and resume from
+    /// it when rerun after an interruption (unsorted MAF, VCF or JSONL;
+    /// not gzip-compressed)
+    #[arg(long)]
+    checkpoint: bool,
+
+    /// Seconds between checkpoints [default: 60]
+    #[arg(long, value_name = "SECONDS", requires = "checkpoint")]
+    checkpoint_interval: Option<u64>,
+
     /// Statistics report output path
     #[arg(long)]
     stats: Option<String>,
 
+    /// Output format: maf, vcf, vcf.gz (BGZF-compressed VCF), jsonl or
+    /// parquet [default: maf]
+    #[arg(long, value_name = "FORMAT")]
+    output_format: Option<OutputFormat>,
+
+    /// Write records in karyotypic order (chr1..chr22, X, Y, M, then other
+    /// contigs), by position and allele, using a bounded-memory external sort
+    #[arg(long, overrides_with = "no_sort")]
+    sort: bool,
+
+    /// Write records in input order
+    #[arg(long, overrides_with = "sort")]
+    no_sort: bool,
+
+    /// Write one MAF row per transcript, flagged by Is_Selected_Transcript
+    #[arg(long, overrides_with = "no_all_transcripts")]
+    all_transcripts: bool,
+
+    /// Write only the selected transcript's row
+    #[arg(long, overrides_with = "all_transcripts")]
+    no_all_transcripts: bool,
+
+    /// With --all-transcripts, only write transcripts from this source
+    /// (refseq or ensembl); the selected transcript is always written
+    #[arg(long, value_name = "SOURCE")]
+    all_transcripts_source: Option<TranscriptSource>,
+
+    /// With --all-transcripts, only write protein-coding transcripts
+    #[arg(long, overrides_with = "no_protein_coding_only")]
+    protein_coding_only: bool,
+
+    /// With --all-transcripts, write transcripts of every biotype
+    #[arg(long, overrides_with = "protein_coding_only")]
+    no_protein_coding_only: bool,
+
+    /// Fill the all_effects column with every transcript's effect
+    #[arg(long, overrides_with = "no_all_effects")]
+    all_effects: bool,
+
+    /// Leave the all_effects column empty
+    #[arg(long, overrides_with = "all_effects")]
+    no_all_effects: bool,
+
+    /// Fill the ClinVar_All column with every ClinVar record and its review stars
+    #[arg(long, overrides_with = "no_clinvar_all")]
+    clinvar_all: bool,
+
+    /// Leave the ClinVar_All column empty
+    #[arg(long, overrides_with = "clinvar_all")]
+    no_clinvar_all: bool,
+
+    /// Override the Variant_Classification of an SO term, as TERM=CLASS
+    /// (e.g. splice_region_variant=Intron; repeatable)
+    #[arg(long = "variant-classification", value_name = "TERM=CLASS", value_parser = parse_classification)]
+    variant_classifications: Vec<(String, String)>,
+
+    /// Write rejected variants (with stage, reason and QC flags) to this TSV
+    /// (Parquet with --output-format parquet)
+    #[arg(long)]
+    rejected_output: Option<String>,
+
+    /// Tumor_Sample_Barcode [default: the reported sample's name from the Nirvana header]
+    #[arg(long)]
+    tumor_barcode: Option<String>,
+
+    /// Matched_Norm_Sample_Barcode [default: the second sample's name from the Nirvana header, if any]
+    #[arg(long)]
+    normal_barcode: Option<String>,
+
+    /// SAMPLE<TAB>BARCODE file mapping Nirvana sample names to LIMS IDs
+    #[arg(long)]
+    sample_sheet: Option<String>,
+
     /// Verbose output mode
     #[arg(short, long)]
     verbose: bool,
@@ -65,14 +316,387 @@ struct Args {
     threads: Option<usize>,
 
     /// Exclude benign and likely benign variants
-    #[arg(long)]
+    #[arg(long, overrides_with = "no_exclude_benign")]
     exclude_benign: bool,
 
+    /// Keep benign and likely benign variants
+    #[arg(long, overrides_with = "exclude_benign")]
+    no_exclude_benign: bool,
+
+    /// Include variants matching this filter expression even if the decision
+    /// engine excluded them, e.g. 'revel >= 0.7 && consequence ~ "missense"'
+    /// (repeatable; any match includes)
+    #[arg(long = "include", value_name = "EXPR")]
+    include_rules: Vec<String>,
+
+    /// Exclude variants matching this filter expression, e.g.
+    /// 'gene in ["TTN","MUC16"]' (repeatable; any match excludes)
+    #[arg(long = "exclude", value_name = "EXPR")]
+    exclude_rules: Vec<String>,
+
+    /// Pipeline stages to run, in order (comma-separated; any of quality,
+    /// consequence, clinvar, predictive, lof, decision) [default: all, in that order]
+    #[arg(long, value_delimiter = ',')]
+    stages: Option<Vec<String>>,
+
     /// Number of variants held in memory per parallel filtering batch.
     /// Lower this on machines with limited RAM to reduce peak memory usage
-    /// at the cost of somewhat less parallelism.
+    /// at the cost of somewhat less parallelism. [default: 20000]
+    #[arg(long)]
+    batch_size: Option<usize>,
+}
+
+impl Args {
+    /// Overlays the flags that were given on the command line onto the
+    /// configuration resolved from defaults, preset and config file.
+    fn apply_overrides(&self, run: &mut RunConfig) {
+        let filter = &mut run.filter;
+
+        fn set<T: Clone>(target: &mut T, value: &Option<T>) {
+            if let Some(v) = value {
+                *target = v.clone();
+            }
+        }
+
+        // An optional threshold is set by `--NAME VALUE` and cleared by
+        // `--no-NAME`; the later of the two wins
+        fn set_optional<T: Clone>(target: &mut Option<T>, value: &Option<T>, clear: bool) {
+            if clear {
+                *target = None;
+            } else if value.is_some() {
+                *target = value.clone();
+            }
+        }
+
+        // A switch is turned on by `--NAME` and off by `--no-NAME`; `None`
+        // if neither was given
+        fn switch(on: bool, off: bool) -> Option<bool> {
+            (on || off).then_some(on)
+        }
+
+        set(&mut filter.accept_filters, &self.accept_filters);
+        set(&mut filter.reject_filters, &self.reject_filters);
+        set(&mut filter.min_total_depth, &self.min_depth);
+        set(&mut filter.min_variant_frequency, &self.min_vaf);
+        set(&mut filter.missing_sample_data, &self.missing_sample_data);
+        set(&mut filter.max_eas_af, &self.max_eas_af);
+        set(&mut filter.missing_population_af, &self.missing_population_af);
+        set(&mut filter.include_consequences, &self.include_consequences);
+        set(&mut filter.exclude_consequences, &self.exclude_consequences);
+        set(&mut filter.min_revel_score, &self.min_revel);
+        set(&mut filter.min_primate_ai_score, &self.min_primate_ai);
+        set(&mut filter.min_dann_score, &self.min_dann);
+        set(&mut filter.transcript_policy, &self.transcript_policy);
+        set(&mut filter.score_transcript_policy, &self.score_transcript);
+        set(&mut filter.predictive_model, &self.predictive_model);
+        set(&mut filter.min_predictive_weight, &self.min_predictive_weight);
+        set(&mut filter.min_evidence_strength, &self.min_evidence_strength);
+        set(&mut filter.lof_max_cds_fraction, &self.lof_max_cds_fraction);
+        set(&mut filter.stages, &self.stages);
+
+        set_optional(&mut filter.min_alt_reads, &self.min_alt_reads, self.no_min_alt_reads);
+        set_optional(&mut filter.min_genotype_quality, &self.min_gq, self.no_min_gq);
+        set_optional(&mut filter.max_strand_bias, &self.max_strand_bias, self.no_max_strand_bias);
+        set_optional(&mut filter.min_somatic_quality, &self.min_somatic_quality, self.no_min_somatic_quality);
+        set_optional(&mut filter.min_impact, &self.min_impact, self.no_min_impact);
+        if self.transcript_source.is_some() {
+            filter.transcript_source = self.transcript_source;
+        }
+        if self.preferred_transcripts.is_some() {
+            filter.preferred_transcripts = self.preferred_transcripts.clone();
+        }
+
+        set(&mut filter.fail_missing_filters, &switch(self.fail_missing_filters, self.no_fail_missing_filters));
+        set(&mut filter.enable_lof_rescue, &switch(self.lof_rescue, self.no_lof_rescue));
+        set(&mut filter.exclude_benign, &switch(self.exclude_benign, self.no_exclude_benign));
+        if let Some(any) = switch(self.any_transcript, self.no_any_transcript) {
+            filter.consequence_scope = if any {
+                ConsequenceScope::AnyTranscript
+            } else {
+                ConsequenceScope::SelectedTranscript
+            };
+        }
+
+        // Individual weights adjust the configured table
+        for (name, weight) in &self.score_weights {
+            filter.score_weights.insert(name.clone(), *weight);
+        }
+
+        if !self.include_rules.is_empty() {
+            filter.include_rules = self.include_rules.clone();
+        }
+        if !self.exclude_rules.is_empty() {
+            filter.exclude_rules = self.exclude_rules.clone();
+        }
+
+        if self.stats.is_some() {
+            run.output.stats = self.stats.clone();
+        }
+        set(&mut run.output.format, &self.output_format);
+        set(&mut run.output.compression, &self.compress);
+        set(&mut run.output.sort, &switch(self.sort, self.no_sort));
+        if self.rejected_output.is_some() {
+            run.output.rejected_output = self.rejected_output.clone();
+        }
+        if self.tumor_barcode.is_some() {
+            run.output.tumor_barcode = self.tumor_barcode.clone();
+        }
+        if self.normal_barcode.is_some() {
+            run.output.normal_barcode = self.normal_barcode.clone();
+        }
+        if self.sample_sheet.is_some() {
+            run.output.sample_sheet = self.sample_sheet.clone();
+        }
+        let transcripts = &mut run.output.transcripts;
+        set(&mut transcripts.all_transcripts, &switch(self.all_transcripts, self.no_all_transcripts));
+        set(&mut transcripts.protein_coding_only, &switch(self.protein_coding_only, self.no_protein_coding_only));
+        set(&mut transcripts.all_effects, &switch(self.all_effects, self.no_all_effects));
+        if self.all_transcripts_source.is_some() {
+            transcripts.source = self.all_transcripts_source;
+        }
+        set(&mut run.output.clinvar_all, &switch(self.clinvar_all, self.no_clinvar_all));
+        for (term, class) in &self.variant_classifications {
+            run.output.variant_classification.insert(term.clone(), class.clone());
+        }
+
+        if self.threads.is_some() {
+            run.runtime.threads = self.threads;
+        }
+        if let Some(batch_size) = self.batch_size {
+            run.runtime.batch_size = batch_size;
+        }
+    }
+}
+
+fn parse_score_weight(s: &str) -> Result<(String, f64)> {
+    let (name, weight) = s
+        .split_once('=')
+        .with_context(|| format!("expected NAME=WEIGHT, got '{}'", s))?;
+    let weight = weight
+        .trim()
+        .parse()
+        .with_context(|| format!("invalid weight '{}' for {}", weight, name))?;
+    Ok((name.trim().to_string(), weight))
+}
+
+fn parse_classification(s: &str) -> Result<(String, String)> {
+    let (term, class) = s
+        .split_once('=')
+        .with_context(|| format!("expected TERM=CLASS, got '{}'", s))?;
+    Ok((term.trim().to_string(), class.trim().to_string()))
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Merge MAF files into one cohort MAF
+    Merge(MergeArgs),
+    /// Process every sample of a manifest with one shared configuration
+    Batch(BatchArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct MergeArgs {
+    /// Input MAFs or quoted glob patterns (e.g. 'results/*.maf.gz'); a
+    /// missing file or a pattern matching nothing is an error
+    #[arg(required = true, value_name = "MAF")]
+    inputs: Vec<String>,
+
+    /// Merged MAF output path
+    #[arg(short, long)]
+    output: String,
+
+    /// Drop rows repeating an earlier row's sample, locus, alleles and
+    /// transcript. Without --sort, every distinct row's key is held in
+    /// memory
+    #[arg(long)]
+    dedup: bool,
+
+    /// Fill empty Tumor_Sample_Barcode values from each input's file name
+    #[arg(long)]
+    barcode_from_filename: bool,
+
+    /// Write rows in karyotypic order
+    #[arg(long)]
+    sort: bool,
+
+    /// Rows held in memory per sort chunk
     #[arg(long, default_value_t = 20_000)]
     batch_size: usize,
+
+    /// Output compression: auto (BGZF for paths ending in .gz), none, gzip
+    /// or bgzf
+    #[arg(long, value_name = "METHOD", default_value_t = OutputCompression::Auto)]
+    compress: OutputCompression,
+
+    /// Keep the temporary output file if the merge fails
+    #[arg(long)]
+    keep_temp: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct BatchArgs {
+    /// Sample manifest: TSV with sample_id, input, output and optional
+    /// normal_id columns, named in a header row
+    #[arg(short, long)]
+    manifest: String,
+
+    /// Run configuration file shared by every sample
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// Start from a built-in preset (see --list-presets)
+    #[arg(long)]
+    preset: Option<String>,
+
+    /// Filtering threads shared by all samples (defaults to number of CPU cores)
+    #[arg(short = 'j', long)]
+    threads: Option<usize>,
+
+    /// Samples read at once. Each holds up to the configured batch_size
+    /// variants in memory, so peak memory grows with jobs × batch_size
+    /// [default: a quarter of the filtering threads, at least 1]
+    #[arg(long)]
+    jobs: Option<usize>,
+
+    /// Directory for the per-sample statistics reports (SAMPLE.stats.txt)
+    /// [default: each sample's output directory]
+    #[arg(long)]
+    stats_dir: Option<String>,
+
+    /// Write the combined summary table to this file as TSV
+    #[arg(long)]
+    summary: Option<String>,
+
+    /// Keep the temporary output files of failed samples
+    #[arg(long)]
+    keep_temp: bool,
+
+    /// Checkpoint every sample, so a rerun of the batch resumes
+    /// interrupted samples (see json2maf --checkpoint)
+    #[arg(long)]
+    checkpoint: bool,
+
+    /// Seconds between checkpoints [default: 60]
+    #[arg(long, value_name = "SECONDS", requires = "checkpoint")]
+    checkpoint_interval: Option<u64>,
+}
+
+/// Run-level switches that don't affect filtering decisions.
+struct RunOptions {
+    verbose: bool,
+    quiet: bool,
+    files: FileOptions,
+    batch_size: usize,
+    format: OutputFormat,
+    sort: bool,
+    maf: MafOptions,
+    barcodes: BarcodeSettings,
+    checkpoint: Option<CheckpointOptions>,
+}
+
+const DEFAULT_CHECKPOINT_INTERVAL: u64 = 60;
+
+/// How a `--checkpoint` run saves its progress.
+struct CheckpointOptions {
+    interval: Duration,
+    /// `settings_digest` of everything that shapes the output
+    settings: String,
+}
+
+impl CheckpointOptions {
+    /// `sample` adds what the configuration doesn't say about the output,
+    /// such as a batch sample's barcodes.
+    fn new(run_config: &RunConfig, interval: Option<u64>, sample: &str) -> Result<Self> {
+        // Threads and batch size don't change the output, and the resumed
+        // run may well be on a different machine
+        let settings = RunConfig {
+            runtime: RuntimeConfig::default(),
+            ..run_config.clone()
+        };
+        let text = format!("{}\n{}", settings.render(ConfigFormat::Toml)?, sample);
+        Ok(Self {
+            interval: Duration::from_secs(interval.unwrap_or(DEFAULT_CHECKPOINT_INTERVAL)),
+            settings: settings_digest(&text),
+        })
+    }
+}
+
+/// Saves a run's checkpoints and holds the one it resumed from.
+struct Checkpointer {
+    path: PathBuf,
+    state: Checkpoint,
+    resumed: bool,
+    interval: Duration,
+    last_saved: Instant,
+}
+
+impl Checkpointer {
+    /// Fingerprints the input and loads the output's checkpoint, if any,
+    /// checking that it belongs to this input and these settings.
+    fn open(input_path: &str, output_path: &str, options: &CheckpointOptions) -> Result<Self> {
+        let path = checkpoint_path(Path::new(output_path));
+        let input = InputFingerprint::of(Path::new(input_path))?;
+        let saved = Checkpoint::load(&path)?;
+        if let Some(saved) = &saved {
+            saved
+                .verify(&input, &options.settings)
+                .with_context(|| format!("in {}", path.display()))?;
+        }
+        Ok(Self {
+            path,
+            resumed: saved.is_some(),
+            state: saved.unwrap_or(Checkpoint {
+                version: CHECKPOINT_VERSION,
+                input,
+                settings: options.settings.clone(),
+                positions: 0,
+                variants: 0,
+                output: SinkState::default(),
+                rejected: None,
+                stats: FilterStats::default(),
+                finished: false,
+            }),
+            interval: options.interval,
+            last_saved: Instant::now(),
+        })
+    }
+
+    fn due(&self) -> bool {
+        self.last_saved.elapsed() >= self.interval
+    }
+
+    /// Records the progress so far. A report that is already committed
+    /// (`rejected` is `None` on a resumed run) keeps its saved length.
+    fn save(
+        &mut self,
+        positions: u64,
+        variants: u64,
+        sink: &mut dyn RecordSink,
+        rejected: Option<&mut RejectedOutput>,
+        stats: &FilterStats,
+    ) -> Result<()> {
+        let state = &mut self.state;
+        state.positions = positions;
+        state.variants = variants;
+        state.output = sink.checkpoint()?;
+        if let Some(rejected) = rejected {
+            state.rejected = Some(rejected.checkpoint()?);
+        }
+        state.stats = stats.clone();
+        state.save(&self.path)?;
+        self.last_saved = Instant::now();
+        Ok(())
+    }
+
+    /// Removes the checkpoint once every output is committed.
+    fn remove(&self) -> Result<()> {
+        match std::fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove checkpoint {}", self.path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 fn main() -> Result<()> {
@@ -82,57 +706,95 @@ fn main() -> Result<()> {
 
     let args = Args::parse();
 
-    // Set thread pool size
-    if let Some(threads) = args.threads {
-        rayon::ThreadPoolBuilder::new()
-            .num_threads(threads)
-            .build_global()
-            .context("Failed to set thread pool size")?;
+    match &args.command {
+        Some(Command::Merge(merge)) => return run_merge(merge),
+        Some(Command::Batch(batch)) => return run_batch(batch),
+        None => {}
     }
 
-    let num_threads = rayon::current_num_threads();
+    if args.list_presets {
+        for (name, description) in config::PRESETS {
+            println!("{:<20} {}", name, description);
+        }
+        return Ok(());
+    }
 
-    // Create configuration
-    let config = FilterConfig {
-        min_total_depth: args.min_depth,
-        min_variant_frequency: args.min_vaf,
-        max_eas_af: args.max_eas_af,
-        min_revel_score: args.min_revel,
-        min_primate_ai_score: args.min_primate_ai,
-        min_dann_score: args.min_dann,
-        exclude_benign: args.exclude_benign,
-    };
+    // Resolve configuration: defaults < preset < config file < command line
+    let mut run_config = RunConfig::resolve(args.config.as_deref().map(Path::new), args.preset.as_deref())?;
+    args.apply_overrides(&mut run_config);
+
+    // Validate configuration; building the pipeline compiles the rules
+    run_config.validate()?;
+    let pipeline = Pipeline::from_config(&run_config.filter)?;
 
-    // Validate configuration
-    config.validate()?;
+    if let Some(format) = args.dump_config {
+        print!("{}", run_config.render(format)?);
+        return Ok(());
+    }
+
+    let config = &run_config.filter;
+    let parse_options = ParseOptions::from_config(config)?;
+    let maf_options = maf_options(&run_config)?;
+    let barcodes = BarcodeSettings::from_config(&run_config.output)?;
+
+    init_thread_pool(run_config.runtime.threads)?;
+    let num_threads = rayon::current_num_threads();
+
+    // Both are required by clap unless --dump-config/--list-presets was given
+    let input = args.input.as_deref().context("--input is required")?;
+    let output = args.output.as_deref().context("--output is required")?;
 
     // Check input file exists
-    if !Path::new(&args.input).exists() {
-        anyhow::bail!("Input file does not exist: {}", args.input);
+    if !Path::new(input).exists() {
+        anyhow::bail!("Input file does not exist: {}", input);
     }
 
     if args.verbose {
-        println!("\nStarting processing: {}", args.input);
-        println!("Output file: {}", args.output);
+        println!("\nStarting processing: {}", input);
+        println!("Output file: {} ({})", output, run_config.output.format);
+        if let Some(path) = &args.config {
+            println!("Config file: {}", path);
+        }
+        if let Some(preset) = &run_config.preset {
+            println!("Preset: {}", preset);
+        }
         println!("Processing mode: Multi-threaded parallel processing");
         println!("Number of threads: {}", num_threads);
-        display_config(&config);
+        display_config(config);
     }
 
     // Process file
     let stats = process_nirvana_json(
-        &args.input,
-        &args.output,
-        &config,
-        args.verbose,
-        args.quiet,
-        args.keep_temp,
-        args.batch_size.max(1),
+        input,
+        output,
+        run_config.output.rejected_output.as_deref(),
+        config,
+        &pipeline,
+        &parse_options,
+        &RunOptions {
+            verbose: args.verbose,
+            quiet: args.quiet,
+            files: FileOptions {
+                compression: run_config.output.compression,
+                keep_temp: args.keep_temp,
+                ..FileOptions::default()
+            },
+            batch_size: run_config.runtime.batch_size,
+            format: run_config.output.format,
+            sort: run_config.output.sort,
+            maf: maf_options,
+            barcodes,
+            checkpoint: args
+                .checkpoint
+                .then(|| CheckpointOptions::new(&run_config, args.checkpoint_interval, ""))
+                .transpose()?,
+        },
     )?;
 
     // Print statistics
-    if args.verbose || args.stats.is_some() {
-        print_statistics(&stats, num_threads, args.stats.as_deref())?;
+    let stats_path = run_config.output.stats.as_deref();
+    if args.verbose || stats_path.is_some() {
+        print_statistics(&stats, &pipeline.stage_names(), num_threads, stats_path, args.keep_temp)?;
     }
 
     println!("\n✓ Processing complete! (Using {} threads for parallel processing)", num_threads);
@@ -140,121 +802,371 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+fn maf_options(run_config: &RunConfig) -> Result<MafOptions> {
+    Ok(MafOptions {
+        transcripts: run_config.output.transcripts.clone(),
+        classifier: VariantClassifier::new(&run_config.output.variant_classification)?,
+        clinvar_all: run_config.output.clinvar_all,
+        ..MafOptions::default()
+    })
+}
+
+/// Sets the size of the global thread pool that filters every batch.
+fn init_thread_pool(threads: Option<usize>) -> Result<()> {
+    if let Some(threads) = threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .context("Failed to set thread pool size")?;
+    }
+    Ok(())
+}
+
+/// Runs every sample of a manifest. The configuration, pipeline and side
+/// files are loaded once and shared; `--jobs` samples are streamed at once
+/// on their own threads, and all of them filter on the one global pool.
+/// A failed sample is reported in the summary without stopping the others.
+fn run_batch(args: &BatchArgs) -> Result<()> {
+    let mut run_config = RunConfig::resolve(args.config.as_deref().map(Path::new), args.preset.as_deref())?;
+    if args.threads.is_some() {
+        run_config.runtime.threads = args.threads;
+    }
+    run_config.validate()?;
+
+    let output = &run_config.output;
+    if output.rejected_output.is_some() || output.stats.is_some() {
+        anyhow::bail!(
+            "rejected_output and stats name a single file and can't be used in batch mode; \
+             per-sample statistics go to --stats-dir"
+        );
+    }
+    if output.tumor_barcode.is_some() || output.sample_sheet.is_some() {
+        anyhow::bail!(
+            "tumor_barcode and sample_sheet can't be used in batch mode; \
+             each sample's Tumor_Sample_Barcode is its manifest sample_id"
+        );
+    }
+
+    let entries = load_manifest(Path::new(&args.manifest))?;
+    let config = &run_config.filter;
+    let pipeline = Pipeline::from_config(config)?;
+    let stage_names = pipeline.stage_names();
+    let parse_options = ParseOptions::from_config(config)?;
+    let maf_options = maf_options(&run_config)?;
+    let barcodes = BarcodeSettings::from_config(&run_config.output)?;
+
+    init_thread_pool(run_config.runtime.threads)?;
+    let num_threads = rayon::current_num_threads();
+    // Each job's reader thread parses its own input and holds a batch, so
+    // one job per filtering thread would oversubscribe the CPU
+    let jobs = args.jobs.unwrap_or((num_threads / 4).max(1)).clamp(1, entries.len());
+    if let Some(dir) = &args.stats_dir {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir))?;
+    }
+
+    println!(
+        "Processing {} samples from {} ({} at a time, {} filtering threads)",
+        entries.len(),
+        args.manifest,
+        jobs,
+        num_threads
+    );
+
+    let run_sample = |entry: &ManifestEntry| -> Result<FilterStats> {
+        if !Path::new(&entry.input).exists() {
+            anyhow::bail!("Input file does not exist: {}", entry.input);
+        }
+        let stats = process_nirvana_json(
+            &entry.input,
+            &entry.output,
+            None,
+            config,
+            &pipeline,
+            &parse_options,
+            &RunOptions {
+                verbose: false,
+                quiet: true,
+                files: FileOptions {
+                    compression: run_config.output.compression,
+                    keep_temp: args.keep_temp,
+                    ..FileOptions::default()
+                },
+                batch_size: run_config.runtime.batch_size,
+                format: run_config.output.format,
+                sort: run_config.output.sort,
+                maf: maf_options.clone(),
+                barcodes: barcodes.for_sample(&entry.sample_id, entry.normal_id.as_deref()),
+                checkpoint: args
+                    .checkpoint
+                    .then(|| {
+                        let sample = format!("{}\t{}", entry.sample_id, entry.normal_id.as_deref().unwrap_or(""));
+                        CheckpointOptions::new(&run_config, args.checkpoint_interval, &sample)
+                    })
+                    .transpose()?,
+            },
+        )?;
+
+        let stats_dir = match &args.stats_dir {
+            Some(dir) => PathBuf::from(dir),
+            None => Path::new(&entry.output).parent().map(Path::to_path_buf).unwrap_or_default(),
+        };
+        let report = format_statistics(&stats, &stage_names, num_threads);
+        write_report(&stats_dir.join(format!("{}.stats.txt", entry.sample_id)), &report, args.keep_temp)?;
+        Ok(stats)
+    };
+
+    let next = AtomicUsize::new(0);
+    let mut outcomes: Vec<(usize, SampleOutcome)> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(entry) = entries.get(index) else { break };
+                        let started = Instant::now();
+                        let result = run_sample(entry).map_err(|e| format!("{:#}", e));
+                        let seconds = started.elapsed().as_secs_f64();
+                        match &result {
+                            Ok(stats) => println!(
+                                "  ✓ {}: {} of {} variants included ({:.1}s)",
+                                entry.sample_id,
+                                stats.included,
+                                stats.evaluated,
+                                seconds
+                            ),
+                            Err(error) => println!("  ✗ {}: {}", entry.sample_id, error),
+                        }
+                        done.push((
+                            index,
+                            SampleOutcome {
+                                entry: entry.clone(),
+                                result,
+                                seconds,
+                            },
+                        ));
+                    }
+                    done
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("batch worker panicked"))
+            .collect()
+    });
+    outcomes.sort_by_key(|(index, _)| *index);
+    let outcomes: Vec<SampleOutcome> = outcomes.into_iter().map(|(_, outcome)| outcome).collect();
+
+    print!("\n{}", format_summary_table(&outcomes));
+    if let Some(path) = &args.summary {
+        let options = FileOptions {
+            compression: OutputCompression::None,
+            keep_temp: args.keep_temp,
+            ..FileOptions::default()
+        };
+        let mut file = OutputFile::create(path, options)?;
+        write_summary(&mut file, &outcomes).context("Failed to write batch summary")?;
+        file.commit()?;
+        println!("Summary written to: {}", path);
+    }
+
+    let failed = outcomes.iter().filter(|o| o.result.is_err()).count();
+    if failed > 0 {
+        anyhow::bail!("{} of {} samples failed", failed, outcomes.len());
+    }
+    println!("\n✓ Batch complete! ({} samples)", outcomes.len());
+    Ok(())
+}
+
+fn run_merge(args: &MergeArgs) -> Result<()> {
+    if args.batch_size == 0 {
+        anyhow::bail!("--batch-size must be at least 1");
+    }
+    let inputs = expand_inputs(&args.inputs)?;
+    let options = MergeOptions {
+        deduplicate: args.dedup,
+        barcode_from_filename: args.barcode_from_filename,
+        sort_chunk: args.sort.then_some(args.batch_size),
+        files: FileOptions {
+            compression: args.compress,
+            keep_temp: args.keep_temp,
+            ..FileOptions::default()
+        },
+    };
+    let summary = merge_maf_files(&inputs, &args.output, &options)?;
+
+    println!(
+        "✓ Merged {} rows from {} files into {} ({} columns)",
+        summary.rows_written, summary.files, args.output, summary.columns
+    );
+    if args.dedup {
+        println!("  Duplicate rows removed: {}", summary.duplicates_removed);
+    }
+    if args.barcode_from_filename {
+        println!("  Barcodes filled from file names: {}", summary.barcodes_filled);
+    }
+    Ok(())
+}
+
 fn display_config(config: &FilterConfig) {
     println!("============================================================");
     println!("JSON2MAF Filter Configuration");
     println!("============================================================");
     println!();
+    println!("VCF FILTER policy:");
+    println!("  Accepted tags (accept_filters):                   {}", config.accept_filters.join(", "));
+    println!("  Rejected tags (reject_filters):                   {}", display_list(&config.reject_filters));
+    println!("  Fail missing filters (fail_missing_filters):      {}", config.fail_missing_filters);
+    println!();
     println!("Quality filtering parameters:");
     println!("  Minimum sequencing depth (min_total_depth):       {}", config.min_total_depth);
     println!("  Minimum VAF (min_variant_frequency):              {}", config.min_variant_frequency);
+    println!("  Missing sample data policy (missing_sample_data): {}", config.missing_sample_data);
+    println!("  Minimum alt reads (min_alt_reads):                {}", display_threshold(config.min_alt_reads));
+    println!("  Minimum GQ (min_genotype_quality):                {}", display_threshold(config.min_genotype_quality));
+    println!("  Maximum strand bias (max_strand_bias):            {}", display_threshold(config.max_strand_bias));
+    println!("  Minimum somatic quality (min_somatic_quality):    {}", display_threshold(config.min_somatic_quality));
     println!();
     println!("Population frequency filtering parameters:");
     println!("  Maximum East Asian AF (max_eas_af):               {}", config.max_eas_af);
+    println!("  Missing AF policy (missing_population_af):        {}", config.missing_population_af);
+    println!();
+    println!("Consequence filtering:");
+    println!("  Included consequences (include_consequences):     {}", display_list(&config.include_consequences));
+    println!("  Excluded consequences (exclude_consequences):     {}", display_list(&config.exclude_consequences));
+    println!("  Minimum impact (min_impact):                      {}", display_threshold(config.min_impact));
+    println!("  Any transcript (consequence_scope):               {}", config.consequence_scope == ConsequenceScope::AnyTranscript);
+    println!();
+    println!("Transcript selection:");
+    let policy: Vec<String> = config.transcript_policy.iter().map(|c| c.to_string()).collect();
+    println!("  Policy (transcript_policy):                       {}", display_list(&policy));
+    println!("  Source (transcript_source):                       {}", display_threshold(config.transcript_source));
+    println!("  Preferred transcripts (preferred_transcripts):    {}", config.preferred_transcripts.as_deref().unwrap_or("none"));
     println!();
     println!("Predictive score thresholds:");
     println!("  REVEL minimum score (min_revel_score):            {}", config.min_revel_score);
     println!("  PrimateAI-3D minimum score:                       {}", config.min_primate_ai_score);
     println!("  DANN minimum score:                               {}", config.min_dann_score);
+    println!("  Score transcript (score_transcript_policy):       {}", config.score_transcript_policy);
+    println!("  Model (predictive_model):                         {}", config.predictive_model);
+    match config.predictive_model {
+        PredictiveModel::Votes => {}
+        PredictiveModel::Weighted => {
+            let weights: Vec<String> = config
+                .score_weights
+                .iter()
+                .map(|(name, weight)| format!("{}={}", name, weight))
+                .collect();
+            println!("  Score weights (score_weights):                    {}", display_list(&weights));
+            println!("  Required weight (min_predictive_weight):          {}", config.min_predictive_weight);
+        }
+        PredictiveModel::Calibrated => {
+            let tools: Vec<String> = config.score_calibrations.keys().cloned().collect();
+            println!("  Calibrated scores (score_calibrations):           {}", display_list(&tools));
+            println!("  Minimum evidence (min_evidence_strength):         {}", config.min_evidence_strength.acmg_code());
+        }
+    }
+    println!();
+    println!("Loss-of-function evidence:");
+    println!("  LoF rescue enabled (enable_lof_rescue):           {}", config.enable_lof_rescue);
+    println!("  Maximum CDS fraction (lof_max_cds_fraction):      {}", config.lof_max_cds_fraction);
     println!();
     println!("ClinVar filtering options:");
     println!("  Exclude benign/likely benign variants:            {}", config.exclude_benign);
     println!();
+    println!("Pipeline:");
+    println!("  Stages (stages):                                  {}", display_list(&config.stages));
+    println!();
+    println!("Custom rules:");
+    println!("  Include rules (include_rules):                    {}", display_list(&config.include_rules));
+    println!("  Exclude rules (exclude_rules):                    {}", display_list(&config.exclude_rules));
+    println!();
     println!("============================================================");
 }
 
+fn display_barcode(barcode: &str) -> &str {
+    if barcode.is_empty() {
+        "none"
+    } else {
+        barcode
+    }
+}
+
+fn display_list(items: &[String]) -> String {
+    if items.is_empty() {
+        "none".to_string()
+    } else {
+        items.join(", ")
+    }
+}
+
+fn display_threshold<T: std::fmt::Display>(threshold: Option<T>) -> String {
+    threshold
+        .map(|t| t.to_string())
+        .unwrap_or_else(|| "off".to_string())
+}
+
 /// Filters and converts one batch of already-parsed variants in parallel,
-/// writing MAF rows immediately and merging statistics, then clears the
+/// writing output records immediately and merging statistics, then clears the
 /// batch. Keeping this at batch granularity (instead of collecting every
 /// variant in the file first) is what bounds peak memory to O(batch_size)
 /// rather than O(file size) — the whole point of the streaming pipeline.
 fn process_batch(
     batch: &mut Vec<VariantPosition>,
     config: &FilterConfig,
-    writer: &mut MAFWriter,
+    pipeline: &Pipeline,
+    maf_options: &MafOptions,
+    sink: &mut dyn RecordSink,
+    mut rejected_writer: Option<&mut RejectedOutput>,
     total_stats: &mut FilterStats,
 ) -> Result<()> {
-    let results: Vec<(Option<MAFRecord>, FilterStats)> = batch
+    let collect_rejected = rejected_writer.is_some();
+
+    type Included<'a> = (VariantContext<'a>, FilterDecision, Vec<MAFRecord>);
+    type Rejected<'a> = (VariantContext<'a>, &'static str, Reason);
+    let results: Vec<(Option<Included>, Option<Rejected>, FilterStats)> = batch
         .par_iter()
         .map(|variant| {
             let mut thread_stats = FilterStats::default();
+            let outcome = pipeline.run(variant, config, &mut thread_stats);
+            let flags = &outcome.context.flags;
 
-            // Quality filtering
-            let quality_result = apply_quality_filters(variant, config);
-
-            if !quality_result.passes_quality {
-                if let Some(reason) = &quality_result.failure_reason {
-                    let reason_lower = reason.to_lowercase();
-                    if reason_lower.contains("depth") {
-                        thread_stats.failed_depth += 1;
-                    } else if reason_lower.contains("frequency") || reason_lower.contains("vaf") {
-                        thread_stats.failed_vaf += 1;
-                    } else if reason_lower.contains("population")
-                        || reason_lower.contains("allele frequency")
-                        || reason_lower.contains("east asian")
-                    {
-                        thread_stats.failed_af += 1;
-                    }
-                }
-                return (None, thread_stats);
-            }
-
-            thread_stats.passed_quality += 1;
-
-            // ClinVar assessment
-            let clinvar_assessment = assess_clinvar_pathogenicity(&variant.clinvar);
-
-            // Predictive scores assessment
-            let predictive_assessment = assess_predictive_scores(variant, config);
-
-            // Integrated decision
-            let decision = make_filter_decision_with_config(
-                variant,
-                &clinvar_assessment,
-                &predictive_assessment,
-                config.exclude_benign,
-            );
-
-            // Update statistics
-            if decision.should_include {
-                thread_stats.included += 1;
-
-                if decision.primary_evidence == "ClinVar" {
-                    if decision.pathogenicity_class == "Pathogenic" {
-                        thread_stats.clinvar_pathogenic += 1;
-                    } else if decision.pathogenicity_class == "Likely pathogenic" {
-                        thread_stats.clinvar_likely += 1;
-                    }
-                } else if decision.primary_evidence == "Predictive" {
-                    thread_stats.predictive_likely += 1;
-                    if has_primate_ai_support(&predictive_assessment)
-                        && count_supporting_predictive_scores(&predictive_assessment) == 1
-                    {
-                        thread_stats.primate_ai_only += 1;
-                    } else {
-                        thread_stats.multi_score += 1;
+            match outcome.verdict {
+                Verdict::Included(decision) => {
+                    let predictive_evidence = outcome
+                        .context
+                        .predictive
+                        .as_ref()
+                        .filter(|p| !p.calibrated_scores.is_empty())
+                        .map(|p| p.evidence_strength.acmg_code().to_string())
+                        .unwrap_or_default();
+                    let mut rows = variant_to_maf_rows(variant, outcome.context.clinvar.as_ref(), maf_options);
+                    for row in &mut rows {
+                        row.qc_flags = flags.join(";");
+                        row.predictive_evidence = predictive_evidence.clone();
                     }
+                    (Some((outcome.context, decision, rows)), None, thread_stats)
                 }
-
-                let maf_record = variant_to_maf(variant, &decision);
-                (Some(maf_record), thread_stats)
-            } else {
-                thread_stats.excluded += 1;
-
-                // Track benign exclusions separately
-                if decision.pathogenicity_class.contains("Benign") {
-                    thread_stats.excluded_benign += 1;
+                Verdict::Rejected { stage, reason } => {
+                    let rejected = collect_rejected.then_some((outcome.context, stage, reason));
+                    (None, rejected, thread_stats)
                 }
-
-                (None, thread_stats)
             }
         })
         .collect();
 
-    for (record, stats) in results {
+    for (included, rejected, stats) in results {
         total_stats.merge(&stats);
-        if let Some(rec) = record {
-            writer.write_record(&rec)?;
+        if let Some((context, decision, rows)) = &included {
+            sink.write(&OutputRecord {
+                context,
+                decision,
+                rows,
+            })?;
+        }
+        if let (Some((context, stage, reason)), Some(rw)) = (rejected, rejected_writer.as_deref_mut()) {
+            rw.write(&context, stage, &reason.message)?;
         }
     }
 
@@ -265,14 +1177,28 @@ fn process_batch(
 fn process_nirvana_json(
     input_path: &str,
     output_path: &str,
+    rejected_path: Option<&str>,
     config: &FilterConfig,
-    verbose: bool,
-    quiet: bool,
-    _keep_temp: bool,
-    batch_size: usize,
+    pipeline: &Pipeline,
+    parse_options: &ParseOptions,
+    options: &RunOptions,
 ) -> Result<FilterStats> {
+    let RunOptions {
+        verbose,
+        quiet,
+        files,
+        batch_size,
+        format,
+        sort,
+        ..
+    } = *options;
+
     if verbose {
-        println!("\nStreaming Nirvana JSON, filtering, and writing MAF in batches of {}...", batch_size);
+        println!(
+            "\nStreaming Nirvana JSON, filtering, and writing {} in batches of {}...",
+            format.as_str().to_uppercase(),
+            batch_size
+        );
     }
 
     let progress = if !quiet {
@@ -287,19 +1213,102 @@ fn process_nirvana_json(
         None
     };
 
-    let mut writer = MAFWriter::new(output_path)?;
-    let mut total_stats = FilterStats::default();
+    let files = FileOptions {
+        checkpoint: options.checkpoint.is_some(),
+        ..files
+    };
+    let mut checkpointer = options
+        .checkpoint
+        .as_ref()
+        .map(|checkpoint| Checkpointer::open(input_path, output_path, checkpoint))
+        .transpose()?;
+    let resumed = checkpointer.as_ref().filter(|c| c.resumed).map(|c| c.state.clone());
+    // A run interrupted while committing its outputs may have committed
+    // some of them already
+    let committed = |saved: &Checkpoint, path: &str| -> Result<bool> {
+        Ok(saved.finished && is_committed(Path::new(path))?)
+    };
+
+    let sink: Box<dyn RecordSink> = match &resumed {
+        Some(saved) if committed(saved, output_path)? => Box::new(CommittedSink::new(saved.output)),
+        Some(saved) => resume_sink(format, output_path, saved.output, files)?,
+        None => create_sink(format, output_path, batch_size, sort, files)?,
+    };
+    let sink = RefCell::new(sink);
+    let rejected_writer = match (rejected_path, &resumed) {
+        (Some(path), Some(saved)) if committed(saved, path)? => None,
+        (Some(path), Some(saved)) => {
+            let bytes = saved.rejected.context("The checkpoint has no rejected-variant report")?;
+            Some(RejectedOutput::resume(format, path, bytes, files)?)
+        }
+        (Some(path), None) => Some(RejectedOutput::create(format, path, batch_size, files)?),
+        (None, _) => None,
+    };
+    let rejected_writer = RefCell::new(rejected_writer);
     let mut batch: Vec<VariantPosition> = Vec::with_capacity(batch_size);
-    let mut processed: u64 = 0;
 
-    let _header = parse_nirvana_streaming(input_path, |position| {
-        if let Some(variant_pos) = position_to_variant(position)? {
+    // A resumed run skips the positions whose output is already written
+    let (skip, mut processed, mut total_stats) = match resumed {
+        Some(saved) => {
+            log::info!(
+                "Resuming {} from its checkpoint after {} variants",
+                output_path,
+                saved.variants
+            );
+            (saved.positions, saved.variants, saved.stats)
+        }
+        None => (0, 0, FilterStats::default()),
+    };
+    let mut positions: u64 = 0;
+
+    // Barcodes and VCF contigs come from the header, which is read before
+    // any position
+    let maf_options = RefCell::new(options.maf.clone());
+    let on_header = |header: &NirvanaHeader| {
+        let (tumor, normal) = options.barcodes.resolve(&header.samples)?;
+        if verbose {
+            println!("  Tumor_Sample_Barcode: {}", display_barcode(&tumor));
+            println!("  Matched_Norm_Sample_Barcode: {}", display_barcode(&normal));
+        }
+        if let Some(rw) = rejected_writer.borrow_mut().as_mut() {
+            rw.set_barcodes(&tumor, &normal);
+        }
+        let mut maf_options = maf_options.borrow_mut();
+        maf_options.tumor_sample_barcode = tumor;
+        maf_options.normal_sample_barcode = normal;
+        sink.borrow_mut().begin(header)
+    };
+
+    let _header = parse_nirvana_streaming_with_header(input_path, on_header, |position| {
+        positions += 1;
+        if positions <= skip {
+            return Ok(());
+        }
+        if let Some(variant_pos) = position_to_variant_with(position, parse_options)? {
             batch.push(variant_pos);
         }
 
         if batch.len() >= batch_size {
-            process_batch(&mut batch, config, &mut writer, &mut total_stats)?;
+            process_batch(
+                &mut batch,
+                config,
+                pipeline,
+                &maf_options.borrow(),
+                sink.borrow_mut().as_mut(),
+                rejected_writer.borrow_mut().as_mut(),
+                &mut total_stats,
+            )?;
             processed += batch_size as u64;
+            // Every position read so far is in the output: a consistent point
+            if let Some(checkpointer) = checkpointer.as_mut().filter(|c| c.due()) {
+                checkpointer.save(
+                    positions,
+                    processed,
+                    sink.borrow_mut().as_mut(),
+                    rejected_writer.borrow_mut().as_mut(),
+                    &total_stats,
+                )?;
+            }
             if let Some(pb) = &progress {
                 pb.set_message(format!(
                     "{} variants processed, {} included",
@@ -314,7 +1323,15 @@ fn process_nirvana_json(
 
     if !batch.is_empty() {
         processed += batch.len() as u64;
-        process_batch(&mut batch, config, &mut writer, &mut total_stats)?;
+        process_batch(
+            &mut batch,
+            config,
+            pipeline,
+            &maf_options.borrow(),
+            sink.borrow_mut().as_mut(),
+            rejected_writer.borrow_mut().as_mut(),
+            &mut total_stats,
+        )?;
     }
 
     if let Some(pb) = progress {
@@ -324,7 +1341,21 @@ fn process_nirvana_json(
         ));
     }
 
-    writer.flush()?;
+    let mut sink = sink.into_inner();
+    let mut rejected_writer = rejected_writer.into_inner();
+    // Commit the outputs only after checkpointing their final state, and
+    // drop the checkpoint only once all of them are in place
+    if let Some(checkpointer) = checkpointer.as_mut() {
+        checkpointer.state.finished = true;
+        checkpointer.save(positions, processed, sink.as_mut(), rejected_writer.as_mut(), &total_stats)?;
+    }
+    sink.finish()?;
+    if let Some(rw) = rejected_writer.as_mut() {
+        rw.finish()?;
+    }
+    if let Some(checkpointer) = &checkpointer {
+        checkpointer.remove()?;
+    }
 
     if verbose {
         println!(
@@ -336,14 +1367,76 @@ fn process_nirvana_json(
     Ok(total_stats)
 }
 
-fn print_statistics(stats: &FilterStats, num_threads: usize, output_path: Option<&str>) -> Result<()> {
+fn print_statistics(
+    stats: &FilterStats,
+    stage_names: &[&str],
+    num_threads: usize,
+    output_path: Option<&str>,
+    keep_temp: bool,
+) -> Result<()> {
+    let report = format_statistics(stats, stage_names, num_threads);
+    println!("{}", report);
+
+    if let Some(path) = output_path {
+        write_report(Path::new(path), &report, keep_temp)?;
+        println!("Statistics report written to: {}", path);
+    }
+
+    Ok(())
+}
+
+fn write_report(path: &Path, report: &str, keep_temp: bool) -> Result<()> {
+    let options = FileOptions {
+        compression: OutputCompression::None,
+        keep_temp,
+        ..FileOptions::default()
+    };
+    let mut file = OutputFile::create(path, options)?;
+    file.write_all(report.as_bytes())
+        .context("Failed to write statistics report")?;
+    file.commit()
+}
+
+fn format_statistics(stats: &FilterStats, stage_names: &[&str], num_threads: usize) -> String {
+    let stage_lines: String = stage_names
+        .iter()
+        .filter_map(|name| stats.stages.get(*name).map(|s| (name, s)))
+        .map(|(name, s)| {
+            format!(
+                "  - {:<12} evaluated {}, passed {}, annotated {}, failed {}\n",
+                name, s.evaluated, s.passed, s.annotated, s.failed
+            )
+        })
+        .collect();
+
     let benign_section = if stats.excluded_benign > 0 {
         format!("\nClinVar benign filtering:\n  - Excluded benign/likely benign: {}\n", stats.excluded_benign)
     } else {
         String::new()
     };
 
-    let report = format!(
+    let rule_section = if stats.rule_included + stats.rule_excluded > 0 {
+        format!(
+            "\nCustom rules:\n  - Included by rule: {}\n  - Excluded by rule: {}\n",
+            stats.rule_included, stats.rule_excluded
+        )
+    } else {
+        String::new()
+    };
+
+    let filter_tag_lines: String = stats
+        .failed_filter_tags
+        .iter()
+        .map(|(tag, count)| format!("    * {}: {}\n", tag, count))
+        .collect();
+
+    let consequence_term_lines: String = stats
+        .consequence_exclusions
+        .iter()
+        .map(|(term, count)| format!("    * {}: {}\n", term, count))
+        .collect();
+
+    format!(
         r#"
 ═══════════════════════════════════════════════════════════
                   Filtering Statistics Report
@@ -352,19 +1445,34 @@ fn print_statistics(stats: &FilterStats, num_threads: usize, output_path: Option
 Processing mode:        Multi-threaded parallel processing
 Number of threads:      {}
 
+Pipeline stages:
+{}
 Quality filtering:
   - Passed quality:     {}
-  - Insufficient depth: {}
+  - Failed VCF FILTER:  {}
+{}  - Insufficient depth: {}
   - VAF too low:        {}
+  - Alt reads too low:  {}
+  - GQ too low:         {}
+  - Strand bias too high: {}
+  - Somatic quality too low: {}
   - Population freq too high: {}
+  - Missing sample data: {} ({} rejected)
+  - Missing population AF: {} ({} rejected)
 
+Consequence filtering:
+  - Consequence excluded: {}
+  - Impact too low:       {}
+{}
 Pathogenicity assessment:
   - ClinVar Pathogenic:         {}
   - ClinVar Likely pathogenic:  {}
+  - High-confidence LoF:        {}
+    * Low-confidence LoF seen:  {}
   - Predictive scores support:  {}
     * PrimateAI-3D solo support: {}
     * 2+ scores support:         {}
-{}
+{}{}
 Final results:
   - Included variants:  {}
   - Excluded variants:  {}
@@ -372,26 +1480,160 @@ Final results:
 ═══════════════════════════════════════════════════════════
 "#,
         num_threads,
+        stage_lines,
         stats.passed_quality,
+        stats.failed_vcf_filter,
+        filter_tag_lines,
         stats.failed_depth,
         stats.failed_vaf,
+        stats.failed_alt_reads,
+        stats.failed_genotype_quality,
+        stats.failed_strand_bias,
+        stats.failed_somatic_quality,
         stats.failed_af,
+        stats.missing_sample_data,
+        stats.failed_missing_sample_data,
+        stats.missing_population_af,
+        stats.failed_missing_population_af,
+        stats.failed_consequence,
+        stats.failed_impact,
+        consequence_term_lines,
         stats.clinvar_pathogenic,
         stats.clinvar_likely,
+        stats.lof_rescued,
+        stats.low_confidence_lof,
         stats.predictive_likely,
         stats.primate_ai_only,
         stats.multi_score,
         benign_section,
+        rule_section,
         stats.included,
         stats.excluded
-    );
+    )
+}
 
-    println!("{}", report);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if let Some(path) = output_path {
-        fs::write(path, report).context("Failed to write statistics report")?;
-        println!("Statistics report written to: {}", path);
+    fn resolve(preset: &str, flags: &[&str]) -> RunConfig {
+        let args = Args::try_parse_from(["json2maf", "-i", "in.json.gz", "-o", "out.maf"].iter().chain(flags)).unwrap();
+        let mut config = RunConfig::resolve(None, Some(preset)).unwrap();
+        args.apply_overrides(&mut config);
+        config
     }
 
-    Ok(())
+    #[test]
+    fn test_command_line_turns_off_preset_settings() {
+        let config = resolve("germline-clinical", &[]);
+        assert!(config.filter.exclude_benign && config.filter.enable_lof_rescue);
+        assert_eq!(config.filter.min_genotype_quality, Some(20.0));
+
+        let config = resolve(
+            "germline-clinical",
+            &["--no-exclude-benign", "--no-fail-missing-filters", "--no-min-gq", "--min-depth", "50"],
+        );
+        assert!(!config.filter.exclude_benign);
+        assert!(!config.filter.fail_missing_filters);
+        assert_eq!(config.filter.min_genotype_quality, None);
+        assert_eq!(config.filter.min_total_depth, 50);
+        // Untouched preset settings survive
+        assert!(config.filter.enable_lof_rescue);
+        assert_eq!(config.filter.min_variant_frequency, 0.2);
+
+        // The later of a flag and its --no- form wins
+        let config = resolve("somatic-tumor-only", &["--no-lof-rescue", "--lof-rescue", "--min-alt-reads", "8"]);
+        assert!(config.filter.enable_lof_rescue);
+        assert_eq!(config.filter.min_alt_reads, Some(8));
+        let config = resolve("somatic-tumor-only", &["--min-alt-reads", "8", "--no-min-alt-reads"]);
+        assert_eq!(config.filter.min_alt_reads, None);
+    }
+
+    /// Converts `input` with checkpointing, writing `out.maf` and
+    /// `rejected.tsv` in `dir`.
+    fn convert(input: &Path, dir: &Path, run_config: &RunConfig) -> Result<FilterStats> {
+        let config = &run_config.filter;
+        let options = RunOptions {
+            verbose: false,
+            quiet: true,
+            files: FileOptions::default(),
+            batch_size: 2,
+            format: OutputFormat::Maf,
+            sort: false,
+            maf: maf_options(run_config)?,
+            barcodes: BarcodeSettings::from_config(&run_config.output)?,
+            checkpoint: Some(CheckpointOptions::new(run_config, Some(0), "")?),
+        };
+        process_nirvana_json(
+            input.to_str().unwrap(),
+            dir.join("out.maf").to_str().unwrap(),
+            Some(dir.join("rejected.tsv").to_str().unwrap()),
+            config,
+            &Pipeline::from_config(config)?,
+            &ParseOptions::from_config(config)?,
+            &options,
+        )
+    }
+
+    /// Runs a conversion that fails after committing `out.maf` but before
+    /// committing `rejected.tsv`, which is blocked by a directory.
+    fn crash_mid_finish(dir: &Path, run_config: &RunConfig) -> Result<PathBuf> {
+        let input = dir.join("input.json.gz");
+        std::fs::copy(concat!(env!("CARGO_MANIFEST_DIR"), "/examples/sample_data/test_mini.json.gz"), &input)?;
+        std::fs::create_dir_all(dir.join("rejected.tsv/blocked"))?;
+        assert!(convert(&input, dir, run_config).is_err());
+        assert!(is_committed(&dir.join("out.maf"))?);
+        assert!(checkpoint_path(&dir.join("out.maf")).exists());
+        std::fs::remove_dir_all(dir.join("rejected.tsv"))?;
+        Ok(input)
+    }
+
+    #[test]
+    fn test_resume_after_crash_mid_finish() -> Result<()> {
+        let run_config = RunConfig::default();
+        let expected_dir = tempfile::TempDir::new()?;
+        let input = expected_dir.path().join("input.json.gz");
+        std::fs::copy(concat!(env!("CARGO_MANIFEST_DIR"), "/examples/sample_data/test_mini.json.gz"), &input)?;
+        let expected = convert(&input, expected_dir.path(), &run_config)?;
+
+        let dir = tempfile::TempDir::new()?;
+        let input = crash_mid_finish(dir.path(), &run_config)?;
+        let stats = convert(&input, dir.path(), &run_config)?;
+
+        assert_eq!(stats.included, expected.included);
+        assert_eq!(stats.excluded, expected.excluded);
+        assert!(!checkpoint_path(&dir.path().join("out.maf")).exists());
+        for name in ["out.maf", "rejected.tsv"] {
+            assert_eq!(
+                std::fs::read(dir.path().join(name))?,
+                std::fs::read(expected_dir.path().join(name))?,
+                "{}",
+                name
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_resume_refuses_other_settings_or_input() -> Result<()> {
+        let run_config = RunConfig::default();
+        let dir = tempfile::TempDir::new()?;
+        let input = crash_mid_finish(dir.path(), &run_config)?;
+
+        let mut other_settings = run_config.clone();
+        other_settings.filter.min_total_depth += 1;
+        let error = convert(&input, dir.path(), &other_settings).unwrap_err();
+        assert!(format!("{:#}", error).contains("different settings"), "{:#}", error);
+
+        let mut contents = std::fs::read(&input)?;
+        contents.push(0);
+        std::fs::write(&input, contents)?;
+        let error = convert(&input, dir.path(), &run_config).unwrap_err();
+        assert!(format!("{:#}", error).contains("the input was"), "{:#}", error);
+
+        // Both were refused before touching the outputs
+        assert!(is_committed(&dir.path().join("out.maf"))?);
+        assert!(dir.path().join(".rejected.tsv.partial").exists());
+        Ok(())
+    }
 }
@@ -0,0 +1,356 @@
+/// Merging per-sample MAFs into one cohort MAF.
+///
+/// Inputs may come from different tools or versions, so rows are handled
+/// as plain columns rather than `MAFRecord`s: the output header is the
+/// union of the input headers in first-seen order, and a row's missing
+/// columns are left empty. `#` lines (e.g. `#version 2.4`) are skipped and
+/// gzip/BGZF inputs are decompressed.
+use crate::maf_reader::open_tsv;
+use crate::output::{FileOptions, OutputFile};
+use crate::sort::{ExternalSorter, SortKey};
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+const BARCODE_COLUMN: &str = "Tumor_Sample_Barcode";
+
+/// Columns identifying a duplicate row. `Transcript_ID` keeps the
+/// per-transcript rows of `--all-transcripts` output apart.
+const DEDUP_COLUMNS: &[&str] = &[
+    "Tumor_Sample_Barcode",
+    "Chromosome",
+    "Start_Position",
+    "End_Position",
+    "Reference_Allele",
+    "Tumor_Seq_Allele2",
+    "Transcript_ID",
+];
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MergeOptions {
+    /// Drop rows repeating an earlier row's `DEDUP_COLUMNS`. Unsorted, the
+    /// key of every distinct row is kept in memory; sorted, duplicates are
+    /// adjacent and only the current locus's keys are.
+    pub deduplicate: bool,
+    /// Fill empty `Tumor_Sample_Barcode` values from the input's file name
+    pub barcode_from_filename: bool,
+    /// Sort rows genomically, holding this many rows in memory per chunk
+    pub sort_chunk: Option<usize>,
+    pub files: FileOptions,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeSummary {
+    pub files: usize,
+    pub columns: usize,
+    pub rows_written: usize,
+    pub duplicates_removed: usize,
+    pub barcodes_filled: usize,
+}
+
+/// Expands glob patterns (`*`, `?`, `[...]`) among `inputs`, in sorted
+/// order. A pattern matching nothing or a missing file is an error.
+pub fn expand_inputs(inputs: &[String]) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for input in inputs {
+        if input.contains(['*', '?', '[']) {
+            let mut matches = glob::glob(input)
+                .with_context(|| format!("Invalid glob pattern: {}", input))?
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("Failed to expand {}", input))?;
+            if matches.is_empty() {
+                anyhow::bail!("No files match {}", input);
+            }
+            matches.sort();
+            paths.extend(matches);
+        } else {
+            let path = PathBuf::from(input);
+            if !path.is_file() {
+                anyhow::bail!("Input file does not exist: {}", input);
+            }
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
+/// Sample name implied by a file name: `TUMOR_01.maf.gz` gives `TUMOR_01`.
+pub fn barcode_from_path(path: &Path) -> String {
+    let mut name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    for ext in [".gz", ".bgz"] {
+        if let Some(stem) = name.strip_suffix(ext) {
+            name = stem.to_string();
+        }
+    }
+    for ext in [".maf", ".tsv", ".txt"] {
+        if let Some(stem) = name.strip_suffix(ext) {
+            name = stem.to_string();
+            break;
+        }
+    }
+    name
+}
+
+fn read_columns(path: &Path) -> Result<Vec<String>> {
+    let mut reader = open_tsv(path)?;
+    let headers = reader
+        .headers()
+        .with_context(|| format!("Failed to read the header of {}", path.display()))?;
+    Ok(headers.iter().map(str::to_string).collect())
+}
+
+/// Union of the inputs' columns, in first-seen order.
+fn union_columns(paths: &[PathBuf], options: &MergeOptions) -> Result<Vec<String>> {
+    let mut columns: Vec<String> = Vec::new();
+    for path in paths {
+        for column in read_columns(path)? {
+            if !columns.contains(&column) {
+                columns.push(column);
+            }
+        }
+    }
+    if options.barcode_from_filename && !columns.iter().any(|c| c == BARCODE_COLUMN) {
+        columns.push(BARCODE_COLUMN.to_string());
+    }
+    Ok(columns)
+}
+
+/// Merges `paths` into one MAF at `output_path`.
+pub fn merge_maf_files(paths: &[PathBuf], output_path: &str, options: &MergeOptions) -> Result<MergeSummary> {
+    let columns = union_columns(paths, options)?;
+    let column_index = |name: &str| columns.iter().position(|c| c == name);
+    let barcode_index = column_index(BARCODE_COLUMN);
+    let dedup_indices: Vec<usize> = DEDUP_COLUMNS.iter().filter_map(|c| column_index(c)).collect();
+
+    let mut sorter = match options.sort_chunk {
+        Some(chunk_size) => {
+            let (Some(chrom), Some(start)) = (column_index("Chromosome"), column_index("Start_Position")) else {
+                anyhow::bail!("Sorting needs Chromosome and Start_Position columns");
+            };
+            let sort_columns = (chrom, start, column_index("Reference_Allele"), column_index("Tumor_Seq_Allele2"));
+            Some((ExternalSorter::for_output(chunk_size, Path::new(output_path)), sort_columns))
+        }
+        None => None,
+    };
+
+    let file = OutputFile::create(output_path, options.files)?;
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .comment(Some(b'#'))
+        .from_writer(file);
+    writer.write_record(&columns).context("Failed to write MAF header")?;
+
+    let mut summary = MergeSummary {
+        files: paths.len(),
+        columns: columns.len(),
+        ..MergeSummary::default()
+    };
+    let mut seen: HashSet<Vec<String>> = HashSet::new();
+
+    for path in paths {
+        let mut reader = open_tsv(path)?;
+        let input_columns: Vec<Option<usize>> = reader
+            .headers()
+            .with_context(|| format!("Failed to read the header of {}", path.display()))?
+            .iter()
+            .map(column_index)
+            .collect();
+        let file_barcode = barcode_from_path(path);
+
+        for result in reader.records() {
+            let record = result.with_context(|| format!("Failed to read {}", path.display()))?;
+            let mut row = vec![String::new(); columns.len()];
+            for (value, index) in record.iter().zip(&input_columns) {
+                if let Some(index) = index {
+                    row[*index] = value.to_string();
+                }
+            }
+
+            let mut filled = false;
+            if let (true, Some(index)) = (options.barcode_from_filename, barcode_index) {
+                if row[index].is_empty() {
+                    row[index] = file_barcode.clone();
+                    filled = true;
+                }
+            }
+
+            match sorter.as_mut() {
+                Some((sorter, (chrom, start, reference, alternate))) => {
+                    let position: i64 = row[*start].parse().with_context(|| {
+                        format!("Invalid Start_Position '{}' in {}", row[*start], path.display())
+                    })?;
+                    let allele = |index: &Option<usize>| index.map(|i| row[i].as_str()).unwrap_or("");
+                    let key = SortKey::new(&row[*chrom], position, allele(reference), allele(alternate));
+                    sorter.push(key, (row, filled))?;
+                }
+                None => {
+                    if options.deduplicate {
+                        let key: Vec<String> = dedup_indices.iter().map(|&i| row[i].clone()).collect();
+                        if !seen.insert(key) {
+                            summary.duplicates_removed += 1;
+                            continue;
+                        }
+                    }
+                    writer.write_record(&row).context("Failed to write MAF record")?;
+                    summary.rows_written += 1;
+                    summary.barcodes_filled += usize::from(filled);
+                }
+            }
+        }
+    }
+
+    if let Some((sorter, (chrom, start, reference, alternate))) = sorter {
+        // The dedup columns include the sort key and the sort is stable, so
+        // a row's duplicates follow it within the same locus
+        let locus_of = |row: &[String]| -> Vec<String> {
+            [Some(chrom), Some(start), reference, alternate]
+                .iter()
+                .map(|index| index.map(|i| row[i].clone()).unwrap_or_default())
+                .collect()
+        };
+        let mut locus: Vec<String> = Vec::new();
+        sorter.finish(|(row, filled): (Vec<String>, bool)| {
+            if options.deduplicate {
+                let row_locus = locus_of(&row);
+                if row_locus != locus {
+                    locus = row_locus;
+                    seen.clear();
+                }
+                let key: Vec<String> = dedup_indices.iter().map(|&i| row[i].clone()).collect();
+                if !seen.insert(key) {
+                    summary.duplicates_removed += 1;
+                    return Ok(());
+                }
+            }
+            writer.write_record(&row).context("Failed to write MAF record")?;
+            summary.rows_written += 1;
+            summary.barcodes_filled += usize::from(filled);
+            Ok(())
+        })?;
+    }
+    let mut file = writer
+        .into_inner()
+        .map_err(|e| e.into_error())
+        .context("Failed to flush writer")?;
+    file.commit()?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn read_rows(path: &Path) -> Result<Vec<Vec<String>>> {
+        let mut reader = open_tsv(path)?;
+        let mut rows = vec![reader.headers()?.iter().map(str::to_string).collect()];
+        for record in reader.records() {
+            rows.push(record?.iter().map(str::to_string).collect());
+        }
+        Ok(rows)
+    }
+
+    #[test]
+    fn test_merge_reconciles_columns() -> Result<()> {
+        let dir = TempDir::new()?;
+        let a = dir.path().join("TUMOR_A.maf");
+        let b = dir.path().join("TUMOR_B.maf");
+        fs::write(
+            &a,
+            "#version 2.4\nHugo_Symbol\tChromosome\tStart_Position\tTumor_Sample_Barcode\n\
+             BRAF\tchr7\t140453136\t\nKRAS\tchr12\t25398284\tS1\n",
+        )?;
+        fs::write(
+            &b,
+            "Chromosome\tStart_Position\tHugo_Symbol\tHGVSp_Short\n\
+             chr7\t140453136\tBRAF\tp.V600E\nchr7\t140453136\tBRAF\tp.V600E\n",
+        )?;
+
+        let output = dir.path().join("cohort.maf");
+        let options = MergeOptions {
+            deduplicate: true,
+            barcode_from_filename: true,
+            ..MergeOptions::default()
+        };
+        let summary = merge_maf_files(&[a, b], output.to_str().unwrap(), &options)?;
+        assert_eq!(summary.rows_written, 3);
+        assert_eq!(summary.duplicates_removed, 1);
+        assert_eq!(summary.barcodes_filled, 2);
+
+        let rows = read_rows(&output)?;
+        assert_eq!(
+            rows[0],
+            ["Hugo_Symbol", "Chromosome", "Start_Position", "Tumor_Sample_Barcode", "HGVSp_Short"]
+        );
+        assert_eq!(rows[1], ["BRAF", "chr7", "140453136", "TUMOR_A", ""]);
+        assert_eq!(rows[2], ["KRAS", "chr12", "25398284", "S1", ""]);
+        assert_eq!(rows[3], ["BRAF", "chr7", "140453136", "TUMOR_B", "p.V600E"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_sorted() -> Result<()> {
+        let dir = TempDir::new()?;
+        let mut inputs = Vec::new();
+        for (name, body) in [("a.maf", "chrX\t5\nchr2\t30\n"), ("b.maf", "chr10\t1\nchr2\t7\n")] {
+            let path = dir.path().join(name);
+            fs::write(&path, format!("Chromosome\tStart_Position\n{}", body))?;
+            inputs.push(path);
+        }
+
+        let output = dir.path().join("merged.maf");
+        let options = MergeOptions {
+            sort_chunk: Some(1),
+            ..MergeOptions::default()
+        };
+        merge_maf_files(&inputs, output.to_str().unwrap(), &options)?;
+        let loci: Vec<String> = read_rows(&output)?.iter().skip(1).map(|r| r.join(":")).collect();
+        assert_eq!(loci, ["chr2:7", "chr2:30", "chr10:1", "chrX:5"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_sorted_dedup() -> Result<()> {
+        let dir = TempDir::new()?;
+        let mut inputs = Vec::new();
+        for (name, body) in [
+            ("a.maf", "chr2\t7\tS1\nchr1\t5\tS1\n"),
+            ("b.maf", "chr1\t5\tS2\nchr2\t7\tS1\nchr1\t5\tS1\n"),
+        ] {
+            let path = dir.path().join(name);
+            fs::write(&path, format!("Chromosome\tStart_Position\tTumor_Sample_Barcode\n{}", body))?;
+            inputs.push(path);
+        }
+
+        let output = dir.path().join("merged.maf");
+        let options = MergeOptions {
+            deduplicate: true,
+            sort_chunk: Some(1),
+            ..MergeOptions::default()
+        };
+        let summary = merge_maf_files(&inputs, output.to_str().unwrap(), &options)?;
+        assert_eq!(summary.rows_written, 3);
+        assert_eq!(summary.duplicates_removed, 2);
+        let rows: Vec<String> = read_rows(&output)?.iter().skip(1).map(|r| r.join(":")).collect();
+        assert_eq!(rows, ["chr1:5:S1", "chr1:5:S2", "chr2:7:S1"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_inputs() -> Result<()> {
+        let dir = TempDir::new()?;
+        for name in ["b.maf", "a.maf", "notes.txt"] {
+            fs::write(dir.path().join(name), "")?;
+        }
+        let pattern = format!("{}/*.maf", dir.path().display());
+        let paths = expand_inputs(&[pattern])?;
+        assert_eq!(paths, [dir.path().join("a.maf"), dir.path().join("b.maf")]);
+
+        let missing = dir.path().join("missing.maf").display().to_string();
+        assert!(expand_inputs(&[missing]).is_err());
+        assert!(expand_inputs(&[format!("{}/*.vcf", dir.path().display())]).is_err());
+        assert_eq!(barcode_from_path(Path::new("/data/TUMOR_01.maf.gz")), "TUMOR_01");
+        Ok(())
+    }
+}
@@ -0,0 +1,662 @@
+/// Output files that only appear at their final path once complete.
+///
+/// Data is written to a hidden temporary file next to the destination
+/// (`.NAME.XXXXXX.tmp`), optionally gzip- or BGZF-compressed. `commit`
+/// fsyncs it, renames it over the destination and then writes a `NAME.done`
+/// sidecar holding the file's SHA-256 in `sha256sum` format. A run that
+/// stops before `commit` leaves no file at the destination, and its
+/// temporary file is removed unless `keep_temp` is set.
+///
+/// Checkpointed outputs instead write to a fixed `.NAME.partial` file that
+/// is always kept. `checkpoint` makes its contents durable and returns its
+/// length, and `resume` reopens it at that length and appends from there.
+/// Gzip output can't be resumed (a gzip stream can't be cut and continued),
+/// but BGZF blocks are independent, so `checkpoint` ends the current block.
+///
+/// `FileOptions::direct` outputs (used by the writers' plain `new`
+/// constructors) write straight to the destination instead, so flushed data
+/// is visible at once and nothing is removed when they are dropped without
+/// `commit`; `commit` then only finishes the file and writes its sidecar.
+use crate::bgzf::BgzfWriter;
+use anyhow::{Context, Result};
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tempfile::TempPath;
+
+/// Compression of an output file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputCompression {
+    /// BGZF for paths ending in `.gz` or `.bgz`, none otherwise
+    #[default]
+    Auto,
+    None,
+    Gzip,
+    /// Blocked gzip, readable by any gzip reader and indexable with tabix
+    Bgzf,
+}
+
+impl OutputCompression {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutputCompression::Auto => "auto",
+            OutputCompression::None => "none",
+            OutputCompression::Gzip => "gzip",
+            OutputCompression::Bgzf => "bgzf",
+        }
+    }
+
+    /// The compression used for `path`; never `Auto`.
+    pub fn resolve(self, path: &Path) -> OutputCompression {
+        match self {
+            OutputCompression::Auto => match path.extension().and_then(|e| e.to_str()) {
+                Some("gz" | "bgz") => OutputCompression::Bgzf,
+                _ => OutputCompression::None,
+            },
+            other => other,
+        }
+    }
+}
+
+impl fmt::Display for OutputCompression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for OutputCompression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(OutputCompression::Auto),
+            "none" => Ok(OutputCompression::None),
+            "gzip" | "gz" => Ok(OutputCompression::Gzip),
+            "bgzf" | "bgzip" => Ok(OutputCompression::Bgzf),
+            _ => anyhow::bail!("invalid compression '{}' (expected auto, none, gzip or bgzf)", s),
+        }
+    }
+}
+
+/// How output files are written.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileOptions {
+    pub compression: OutputCompression,
+    /// Keep the temporary file of an output that was never committed
+    pub keep_temp: bool,
+    /// Write to a `.NAME.partial` file that can be resumed after a crash
+    pub checkpoint: bool,
+    /// Write straight to the destination rather than a temporary file
+    pub direct: bool,
+}
+
+impl FileOptions {
+    /// Uncompressed output written in place, as the writers produced before
+    /// outputs were committed atomically.
+    pub fn direct() -> Self {
+        Self {
+            compression: OutputCompression::None,
+            direct: true,
+            ..Self::default()
+        }
+    }
+}
+
+/// The `.done` sidecar written next to a committed output.
+pub fn done_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".done");
+    PathBuf::from(name)
+}
+
+/// The file a checkpointed output is written to until it is committed.
+pub fn partial_path(path: &Path) -> Result<PathBuf> {
+    let name = path
+        .file_name()
+        .with_context(|| format!("Output path has no file name: {}", path.display()))?;
+    let mut partial = std::ffi::OsString::from(".");
+    partial.push(name);
+    partial.push(".partial");
+    Ok(path.with_file_name(partial))
+}
+
+/// Whether a checkpointed output has been committed: its partial file has
+/// been moved into place and its `.done` sidecar written.
+pub fn is_committed(path: &Path) -> Result<bool> {
+    Ok(!partial_path(path)?.exists() && done_path(path).exists())
+}
+
+/// Lowercase hex of a finished SHA-256.
+pub(crate) fn hex_digest(hasher: Sha256) -> String {
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Hashes the bytes that reach the file, i.e. after compression.
+struct HashingWriter {
+    file: File,
+    hasher: Sha256,
+    len: u64,
+}
+
+impl Write for HashingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.file.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.len += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+enum Encoder {
+    Plain(HashingWriter),
+    Gzip(GzEncoder<HashingWriter>),
+    Bgzf(BgzfWriter<HashingWriter>),
+}
+
+impl Encoder {
+    fn finish(self) -> io::Result<HashingWriter> {
+        match self {
+            Encoder::Plain(w) => Ok(w),
+            Encoder::Gzip(w) => w.finish(),
+            Encoder::Bgzf(w) => w.finish(),
+        }
+    }
+}
+
+impl Write for Encoder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Plain(w) => w.write(buf),
+            Encoder::Gzip(w) => w.write(buf),
+            Encoder::Bgzf(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Plain(w) => w.flush(),
+            Encoder::Gzip(w) => w.flush(),
+            Encoder::Bgzf(w) => w.flush(),
+        }
+    }
+}
+
+pub struct OutputFile {
+    path: PathBuf,
+    keep_temp: bool,
+    checkpoint: bool,
+    writer: Option<BufWriter<Encoder>>,
+    /// `None` once committed, and throughout for direct output
+    temp_path: Option<TempPath>,
+}
+
+impl OutputFile {
+    pub fn create(path: impl AsRef<Path>, options: FileOptions) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let name = path
+            .file_name()
+            .with_context(|| format!("Output path has no file name: {}", path.display()))?
+            .to_string_lossy();
+        if options.checkpoint {
+            check_resumable(&path, options)?;
+        }
+        remove_done(&path)?;
+
+        let (file, temp_path) = if options.checkpoint {
+            let partial = partial_path(&path)?;
+            let file = File::create(&partial)
+                .with_context(|| format!("Failed to create output file: {}", partial.display()))?;
+            (file, Some(TempPath::from_path(partial)))
+        } else if options.direct {
+            let file = File::create(&path)
+                .with_context(|| format!("Failed to create output file: {}", path.display()))?;
+            (file, None)
+        } else {
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            let (file, temp_path) = tempfile::Builder::new()
+                .prefix(&format!(".{}.", name))
+                .suffix(".tmp")
+                .tempfile_in(dir)
+                .with_context(|| format!("Failed to create output file: {}", path.display()))?
+                .into_parts();
+            (file, Some(temp_path))
+        };
+        let file = HashingWriter {
+            file,
+            hasher: Sha256::new(),
+            len: 0,
+        };
+        Self::with_writer(path, options, file, temp_path)
+    }
+
+    /// Reopens the `.NAME.partial` file of a checkpointed output, drops
+    /// anything written after the first `len` bytes (the length returned by
+    /// the last `checkpoint`) and appends from there.
+    pub fn resume(path: impl AsRef<Path>, options: FileOptions, len: u64) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let partial = partial_path(&path)?;
+        check_resumable(&path, options)?;
+        remove_done(&path)?;
+
+        let context = || format!("Failed to resume output file: {}", partial.display());
+        let mut file = OpenOptions::new().read(true).write(true).open(&partial).with_context(context)?;
+        let found = file.metadata().with_context(context)?.len();
+        if found < len {
+            anyhow::bail!(
+                "Partial output {} is shorter than its checkpoint ({} of {} bytes)",
+                partial.display(),
+                found,
+                len
+            );
+        }
+        file.set_len(len).with_context(context)?;
+
+        // The .done checksum covers the whole file, including the part
+        // written before the checkpoint
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; 1 << 20];
+        let mut reader = (&file).take(len);
+        loop {
+            let n = reader.read(&mut buffer).with_context(context)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buffer[..n]);
+        }
+        file.seek(SeekFrom::Start(len)).with_context(context)?;
+
+        let file = HashingWriter { file, hasher, len };
+        let options = FileOptions {
+            checkpoint: true,
+            ..options
+        };
+        Self::with_writer(path, options, file, Some(TempPath::from_path(partial)))
+    }
+
+    fn with_writer(
+        path: PathBuf,
+        options: FileOptions,
+        file: HashingWriter,
+        temp_path: Option<TempPath>,
+    ) -> Result<Self> {
+        let encoder = match options.compression.resolve(&path) {
+            OutputCompression::Gzip => Encoder::Gzip(GzEncoder::new(file, flate2::Compression::default())),
+            OutputCompression::Bgzf => Encoder::Bgzf(BgzfWriter::new(file)),
+            OutputCompression::None | OutputCompression::Auto => Encoder::Plain(file),
+        };
+
+        Ok(Self {
+            path,
+            keep_temp: options.keep_temp,
+            checkpoint: options.checkpoint,
+            writer: Some(BufWriter::new(encoder)),
+            temp_path,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Finishes compression, fsyncs and renames the file to its final path
+    /// (unless it was written there directly), then writes the `.done`
+    /// checksum sidecar.
+    pub fn commit(&mut self) -> Result<()> {
+        let checksum = self.persist()?;
+        let name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        let mut done = OutputFile::create(done_path(&self.path), FileOptions::default())?;
+        writeln!(done, "{}  {}", checksum, name)?;
+        done.persist()?;
+        Ok(())
+    }
+
+    /// Writes out everything written so far, ending the current BGZF block,
+    /// and fsyncs it. Returns the file's length, from which `resume` can
+    /// continue.
+    pub fn checkpoint(&mut self) -> Result<u64> {
+        let context = || format!("Failed to checkpoint output file: {}", self.path.display());
+        let writer = self.writer.as_mut().with_context(|| format!("Output file already committed: {}", self.path.display()))?;
+        writer.flush().with_context(context)?;
+        let file = match writer.get_mut() {
+            Encoder::Plain(w) => w,
+            Encoder::Bgzf(w) => w.get_mut(),
+            Encoder::Gzip(_) => anyhow::bail!("gzip output can't be checkpointed"),
+        };
+        file.file.sync_data().with_context(context)?;
+        Ok(file.len)
+    }
+
+    /// Finishes the file without moving it into place and reads it back
+    /// from the start, for staging data that is copied into another output.
+    /// A checkpointed file stays on disk until `StagedFile::remove`, so a run
+    /// that fails while copying can still resume from it.
+    pub fn into_reader(mut self) -> Result<StagedFile> {
+        let context = || format!("Failed to read back output file: {}", self.path.display());
+        let (writer, temp_path) = match (self.writer.take(), self.temp_path.take()) {
+            (Some(writer), Some(temp_path)) => (writer, temp_path),
+            (Some(_), None) => anyhow::bail!("Direct output can't be staged: {}", self.path.display()),
+            _ => anyhow::bail!("Output file already committed: {}", self.path.display()),
+        };
+
+        let encoder = writer.into_inner().map_err(|e| e.into_error()).with_context(context)?;
+        drop(encoder.finish().with_context(context)?);
+        let file = File::open(&temp_path).with_context(context)?;
+        Ok(StagedFile {
+            reader: io::BufReader::new(file),
+            temp_path: Some(temp_path),
+            keep: self.checkpoint,
+        })
+    }
+
+    /// Moves the finished file into place and returns its SHA-256.
+    fn persist(&mut self) -> Result<String> {
+        let context = || format!("Failed to write output file: {}", self.path.display());
+        let writer = self
+            .writer
+            .take()
+            .with_context(|| format!("Output file already committed: {}", self.path.display()))?;
+
+        let encoder = writer.into_inner().map_err(|e| e.into_error()).with_context(context)?;
+        let HashingWriter { file, hasher, .. } = encoder.finish().with_context(context)?;
+        file.sync_all().with_context(context)?;
+        set_readable(&file);
+        drop(file);
+
+        if let Some(temp_path) = self.temp_path.take() {
+            if let Err(e) = temp_path.persist(&self.path) {
+                // Dropping the file decides whether it is kept
+                self.temp_path = Some(e.path);
+                return Err(e.error)
+                    .with_context(|| format!("Failed to move output into place: {}", self.path.display()));
+            }
+        }
+        sync_parent(&self.path);
+
+        Ok(hex_digest(hasher))
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.writer {
+            Some(w) => w.write(buf),
+            None => Err(io::Error::new(io::ErrorKind::BrokenPipe, "output file already committed")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.writer {
+            Some(w) => w.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for OutputFile {
+    fn drop(&mut self) {
+        // Uncommitted: the TempPath removes the file when dropped
+        if let Some(temp_path) = self.temp_path.take() {
+            if self.checkpoint {
+                let _ = temp_path.keep();
+            } else if self.keep_temp {
+                if let Ok(kept) = temp_path.keep() {
+                    log::warn!("Kept incomplete output for {} at {}", self.path.display(), kept.display());
+                }
+            }
+        }
+    }
+}
+
+/// A finished, uncommitted output opened for reading (see
+/// `OutputFile::into_reader`).
+pub struct StagedFile {
+    reader: io::BufReader<File>,
+    temp_path: Option<TempPath>,
+    keep: bool,
+}
+
+impl StagedFile {
+    /// Deletes the file once its contents have been committed elsewhere.
+    pub fn remove(mut self) -> Result<()> {
+        match self.temp_path.take() {
+            Some(temp_path) => {
+                let path = temp_path.to_path_buf();
+                temp_path.close().with_context(|| format!("Failed to remove {}", path.display()))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for StagedFile {
+    fn drop(&mut self) {
+        if let Some(temp_path) = self.temp_path.take() {
+            if self.keep {
+                let _ = temp_path.keep();
+            }
+        }
+    }
+}
+
+impl Read for StagedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+fn check_resumable(path: &Path, options: FileOptions) -> Result<()> {
+    if options.compression.resolve(path) == OutputCompression::Gzip {
+        anyhow::bail!("gzip output can't be checkpointed; use bgzf or none");
+    }
+    Ok(())
+}
+
+/// A sidecar from an earlier run must not vouch for this one.
+fn remove_done(path: &Path) -> Result<()> {
+    let done = done_path(path);
+    if done.exists() {
+        std::fs::remove_file(&done).with_context(|| format!("Failed to remove {}", done.display()))?;
+    }
+    Ok(())
+}
+
+/// Temporary files are created owner-only; outputs get the usual 0644.
+#[cfg(unix)]
+fn set_readable(file: &File) {
+    use std::os::unix::fs::PermissionsExt;
+    let _ = file.set_permissions(std::fs::Permissions::from_mode(0o644));
+}
+
+#[cfg(not(unix))]
+fn set_readable(_file: &File) {}
+
+/// Makes the rename durable. Best effort: not every platform can fsync a
+/// directory.
+fn sync_parent(path: &Path) {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::MultiGzDecoder;
+    use std::io::Read;
+    use tempfile::TempDir;
+
+    fn sha256_hex(bytes: &[u8]) -> String {
+        Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_commit_is_atomic() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("out.maf");
+
+        let mut file = OutputFile::create(&path, FileOptions::default())?;
+        file.write_all(b"Hugo_Symbol\nBRAF\n")?;
+        file.flush()?;
+        assert!(!path.exists());
+        assert_eq!(std::fs::read_dir(temp_dir.path())?.count(), 1);
+
+        file.commit()?;
+        assert_eq!(std::fs::read(&path)?, b"Hugo_Symbol\nBRAF\n");
+        assert_eq!(
+            std::fs::read_to_string(done_path(&path))?,
+            format!("{}  out.maf\n", sha256_hex(b"Hugo_Symbol\nBRAF\n"))
+        );
+        // Only the output and its sidecar remain
+        assert_eq!(std::fs::read_dir(temp_dir.path())?.count(), 2);
+
+        // A rerun that fails leaves the earlier output but drops its sidecar
+        let mut file = OutputFile::create(&path, FileOptions::default())?;
+        file.write_all(b"partial")?;
+        drop(file);
+        assert_eq!(std::fs::read(&path)?, b"Hugo_Symbol\nBRAF\n");
+        assert!(!done_path(&path).exists());
+        assert_eq!(std::fs::read_dir(temp_dir.path())?.count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_direct_output() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("out.maf.gz");
+
+        // Written in place and uncompressed, whatever the extension
+        let mut file = OutputFile::create(&path, FileOptions::direct())?;
+        file.write_all(b"Hugo_Symbol\n")?;
+        file.flush()?;
+        assert_eq!(std::fs::read(&path)?, b"Hugo_Symbol\n");
+        file.write_all(b"BRAF\n")?;
+        drop(file);
+        assert_eq!(std::fs::read(&path)?, b"Hugo_Symbol\nBRAF\n");
+        assert!(!done_path(&path).exists());
+
+        let mut file = OutputFile::create(&path, FileOptions::direct())?;
+        file.write_all(b"Hugo_Symbol\n")?;
+        file.commit()?;
+        assert_eq!(
+            std::fs::read_to_string(done_path(&path))?,
+            format!("{}  out.maf.gz\n", sha256_hex(b"Hugo_Symbol\n"))
+        );
+        assert_eq!(std::fs::read_dir(temp_dir.path())?.count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_keep_temp() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("out.maf");
+        let options = FileOptions {
+            keep_temp: true,
+            ..FileOptions::default()
+        };
+
+        let mut file = OutputFile::create(&path, options)?;
+        file.write_all(b"partial")?;
+        drop(file);
+
+        assert!(!path.exists());
+        let kept: Vec<_> = std::fs::read_dir(temp_dir.path())?.collect::<io::Result<_>>()?;
+        assert_eq!(kept.len(), 1);
+        assert!(kept[0].file_name().to_string_lossy().starts_with(".out.maf."));
+        Ok(())
+    }
+
+    #[test]
+    fn test_compression() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let data = "Hugo_Symbol\tChromosome\n".repeat(1000);
+
+        for (name, compression) in [
+            ("auto.maf.gz", OutputCompression::Auto),
+            ("gzip.maf", OutputCompression::Gzip),
+        ] {
+            let path = temp_dir.path().join(name);
+            let mut file = OutputFile::create(
+                &path,
+                FileOptions {
+                    compression,
+                    ..FileOptions::default()
+                },
+            )?;
+            file.write_all(data.as_bytes())?;
+            file.commit()?;
+
+            let compressed = std::fs::read(&path)?;
+            let mut decoded = String::new();
+            MultiGzDecoder::new(&compressed[..]).read_to_string(&mut decoded)?;
+            assert_eq!(decoded, data);
+            // The checksum covers the bytes on disk
+            assert!(std::fs::read_to_string(done_path(&path))?.starts_with(&sha256_hex(&compressed)));
+        }
+
+        assert_eq!(OutputCompression::Auto.resolve(Path::new("x.vcf.gz")), OutputCompression::Bgzf);
+        assert_eq!(OutputCompression::Auto.resolve(Path::new("x.maf")), OutputCompression::None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkpoint_resume() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+
+        for (name, compression) in [("out.maf", OutputCompression::None), ("out.maf.gz", OutputCompression::Bgzf)] {
+            let path = temp_dir.path().join(name);
+            let options = FileOptions {
+                compression,
+                checkpoint: true,
+                ..FileOptions::default()
+            };
+            let mut file = OutputFile::create(&path, options)?;
+            file.write_all(b"Hugo_Symbol\nBRAF\n")?;
+            let len = file.checkpoint()?;
+            // Written after the checkpoint, then the run dies
+            file.write_all(b"KRAS\n")?;
+            file.flush()?;
+            drop(file);
+            assert!(partial_path(&path)?.exists());
+
+            let mut file = OutputFile::resume(&path, options, len)?;
+            file.write_all(b"TP53\n")?;
+            file.commit()?;
+            assert!(!partial_path(&path)?.exists());
+
+            let written = std::fs::read(&path)?;
+            let mut decoded = String::new();
+            match compression {
+                OutputCompression::Bgzf => MultiGzDecoder::new(&written[..]).read_to_string(&mut decoded)?,
+                _ => written.as_slice().read_to_string(&mut decoded)?,
+            };
+            assert_eq!(decoded, "Hugo_Symbol\nBRAF\nTP53\n");
+            assert!(std::fs::read_to_string(done_path(&path))?.starts_with(&sha256_hex(&written)));
+        }
+
+        let options = FileOptions {
+            compression: OutputCompression::Gzip,
+            checkpoint: true,
+            ..FileOptions::default()
+        };
+        assert!(OutputFile::create(temp_dir.path().join("gzip.maf"), options).is_err());
+        assert!(OutputFile::resume(temp_dir.path().join("missing.maf"), FileOptions::default(), 0).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,461 @@
+/// Parquet output with typed columns, for loading whole cohorts into
+/// DuckDB or Spark.
+///
+/// Positions, depths and read counts are integers and scores and allele
+/// frequencies are floats, at full precision. Transcripts, ClinVar entries
+/// and population frequencies are lists of structs. Rows are buffered up to
+/// the row group size, which comes from `batch_size`. Each full buffer is
+/// written as one row group, so memory stays bounded as in `process_batch`.
+///
+/// Included and rejected variants share one schema. The decision columns
+/// are null for rejected rows, and `rejection_stage`/`rejection_reason` are
+/// null for included rows.
+use crate::filters::clinvar::review_status_stars;
+use crate::filters::VariantContext;
+use crate::output::{FileOptions, OutputCompression, OutputFile};
+use crate::transcript::select_default_index;
+use crate::types::{
+    ClinVarEntry, FilterDecision, MAFRecord, PopulationFrequency, TranscriptAnnotation, VariantPosition,
+};
+use crate::writer::{OutputRecord, RecordSink};
+use anyhow::{Context, Result};
+use arrow_array::builder::{ListBuilder, StringBuilder};
+use arrow_array::{
+    Array, ArrayRef, BooleanArray, Float64Array, Int32Array, ListArray, RecordBatch, StringArray, StructArray,
+};
+use arrow_buffer::OffsetBuffer;
+use arrow_schema::{Field, Fields, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use std::path::Path;
+use std::sync::Arc;
+
+type Column = (Field, ArrayRef);
+
+/// One buffered output row.
+struct Row {
+    variant: VariantPosition,
+    tumor_sample_barcode: String,
+    normal_sample_barcode: String,
+    decision: Option<FilterDecision>,
+    /// The selected transcript's MAF row, for included variants
+    maf: Option<MAFRecord>,
+    rejection: Option<(String, String)>,
+    qc_flags: Vec<String>,
+    cosmic_ids: Vec<String>,
+}
+
+impl Row {
+    fn new(context: &VariantContext, tumor_sample_barcode: &str, normal_sample_barcode: &str) -> Self {
+        let variant = context.variant.clone();
+        let cosmic_ids = variant.cosmic.iter().filter_map(|c| c.id.clone()).collect();
+        Row {
+            variant,
+            tumor_sample_barcode: tumor_sample_barcode.to_string(),
+            normal_sample_barcode: normal_sample_barcode.to_string(),
+            decision: None,
+            maf: None,
+            rejection: None,
+            qc_flags: context.flags.clone(),
+            cosmic_ids,
+        }
+    }
+
+    fn selected_index(&self) -> Option<usize> {
+        self.variant
+            .selected_transcript_index
+            .or_else(|| select_default_index(&self.variant.transcripts))
+    }
+
+    fn selected_transcript(&self) -> Option<&TranscriptAnnotation> {
+        self.selected_index().and_then(|i| self.variant.transcripts.get(i))
+    }
+
+    fn maf_field(&self, field: fn(&MAFRecord) -> &str) -> Option<&str> {
+        self.maf.as_ref().map(field).filter(|s| !s.is_empty())
+    }
+}
+
+fn column(name: &str, array: ArrayRef) -> Column {
+    (Field::new(name, array.data_type().clone(), true), array)
+}
+
+fn utf8<'a, T: 'a>(name: &str, items: &'a [T], f: impl Fn(&'a T) -> Option<&'a str>) -> Column {
+    column(name, Arc::new(items.iter().map(f).collect::<StringArray>()))
+}
+
+fn int32<T>(name: &str, items: &[T], f: impl Fn(&T) -> Option<i32>) -> Column {
+    column(name, Arc::new(items.iter().map(f).collect::<Int32Array>()))
+}
+
+fn float64<T>(name: &str, items: &[T], f: impl Fn(&T) -> Option<f64>) -> Column {
+    column(name, Arc::new(items.iter().map(f).collect::<Float64Array>()))
+}
+
+fn boolean<T>(name: &str, items: &[T], f: impl Fn(&T) -> Option<bool>) -> Column {
+    column(name, Arc::new(items.iter().map(f).collect::<BooleanArray>()))
+}
+
+fn utf8_list<'a, T: 'a>(name: &str, items: &'a [T], f: impl Fn(&'a T) -> &'a [String]) -> Column {
+    let mut builder = ListBuilder::new(StringBuilder::new());
+    for item in items {
+        for value in f(item) {
+            builder.values().append_value(value);
+        }
+        builder.append(true);
+    }
+    column(name, Arc::new(builder.finish()))
+}
+
+/// A list-of-struct column: `lengths` gives each row's number of elements,
+/// `children` the struct fields over all elements in row order.
+fn struct_list(name: &str, lengths: impl Iterator<Item = usize>, children: Vec<Column>) -> Column {
+    let (fields, arrays): (Vec<Field>, Vec<ArrayRef>) = children.into_iter().unzip();
+    let values = StructArray::new(Fields::from(fields), arrays, None);
+    let item = Arc::new(Field::new("item", values.data_type().clone(), true));
+    let list = ListArray::new(item, OffsetBuffer::from_lengths(lengths), Arc::new(values), None);
+    column(name, Arc::new(list))
+}
+
+fn transcripts_column(rows: &[Row]) -> Column {
+    let items: Vec<(&TranscriptAnnotation, bool)> = rows
+        .iter()
+        .flat_map(|row| {
+            let selected = row.selected_index();
+            row.variant
+                .transcripts
+                .iter()
+                .enumerate()
+                .map(move |(i, t)| (t, Some(i) == selected))
+        })
+        .collect();
+    struct_list(
+        "transcripts",
+        rows.iter().map(|row| row.variant.transcripts.len()),
+        vec![
+            utf8("transcript_id", &items, |(t, _)| t.id.as_deref()),
+            utf8("source", &items, |(t, _)| t.source.as_deref()),
+            utf8("gene", &items, |(t, _)| t.hgnc.as_deref()),
+            utf8_list("consequence", &items, |(t, _)| &t.consequence),
+            utf8("impact", &items, |(t, _)| t.impact.as_deref()),
+            utf8("hgvsc", &items, |(t, _)| t.hgvsc.as_deref()),
+            utf8("hgvsp", &items, |(t, _)| t.hgvsp.as_deref()),
+            utf8("exon", &items, |(t, _)| t.exons.as_deref()),
+            utf8("cds_position", &items, |(t, _)| t.cds_pos.as_deref()),
+            utf8("protein_position", &items, |(t, _)| t.protein_pos.as_deref()),
+            utf8("bio_type", &items, |(t, _)| t.bio_type.as_deref()),
+            boolean("is_canonical", &items, |(t, _)| t.is_canonical),
+            boolean("is_mane_select", &items, |(t, _)| t.is_mane_select),
+            boolean("is_selected", &items, |(_, selected)| Some(*selected)),
+        ],
+    )
+}
+
+fn clinvar_column(rows: &[Row]) -> Column {
+    let items: Vec<&ClinVarEntry> = rows.iter().flat_map(|row| &row.variant.clinvar).collect();
+    struct_list(
+        "clinvar",
+        rows.iter().map(|row| row.variant.clinvar.len()),
+        vec![
+            utf8("id", &items, |e| e.id.as_deref()),
+            utf8("allele_id", &items, |e| e.allele_id.as_deref()),
+            utf8_list("significance", &items, |e| &e.clinical_significance),
+            utf8("review_status", &items, |e| e.review_status.as_deref()),
+            int32("review_stars", &items, |e| {
+                Some(i32::from(review_status_stars(e.review_status.as_deref().unwrap_or(""))))
+            }),
+            utf8_list("phenotypes", &items, |e| &e.phenotypes),
+            utf8("last_evaluated", &items, |e| e.last_evaluated.as_deref()),
+        ],
+    )
+}
+
+fn population_frequencies_column(rows: &[Row]) -> Column {
+    let items: Vec<&PopulationFrequency> = rows
+        .iter()
+        .flat_map(|row| &row.variant.population_frequencies)
+        .collect();
+    struct_list(
+        "population_frequencies",
+        rows.iter().map(|row| row.variant.population_frequencies.len()),
+        vec![
+            utf8("source", &items, |pf| Some(pf.source.as_str())),
+            float64("all_af", &items, |pf| pf.all_af),
+            float64("eas_af", &items, |pf| pf.eas_af),
+            float64("afr_af", &items, |pf| pf.afr_af),
+            float64("amr_af", &items, |pf| pf.amr_af),
+            float64("eur_af", &items, |pf| pf.eur_af),
+        ],
+    )
+}
+
+/// Every output column, in schema order. Called with no rows to get the
+/// schema.
+fn build_columns(rows: &[Row]) -> Vec<Column> {
+    vec![
+        utf8("chromosome", rows, |r| Some(r.variant.chromosome.as_str())),
+        int32("start_position", rows, |r| Some(r.variant.start)),
+        int32("end_position", rows, |r| Some(r.variant.end_pos)),
+        utf8("reference_allele", rows, |r| Some(r.variant.reference_allele.as_str())),
+        utf8("alternate_allele", rows, |r| Some(r.variant.alternate_allele.as_str())),
+        utf8("variant_type", rows, |r| Some(r.variant.variant_type.as_str())),
+        utf8_list("filters", rows, |r| &r.variant.filters),
+        utf8("tumor_sample_barcode", rows, |r| Some(r.tumor_sample_barcode.as_str())),
+        utf8("matched_norm_sample_barcode", rows, |r| Some(r.normal_sample_barcode.as_str())),
+        utf8("hugo_symbol", rows, |r| r.selected_transcript().and_then(|t| t.hgnc.as_deref())),
+        utf8("transcript_id", rows, |r| r.selected_transcript().and_then(|t| t.id.as_deref())),
+        utf8_list("consequence", rows, |r| {
+            r.selected_transcript().map_or(&[], |t| t.consequence.as_slice())
+        }),
+        utf8("impact", rows, |r| r.selected_transcript().and_then(|t| t.impact.as_deref())),
+        utf8("variant_classification", rows, |r| r.maf_field(|m| &m.variant_classification)),
+        utf8("hgvsc", rows, |r| r.maf_field(|m| &m.hgvsc)),
+        utf8("hgvsp", rows, |r| r.maf_field(|m| &m.hgvsp)),
+        utf8("hgvsp_short", rows, |r| r.maf_field(|m| &m.hgvsp_short)),
+        int32("total_depth", rows, |r| r.variant.total_depth),
+        float64("vaf", rows, |r| {
+            r.variant.variant_frequencies.as_ref().and_then(|vf| vf.first().copied())
+        }),
+        int32("t_ref_count", rows, |r| r.variant.ref_read_count()),
+        int32("t_alt_count", rows, |r| r.variant.alt_read_count()),
+        utf8("genotype", rows, |r| r.variant.genotype.as_deref()),
+        float64("genotype_quality", rows, |r| r.variant.genotype_quality),
+        float64("strand_bias", rows, |r| r.variant.strand_bias),
+        float64("somatic_quality", rows, |r| r.variant.somatic_quality),
+        utf8_list("dbsnp_ids", rows, |r| &r.variant.dbsnp_ids),
+        utf8_list("cosmic_ids", rows, |r| &r.cosmic_ids),
+        float64("gnomad_af", rows, |r| r.variant.gnomad_exome().and_then(|pf| pf.all_af)),
+        float64("gnomad_eas_af", rows, |r| r.variant.gnomad_exome().and_then(|pf| pf.eas_af)),
+        float64("primate_ai_3d_score", rows, |r| r.variant.primate_ai_3d),
+        float64("primate_ai_score", rows, |r| r.variant.primate_ai),
+        float64("revel_score", rows, |r| r.variant.revel_score),
+        float64("dann_score", rows, |r| r.variant.dann_score),
+        utf8("predictive_evidence", rows, |r| r.maf_field(|m| &m.predictive_evidence)),
+        utf8("pathogenicity_class", rows, |r| r.decision.as_ref().map(|d| d.pathogenicity_class.as_str())),
+        utf8("primary_evidence", rows, |r| r.decision.as_ref().map(|d| d.primary_evidence.as_str())),
+        utf8("justification", rows, |r| r.decision.as_ref().map(|d| d.justification.as_str())),
+        utf8("clinvar_id", rows, |r| r.maf_field(|m| &m.clinvar_id)),
+        utf8("rejection_stage", rows, |r| r.rejection.as_ref().map(|(stage, _)| stage.as_str())),
+        utf8("rejection_reason", rows, |r| r.rejection.as_ref().map(|(_, reason)| reason.as_str())),
+        utf8_list("qc_flags", rows, |r| &r.qc_flags),
+        transcripts_column(rows),
+        clinvar_column(rows),
+        population_frequencies_column(rows),
+    ]
+}
+
+/// Writes included (or rejected) variants as ZSTD-compressed Parquet.
+pub struct ParquetWriter {
+    writer: Option<ArrowWriter<OutputFile>>,
+    schema: SchemaRef,
+    rows: Vec<Row>,
+    row_group_size: usize,
+    records_written: usize,
+    tumor_sample_barcode: String,
+    normal_sample_barcode: String,
+}
+
+impl ParquetWriter {
+    pub fn create(output_path: &str, row_group_size: usize, options: FileOptions) -> Result<Self> {
+        // Pages are ZSTD-compressed; a gzip layer would make the file unreadable
+        let compression = options.compression.resolve(Path::new(output_path));
+        if compression != OutputCompression::None {
+            anyhow::bail!("Parquet output is compressed internally and cannot be {}-compressed", compression);
+        }
+        let file = OutputFile::create(output_path, options)?;
+        let row_group_size = row_group_size.max(1);
+        let (fields, _): (Vec<Field>, Vec<ArrayRef>) = build_columns(&[]).into_iter().unzip();
+        let schema = Arc::new(Schema::new(fields));
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::default()))
+            .set_max_row_group_size(row_group_size)
+            .build();
+        let writer = ArrowWriter::try_new(file, schema.clone(), Some(properties))
+            .context("Failed to create Parquet writer")?;
+        Ok(Self {
+            writer: Some(writer),
+            schema,
+            rows: Vec::with_capacity(row_group_size),
+            row_group_size,
+            records_written: 0,
+            tumor_sample_barcode: String::new(),
+            normal_sample_barcode: String::new(),
+        })
+    }
+
+    /// Barcodes for rejected rows; included rows take theirs from the MAF row.
+    pub fn set_barcodes(&mut self, tumor: &str, normal: &str) {
+        self.tumor_sample_barcode = tumor.to_string();
+        self.normal_sample_barcode = normal.to_string();
+    }
+
+    /// Adds a variant rejected at `stage`, for the rejected-variant report.
+    pub fn write_rejected(&mut self, context: &VariantContext, stage: &str, reason: &str) -> Result<()> {
+        let mut row = Row::new(context, &self.tumor_sample_barcode, &self.normal_sample_barcode);
+        row.rejection = Some((stage.to_string(), reason.to_string()));
+        self.push(row)
+    }
+
+    fn push(&mut self, row: Row) -> Result<()> {
+        self.rows.push(row);
+        self.records_written += 1;
+        if self.rows.len() >= self.row_group_size {
+            self.write_row_group()?;
+        }
+        Ok(())
+    }
+
+    fn write_row_group(&mut self) -> Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        let arrays = build_columns(&self.rows).into_iter().map(|(_, array)| array).collect();
+        let batch = RecordBatch::try_new(self.schema.clone(), arrays).context("Failed to build Parquet row group")?;
+        self.writer
+            .as_mut()
+            .context("Parquet writer already finished")?
+            .write(&batch)
+            .context("Failed to write Parquet row group")?;
+        self.rows.clear();
+        Ok(())
+    }
+}
+
+impl RecordSink for ParquetWriter {
+    fn write(&mut self, record: &OutputRecord) -> Result<()> {
+        let maf = record.selected_row().cloned();
+        let (tumor, normal) = match &maf {
+            Some(maf) => (maf.tumor_sample_barcode.as_str(), maf.matched_norm_sample_barcode.as_str()),
+            None => (self.tumor_sample_barcode.as_str(), self.normal_sample_barcode.as_str()),
+        };
+        let mut row = Row::new(record.context, tumor, normal);
+        row.decision = Some(record.decision.clone());
+        row.maf = maf;
+        self.push(row)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.write_row_group()?;
+        if let Some(writer) = self.writer.take() {
+            let mut file = writer.into_inner().context("Failed to finish Parquet output")?;
+            file.commit()?;
+        }
+        Ok(())
+    }
+
+    fn records_written(&self) -> usize {
+        self.records_written
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::variant_to_maf;
+    use crate::types::{FilterConfig, TranscriptAnnotation};
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Float64Type, Int32Type};
+    use arrow_schema::DataType;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use tempfile::TempDir;
+
+    fn create_test_variant(start: i32) -> VariantPosition {
+        VariantPosition {
+            chromosome: "chr12".to_string(),
+            start,
+            end_pos: start,
+            reference_allele: "C".to_string(),
+            total_depth: Some(200),
+            variant_frequencies: Some(vec![0.125]),
+            genotype: Some("0/1".to_string()),
+            allele_depths: Some(vec![175, 25]),
+            transcripts: vec![TranscriptAnnotation {
+                id: Some("NM_004985.5".to_string()),
+                source: Some("RefSeq".to_string()),
+                hgnc: Some("KRAS".to_string()),
+                consequence: vec!["missense_variant".to_string()],
+                impact: Some("MODERATE".to_string()),
+                amino_acids: None,
+                cdna_pos: None,
+                cds_pos: Some("35".to_string()),
+                exons: Some("2/5".to_string()),
+                codons: None,
+                protein_pos: Some("12".to_string()),
+                hgvsc: Some("NM_004985.5:c.35G>A".to_string()),
+                hgvsp: Some("NP_004976.2:p.(Gly12Asp)".to_string()),
+                is_canonical: Some(true),
+                is_mane_select: Some(true),
+                is_mane_plus_clinical: None,
+                bio_type: Some("protein_coding".to_string()),
+            }],
+            revel_score: Some(0.123456),
+            dbsnp_ids: vec!["rs121913529".to_string()],
+            ..VariantPosition::test_default()
+        }
+    }
+
+    #[test]
+    fn test_write_parquet() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("out.parquet");
+        let path = path.to_str().unwrap();
+
+        let config = FilterConfig::default();
+        let decision = FilterDecision {
+            should_include: true,
+            pathogenicity_class: "Likely pathogenic".to_string(),
+            primary_evidence: "Predictive".to_string(),
+            justification: String::new(),
+        };
+        let variants: Vec<VariantPosition> = (0..3).map(|i| create_test_variant(25245350 + i)).collect();
+
+        let mut writer = ParquetWriter::create(path, 2, FileOptions::default())?;
+        writer.set_barcodes("TUMOR", "");
+        for variant in &variants[..2] {
+            let rows = vec![variant_to_maf(variant, None)];
+            writer.write(&OutputRecord {
+                context: &VariantContext::new(variant, &config),
+                decision: &decision,
+                rows: &rows,
+            })?;
+        }
+        writer.write_rejected(&VariantContext::new(&variants[2], &config), "quality", "Low VAF")?;
+        writer.finish()?;
+        assert_eq!(writer.records_written(), 3);
+
+        let builder = ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(path)?)?;
+        assert_eq!(builder.metadata().num_row_groups(), 2);
+        let schema = builder.schema().clone();
+        assert_eq!(schema.field_with_name("start_position")?.data_type(), &DataType::Int32);
+        assert_eq!(schema.field_with_name("revel_score")?.data_type(), &DataType::Float64);
+        assert!(matches!(
+            schema.field_with_name("transcripts")?.data_type(),
+            DataType::List(item) if matches!(item.data_type(), DataType::Struct(_))
+        ));
+
+        let batches = builder.with_batch_size(2).build()?.collect::<std::result::Result<Vec<_>, _>>()?;
+        assert_eq!(batches.iter().map(|b| b.num_rows()).collect::<Vec<_>>(), vec![2, 1]);
+        let (included, rejected) = (&batches[0], &batches[1]);
+
+        let start = rejected.column_by_name("start_position").unwrap().as_primitive::<Int32Type>();
+        assert_eq!(start.value(0), 25245352);
+        let revel = included.column_by_name("revel_score").unwrap().as_primitive::<Float64Type>();
+        assert_eq!(revel.value(0), 0.123456);
+        let class = included.column_by_name("pathogenicity_class").unwrap().as_string::<i32>();
+        assert_eq!(class.value(0), "Likely pathogenic");
+        assert!(rejected.column_by_name("pathogenicity_class").unwrap().is_null(0));
+        assert!(included.column_by_name("rejection_stage").unwrap().is_null(0));
+        let stage = rejected.column_by_name("rejection_stage").unwrap().as_string::<i32>();
+        assert_eq!(stage.value(0), "quality");
+        let barcode = rejected.column_by_name("tumor_sample_barcode").unwrap().as_string::<i32>();
+        assert_eq!(barcode.value(0), "TUMOR");
+
+        let transcripts = included.column_by_name("transcripts").unwrap().as_list::<i32>();
+        let first = transcripts.value(0);
+        let first = first.as_struct();
+        assert_eq!(first.column_by_name("transcript_id").unwrap().as_string::<i32>().value(0), "NM_004985.5");
+        assert!(first.column_by_name("is_selected").unwrap().as_boolean().value(0));
+        Ok(())
+    }
+}
@@ -1,17 +1,52 @@
 use crate::json_stream::stream_positions;
+use crate::transcript::{unversioned, TranscriptPolicy};
 use crate::types::*;
 use anyhow::Result;
 
+/// Run settings that change how annotations are read into a
+/// `VariantPosition`.
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Which transcript is reported
+    pub transcripts: TranscriptPolicy,
+    /// Which transcript's PrimateAI-3D/PrimateAI score to keep
+    pub score_transcripts: ScoreTranscriptPolicy,
+}
+
+impl ParseOptions {
+    /// Fails if the configured preferred-transcripts file can't be read.
+    pub fn from_config(config: &FilterConfig) -> Result<Self> {
+        Ok(Self {
+            transcripts: TranscriptPolicy::from_config(config)?,
+            score_transcripts: config.score_transcript_policy,
+        })
+    }
+}
+
 /// Streams `Position` records straight out of the gzipped Nirvana JSON,
 /// one at a time, without ever holding the whole file (or even the whole
 /// `positions` array) in memory as a `serde_json::Value` DOM. `on_position`
 /// is invoked once per position in file order; return `Err` from it to
 /// abort parsing early.
-pub fn parse_nirvana_streaming<F>(file_path: &str, mut on_position: F) -> Result<NirvanaHeader>
+pub fn parse_nirvana_streaming<F>(file_path: &str, on_position: F) -> Result<NirvanaHeader>
 where
     F: FnMut(Position) -> Result<()>,
 {
-    stream_positions::<Position, NirvanaHeader, _, _>(file_path, |_header| Ok(()), &mut on_position)
+    parse_nirvana_streaming_with_header(file_path, |_header| Ok(()), on_position)
+}
+
+/// `parse_nirvana_streaming` that also passes the header to `on_header` as
+/// soon as it has been read, which in Nirvana output is before any position.
+pub fn parse_nirvana_streaming_with_header<H, F>(
+    file_path: &str,
+    on_header: H,
+    mut on_position: F,
+) -> Result<NirvanaHeader>
+where
+    H: FnMut(&NirvanaHeader) -> Result<()>,
+    F: FnMut(Position) -> Result<()>,
+{
+    stream_positions::<Position, NirvanaHeader, _, _>(file_path, on_header, &mut on_position)
 }
 
 /// Convenience wrapper that collects every position into a `Vec`. Kept for
@@ -39,6 +74,14 @@ pub fn parse_nirvana_json(file_path: &str) -> Result<(NirvanaHeader, Vec<Variant
 /// position, which was pure overhead once `Position` is deserialized
 /// directly from the JSON stream.
 pub fn position_to_variant(position: Position) -> Result<Option<VariantPosition>> {
+    position_to_variant_with(position, &ParseOptions::default())
+}
+
+/// `position_to_variant` with explicit `ParseOptions`.
+pub fn position_to_variant_with(
+    position: Position,
+    options: &ParseOptions,
+) -> Result<Option<VariantPosition>> {
     if position.variants.is_empty() {
         return Ok(None);
     }
@@ -49,16 +92,9 @@ pub fn position_to_variant(position: Position) -> Result<Option<VariantPosition>
         .next()
         .expect("checked non-empty above");
 
-    let filters = if position.filters.is_empty() {
-        vec!["PASS".to_string()]
-    } else {
-        position.filters
-    };
-
-    let (total_depth, variant_frequencies) = match position.samples.into_iter().next() {
-        Some(sample) => (sample.total_depth, sample.variant_frequencies),
-        None => (None, None),
-    };
+    // Only the first sample column is reported; a position without sample
+    // data yields an all-`None` sample rather than an error.
+    let sample = position.samples.into_iter().next().unwrap_or_default();
 
     let alternate_allele = position
         .alternate_alleles
@@ -68,11 +104,19 @@ pub fn position_to_variant(position: Position) -> Result<Option<VariantPosition>
 
     let population_frequencies = extract_population_frequencies(&variant);
 
-    let primate_ai_3d = variant.primate_ai_3d.first().and_then(|entry| entry.score);
-    let primate_ai = variant
-        .primate_ai
-        .first()
-        .and_then(|entry| entry.score_percentile);
+    let selected_transcript_index = options.transcripts.select_index(&variant.transcripts);
+    let selected = selected_transcript_index.map(|i| &variant.transcripts[i]);
+
+    let (primate_ai_3d, primate_ai_3d_transcript) = select_primate_ai_3d(
+        &variant.primate_ai_3d,
+        &variant.transcripts,
+        selected,
+        options.score_transcripts,
+    )
+    .unzip();
+    let primate_ai = select_primate_ai(&variant.primate_ai, selected, options.score_transcripts);
+    // Nirvana reports a single REVEL score per allele (the dbNSFP maximum),
+    // so there is no transcript to match it against.
     let dann_score = variant.dann_score;
     let revel_score = variant.revel_score.as_ref().and_then(|rs| rs.score);
 
@@ -83,14 +127,25 @@ pub fn position_to_variant(position: Position) -> Result<Option<VariantPosition>
         reference_allele: position.reference_allele,
         alternate_allele,
         variant_type: variant.variant_type,
-        filters,
-        total_depth,
-        variant_frequencies,
+        // Left empty when Nirvana omitted the FILTER column; whether that
+        // counts as a pass is decided by the quality filter's policy.
+        filters: position.filters,
+        total_depth: sample.total_depth,
+        variant_frequencies: sample.variant_frequencies,
+        genotype: sample.genotype,
+        genotype_quality: sample.genotype_quality,
+        allele_depths: sample.allele_depths,
+        strand_bias: sample.strand_bias,
+        somatic_quality: sample.somatic_quality,
+        is_loss_of_heterozygosity: sample.is_loss_of_heterozygosity,
+        failed_filter: sample.failed_filter,
         transcripts: variant.transcripts,
+        selected_transcript_index,
         clinvar: variant.clinvar,
         cosmic: variant.cosmic,
         population_frequencies,
         primate_ai_3d,
+        primate_ai_3d_transcript: primate_ai_3d_transcript.flatten(),
         primate_ai,
         dann_score,
         revel_score,
@@ -98,6 +153,91 @@ pub fn position_to_variant(position: Position) -> Result<Option<VariantPosition>
     }))
 }
 
+/// Picks the PrimateAI-3D score (and its transcript) for the transcript that
+/// will be reported. If the selected transcript has no entry, the best entry
+/// among other transcripts of the same gene is used, so a score from an
+/// overlapping gene never ends up next to this gene's symbol. Variants
+/// without transcripts fall back to the maximum.
+fn select_primate_ai_3d(
+    entries: &[PrimateAI3DEntry],
+    transcripts: &[TranscriptAnnotation],
+    selected: Option<&TranscriptAnnotation>,
+    policy: ScoreTranscriptPolicy,
+) -> Option<(f64, Option<String>)> {
+    let prefer_refseq = selected.and_then(|t| t.source.as_deref()) == Some("RefSeq");
+
+    // Report the id from the same source as the selected transcript
+    let entry_id = |entry: &PrimateAI3DEntry| {
+        let (first, second) = if prefer_refseq {
+            (&entry.ref_seq_transcript_id, &entry.ensembl_transcript_id)
+        } else {
+            (&entry.ensembl_transcript_id, &entry.ref_seq_transcript_id)
+        };
+        first.clone().or_else(|| second.clone())
+    };
+    let best = |candidates: &mut dyn Iterator<Item = &PrimateAI3DEntry>| {
+        candidates
+            .filter_map(|entry| Some((entry.score?, entry)))
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(score, entry)| (score, entry_id(entry)))
+    };
+
+    // Entries without transcript ids can't be attributed; use them as is
+    let attributable = entries
+        .iter()
+        .any(|e| e.ensembl_transcript_id.is_some() || e.ref_seq_transcript_id.is_some());
+    let selected = match (policy, selected) {
+        (ScoreTranscriptPolicy::Selected, Some(selected)) if attributable => selected,
+        _ => return best(&mut entries.iter()),
+    };
+
+    let matches = |entry: &PrimateAI3DEntry, id: &str| {
+        [&entry.ensembl_transcript_id, &entry.ref_seq_transcript_id]
+            .into_iter()
+            .flatten()
+            .any(|entry_id| same_transcript(entry_id, id))
+    };
+
+    // A selected transcript without an id can still be matched by gene
+    let by_id = selected
+        .id
+        .as_deref()
+        .and_then(|selected_id| best(&mut entries.iter().filter(|e| matches(e, selected_id))));
+    by_id.or_else(|| {
+        let gene = selected.hgnc.as_deref()?;
+        let gene_ids: Vec<&str> = transcripts
+            .iter()
+            .filter(|t| t.hgnc.as_deref() == Some(gene))
+            .filter_map(|t| t.id.as_deref())
+            .collect();
+        best(&mut entries.iter().filter(|e| gene_ids.iter().any(|id| matches(e, id))))
+    })
+}
+
+/// Picks the (gene-level) PrimateAI percentile for the selected transcript's
+/// gene, or the maximum when `policy` is `Max` or there is no transcript.
+fn select_primate_ai(
+    entries: &[PrimateAIEntry],
+    selected: Option<&TranscriptAnnotation>,
+    policy: ScoreTranscriptPolicy,
+) -> Option<f64> {
+    let gene = selected.and_then(|t| t.hgnc.as_deref());
+    entries
+        .iter()
+        .filter(|entry| match (policy, gene) {
+            (ScoreTranscriptPolicy::Selected, Some(gene)) => entry.hgnc.as_deref() == Some(gene),
+            _ => true,
+        })
+        .filter_map(|entry| entry.score_percentile)
+        .max_by(f64::total_cmp)
+}
+
+/// Transcript ids match regardless of version suffix (`ENST00000269305.9`
+/// vs `ENST00000269305`).
+fn same_transcript(a: &str, b: &str) -> bool {
+    unversioned(a) == unversioned(b)
+}
+
 fn extract_population_frequencies(variant: &Variant) -> Vec<PopulationFrequency> {
     let mut result = Vec::new();
 
@@ -0,0 +1,209 @@
+/// Sample barcodes for the MAF `Tumor_Sample_Barcode` and
+/// `Matched_Norm_Sample_Barcode` columns.
+///
+/// The tumor barcode defaults to the name of the reported sample (the first
+/// one in the Nirvana header) and the normal barcode to the second one, each
+/// optionally mapped to a LIMS ID through a sample sheet; `--tumor-barcode`
+/// and `--normal-barcode` set them directly.
+use crate::config::OutputConfig;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Rejects barcodes that would break the tab-separated output.
+pub fn validate_barcode(barcode: &str) -> Result<()> {
+    if barcode.contains(['\t', '\n', '\r']) {
+        anyhow::bail!("sample barcode {:?} contains a tab or newline", barcode);
+    }
+    Ok(())
+}
+
+/// Nirvana sample name to LIMS barcode.
+#[derive(Debug, Clone, Default)]
+pub struct SampleSheet {
+    barcodes: BTreeMap<String, String>,
+}
+
+impl SampleSheet {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read sample sheet {}", path.display()))?;
+        Self::parse(&contents).with_context(|| format!("in {}", path.display()))
+    }
+
+    /// Parses `SAMPLE<TAB>BARCODE` lines. Blank lines and lines starting
+    /// with `#` are ignored.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut sheet = SampleSheet::default();
+        for (n, line) in contents.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (sample, barcode) = match line.split('\t').collect::<Vec<_>>()[..] {
+                [sample, barcode] if !sample.trim().is_empty() && !barcode.trim().is_empty() => {
+                    (sample.trim(), barcode.trim())
+                }
+                _ => anyhow::bail!("line {}: expected SAMPLE<TAB>BARCODE, got '{}'", n + 1, line),
+            };
+            if let Some(existing) = sheet.barcodes.get(sample) {
+                anyhow::bail!("line {}: {} is already mapped to {}", n + 1, sample, existing);
+            }
+            sheet.barcodes.insert(sample.to_string(), barcode.to_string());
+        }
+        Ok(sheet)
+    }
+
+    pub fn get(&self, sample: &str) -> Option<&str> {
+        self.barcodes.get(sample).map(String::as_str)
+    }
+}
+
+/// Barcode settings from the run configuration, resolved against the
+/// sample names once the Nirvana header has been read.
+#[derive(Debug, Clone, Default)]
+pub struct BarcodeSettings {
+    tumor: Option<String>,
+    normal: Option<String>,
+    sample_sheet: Option<SampleSheet>,
+}
+
+impl BarcodeSettings {
+    pub fn from_config(output: &OutputConfig) -> Result<Self> {
+        let sample_sheet = match &output.sample_sheet {
+            Some(path) => Some(SampleSheet::load(Path::new(path))?),
+            None => None,
+        };
+        Ok(Self {
+            tumor: output.tumor_barcode.clone(),
+            normal: output.normal_barcode.clone(),
+            sample_sheet,
+        })
+    }
+
+    /// The settings for one sample of a batch: `tumor` replaces the sample
+    /// name, and `normal`, when given, the configured normal barcode.
+    pub fn for_sample(&self, tumor: &str, normal: Option<&str>) -> Self {
+        Self {
+            tumor: Some(tumor.to_string()),
+            normal: normal.map(str::to_string).or_else(|| self.normal.clone()),
+            sample_sheet: None,
+        }
+    }
+
+    /// Tumor and normal barcodes for a file whose header lists `samples`
+    /// (tumor first, then the matched normal of a paired call). A sample
+    /// missing from the sample sheet is an error, so unmapped names never
+    /// reach the output.
+    pub fn resolve(&self, samples: &[String]) -> Result<(String, String)> {
+        let tumor = match (&self.tumor, samples.first()) {
+            (Some(tumor), _) => tumor.clone(),
+            (None, Some(sample)) => self.barcode(sample)?,
+            (None, None) => {
+                log::warn!("Nirvana header lists no samples; Tumor_Sample_Barcode will be empty");
+                String::new()
+            }
+        };
+        validate_barcode(&tumor)?;
+        // A tumor-only file has no normal; that is not worth a warning
+        let normal = match (&self.normal, samples.get(1)) {
+            (Some(normal), _) => normal.clone(),
+            (None, Some(sample)) => self.barcode(sample)?,
+            (None, None) => String::new(),
+        };
+        validate_barcode(&normal)?;
+        Ok((tumor, normal))
+    }
+
+    /// A header sample name, mapped through the sample sheet when there is one.
+    fn barcode(&self, sample: &str) -> Result<String> {
+        match &self.sample_sheet {
+            Some(sheet) => Ok(sheet
+                .get(sample)
+                .with_context(|| format!("sample '{}' is not in the sample sheet", sample))?
+                .to_string()),
+            None => Ok(sample.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_barcodes() {
+        let samples = vec!["TUMOR_01".to_string()];
+
+        let settings = BarcodeSettings::default();
+        assert_eq!(settings.resolve(&samples).unwrap(), ("TUMOR_01".to_string(), String::new()));
+
+        let settings = BarcodeSettings {
+            sample_sheet: Some(SampleSheet::parse("# sample\tlims\nTUMOR_01\tLIMS-0042\n").unwrap()),
+            normal: Some("LIMS-0043".to_string()),
+            ..BarcodeSettings::default()
+        };
+        assert_eq!(
+            settings.resolve(&samples).unwrap(),
+            ("LIMS-0042".to_string(), "LIMS-0043".to_string())
+        );
+        assert!(settings.resolve(&["OTHER".to_string()]).is_err());
+
+        let settings = BarcodeSettings {
+            tumor: Some("OVERRIDE".to_string()),
+            ..settings
+        };
+        assert_eq!(settings.resolve(&samples).unwrap().0, "OVERRIDE");
+    }
+
+    #[test]
+    fn test_resolve_normal_from_header() {
+        let samples = vec!["TUMOR_01".to_string(), "NORMAL_01".to_string()];
+
+        let settings = BarcodeSettings::default();
+        assert_eq!(
+            settings.resolve(&samples).unwrap(),
+            ("TUMOR_01".to_string(), "NORMAL_01".to_string())
+        );
+
+        let settings = BarcodeSettings {
+            sample_sheet: Some(SampleSheet::parse("TUMOR_01\tLIMS-0042\nNORMAL_01\tLIMS-0043\n").unwrap()),
+            ..BarcodeSettings::default()
+        };
+        assert_eq!(
+            settings.resolve(&samples).unwrap(),
+            ("LIMS-0042".to_string(), "LIMS-0043".to_string())
+        );
+        // The normal must be in the sheet too
+        let settings = BarcodeSettings {
+            sample_sheet: Some(SampleSheet::parse("TUMOR_01\tLIMS-0042\n").unwrap()),
+            ..BarcodeSettings::default()
+        };
+        assert!(settings.resolve(&samples).is_err());
+
+        let settings = BarcodeSettings {
+            normal: Some("OVERRIDE".to_string()),
+            ..settings
+        };
+        assert_eq!(settings.resolve(&samples).unwrap().1, "OVERRIDE");
+    }
+
+    #[test]
+    fn test_invalid_barcodes() {
+        assert!(validate_barcode("S1\tS2").is_err());
+        assert!(validate_barcode("S1\n").is_err());
+        assert!(SampleSheet::parse("TUMOR_01\n").is_err());
+        assert!(SampleSheet::parse("TUMOR_01\tA\nTUMOR_01\tB\n").is_err());
+        assert!(BarcodeSettings::default().resolve(&["bad\tname".to_string()]).is_err());
+
+        let samples = ["TUMOR_01".to_string()];
+        let settings = BarcodeSettings {
+            normal: Some("NORMAL\t01".to_string()),
+            ..BarcodeSettings::default()
+        };
+        assert!(settings.resolve(&samples).is_err());
+        // A batch manifest's normal_id goes through the same check
+        let settings = BarcodeSettings::default().for_sample("TUMOR_01", Some("NORMAL_01\n"));
+        assert!(settings.resolve(&samples).is_err());
+    }
+}
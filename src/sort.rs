@@ -0,0 +1,252 @@
+/// Genomic ordering of output records.
+///
+/// Records are ordered karyotypically (chr1..chr22, X, Y, M, then any other
+/// contig by name), then by position, reference and alternate allele.
+/// `ExternalSorter` holds at most one chunk of records in memory; full
+/// chunks are sorted and spilled to anonymous temporary files, which are
+/// k-way merged at the end, so sorting keeps the O(batch_size) memory bound
+/// of the streaming pipeline.
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Spilled runs are merged down to one whenever this many are open, which
+/// bounds the number of open files for very large outputs.
+const MAX_OPEN_RUNS: usize = 64;
+
+/// Rank of contigs outside chr1..chr22, X, Y and M; they sort by name.
+const OTHER_CONTIG_RANK: u32 = 26;
+
+/// Karyotypic rank of a chromosome name, with or without a `chr` prefix:
+/// 1-22 for autosomes, then 23 for X, 24 for Y and 25 for M/MT.
+pub fn chromosome_rank(chromosome: &str) -> u32 {
+    let name = chromosome
+        .strip_prefix("chr")
+        .or_else(|| chromosome.strip_prefix("Chr"))
+        .or_else(|| chromosome.strip_prefix("CHR"))
+        .unwrap_or(chromosome);
+    match name {
+        "X" | "x" => 23,
+        "Y" | "y" => 24,
+        "M" | "m" | "MT" | "Mt" => 25,
+        _ => match name.parse::<u32>() {
+            Ok(n) if (1..=22).contains(&n) && !name.starts_with('0') => n,
+            _ => OTHER_CONTIG_RANK,
+        },
+    }
+}
+
+/// Sort key of one output record.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct SortKey {
+    rank: u32,
+    chromosome: String,
+    position: i64,
+    reference: String,
+    alternate: String,
+}
+
+impl SortKey {
+    pub fn new(chromosome: &str, position: i64, reference: &str, alternate: &str) -> Self {
+        Self {
+            rank: chromosome_rank(chromosome),
+            chromosome: chromosome.to_string(),
+            position,
+            reference: reference.to_string(),
+            alternate: alternate.to_string(),
+        }
+    }
+}
+
+/// Sorts records by `SortKey` in bounded memory. Records with equal keys
+/// keep their insertion order, so the rows of one variant stay together.
+pub struct ExternalSorter<T> {
+    chunk_size: usize,
+    temp_dir: PathBuf,
+    buffer: Vec<(SortKey, T)>,
+    runs: Vec<File>,
+}
+
+impl<T: Serialize + DeserializeOwned> ExternalSorter<T> {
+    /// Creates a sorter holding up to `chunk_size` records in memory and
+    /// spilling to temporary files in `temp_dir`.
+    pub fn new(chunk_size: usize, temp_dir: &Path) -> Self {
+        let chunk_size = chunk_size.max(1);
+        Self {
+            chunk_size,
+            temp_dir: temp_dir.to_path_buf(),
+            buffer: Vec::with_capacity(chunk_size),
+            runs: Vec::new(),
+        }
+    }
+
+    /// A sorter spilling next to `output_path`, so temporary data lands on
+    /// the same filesystem as the output.
+    pub fn for_output(chunk_size: usize, output_path: &Path) -> Self {
+        let dir = match output_path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        Self::new(chunk_size, dir)
+    }
+
+    pub fn push(&mut self, key: SortKey, record: T) -> Result<()> {
+        self.buffer.push((key, record));
+        if self.buffer.len() >= self.chunk_size {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Sorts the buffered chunk and writes it out as a new run.
+    fn spill(&mut self) -> Result<()> {
+        self.buffer.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut file = self.create_run()?;
+        {
+            let mut out = BufWriter::new(&mut file);
+            for entry in self.buffer.drain(..) {
+                write_entry(&mut out, &entry)?;
+            }
+            out.flush().context("Failed to write sort run")?;
+        }
+        self.runs.push(file);
+
+        if self.runs.len() >= MAX_OPEN_RUNS {
+            let runs = std::mem::take(&mut self.runs);
+            let mut merged = self.create_run()?;
+            {
+                let mut out = BufWriter::new(&mut merged);
+                merge_runs(runs, |entry: (SortKey, T)| write_entry(&mut out, &entry))?;
+                out.flush().context("Failed to write sort run")?;
+            }
+            self.runs.push(merged);
+        }
+        Ok(())
+    }
+
+    fn create_run(&self) -> Result<File> {
+        tempfile::tempfile_in(&self.temp_dir)
+            .with_context(|| format!("Failed to create sort run in {}", self.temp_dir.display()))
+    }
+
+    /// Passes every record to `f` in sorted order.
+    pub fn finish(mut self, mut f: impl FnMut(T) -> Result<()>) -> Result<()> {
+        if self.runs.is_empty() {
+            self.buffer.sort_by(|a, b| a.0.cmp(&b.0));
+            for (_, record) in self.buffer {
+                f(record)?;
+            }
+            return Ok(());
+        }
+        if !self.buffer.is_empty() {
+            self.spill()?;
+        }
+        merge_runs(self.runs, |(_, record)| f(record))
+    }
+}
+
+fn write_entry<T: Serialize>(out: &mut impl Write, entry: &(SortKey, T)) -> Result<()> {
+    serde_json::to_writer(&mut *out, entry).context("Failed to write sort run")?;
+    out.write_all(b"\n").context("Failed to write sort run")
+}
+
+fn read_entry<T: DeserializeOwned>(reader: &mut impl BufRead, line: &mut String) -> Result<Option<(SortKey, T)>> {
+    line.clear();
+    if reader.read_line(line).context("Failed to read sort run")? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(line).context("Corrupt sort run")?))
+}
+
+/// K-way merge of sorted runs. Ties go to the earlier run, which keeps the
+/// sort stable.
+fn merge_runs<T: DeserializeOwned>(
+    runs: Vec<File>,
+    mut f: impl FnMut((SortKey, T)) -> Result<()>,
+) -> Result<()> {
+    let mut readers = Vec::with_capacity(runs.len());
+    for mut file in runs {
+        file.seek(SeekFrom::Start(0)).context("Failed to rewind sort run")?;
+        readers.push(BufReader::new(file));
+    }
+
+    let mut line = String::new();
+    let mut pending: Vec<Option<T>> = Vec::with_capacity(readers.len());
+    let mut heap = BinaryHeap::new();
+    for (index, reader) in readers.iter_mut().enumerate() {
+        match read_entry(reader, &mut line)? {
+            Some((key, record)) => {
+                heap.push(Reverse((key, index)));
+                pending.push(Some(record));
+            }
+            None => pending.push(None),
+        }
+    }
+
+    while let Some(Reverse((key, index))) = heap.pop() {
+        let record = pending[index].take().context("Sort run out of step")?;
+        if let Some((next_key, next)) = read_entry(&mut readers[index], &mut line)? {
+            heap.push(Reverse((next_key, index)));
+            pending[index] = Some(next);
+        }
+        f((key, record))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_chromosome_rank() {
+        assert_eq!(chromosome_rank("chr1"), 1);
+        assert_eq!(chromosome_rank("22"), 22);
+        assert_eq!(chromosome_rank("chrX"), 23);
+        assert_eq!(chromosome_rank("Y"), 24);
+        assert_eq!(chromosome_rank("chrM"), 25);
+        assert_eq!(chromosome_rank("MT"), 25);
+        assert_eq!(chromosome_rank("chr1_KI270706v1_random"), OTHER_CONTIG_RANK);
+        assert_eq!(chromosome_rank("chr23"), OTHER_CONTIG_RANK);
+        assert_eq!(chromosome_rank("chr01"), OTHER_CONTIG_RANK);
+    }
+
+    #[test]
+    fn test_external_sort() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let chroms = ["chr10", "chr2", "chrX", "chrM", "chrUn_gl000220", "chr1", "chrY"];
+        let mut expected = Vec::new();
+        // Chunks of 3 force spills, and 300 records force a merge of runs
+        let mut sorter = ExternalSorter::new(3, temp_dir.path());
+        for i in 0..300i64 {
+            let chrom = chroms[(i as usize * 3) % chroms.len()];
+            let position = (i * 7919) % 50;
+            let key = SortKey::new(chrom, position, "A", "T");
+            expected.push((key.clone(), i));
+            sorter.push(key, i)?;
+        }
+        expected.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut sorted = Vec::new();
+        sorter.finish(|i| {
+            sorted.push(i);
+            Ok(())
+        })?;
+        assert_eq!(sorted, expected.iter().map(|(_, i)| *i).collect::<Vec<_>>());
+
+        let order: Vec<&str> = expected.iter().map(|(k, _)| k.chromosome.as_str()).collect();
+        let first = |c: &str| order.iter().position(|o| *o == c).unwrap();
+        assert!(first("chr1") < first("chr2"));
+        assert!(first("chr2") < first("chr10"));
+        assert!(first("chr10") < first("chrX"));
+        assert!(first("chrY") < first("chrM"));
+        assert!(first("chrM") < first("chrUn_gl000220"));
+        Ok(())
+    }
+}
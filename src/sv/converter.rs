@@ -3,10 +3,12 @@
 /// Key decisions made here:
 /// - **Gene symbol**: collected from all transcripts, deduplicated, sorted
 /// - **Consequence**: most severe term wins (priority list mirrors VEP severity)
-/// - **Transcript**: MANE Select preferred; fallback to first transcript with HGVSc
+/// - **Transcript**: chosen by a `TranscriptPolicy` (default: preferred list,
+///   MANE Select, source, then any transcript with HGVSc)
 /// - **ClinGen**: pathogenic entry preferred over first available
 use std::collections::BTreeSet;
 
+use crate::transcript::TranscriptPolicy;
 use crate::TranscriptAnnotation;
 use super::types::*;
 
@@ -35,8 +37,14 @@ const CONSEQUENCE_SEVERITY: &[&str] = &[
 ];
 
 pub fn sv_position_to_record(pos: &SVPosition) -> SVRecord {
+    sv_position_to_record_with(pos, &TranscriptPolicy::sv_default())
+}
+
+/// `sv_position_to_record` with an explicit transcript selection policy,
+/// shared with the SNV converter.
+pub fn sv_position_to_record_with(pos: &SVPosition, policy: &TranscriptPolicy) -> SVRecord {
     let hugo_symbol = collect_gene_symbols(&pos.transcripts);
-    let best_tx = select_best_transcript(&pos.transcripts);
+    let best_tx = policy.select(&pos.transcripts);
 
     let all_consequences: Vec<String> = pos.transcripts
         .iter()
@@ -94,17 +102,6 @@ fn collect_gene_symbols(transcripts: &[TranscriptAnnotation]) -> String {
     genes.into_iter().collect::<Vec<_>>().join(";")
 }
 
-/// Selects the most relevant transcript for HGVSc/HGVSp annotation.
-/// Priority: MANE Select with HGVSc > MANE Select > any with HGVSc > first
-fn select_best_transcript(transcripts: &[TranscriptAnnotation]) -> Option<&TranscriptAnnotation> {
-    transcripts
-        .iter()
-        .find(|t| t.is_mane_select == Some(true) && t.hgvsc.is_some())
-        .or_else(|| transcripts.iter().find(|t| t.is_mane_select == Some(true)))
-        .or_else(|| transcripts.iter().find(|t| t.hgvsc.is_some()))
-        .or_else(|| transcripts.first())
-}
-
 /// Returns the most severe consequence term found in the list.
 fn pick_most_severe_consequence(consequences: &[String]) -> String {
     for &severe in CONSEQUENCE_SEVERITY {
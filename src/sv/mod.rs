@@ -3,7 +3,7 @@ pub mod parser;
 pub mod types;
 pub mod writer;
 
-pub use converter::sv_position_to_record;
+pub use converter::{sv_position_to_record, sv_position_to_record_with};
 pub use parser::{parse_sv_nirvana_json, parse_sv_nirvana_streaming};
 pub use types::{SVPosition, SVRecord, SVType};
 pub use writer::SVWriter;
@@ -1,7 +1,7 @@
 /// Tab-separated output writer for SV records.
+use crate::output::{FileOptions, OutputFile};
 use anyhow::Result;
-use std::fs::File;
-use std::io::{BufWriter, Write};
+use std::io::Write;
 
 use super::types::SVRecord;
 
@@ -31,13 +31,17 @@ pub const SV_TSV_HEADERS: &[&str] = &[
 ];
 
 pub struct SVWriter {
-    inner: BufWriter<File>,
+    inner: OutputFile,
 }
 
 impl SVWriter {
+    /// Writes straight to `path`, like `MAFWriter::new`.
     pub fn new(path: &str) -> Result<Self> {
-        let file = File::create(path)?;
-        let mut inner = BufWriter::new(file);
+        Self::with_options(path, FileOptions::direct())
+    }
+
+    pub fn with_options(path: &str, options: FileOptions) -> Result<Self> {
+        let mut inner = OutputFile::create(path, options)?;
         writeln!(inner, "{}", SV_TSV_HEADERS.join("\t"))?;
         Ok(Self { inner })
     }
@@ -75,4 +79,9 @@ impl SVWriter {
     pub fn flush(&mut self) -> Result<()> {
         self.inner.flush().map_err(Into::into)
     }
+
+    /// Flushes the file and commits it to its final path.
+    pub fn finish(&mut self) -> Result<()> {
+        self.inner.commit()
+    }
 }
@@ -0,0 +1,429 @@
+//! Choosing the transcript reported for a variant (SNV and SV output alike).
+//!
+//! A `TranscriptPolicy` is an ordered chain of criteria. Starting from all
+//! transcripts, each criterion keeps the candidates that satisfy it best; a
+//! criterion that no candidate satisfies is skipped. Ties left at the end of
+//! the chain go to the transcript listed first in the annotation.
+use crate::consequence::{most_severe, severity_rank};
+use crate::types::TranscriptAnnotation;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+/// One step of a transcript selection policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TranscriptCriterion {
+    /// Listed in the preferred-transcripts file
+    Preferred,
+    /// `isManeSelect`
+    ManeSelect,
+    /// `isManePlusClinical`, for annotation versions that report it
+    ManePlusClinical,
+    /// `isCanonical`
+    Canonical,
+    /// From the preferred source (RefSeq or Ensembl); no-op when none is set
+    Source,
+    /// Most severe consequence term
+    MostSevere,
+    /// Longest CDS, when the annotation gives the CDS length ("start/length")
+    LongestCds,
+    /// Has an HGVSc description
+    HasHgvsc,
+}
+
+impl TranscriptCriterion {
+    pub const ALL: &'static [TranscriptCriterion] = &[
+        TranscriptCriterion::Preferred,
+        TranscriptCriterion::ManeSelect,
+        TranscriptCriterion::ManePlusClinical,
+        TranscriptCriterion::Canonical,
+        TranscriptCriterion::Source,
+        TranscriptCriterion::MostSevere,
+        TranscriptCriterion::LongestCds,
+        TranscriptCriterion::HasHgvsc,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TranscriptCriterion::Preferred => "preferred",
+            TranscriptCriterion::ManeSelect => "mane_select",
+            TranscriptCriterion::ManePlusClinical => "mane_plus_clinical",
+            TranscriptCriterion::Canonical => "canonical",
+            TranscriptCriterion::Source => "source",
+            TranscriptCriterion::MostSevere => "most_severe",
+            TranscriptCriterion::LongestCds => "longest_cds",
+            TranscriptCriterion::HasHgvsc => "has_hgvsc",
+        }
+    }
+}
+
+impl std::fmt::Display for TranscriptCriterion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for TranscriptCriterion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let normalized = s.trim().to_lowercase().replace('-', "_");
+        TranscriptCriterion::ALL
+            .iter()
+            .copied()
+            .find(|c| c.as_str() == normalized)
+            .with_context(|| {
+                let names: Vec<&str> = TranscriptCriterion::ALL.iter().map(|c| c.as_str()).collect();
+                format!("invalid transcript criterion '{}' (expected one of {})", s, names.join(", "))
+            })
+    }
+}
+
+/// Transcript source, as reported in Nirvana's `source` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TranscriptSource {
+    RefSeq,
+    Ensembl,
+}
+
+impl TranscriptSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TranscriptSource::RefSeq => "RefSeq",
+            TranscriptSource::Ensembl => "Ensembl",
+        }
+    }
+
+    pub fn matches(&self, transcript: &TranscriptAnnotation) -> bool {
+        transcript
+            .source
+            .as_deref()
+            .is_some_and(|s| s.eq_ignore_ascii_case(self.as_str()))
+    }
+}
+
+impl std::fmt::Display for TranscriptSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for TranscriptSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "refseq" => Ok(TranscriptSource::RefSeq),
+            "ensembl" => Ok(TranscriptSource::Ensembl),
+            _ => anyhow::bail!("invalid transcript source '{}' (expected refseq or ensembl)", s),
+        }
+    }
+}
+
+/// Default chain for SNV/indel output: lab list, then MANE Select, then the
+/// preferred source. Without a list or source this is the historical
+/// "MANE Select, else first" rule.
+pub const DEFAULT_TRANSCRIPT_POLICY: &[TranscriptCriterion] = &[
+    TranscriptCriterion::Preferred,
+    TranscriptCriterion::ManeSelect,
+    TranscriptCriterion::Source,
+];
+
+/// Default chain for SV output, which also needs an HGVSc description.
+pub const SV_TRANSCRIPT_POLICY: &[TranscriptCriterion] = &[
+    TranscriptCriterion::Preferred,
+    TranscriptCriterion::ManeSelect,
+    TranscriptCriterion::Source,
+    TranscriptCriterion::HasHgvsc,
+];
+
+/// Lab-approved transcripts, one accession per gene. Accessions match
+/// regardless of version suffix.
+#[derive(Debug, Clone, Default)]
+pub struct PreferredTranscripts {
+    by_gene: BTreeMap<String, String>,
+    accessions: HashSet<String>,
+}
+
+impl PreferredTranscripts {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read preferred transcripts file {}", path.display()))?;
+        Self::parse(&contents).with_context(|| format!("in {}", path.display()))
+    }
+
+    /// Parses `GENE<whitespace>ACCESSION` lines. Blank lines and lines
+    /// starting with `#` are ignored.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut preferred = PreferredTranscripts::default();
+        for (n, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (gene, accession) = match fields[..] {
+                [gene, accession] => (gene, accession),
+                _ => anyhow::bail!("line {}: expected GENE and ACCESSION, got '{}'", n + 1, line),
+            };
+            if let Some(existing) = preferred.by_gene.get(gene) {
+                anyhow::bail!(
+                    "line {}: {} already has preferred transcript {} (one per gene)",
+                    n + 1,
+                    gene,
+                    existing
+                );
+            }
+            preferred.accessions.insert(unversioned(accession).to_string());
+            preferred.by_gene.insert(gene.to_string(), accession.to_string());
+        }
+        Ok(preferred)
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_gene.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_gene.is_empty()
+    }
+
+    /// Preferred accession for a gene, as written in the file.
+    pub fn get(&self, gene: &str) -> Option<&str> {
+        self.by_gene.get(gene).map(String::as_str)
+    }
+
+    pub fn contains(&self, transcript: &TranscriptAnnotation) -> bool {
+        transcript
+            .id
+            .as_deref()
+            .is_some_and(|id| self.accessions.contains(unversioned(id)))
+    }
+}
+
+/// A transcript selection policy: criteria chain, source preference and
+/// lab-preferred transcripts.
+#[derive(Debug, Clone)]
+pub struct TranscriptPolicy {
+    pub criteria: Vec<TranscriptCriterion>,
+    pub source: Option<TranscriptSource>,
+    pub preferred: PreferredTranscripts,
+}
+
+impl Default for TranscriptPolicy {
+    fn default() -> Self {
+        Self {
+            criteria: DEFAULT_TRANSCRIPT_POLICY.to_vec(),
+            source: None,
+            preferred: PreferredTranscripts::default(),
+        }
+    }
+}
+
+impl TranscriptPolicy {
+    pub fn sv_default() -> Self {
+        Self {
+            criteria: SV_TRANSCRIPT_POLICY.to_vec(),
+            ..Self::default()
+        }
+    }
+
+    /// Builds the policy from a `FilterConfig`, loading the preferred
+    /// transcripts file if one is configured.
+    pub fn from_config(config: &crate::types::FilterConfig) -> Result<Self> {
+        let preferred = match &config.preferred_transcripts {
+            Some(path) => PreferredTranscripts::load(Path::new(path))?,
+            None => PreferredTranscripts::default(),
+        };
+        Ok(Self {
+            criteria: config.transcript_policy.clone(),
+            source: config.transcript_source,
+            preferred,
+        })
+    }
+
+    pub fn select<'a>(&self, transcripts: &'a [TranscriptAnnotation]) -> Option<&'a TranscriptAnnotation> {
+        self.select_index(transcripts).map(|i| &transcripts[i])
+    }
+
+    /// Index of the selected transcript, or `None` if there are none.
+    pub fn select_index(&self, transcripts: &[TranscriptAnnotation]) -> Option<usize> {
+        select_index(&self.criteria, self.source, Some(&self.preferred), transcripts)
+    }
+}
+
+/// Checks a criteria chain for duplicates.
+pub fn validate_policy(criteria: &[TranscriptCriterion]) -> Result<()> {
+    for (i, criterion) in criteria.iter().enumerate() {
+        if criteria[..i].contains(criterion) {
+            anyhow::bail!("transcript_policy lists '{}' more than once", criterion);
+        }
+    }
+    Ok(())
+}
+
+/// Selection with the default policy and no preferred transcripts or source.
+pub(crate) fn select_default_index(transcripts: &[TranscriptAnnotation]) -> Option<usize> {
+    select_index(DEFAULT_TRANSCRIPT_POLICY, None, None, transcripts)
+}
+
+fn select_index(
+    criteria: &[TranscriptCriterion],
+    source: Option<TranscriptSource>,
+    preferred: Option<&PreferredTranscripts>,
+    transcripts: &[TranscriptAnnotation],
+) -> Option<usize> {
+    let mut candidates: Vec<usize> = (0..transcripts.len()).collect();
+
+    for criterion in criteria {
+        if candidates.len() <= 1 {
+            break;
+        }
+        let keep = |pred: &dyn Fn(&TranscriptAnnotation) -> bool| -> Vec<usize> {
+            candidates.iter().copied().filter(|&i| pred(&transcripts[i])).collect()
+        };
+        let narrowed = match criterion {
+            TranscriptCriterion::Preferred => match preferred {
+                Some(preferred) if !preferred.is_empty() => keep(&|t| preferred.contains(t)),
+                _ => continue,
+            },
+            TranscriptCriterion::ManeSelect => keep(&|t| t.is_mane_select == Some(true)),
+            TranscriptCriterion::ManePlusClinical => keep(&|t| t.is_mane_plus_clinical == Some(true)),
+            TranscriptCriterion::Canonical => keep(&|t| t.is_canonical == Some(true)),
+            TranscriptCriterion::Source => match source {
+                Some(source) => keep(&|t| source.matches(t)),
+                None => continue,
+            },
+            TranscriptCriterion::HasHgvsc => keep(&|t| t.hgvsc.is_some()),
+            TranscriptCriterion::MostSevere => {
+                let rank = |i: usize| most_severe(&transcripts[i].consequence).map_or(usize::MAX, severity_rank);
+                let best = candidates.iter().map(|&i| rank(i)).min().unwrap_or(usize::MAX);
+                candidates.iter().copied().filter(|&i| rank(i) == best).collect()
+            }
+            TranscriptCriterion::LongestCds => {
+                let longest = candidates.iter().filter_map(|&i| cds_length(&transcripts[i])).max();
+                match longest {
+                    Some(longest) => keep(&|t| cds_length(t) == Some(longest)),
+                    None => continue,
+                }
+            }
+        };
+        if !narrowed.is_empty() {
+            candidates = narrowed;
+        }
+    }
+
+    candidates.first().copied()
+}
+
+/// CDS length from a `cdsPos` of the form "start[-end]/length", or from
+/// `proteinPos` ("start/length", in codons).
+fn cds_length(transcript: &TranscriptAnnotation) -> Option<u32> {
+    let length = |pos: Option<&str>| -> Option<u32> { pos?.split_once('/')?.1.trim().parse().ok() };
+    length(transcript.cds_pos.as_deref())
+        .or_else(|| length(transcript.protein_pos.as_deref()).map(|codons| codons * 3))
+}
+
+/// Whether a transcript codes for protein: Nirvana's `bioType` when present,
+/// otherwise whether it has a CDS position or protein change.
+pub fn is_protein_coding(transcript: &TranscriptAnnotation) -> bool {
+    match transcript.bio_type.as_deref() {
+        Some(bio_type) => bio_type == "protein_coding",
+        None => transcript.cds_pos.is_some() || transcript.hgvsp.is_some(),
+    }
+}
+
+/// Accession without its version suffix (`NM_000546.6` -> `NM_000546`).
+pub fn unversioned(id: &str) -> &str {
+    id.split_once('.').map_or(id, |(base, _)| base)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_policy_prefers_mane_then_first() {
+        let transcripts = vec![
+            create_transcript("XM_011521040.2", "RefSeq", &["missense_variant"]),
+            TranscriptAnnotation {
+                is_mane_select: Some(true),
+                ..create_transcript("NM_000546.6", "RefSeq", &["missense_variant"])
+            },
+        ];
+        assert_eq!(TranscriptPolicy::default().select_index(&transcripts), Some(1));
+        assert_eq!(TranscriptPolicy::default().select_index(&transcripts[..1]), Some(0));
+        assert_eq!(TranscriptPolicy::default().select_index(&[]), None);
+    }
+
+    #[test]
+    fn test_policy_chain() {
+        let transcripts = vec![
+            create_transcript("XM_011521040.2", "RefSeq", &["intron_variant"]),
+            create_transcript("ENST00000269305.9", "Ensembl", &["stop_gained"]),
+            TranscriptAnnotation {
+                is_canonical: Some(true),
+                cds_pos: Some("120/1182".to_string()),
+                ..create_transcript("NM_001126112.3", "RefSeq", &["missense_variant"])
+            },
+            TranscriptAnnotation {
+                is_canonical: Some(true),
+                cds_pos: Some("120/1146".to_string()),
+                ..create_transcript("NM_001126113.3", "RefSeq", &["missense_variant"])
+            },
+        ];
+
+        let policy = |criteria: &[TranscriptCriterion], source| TranscriptPolicy {
+            criteria: criteria.to_vec(),
+            source,
+            preferred: PreferredTranscripts::default(),
+        };
+        use TranscriptCriterion::*;
+        assert_eq!(policy(&[MostSevere], None).select_index(&transcripts), Some(1));
+        assert_eq!(policy(&[Canonical, LongestCds], None).select_index(&transcripts), Some(2));
+        assert_eq!(
+            policy(&[Source, MostSevere], Some(TranscriptSource::RefSeq)).select_index(&transcripts),
+            Some(2)
+        );
+        // Unsatisfiable criteria are skipped
+        assert_eq!(policy(&[ManePlusClinical, MostSevere], None).select_index(&transcripts), Some(1));
+
+        let mut with_list = policy(DEFAULT_TRANSCRIPT_POLICY, None);
+        with_list.preferred = PreferredTranscripts::parse("# lab list\nTP53\tNM_001126113.2\n").unwrap();
+        assert_eq!(with_list.preferred.get("TP53"), Some("NM_001126113.2"));
+        assert_eq!(with_list.select_index(&transcripts), Some(3));
+    }
+
+    #[test]
+    fn test_parse_preferred_transcripts() {
+        assert!(PreferredTranscripts::parse("TP53 NM_000546.6\nTP53 NM_001126112.3\n").is_err());
+        assert!(PreferredTranscripts::parse("TP53\n").is_err());
+        assert_eq!("has-hgvsc".parse::<TranscriptCriterion>().unwrap(), TranscriptCriterion::HasHgvsc);
+        assert!(validate_policy(&[TranscriptCriterion::Canonical, TranscriptCriterion::Canonical]).is_err());
+    }
+
+    fn create_transcript(id: &str, source: &str, consequence: &[&str]) -> TranscriptAnnotation {
+        TranscriptAnnotation {
+            id: Some(id.to_string()),
+            source: Some(source.to_string()),
+            hgnc: Some("TP53".to_string()),
+            consequence: consequence.iter().map(|c| c.to_string()).collect(),
+            impact: None,
+            amino_acids: None,
+            cdna_pos: None,
+            cds_pos: None,
+            exons: None,
+            codons: None,
+            protein_pos: None,
+            hgvsc: None,
+            hgvsp: None,
+            is_canonical: None,
+            is_mane_select: None,
+            is_mane_plus_clinical: None,
+            bio_type: None,
+        }
+    }
+}
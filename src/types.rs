@@ -1,44 +1,456 @@
+use crate::consequence::Impact;
+use crate::transcript::{TranscriptCriterion, TranscriptSource};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 
 // ============================================================================
 // Filter Configuration
 // ============================================================================
 
-#[derive(Debug, Clone)]
+/// What to do with a variant when the data a check needs is absent
+/// (no sample depth/VAF or per-sample threshold field, no population allele
+/// frequency).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MissingDataPolicy {
+    /// Skip the check and let the variant through (historical behavior)
+    #[default]
+    Pass,
+    /// Reject the variant at the quality stage
+    Fail,
+    /// Let the variant through but record a QC flag on it
+    Flag,
+}
+
+impl MissingDataPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MissingDataPolicy::Pass => "pass",
+            MissingDataPolicy::Fail => "fail",
+            MissingDataPolicy::Flag => "flag",
+        }
+    }
+}
+
+impl std::fmt::Display for MissingDataPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for MissingDataPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_lowercase().as_str() {
+            "pass" => Ok(MissingDataPolicy::Pass),
+            "fail" => Ok(MissingDataPolicy::Fail),
+            "flag" => Ok(MissingDataPolicy::Flag),
+            _ => anyhow::bail!("invalid missing-data policy '{}' (expected pass, fail or flag)", s),
+        }
+    }
+}
+
+/// Which transcripts the consequence/impact filters look at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsequenceScope {
+    /// Only the transcript chosen for output (`VariantPosition::selected_transcript`)
+    #[default]
+    SelectedTranscript,
+    /// Pass if any overlapping transcript satisfies the filters
+    AnyTranscript,
+}
+
+/// How predictive scores are combined into a pathogenicity suggestion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PredictiveModel {
+    /// PrimateAI-3D alone, or any two of REVEL/DANN/PrimateAI-3D/COSMIC
+    /// crossing their thresholds (historical behavior)
+    #[default]
+    Votes,
+    /// Sum of `score_weights` for the scores crossing their thresholds,
+    /// compared against `min_predictive_weight`
+    Weighted,
+    /// ACMG/AMP PP3/BP4 evidence strength from `score_calibrations`,
+    /// compared against `min_evidence_strength`
+    Calibrated,
+}
+
+impl PredictiveModel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PredictiveModel::Votes => "votes",
+            PredictiveModel::Weighted => "weighted",
+            PredictiveModel::Calibrated => "calibrated",
+        }
+    }
+}
+
+impl std::fmt::Display for PredictiveModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for PredictiveModel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_lowercase().as_str() {
+            "votes" => Ok(PredictiveModel::Votes),
+            "weighted" => Ok(PredictiveModel::Weighted),
+            "calibrated" => Ok(PredictiveModel::Calibrated),
+            _ => anyhow::bail!("invalid predictive model '{}' (expected votes, weighted or calibrated)", s),
+        }
+    }
+}
+
+/// Strength of computational evidence under the ACMG/AMP framework:
+/// PP3 (pathogenic) or BP4 (benign) at supporting, moderate or strong level.
+/// Ordered from strongest benign to strongest pathogenic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EvidenceStrength {
+    BenignStrong,
+    BenignModerate,
+    BenignSupporting,
+    /// Score between the benign and pathogenic intervals, or conflicting tools
+    #[default]
+    Indeterminate,
+    PathogenicSupporting,
+    PathogenicModerate,
+    PathogenicStrong,
+}
+
+impl EvidenceStrength {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EvidenceStrength::BenignStrong => "benign_strong",
+            EvidenceStrength::BenignModerate => "benign_moderate",
+            EvidenceStrength::BenignSupporting => "benign_supporting",
+            EvidenceStrength::Indeterminate => "indeterminate",
+            EvidenceStrength::PathogenicSupporting => "pathogenic_supporting",
+            EvidenceStrength::PathogenicModerate => "pathogenic_moderate",
+            EvidenceStrength::PathogenicStrong => "pathogenic_strong",
+        }
+    }
+
+    /// ACMG/AMP criterion label, e.g. `PP3_Moderate` or `BP4`.
+    pub fn acmg_code(&self) -> &'static str {
+        match self {
+            EvidenceStrength::BenignStrong => "BP4_Strong",
+            EvidenceStrength::BenignModerate => "BP4_Moderate",
+            EvidenceStrength::BenignSupporting => "BP4",
+            EvidenceStrength::Indeterminate => "Indeterminate",
+            EvidenceStrength::PathogenicSupporting => "PP3",
+            EvidenceStrength::PathogenicModerate => "PP3_Moderate",
+            EvidenceStrength::PathogenicStrong => "PP3_Strong",
+        }
+    }
+
+    pub fn is_pathogenic(&self) -> bool {
+        *self > EvidenceStrength::Indeterminate
+    }
+
+    pub fn is_benign(&self) -> bool {
+        *self < EvidenceStrength::Indeterminate
+    }
+}
+
+impl std::fmt::Display for EvidenceStrength {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for EvidenceStrength {
+    type Err = anyhow::Error;
+
+    /// Accepts the snake_case names, ACMG codes (`pp3_moderate`, `bp4`) and,
+    /// for thresholds, the bare levels `supporting`, `moderate`, `strong`
+    /// (pathogenic direction).
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let strength = match s.to_lowercase().as_str() {
+            "benign_strong" | "bp4_strong" => EvidenceStrength::BenignStrong,
+            "benign_moderate" | "bp4_moderate" => EvidenceStrength::BenignModerate,
+            "benign_supporting" | "bp4" | "bp4_supporting" => EvidenceStrength::BenignSupporting,
+            "indeterminate" => EvidenceStrength::Indeterminate,
+            "pathogenic_supporting" | "pp3" | "pp3_supporting" | "supporting" => {
+                EvidenceStrength::PathogenicSupporting
+            }
+            "pathogenic_moderate" | "pp3_moderate" | "moderate" => EvidenceStrength::PathogenicModerate,
+            "pathogenic_strong" | "pp3_strong" | "strong" => EvidenceStrength::PathogenicStrong,
+            _ => anyhow::bail!(
+                "invalid evidence strength '{}' (expected supporting, moderate, strong or e.g. pp3_moderate)",
+                s
+            ),
+        };
+        Ok(strength)
+    }
+}
+
+/// Score thresholds for one predictor at each evidence strength, as published
+/// by the ClinGen Sequence Variant Interpretation group. Pathogenic thresholds
+/// are lower bounds (score >= t), benign thresholds are upper bounds
+/// (score <= t); `None` means the tool cannot reach that strength.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScoreCalibration {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pathogenic_supporting: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pathogenic_moderate: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pathogenic_strong: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub benign_supporting: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub benign_moderate: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub benign_strong: Option<f64>,
+}
+
+impl ScoreCalibration {
+    /// REVEL thresholds from Pejaver et al. 2022 (Am J Hum Genet 109:2163).
+    /// The BP4 very-strong interval (<= 0.003) is folded into strong.
+    pub fn revel() -> Self {
+        Self {
+            pathogenic_supporting: Some(0.644),
+            pathogenic_moderate: Some(0.773),
+            pathogenic_strong: Some(0.932),
+            benign_supporting: Some(0.290),
+            benign_moderate: Some(0.183),
+            benign_strong: Some(0.016),
+        }
+    }
+
+    /// Evidence strength of a single score.
+    pub fn strength(&self, score: f64) -> EvidenceStrength {
+        let at_least = |t: Option<f64>| t.is_some_and(|t| score >= t);
+        let at_most = |t: Option<f64>| t.is_some_and(|t| score <= t);
+
+        if at_least(self.pathogenic_strong) {
+            EvidenceStrength::PathogenicStrong
+        } else if at_least(self.pathogenic_moderate) {
+            EvidenceStrength::PathogenicModerate
+        } else if at_least(self.pathogenic_supporting) {
+            EvidenceStrength::PathogenicSupporting
+        } else if at_most(self.benign_strong) {
+            EvidenceStrength::BenignStrong
+        } else if at_most(self.benign_moderate) {
+            EvidenceStrength::BenignModerate
+        } else if at_most(self.benign_supporting) {
+            EvidenceStrength::BenignSupporting
+        } else {
+            EvidenceStrength::Indeterminate
+        }
+    }
+
+    fn validate(&self, name: &str) -> anyhow::Result<()> {
+        let pathogenic = [self.pathogenic_supporting, self.pathogenic_moderate, self.pathogenic_strong];
+        let benign = [self.benign_supporting, self.benign_moderate, self.benign_strong];
+
+        for t in pathogenic.iter().chain(&benign).flatten() {
+            if t.is_nan() {
+                anyhow::bail!("score_calibrations.{}: thresholds must be numbers", name);
+            }
+        }
+        // Stronger evidence needs a more extreme score
+        let defined = |ts: &[Option<f64>]| ts.iter().flatten().copied().collect::<Vec<f64>>();
+        if defined(&pathogenic).windows(2).any(|w| w[0] > w[1]) {
+            anyhow::bail!("score_calibrations.{}: pathogenic thresholds must increase from supporting to strong", name);
+        }
+        if defined(&benign).windows(2).any(|w| w[0] < w[1]) {
+            anyhow::bail!("score_calibrations.{}: benign thresholds must decrease from supporting to strong", name);
+        }
+        if let (Some(b), Some(p)) = (defined(&benign).first(), defined(&pathogenic).first()) {
+            if b >= p {
+                anyhow::bail!("score_calibrations.{}: benign and pathogenic intervals overlap ({} >= {})", name, b, p);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Which transcript-specific predictive score is reported when Nirvana
+/// provides one per transcript (PrimateAI-3D, PrimateAI).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScoreTranscriptPolicy {
+    /// The score for the transcript chosen for output, falling back to
+    /// other transcripts of the same gene
+    #[default]
+    Selected,
+    /// The highest score across all transcripts
+    Max,
+}
+
+impl ScoreTranscriptPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScoreTranscriptPolicy::Selected => "selected",
+            ScoreTranscriptPolicy::Max => "max",
+        }
+    }
+}
+
+impl std::fmt::Display for ScoreTranscriptPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for ScoreTranscriptPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_lowercase().as_str() {
+            "selected" => Ok(ScoreTranscriptPolicy::Selected),
+            "max" => Ok(ScoreTranscriptPolicy::Max),
+            _ => anyhow::bail!("invalid score transcript policy '{}' (expected selected or max)", s),
+        }
+    }
+}
+
+/// Every filtering setting. Config files (see `config::RunConfig`) use the
+/// field names as keys; fields left out keep their defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct FilterConfig {
+    // VCF FILTER acceptance policy (glob patterns, e.g. "PASS", "weak_*")
+    pub accept_filters: Vec<String>,
+    pub reject_filters: Vec<String>,
+    pub fail_missing_filters: bool,
+
     // Quality filtering parameters
     pub min_total_depth: i32,
     pub min_variant_frequency: f64,
+    pub missing_sample_data: MissingDataPolicy,
+
+    // Per-sample quality thresholds (None = not applied)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_alt_reads: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_genotype_quality: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_strand_bias: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_somatic_quality: Option<f64>,
 
     // Population frequency filtering parameters
     pub max_eas_af: f64,
+    pub missing_population_af: MissingDataPolicy,
+
+    // Consequence filtering (SO terms; empty = no restriction)
+    pub include_consequences: Vec<String>,
+    pub exclude_consequences: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_impact: Option<Impact>,
+    pub consequence_scope: ConsequenceScope,
+
+    // Transcript selection (see `transcript::TranscriptPolicy`)
+    pub transcript_policy: Vec<TranscriptCriterion>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transcript_source: Option<TranscriptSource>,
+    /// File of `GENE ACCESSION` lines with the lab's approved transcripts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preferred_transcripts: Option<String>,
 
     // Predictive score thresholds
     pub min_revel_score: f64,
     pub min_primate_ai_score: f64,
     pub min_dann_score: f64,
+    /// Which transcript's PrimateAI-3D score is used (applied while parsing)
+    pub score_transcript_policy: ScoreTranscriptPolicy,
+
+    // Combining predictive scores (see `filters::predictive`)
+    pub predictive_model: PredictiveModel,
+    /// Weight of each score crossing its threshold (`weighted` model)
+    pub score_weights: BTreeMap<String, f64>,
+    /// Total weight needed to suggest pathogenicity (`weighted` model)
+    pub min_predictive_weight: f64,
+    /// Per-tool evidence thresholds (`calibrated` model)
+    pub score_calibrations: BTreeMap<String, ScoreCalibration>,
+    /// Weakest PP3 strength that suggests pathogenicity (`calibrated` model)
+    pub min_evidence_strength: EvidenceStrength,
+
+    // Loss-of-function evidence
+    pub enable_lof_rescue: bool,
+    /// Truncations beyond this fraction of the CDS are not considered LoF
+    pub lof_max_cds_fraction: f64,
 
     // ClinVar filtering options
     pub exclude_benign: bool,
+
+    /// Built-in pipeline stages in evaluation order (see `filters::pipeline`)
+    pub stages: Vec<String>,
+
+    // Custom rules (filter expressions; see `filters::expression`)
+    /// Variants matching any of these are included even if the decision engine excluded them
+    pub include_rules: Vec<String>,
+    /// Variants matching any of these are excluded even if the decision engine included them
+    pub exclude_rules: Vec<String>,
 }
 
 impl Default for FilterConfig {
     fn default() -> Self {
         Self {
+            accept_filters: vec!["PASS".to_string()],
+            reject_filters: Vec::new(),
+            fail_missing_filters: false,
             min_total_depth: 30,
             min_variant_frequency: 0.03,
+            missing_sample_data: MissingDataPolicy::Pass,
+            min_alt_reads: None,
+            min_genotype_quality: None,
+            max_strand_bias: None,
+            min_somatic_quality: None,
             max_eas_af: 0.01,
+            missing_population_af: MissingDataPolicy::Pass,
+            include_consequences: Vec::new(),
+            exclude_consequences: Vec::new(),
+            min_impact: None,
+            consequence_scope: ConsequenceScope::SelectedTranscript,
+            transcript_policy: crate::transcript::DEFAULT_TRANSCRIPT_POLICY.to_vec(),
+            transcript_source: None,
+            preferred_transcripts: None,
             min_revel_score: 0.75,
             min_primate_ai_score: 0.8,
             min_dann_score: 0.96,
+            score_transcript_policy: ScoreTranscriptPolicy::Selected,
+            predictive_model: PredictiveModel::Votes,
+            // PrimateAI-3D counts double so the defaults reproduce the votes model
+            score_weights: [("PrimateAI-3D", 2.0), ("REVEL", 1.0), ("DANN", 1.0), ("COSMIC", 1.0)]
+                .into_iter()
+                .map(|(name, weight)| (name.to_string(), weight))
+                .collect(),
+            min_predictive_weight: 2.0,
+            score_calibrations: [("REVEL".to_string(), ScoreCalibration::revel())].into_iter().collect(),
+            min_evidence_strength: EvidenceStrength::PathogenicSupporting,
+            enable_lof_rescue: false,
+            lof_max_cds_fraction: 0.95,
             exclude_benign: false,
+            stages: crate::filters::pipeline::DEFAULT_STAGES
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            include_rules: Vec::new(),
+            exclude_rules: Vec::new(),
         }
     }
 }
 
 impl FilterConfig {
+    /// Checks the settings. Include/exclude rules are compiled, and their
+    /// errors reported, by `Pipeline::from_config`.
     pub fn validate(&self) -> anyhow::Result<()> {
+        if self.accept_filters.is_empty() {
+            anyhow::bail!("accept_filters must contain at least one pattern (use \"*\" to accept any tag)");
+        }
+
         if self.min_total_depth < 1 {
             anyhow::bail!("min_total_depth must be at least 1, got {}", self.min_total_depth);
         }
@@ -47,10 +459,48 @@ impl FilterConfig {
             anyhow::bail!("min_variant_frequency must be between 0 and 1, got {}", self.min_variant_frequency);
         }
 
+        if let Some(min_alt_reads) = self.min_alt_reads {
+            if min_alt_reads < 0 {
+                anyhow::bail!("min_alt_reads must not be negative, got {}", min_alt_reads);
+            }
+        }
+
+        if let Some(min_gq) = self.min_genotype_quality {
+            if min_gq < 0.0 {
+                anyhow::bail!("min_genotype_quality must not be negative, got {}", min_gq);
+            }
+        }
+
+        if let Some(max_sb) = self.max_strand_bias {
+            if max_sb.is_nan() {
+                anyhow::bail!("max_strand_bias must be a number");
+            }
+        }
+
+        if let Some(min_sq) = self.min_somatic_quality {
+            if min_sq < 0.0 {
+                anyhow::bail!("min_somatic_quality must not be negative, got {}", min_sq);
+            }
+        }
+
         if !(0.0..=1.0).contains(&self.max_eas_af) {
             anyhow::bail!("max_eas_af must be between 0 and 1, got {}", self.max_eas_af);
         }
 
+        for term in self.include_consequences.iter().chain(&self.exclude_consequences) {
+            if !crate::consequence::is_known_term(term) {
+                log::warn!("Unrecognized Sequence Ontology term in consequence filter: {}", term);
+            }
+        }
+
+        crate::transcript::validate_policy(&self.transcript_policy)?;
+        if self.transcript_source.is_some() && !self.transcript_policy.contains(&TranscriptCriterion::Source) {
+            log::warn!("transcript_source is set but transcript_policy has no 'source' step; it has no effect");
+        }
+        if self.preferred_transcripts.is_some() && !self.transcript_policy.contains(&TranscriptCriterion::Preferred) {
+            log::warn!("preferred_transcripts is set but transcript_policy has no 'preferred' step; it has no effect");
+        }
+
         if !(0.0..=1.0).contains(&self.min_revel_score) {
             anyhow::bail!("min_revel_score must be between 0 and 1, got {}", self.min_revel_score);
         }
@@ -63,6 +513,51 @@ impl FilterConfig {
             anyhow::bail!("min_dann_score must be between 0 and 1, got {}", self.min_dann_score);
         }
 
+        for (name, weight) in &self.score_weights {
+            if !crate::filters::predictive::WEIGHTED_SOURCES.contains(&name.as_str()) {
+                anyhow::bail!(
+                    "score_weights: unknown score '{}' (expected one of {})",
+                    name,
+                    crate::filters::predictive::WEIGHTED_SOURCES.join(", ")
+                );
+            }
+            if weight.is_nan() || *weight < 0.0 {
+                anyhow::bail!("score_weights.{} must not be negative, got {}", name, weight);
+            }
+        }
+
+        if self.min_predictive_weight.is_nan() || self.min_predictive_weight <= 0.0 {
+            anyhow::bail!("min_predictive_weight must be positive, got {}", self.min_predictive_weight);
+        }
+
+        for (name, calibration) in &self.score_calibrations {
+            if !crate::filters::predictive::CALIBRATED_SOURCES.contains(&name.as_str()) {
+                anyhow::bail!(
+                    "score_calibrations: unknown score '{}' (expected one of {})",
+                    name,
+                    crate::filters::predictive::CALIBRATED_SOURCES.join(", ")
+                );
+            }
+            calibration.validate(name)?;
+        }
+
+        if !self.min_evidence_strength.is_pathogenic() {
+            anyhow::bail!(
+                "min_evidence_strength must be a pathogenic (PP3) strength, got {}",
+                self.min_evidence_strength
+            );
+        }
+
+        if self.predictive_model == PredictiveModel::Calibrated && self.score_calibrations.is_empty() {
+            anyhow::bail!("the calibrated predictive model needs at least one entry in score_calibrations");
+        }
+
+        if !(0.0..=1.0).contains(&self.lof_max_cds_fraction) {
+            anyhow::bail!("lof_max_cds_fraction must be between 0 and 1, got {}", self.lof_max_cds_fraction);
+        }
+
+        crate::filters::pipeline::validate_stage_order(&self.stages)?;
+
         Ok(())
     }
 }
@@ -91,7 +586,7 @@ pub struct NirvanaHeader {
     pub samples: Vec<String>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ClinVarEntry {
     pub id: Option<String>,
@@ -106,7 +601,7 @@ pub struct ClinVarEntry {
     pub last_evaluated: Option<String>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TranscriptAnnotation {
     #[serde(rename = "transcript")]
@@ -128,6 +623,9 @@ pub struct TranscriptAnnotation {
     pub is_canonical: Option<bool>,
     #[serde(rename = "isManeSelect")]
     pub is_mane_select: Option<bool>,
+    #[serde(rename = "isManePlusClinical")]
+    pub is_mane_plus_clinical: Option<bool>,
+    pub bio_type: Option<String>,
 }
 
 /// Raw population-frequency sub-object shared by the `gnomad`, `gnomad-exome`
@@ -144,7 +642,7 @@ pub struct FrequencyEntry {
     pub eur_af: Option<f64>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PopulationFrequency {
     #[serde(rename = "population")]
@@ -161,7 +659,7 @@ pub struct PopulationFrequency {
     pub eur_af: Option<f64>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CosmicEntry {
     pub id: Option<String>,
     pub gene: Option<String>,
@@ -170,11 +668,22 @@ pub struct CosmicEntry {
     pub count: Option<i32>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Sample {
+    pub genotype: Option<String>,
     pub total_depth: Option<i32>,
     pub variant_frequencies: Option<Vec<f64>>,
+    pub genotype_quality: Option<f64>,
+    /// Read depth per allele, reference first (VCF `AD`)
+    pub allele_depths: Option<Vec<i32>>,
+    pub strand_bias: Option<f64>,
+    pub somatic_quality: Option<f64>,
+    pub copy_number: Option<i32>,
+    pub is_loss_of_heterozygosity: Option<bool>,
+    pub is_de_novo: Option<bool>,
+    /// Sample-level FORMAT/FT failure, distinct from the record-level `filters`
+    pub failed_filter: Option<bool>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -243,7 +752,7 @@ pub struct Position {
     pub variants: Vec<Variant>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct VariantPosition {
     pub chromosome: String,
     pub start: i32,
@@ -258,15 +767,29 @@ pub struct VariantPosition {
     // Sample information
     pub total_depth: Option<i32>,
     pub variant_frequencies: Option<Vec<f64>>,
+    pub genotype: Option<String>,
+    pub genotype_quality: Option<f64>,
+    pub allele_depths: Option<Vec<i32>>,
+    pub strand_bias: Option<f64>,
+    pub somatic_quality: Option<f64>,
+    pub is_loss_of_heterozygosity: Option<bool>,
+    pub failed_filter: Option<bool>,
 
     // Annotation information
     pub transcripts: Vec<TranscriptAnnotation>,
+    /// Index into `transcripts` chosen by the transcript policy while
+    /// parsing; `None` means the default policy applies. JSONL output
+    /// reports the resolved index instead
+    #[serde(skip)]
+    pub selected_transcript_index: Option<usize>,
     pub clinvar: Vec<ClinVarEntry>,
     pub cosmic: Vec<CosmicEntry>,
     pub population_frequencies: Vec<PopulationFrequency>,
 
     // Predictive scores
     pub primate_ai_3d: Option<f64>,
+    /// Transcript the PrimateAI-3D score belongs to
+    pub primate_ai_3d_transcript: Option<String>,
     pub primate_ai: Option<f64>,
     pub dann_score: Option<f64>,
     pub revel_score: Option<f64>,
@@ -275,40 +798,239 @@ pub struct VariantPosition {
     pub dbsnp_ids: Vec<String>,
 }
 
+impl VariantPosition {
+    /// The transcript reported for this variant (see `transcript::TranscriptPolicy`).
+    pub fn selected_transcript(&self) -> Option<&TranscriptAnnotation> {
+        let index = self
+            .selected_transcript_index
+            .or_else(|| crate::transcript::select_default_index(&self.transcripts))?;
+        self.transcripts.get(index)
+    }
+
+    /// gnomAD exome frequencies, the source of the MAF `gnomAD_*` columns.
+    pub fn gnomad_exome(&self) -> Option<&PopulationFrequency> {
+        self.population_frequencies
+            .iter()
+            .find(|pf| pf.source == "gnomad-exome")
+    }
+
+    /// Reads supporting the reference allele (`alleleDepths[0]`).
+    pub fn ref_read_count(&self) -> Option<i32> {
+        self.allele_depths.as_ref().and_then(|ad| ad.first().copied())
+    }
+
+    /// Reads supporting the reported alternate allele (`alleleDepths[1]`).
+    /// Only the first alt allele of a position is carried through, so the
+    /// remaining entries of a multi-allelic `alleleDepths` are ignored.
+    pub fn alt_read_count(&self) -> Option<i32> {
+        self.allele_depths.as_ref().and_then(|ad| ad.get(1).copied())
+    }
+}
+
+#[cfg(test)]
+impl VariantPosition {
+    /// A PASS SNV at chr1:100 without sample data or annotations, for tests
+    /// to fill in with struct update syntax.
+    pub(crate) fn test_default() -> Self {
+        VariantPosition {
+            chromosome: "chr1".to_string(),
+            start: 100,
+            end_pos: 100,
+            reference_allele: "A".to_string(),
+            alternate_allele: "T".to_string(),
+            variant_type: "SNV".to_string(),
+            filters: vec!["PASS".to_string()],
+            total_depth: None,
+            variant_frequencies: None,
+            genotype: None,
+            genotype_quality: None,
+            allele_depths: None,
+            strand_bias: None,
+            somatic_quality: None,
+            is_loss_of_heterozygosity: None,
+            failed_filter: None,
+            transcripts: vec![],
+            selected_transcript_index: None,
+            clinvar: vec![],
+            cosmic: vec![],
+            population_frequencies: vec![],
+            primate_ai_3d: None,
+            primate_ai_3d_transcript: None,
+            primate_ai: None,
+            dann_score: None,
+            revel_score: None,
+            dbsnp_ids: vec![],
+        }
+    }
+}
+
 // ============================================================================
 // Filter Assessment Results
 // ============================================================================
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct QualityFilterResult {
     pub passes_quality: bool,
     pub failure_reason: Option<String>,
+    pub failure_code: Option<ReasonCode>,
+    /// VCF FILTER tags that caused the rejection ("." when filters were missing)
+    pub failed_filters: Vec<String>,
+    /// QC flags reported on the variant: missing-data flags under the `flag`
+    /// policy, or the one that caused a `fail`
+    pub flags: Vec<String>,
+    /// Every missing-data flag observed, regardless of policy (for statistics)
+    pub missing_data: Vec<String>,
     pub depth: Option<i32>,
     pub variant_frequency: Option<f64>,
     pub eas_allele_frequency: Option<f64>,
 }
 
+/// Why a pipeline stage rejected a variant. Built-in stages use the named
+/// codes; stages supplied by library users report `Custom`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ReasonCode {
+    VcfFilter,
+    LowDepth,
+    LowVaf,
+    LowAltReads,
+    LowGenotypeQuality,
+    HighStrandBias,
+    LowSomaticQuality,
+    HighPopulationAf,
+    MissingSampleData,
+    MissingPopulationAf,
+    Consequence,
+    LowImpact,
+    Benign,
+    InsufficientEvidence,
+    Rule,
+    Custom(String),
+}
+
+impl ReasonCode {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ReasonCode::VcfFilter => "vcf_filter",
+            ReasonCode::LowDepth => "low_depth",
+            ReasonCode::LowVaf => "low_vaf",
+            ReasonCode::LowAltReads => "low_alt_reads",
+            ReasonCode::LowGenotypeQuality => "low_genotype_quality",
+            ReasonCode::HighStrandBias => "high_strand_bias",
+            ReasonCode::LowSomaticQuality => "low_somatic_quality",
+            ReasonCode::HighPopulationAf => "high_population_af",
+            ReasonCode::MissingSampleData => "missing_sample_data",
+            ReasonCode::MissingPopulationAf => "missing_population_af",
+            ReasonCode::Consequence => "consequence",
+            ReasonCode::LowImpact => "low_impact",
+            ReasonCode::Benign => "benign",
+            ReasonCode::InsufficientEvidence => "insufficient_evidence",
+            ReasonCode::Rule => "rule",
+            ReasonCode::Custom(code) => code,
+        }
+    }
+}
+
+impl Serialize for ReasonCode {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl std::fmt::Display for ReasonCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A typed rejection reason: a stable code for statistics plus a
+/// human-readable message for the rejected-variant report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reason {
+    pub code: ReasonCode,
+    pub message: String,
+    /// Values the failure refers to, e.g. the FILTER tags or consequence term
+    pub tags: Vec<String>,
+}
+
+impl Reason {
+    pub fn new(code: ReasonCode, message: impl Into<String>) -> Self {
+        Reason {
+            code,
+            message: message.into(),
+            tags: Vec::new(),
+        }
+    }
+
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags = tags;
+        self
+    }
+}
+
 #[derive(Debug, Clone)]
+pub struct ConsequenceFilterResult {
+    pub passes_consequence: bool,
+    pub failure_reason: Option<String>,
+    /// True when the failure came from `min_impact` rather than the term lists
+    pub failed_impact: bool,
+    /// Most severe consequence of the transcript that decided the outcome
+    pub consequence: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LofAssessment {
+    pub is_high_confidence: bool,
+    /// LoF consequence in the selected transcript, if any
+    pub consequence: Option<String>,
+    pub transcript_id: Option<String>,
+    /// LOFTEE-style checks that downgraded the call to low confidence
+    pub failed_checks: Vec<String>,
+    /// Checks that the annotation does not carry enough information to
+    /// apply, and were skipped
+    pub unchecked: Vec<String>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct ClinVarAssessment {
     pub is_pathogenic: bool,
     pub is_likely_pathogenic: bool,
     pub is_benign: bool,
     pub is_likely_benign: bool,
+    /// The entry that decided the assessment: the best pathogenic entry if
+    /// any, else the best benign one, else the best of the rest. `None`
+    /// only without ClinVar entries.
     pub selected_entry: Option<ClinVarEntry>,
     pub confidence_level: String,
     pub reason: String,
 }
 
-#[derive(Debug, Clone)]
+/// Serializes a `HashMap` with its keys in order, so output is reproducible.
+fn serialize_sorted<S: serde::Serializer>(map: &HashMap<String, f64>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(map.iter().collect::<BTreeMap<_, _>>())
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct PredictiveAssessment {
     pub suggests_pathogenic: bool,
+    /// Scores that supported the suggestion under the active model
+    #[serde(serialize_with = "serialize_sorted")]
     pub contributing_scores: HashMap<String, f64>,
+    /// Posterior probability of pathogenicity under the calibrated model;
+    /// the historical support heuristic under the others
     pub confidence: f64,
     pub support_count: usize,
     pub has_primate_ai_support: bool,
+    pub model: PredictiveModel,
+    /// Sum of `score_weights` over the scores crossing their thresholds
+    pub total_weight: f64,
+    /// Evidence strength of each calibrated score present on the variant
+    pub calibrated_scores: BTreeMap<String, EvidenceStrength>,
+    /// Combined PP3/BP4 strength over `calibrated_scores`
+    pub evidence_strength: EvidenceStrength,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct FilterDecision {
     pub should_include: bool,
     pub pathogenicity_class: String,
@@ -320,7 +1042,61 @@ pub struct FilterDecision {
 // MAF Format
 // ============================================================================
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Columns of `MAFRecord`, in output order.
+pub const MAF_COLUMNS: &[&str] = &[
+    "Hugo_Symbol",
+    "Chromosome",
+    "Start_Position",
+    "End_Position",
+    "Strand",
+    "Variant_Classification",
+    "Variant_Type",
+    "Reference_Allele",
+    "Tumor_Seq_Allele1",
+    "Tumor_Seq_Allele2",
+    "Tumor_Sample_Barcode",
+    "Matched_Norm_Sample_Barcode",
+    "HGVSc",
+    "HGVSp",
+    "HGVSp_Short",
+    "Transcript_ID",
+    "Is_Selected_Transcript",
+    "Exon",
+    "Consequence",
+    "IMPACT",
+    "Codons",
+    "Amino_Acids",
+    "cDNA_position",
+    "CDS_position",
+    "Protein_position",
+    "dbSNP_RS",
+    "dbSNP_Val_Status",
+    "COSMIC_ID",
+    "ClinVar_ID",
+    "ClinVar_Review_Status",
+    "ClinVar_Significance",
+    "ClinVar_Disease",
+    "ClinVar_All",
+    "PrimateAI_Score",
+    "PrimateAI_Transcript",
+    "DANN_Score",
+    "REVEL_Score",
+    "Predictive_Evidence",
+    "gnomAD_AF",
+    "gnomAD_EAS_AF",
+    "Depth",
+    "VAF",
+    "t_ref_count",
+    "t_alt_count",
+    "GT",
+    "GQ",
+    "QC_Flags",
+    "all_effects",
+];
+
+/// One MAF row. Columns missing from a file being read are left empty.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MAFRecord {
     #[serde(rename = "Hugo_Symbol")]
     pub hugo_symbol: String,
@@ -344,6 +1120,8 @@ pub struct MAFRecord {
     pub tumor_seq_allele2: String,
     #[serde(rename = "Tumor_Sample_Barcode")]
     pub tumor_sample_barcode: String,
+    #[serde(rename = "Matched_Norm_Sample_Barcode")]
+    pub matched_norm_sample_barcode: String,
     #[serde(rename = "HGVSc")]
     pub hgvsc: String,
     #[serde(rename = "HGVSp")]
@@ -352,6 +1130,9 @@ pub struct MAFRecord {
     pub hgvsp_short: String,
     #[serde(rename = "Transcript_ID")]
     pub transcript_id: String,
+    /// "1" for the row of the transcript chosen by the transcript policy
+    #[serde(rename = "Is_Selected_Transcript")]
+    pub is_selected_transcript: String,
     #[serde(rename = "Exon")]
     pub exon: String,
     #[serde(rename = "Consequence")]
@@ -382,12 +1163,20 @@ pub struct MAFRecord {
     pub clinvar_significance: String,
     #[serde(rename = "ClinVar_Disease")]
     pub clinvar_disease: String,
+    /// Every ClinVar record, with `MafOptions::clinvar_all`
+    #[serde(rename = "ClinVar_All")]
+    pub clinvar_all: String,
     #[serde(rename = "PrimateAI_Score")]
     pub primate_ai_score: String,
+    #[serde(rename = "PrimateAI_Transcript")]
+    pub primate_ai_transcript: String,
     #[serde(rename = "DANN_Score")]
     pub dann_score: String,
     #[serde(rename = "REVEL_Score")]
     pub revel_score: String,
+    /// Combined PP3/BP4 strength of the calibrated scores (blank if none)
+    #[serde(rename = "Predictive_Evidence")]
+    pub predictive_evidence: String,
     #[serde(rename = "gnomAD_AF")]
     pub gnomad_af: String,
     #[serde(rename = "gnomAD_EAS_AF")]
@@ -396,41 +1185,230 @@ pub struct MAFRecord {
     pub depth: String,
     #[serde(rename = "VAF")]
     pub vaf: String,
+    #[serde(rename = "t_ref_count")]
+    pub t_ref_count: String,
+    #[serde(rename = "t_alt_count")]
+    pub t_alt_count: String,
+    #[serde(rename = "GT")]
+    pub genotype: String,
+    #[serde(rename = "GQ")]
+    pub genotype_quality: String,
+    #[serde(rename = "QC_Flags")]
+    pub qc_flags: String,
+    /// Every transcript's effect, with `TranscriptOutput::all_effects`
+    #[serde(rename = "all_effects")]
+    pub all_effects: String,
+}
+
+/// Which transcripts of an included variant are written to the MAF.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TranscriptOutput {
+    /// One row per transcript instead of the selected transcript only
+    pub all_transcripts: bool,
+    /// With `all_transcripts`: only transcripts from this source
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<TranscriptSource>,
+    /// With `all_transcripts`: only protein-coding transcripts
+    pub protein_coding_only: bool,
+    /// Fill the `all_effects` column with every transcript's effect
+    pub all_effects: bool,
+}
+
+impl TranscriptOutput {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !self.all_transcripts && (self.source.is_some() || self.protein_coding_only) {
+            anyhow::bail!("transcripts.source and transcripts.protein_coding_only require all_transcripts");
+        }
+        Ok(())
+    }
+}
+
+/// One row of the optional rejected-variants report: where a variant was
+/// dropped and why.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RejectedRecord {
+    #[serde(rename = "Chromosome")]
+    pub chromosome: String,
+    #[serde(rename = "Start_Position")]
+    pub start_position: i32,
+    #[serde(rename = "Reference_Allele")]
+    pub reference_allele: String,
+    #[serde(rename = "Alternate_Allele")]
+    pub alternate_allele: String,
+    #[serde(rename = "Stage")]
+    pub stage: String,
+    #[serde(rename = "Reason")]
+    pub reason: String,
+    #[serde(rename = "QC_Flags")]
+    pub qc_flags: String,
 }
 
 // ============================================================================
 // Statistics
 // ============================================================================
 
-#[derive(Debug, Clone, Default)]
+/// Generic counters kept for every pipeline stage, built-in or custom.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StageStats {
+    pub evaluated: usize,
+    pub passed: usize,
+    pub annotated: usize,
+    pub failed: usize,
+    /// Failures by reason code
+    pub reasons: BTreeMap<String, usize>,
+}
+
+impl StageStats {
+    pub fn merge(&mut self, other: &StageStats) {
+        self.evaluated += other.evaluated;
+        self.passed += other.passed;
+        self.annotated += other.annotated;
+        self.failed += other.failed;
+        for (code, count) in &other.reasons {
+            *self.reasons.entry(code.clone()).or_insert(0) += count;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct FilterStats {
+    /// Variants run through the pipeline, whichever stage rejected them
+    #[serde(default)]
+    pub evaluated: usize,
     pub passed_quality: usize,
     pub failed_depth: usize,
     pub failed_vaf: usize,
     pub failed_af: usize,
+    pub failed_vcf_filter: usize,
+    pub failed_filter_tags: BTreeMap<String, usize>,
+    pub failed_alt_reads: usize,
+    pub failed_genotype_quality: usize,
+    pub failed_strand_bias: usize,
+    pub failed_somatic_quality: usize,
+    pub missing_sample_data: usize,
+    pub missing_population_af: usize,
+    pub failed_missing_sample_data: usize,
+    pub failed_missing_population_af: usize,
+    pub failed_consequence: usize,
+    pub failed_impact: usize,
+    pub consequence_exclusions: BTreeMap<String, usize>,
     pub clinvar_pathogenic: usize,
     pub clinvar_likely: usize,
     pub predictive_likely: usize,
+    pub lof_rescued: usize,
+    pub low_confidence_lof: usize,
     pub primate_ai_only: usize,
     pub multi_score: usize,
     pub excluded_benign: usize,
+    pub rule_included: usize,
+    pub rule_excluded: usize,
     pub included: usize,
     pub excluded: usize,
+    /// Per-stage counters, keyed by stage name. Built-in and custom stage
+    /// names are static, so counting a stage doesn't allocate.
+    pub stages: BTreeMap<Cow<'static, str>, StageStats>,
 }
 
 impl FilterStats {
     pub fn merge(&mut self, other: &FilterStats) {
+        self.evaluated += other.evaluated;
         self.passed_quality += other.passed_quality;
         self.failed_depth += other.failed_depth;
         self.failed_vaf += other.failed_vaf;
         self.failed_af += other.failed_af;
+        self.failed_vcf_filter += other.failed_vcf_filter;
+        for (tag, count) in &other.failed_filter_tags {
+            *self.failed_filter_tags.entry(tag.clone()).or_insert(0) += count;
+        }
+        self.failed_alt_reads += other.failed_alt_reads;
+        self.failed_genotype_quality += other.failed_genotype_quality;
+        self.failed_strand_bias += other.failed_strand_bias;
+        self.failed_somatic_quality += other.failed_somatic_quality;
+        self.missing_sample_data += other.missing_sample_data;
+        self.missing_population_af += other.missing_population_af;
+        self.failed_missing_sample_data += other.failed_missing_sample_data;
+        self.failed_missing_population_af += other.failed_missing_population_af;
+        self.failed_consequence += other.failed_consequence;
+        self.failed_impact += other.failed_impact;
+        for (term, count) in &other.consequence_exclusions {
+            *self.consequence_exclusions.entry(term.clone()).or_insert(0) += count;
+        }
         self.clinvar_pathogenic += other.clinvar_pathogenic;
         self.clinvar_likely += other.clinvar_likely;
         self.predictive_likely += other.predictive_likely;
+        self.lof_rescued += other.lof_rescued;
+        self.low_confidence_lof += other.low_confidence_lof;
         self.primate_ai_only += other.primate_ai_only;
         self.multi_score += other.multi_score;
         self.excluded_benign += other.excluded_benign;
+        self.rule_included += other.rule_included;
+        self.rule_excluded += other.rule_excluded;
+        for (name, stage) in &other.stages {
+            self.stages.entry(name.clone()).or_default().merge(stage);
+        }
         self.included += other.included;
         self.excluded += other.excluded;
     }
+
+    /// Counts a rejection under its stage-independent reason counters.
+    /// `excluded` only counts variants rejected after filtering, i.e. by the
+    /// decision engine, custom rules or custom stages.
+    pub fn record_rejection(&mut self, reason: &Reason) {
+        match reason.code {
+            ReasonCode::VcfFilter => {
+                self.failed_vcf_filter += 1;
+                for tag in &reason.tags {
+                    *self.failed_filter_tags.entry(tag.clone()).or_insert(0) += 1;
+                }
+            }
+            ReasonCode::LowDepth => self.failed_depth += 1,
+            ReasonCode::LowVaf => self.failed_vaf += 1,
+            ReasonCode::LowAltReads => self.failed_alt_reads += 1,
+            ReasonCode::LowGenotypeQuality => self.failed_genotype_quality += 1,
+            ReasonCode::HighStrandBias => self.failed_strand_bias += 1,
+            ReasonCode::LowSomaticQuality => self.failed_somatic_quality += 1,
+            ReasonCode::HighPopulationAf => self.failed_af += 1,
+            ReasonCode::MissingSampleData => self.failed_missing_sample_data += 1,
+            ReasonCode::MissingPopulationAf => self.failed_missing_population_af += 1,
+            ReasonCode::Consequence | ReasonCode::LowImpact => {
+                if reason.code == ReasonCode::LowImpact {
+                    self.failed_impact += 1;
+                } else {
+                    self.failed_consequence += 1;
+                }
+                for term in &reason.tags {
+                    *self.consequence_exclusions.entry(term.clone()).or_insert(0) += 1;
+                }
+            }
+            ReasonCode::Benign => {
+                self.excluded_benign += 1;
+                self.excluded += 1;
+            }
+            ReasonCode::Rule => {
+                self.rule_excluded += 1;
+                self.excluded += 1;
+            }
+            ReasonCode::InsufficientEvidence | ReasonCode::Custom(_) => self.excluded += 1,
+        }
+    }
+
+    /// Counts an included variant by the evidence that included it.
+    pub fn record_inclusion(&mut self, decision: &FilterDecision, predictive: Option<&PredictiveAssessment>) {
+        self.included += 1;
+        match decision.primary_evidence.as_str() {
+            "ClinVar" if decision.pathogenicity_class == "Pathogenic" => self.clinvar_pathogenic += 1,
+            "ClinVar" if decision.pathogenicity_class == "Likely pathogenic" => self.clinvar_likely += 1,
+            "LoF" => self.lof_rescued += 1,
+            "Rule" => self.rule_included += 1,
+            "Predictive" => {
+                self.predictive_likely += 1;
+                match predictive {
+                    Some(p) if p.has_primate_ai_support && p.support_count == 1 => self.primate_ai_only += 1,
+                    _ => self.multi_score += 1,
+                }
+            }
+            _ => {}
+        }
+    }
 }
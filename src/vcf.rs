@@ -0,0 +1,908 @@
+/// VCFv4.3 output for included variants.
+///
+/// Each included variant becomes one VCF record with its original REF/ALT,
+/// FILTER and the reported sample's FORMAT fields. json2maf annotations of
+/// the selected transcript go into `J2M_*` INFO fields declared in the
+/// header. Contig lines come from the Nirvana header's genome assembly,
+/// named like the chromosomes in the data (`chr1` or `1`), plus any other
+/// contig (alt, decoy, unplaced) that has a record. Every FILTER value that
+/// appears is declared too.
+///
+/// Since both are only known after the last record, records are staged in
+/// a `NAME.records` file next to the output (or held by the sorter) and the
+/// header is written on `finish`. Like the MAF output, a multi-allelic
+/// position is reported for its first ALT allele only.
+use crate::output::{partial_path, FileOptions, OutputCompression, OutputFile, StagedFile};
+use crate::sort::{ExternalSorter, SortKey};
+use crate::types::{MAFRecord, NirvanaHeader, VariantPosition};
+use crate::writer::{OutputRecord, RecordSink, SinkState};
+use anyhow::{Context, Result};
+use std::collections::{BTreeSet, HashSet};
+use std::io::{BufRead, BufReader, Read, Write};
+
+const GRCH38_CONTIGS: &[(&str, u64)] = &[
+    ("1", 248956422),
+    ("2", 242193529),
+    ("3", 198295559),
+    ("4", 190214555),
+    ("5", 181538259),
+    ("6", 170805979),
+    ("7", 159345973),
+    ("8", 145138636),
+    ("9", 138394717),
+    ("10", 133797422),
+    ("11", 135086622),
+    ("12", 133275309),
+    ("13", 114364328),
+    ("14", 107043718),
+    ("15", 101991189),
+    ("16", 90338345),
+    ("17", 83257441),
+    ("18", 80373285),
+    ("19", 58617616),
+    ("20", 64444167),
+    ("21", 46709983),
+    ("22", 50818468),
+    ("X", 156040895),
+    ("Y", 57227415),
+    ("M", 16569),
+];
+
+const GRCH37_CONTIGS: &[(&str, u64)] = &[
+    ("1", 249250621),
+    ("2", 243199373),
+    ("3", 198022430),
+    ("4", 191154276),
+    ("5", 180915260),
+    ("6", 171115067),
+    ("7", 159138663),
+    ("8", 146364022),
+    ("9", 141213431),
+    ("10", 135534747),
+    ("11", 135006516),
+    ("12", 133851895),
+    ("13", 115169878),
+    ("14", 107349540),
+    ("15", 102531392),
+    ("16", 90354753),
+    ("17", 81195210),
+    ("18", 78077248),
+    ("19", 59128983),
+    ("20", 63025520),
+    ("21", 48129895),
+    ("22", 51304566),
+    ("X", 155270560),
+    ("Y", 59373566),
+    ("MT", 16569),
+];
+
+/// Primary-assembly contigs and lengths for a Nirvana `genomeAssembly`.
+fn assembly_contigs(assembly: &str) -> Option<&'static [(&'static str, u64)]> {
+    match assembly.to_lowercase().as_str() {
+        "grch38" | "hg38" => Some(GRCH38_CONTIGS),
+        "grch37" | "hg19" => Some(GRCH37_CONTIGS),
+        _ => None,
+    }
+}
+
+/// Contig name in the data's naming style; the mitochondrion is `chrM` with
+/// a prefix and `MT` without one, whatever the assembly calls it.
+fn contig_name(name: &str, with_prefix: bool) -> String {
+    let mito = name == "M" || name == "MT";
+    match (with_prefix, mito) {
+        (true, true) => "chrM".to_string(),
+        (true, false) => format!("chr{}", name),
+        (false, true) => "MT".to_string(),
+        (false, false) => name.to_string(),
+    }
+}
+
+/// A declared INFO field and how to fill it from an output record.
+struct InfoField {
+    id: &'static str,
+    number: &'static str,
+    kind: &'static str,
+    description: &'static str,
+    value: fn(&OutputRecord, &MAFRecord) -> String,
+}
+
+const INFO_FIELDS: &[InfoField] = &[
+    InfoField {
+        id: "J2M_CLASS",
+        number: "1",
+        kind: "String",
+        description: "json2maf pathogenicity class",
+        value: |r, _| r.decision.pathogenicity_class.clone(),
+    },
+    InfoField {
+        id: "J2M_EVIDENCE",
+        number: "1",
+        kind: "String",
+        description: "Primary evidence for including the variant",
+        value: |r, _| r.decision.primary_evidence.clone(),
+    },
+    InfoField {
+        id: "J2M_JUSTIFICATION",
+        number: "1",
+        kind: "String",
+        description: "Why the variant was included",
+        value: |r, _| r.decision.justification.clone(),
+    },
+    InfoField {
+        id: "J2M_GENE",
+        number: "1",
+        kind: "String",
+        description: "Gene symbol of the selected transcript",
+        value: |_, m| m.hugo_symbol.clone(),
+    },
+    InfoField {
+        id: "J2M_TRANSCRIPT",
+        number: "1",
+        kind: "String",
+        description: "Selected transcript",
+        value: |_, m| m.transcript_id.clone(),
+    },
+    InfoField {
+        id: "J2M_HGVSC",
+        number: "1",
+        kind: "String",
+        description: "HGVS coding change in the selected transcript",
+        value: |_, m| m.hgvsc.clone(),
+    },
+    InfoField {
+        id: "J2M_HGVSP",
+        number: "1",
+        kind: "String",
+        description: "HGVS protein change in the selected transcript",
+        value: |_, m| m.hgvsp.clone(),
+    },
+    InfoField {
+        id: "J2M_HGVSP_SHORT",
+        number: "1",
+        kind: "String",
+        description: "HGVS protein change with single-letter amino acids",
+        value: |_, m| m.hgvsp_short.clone(),
+    },
+    InfoField {
+        id: "J2M_CONSEQUENCE",
+        number: ".",
+        kind: "String",
+        description: "Sequence Ontology consequences in the selected transcript",
+        value: |_, m| m.consequence.clone(),
+    },
+    InfoField {
+        id: "J2M_VARIANT_CLASSIFICATION",
+        number: "1",
+        kind: "String",
+        description: "MAF Variant_Classification",
+        value: |_, m| m.variant_classification.clone(),
+    },
+    InfoField {
+        id: "J2M_IMPACT",
+        number: "1",
+        kind: "String",
+        description: "VEP impact in the selected transcript",
+        value: |_, m| m.impact.clone(),
+    },
+    InfoField {
+        id: "J2M_CLINVAR_ID",
+        number: "1",
+        kind: "String",
+        description: "ClinVar entry selected by the assessment",
+        value: |_, m| m.clinvar_id.clone(),
+    },
+    InfoField {
+        id: "J2M_CLINVAR_SIG",
+        number: "1",
+        kind: "String",
+        description: "Clinical significance of the selected ClinVar entry",
+        value: |_, m| m.clinvar_significance.clone(),
+    },
+    InfoField {
+        id: "J2M_CLINVAR_REVIEW",
+        number: "1",
+        kind: "String",
+        description: "Review status of the selected ClinVar entry",
+        value: |_, m| m.clinvar_review_status.clone(),
+    },
+    InfoField {
+        id: "J2M_CLINVAR_DISEASE",
+        number: "1",
+        kind: "String",
+        description: "Phenotypes of the selected ClinVar entry",
+        value: |_, m| m.clinvar_disease.clone(),
+    },
+    InfoField {
+        id: "J2M_COSMIC",
+        number: "1",
+        kind: "String",
+        description: "COSMIC identifier",
+        value: |_, m| m.cosmic_id.clone(),
+    },
+    InfoField {
+        id: "J2M_PRIMATEAI",
+        number: "1",
+        kind: "Float",
+        description: "PrimateAI-3D score (gene-level PrimateAI when unavailable)",
+        value: |_, m| m.primate_ai_score.clone(),
+    },
+    InfoField {
+        id: "J2M_PRIMATEAI_TRANSCRIPT",
+        number: "1",
+        kind: "String",
+        description: "Transcript of the PrimateAI-3D score",
+        value: |_, m| m.primate_ai_transcript.clone(),
+    },
+    InfoField {
+        id: "J2M_REVEL",
+        number: "1",
+        kind: "Float",
+        description: "REVEL score",
+        value: |_, m| m.revel_score.clone(),
+    },
+    InfoField {
+        id: "J2M_DANN",
+        number: "1",
+        kind: "Float",
+        description: "DANN score",
+        value: |_, m| m.dann_score.clone(),
+    },
+    InfoField {
+        id: "J2M_PREDICTIVE_EVIDENCE",
+        number: "1",
+        kind: "String",
+        description: "Combined PP3/BP4 strength of the calibrated scores",
+        value: |_, m| m.predictive_evidence.clone(),
+    },
+    InfoField {
+        id: "J2M_GNOMAD_AF",
+        number: "1",
+        kind: "Float",
+        description: "gnomAD exome allele frequency",
+        value: |_, m| m.gnomad_af.clone(),
+    },
+    InfoField {
+        id: "J2M_GNOMAD_EAS_AF",
+        number: "1",
+        kind: "Float",
+        description: "gnomAD exome East Asian allele frequency",
+        value: |_, m| m.gnomad_eas_af.clone(),
+    },
+    InfoField {
+        id: "J2M_QC_FLAGS",
+        number: ".",
+        kind: "String",
+        description: "json2maf QC flags",
+        value: |_, m| m.qc_flags.replace(';', ","),
+    },
+];
+
+const FORMAT_HEADER: &[&str] = &[
+    r#"##FORMAT=<ID=GT,Number=1,Type=String,Description="Genotype">"#,
+    r#"##FORMAT=<ID=GQ,Number=1,Type=Integer,Description="Genotype quality">"#,
+    r#"##FORMAT=<ID=DP,Number=1,Type=Integer,Description="Total read depth">"#,
+    r#"##FORMAT=<ID=AD,Number=R,Type=Integer,Description="Read depth per allele">"#,
+    r#"##FORMAT=<ID=VF,Number=A,Type=Float,Description="Variant allele frequency">"#,
+    r#"##FORMAT=<ID=SB,Number=1,Type=Float,Description="Strand bias">"#,
+    r#"##FORMAT=<ID=SQ,Number=1,Type=Float,Description="Somatic quality">"#,
+    r#"##FORMAT=<ID=FT,Number=1,Type=String,Description="Sample filter">"#,
+];
+
+/// Percent-encodes the characters VCF 4.3 reserves in INFO values.
+fn encode_info_value(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '%' => encoded.push_str("%25"),
+            ':' => encoded.push_str("%3A"),
+            ';' => encoded.push_str("%3B"),
+            '=' => encoded.push_str("%3D"),
+            ',' => encoded.push_str("%2C"),
+            '\t' => encoded.push_str("%09"),
+            '\n' => encoded.push_str("%0A"),
+            '\r' => encoded.push_str("%0D"),
+            _ => encoded.push(c),
+        }
+    }
+    encoded
+}
+
+fn info_column(record: &OutputRecord) -> String {
+    let Some(row) = record.selected_row() else {
+        return ".".to_string();
+    };
+    let fields: Vec<String> = INFO_FIELDS
+        .iter()
+        .filter_map(|field| {
+            let value = (field.value)(record, row);
+            if value.is_empty() {
+                return None;
+            }
+            // Lists keep their commas as separators
+            let encoded = if field.number == "." {
+                value.split(',').map(encode_info_value).collect::<Vec<_>>().join(",")
+            } else {
+                encode_info_value(&value)
+            };
+            Some(format!("{}={}", field.id, encoded))
+        })
+        .collect();
+    if fields.is_empty() {
+        ".".to_string()
+    } else {
+        fields.join(";")
+    }
+}
+
+/// FORMAT keys and the sample column for the reported sample.
+fn sample_columns(variant: &VariantPosition) -> (String, String) {
+    let mut keys = Vec::new();
+    let mut values = Vec::new();
+    let mut push = |key: &'static str, value: Option<String>| {
+        if let Some(value) = value {
+            keys.push(key);
+            values.push(value);
+        }
+    };
+
+    push("GT", variant.genotype.clone());
+    push("GQ", variant.genotype_quality.map(|gq| (gq.round() as i64).to_string()));
+    push("DP", variant.total_depth.map(|dp| dp.to_string()));
+    // Only the first ALT is written, so AD keeps REF and that ALT
+    push(
+        "AD",
+        variant
+            .allele_depths
+            .as_ref()
+            .filter(|ad| ad.len() >= 2)
+            .map(|ad| format!("{},{}", ad[0], ad[1])),
+    );
+    push(
+        "VF",
+        variant
+            .variant_frequencies
+            .as_ref()
+            .and_then(|vf| vf.first())
+            .map(|vf| vf.to_string()),
+    );
+    push("SB", variant.strand_bias.map(|sb| sb.to_string()));
+    push("SQ", variant.somatic_quality.map(|sq| sq.to_string()));
+    push(
+        "FT",
+        variant
+            .failed_filter
+            .map(|failed| if failed { "FAIL" } else { "PASS" }.to_string()),
+    );
+
+    if keys.is_empty() {
+        // GT must come first and a sample column cannot be empty
+        ("GT".to_string(), ".".to_string())
+    } else {
+        (keys.join(":"), values.join(":"))
+    }
+}
+
+/// The uncompressed file records are staged in until the header is known.
+fn records_path(output_path: &str) -> String {
+    format!("{}.records", output_path)
+}
+
+fn records_options(options: FileOptions) -> FileOptions {
+    FileOptions {
+        compression: OutputCompression::None,
+        ..options
+    }
+}
+
+/// Contigs in order of their first record, and the FILTER values seen.
+#[derive(Default)]
+struct SeenValues {
+    contigs: Vec<String>,
+    contig_set: HashSet<String>,
+    filters: BTreeSet<String>,
+}
+
+impl SeenValues {
+    fn add(&mut self, chromosome: &str, filters: &[String]) {
+        if self.contigs.last().map(String::as_str) != Some(chromosome) && self.contig_set.insert(chromosome.to_string()) {
+            self.contigs.push(chromosome.to_string());
+        }
+        for filter in filters {
+            if !self.filters.contains(filter) {
+                self.filters.insert(filter.clone());
+            }
+        }
+    }
+
+    /// Rebuilds the values from staged records.
+    fn scan(reader: impl Read) -> Result<Self> {
+        let mut seen = Self::default();
+        for line in BufReader::new(reader).lines() {
+            let line = line.context("Failed to read staged VCF records")?;
+            let fields: Vec<&str> = line.splitn(8, '\t').collect();
+            let (Some(chromosome), Some(filter)) = (fields.first(), fields.get(6)) else {
+                anyhow::bail!("Malformed staged VCF record: {}", line);
+            };
+            let filters: Vec<String> = match *filter {
+                "." => Vec::new(),
+                filter => filter.split(';').map(str::to_string).collect(),
+            };
+            seen.add(chromosome, &filters);
+        }
+        Ok(seen)
+    }
+}
+
+/// Writes included variants as VCFv4.3, optionally BGZF-compressed.
+pub struct VcfWriter {
+    output: OutputFile,
+    header: Option<NirvanaHeader>,
+    /// Unsorted records, until `finish` writes them after the header
+    records: Option<OutputFile>,
+    sorter: Option<ExternalSorter<String>>,
+    seen: SeenValues,
+    records_written: usize,
+}
+
+impl VcfWriter {
+    /// Creates the writer; `.vcf.gz` paths are BGZF-compressed unless
+    /// `options` say otherwise.
+    pub fn create(output_path: &str, options: FileOptions) -> Result<Self> {
+        Ok(Self {
+            output: OutputFile::create(output_path, options)?,
+            header: None,
+            records: Some(OutputFile::create(records_path(output_path), records_options(options))?),
+            sorter: None,
+            seen: SeenValues::default(),
+            records_written: 0,
+        })
+    }
+
+    /// Continues a VCF checkpointed with `state`. Nothing reaches the output
+    /// itself before `finish`, so only the staged records are resumed.
+    pub fn resume(output_path: &str, state: SinkState, options: FileOptions) -> Result<Self> {
+        let staged = records_path(output_path);
+        let partial = partial_path(std::path::Path::new(&staged))?;
+        let file = std::fs::File::open(&partial)
+            .with_context(|| format!("Failed to resume output file: {}", partial.display()))?;
+        let seen = SeenValues::scan(file.take(state.bytes))?;
+        Ok(Self {
+            output: OutputFile::create(output_path, options)?,
+            header: None,
+            records: Some(OutputFile::resume(&staged, records_options(options), state.bytes)?),
+            sorter: None,
+            seen,
+            records_written: state.records,
+        })
+    }
+
+    /// With a chunk size, records are written sorted by CHROM and POS on
+    /// `finish`, so `.vcf.gz` output can be indexed with tabix.
+    pub fn with_sort(mut self, chunk_size: Option<usize>) -> Self {
+        self.sorter = chunk_size.map(|n| ExternalSorter::for_output(n, self.output.path()));
+        if self.sorter.is_some() {
+            // The sorter holds the records instead
+            self.records = None;
+        }
+        self
+    }
+
+    fn has_sample(&self) -> bool {
+        self.header.as_ref().is_some_and(|h| !h.samples.is_empty())
+    }
+
+    /// Writes the header once every record has been seen, so contig names
+    /// follow the data's `chr` prefix convention and every contig and
+    /// FILTER value in the records is declared.
+    fn write_header(&mut self) -> Result<()> {
+        let header = self.header.clone().unwrap_or(NirvanaHeader {
+            annotator: String::new(),
+            creation_time: String::new(),
+            genome_assembly: String::new(),
+            schema_version: 0,
+            data_sources: Vec::new(),
+            samples: Vec::new(),
+        });
+        let with_prefix = match self.seen.contigs.first() {
+            Some(chrom) => chrom.starts_with("chr"),
+            None => header.genome_assembly.eq_ignore_ascii_case("GRCh38"),
+        };
+
+        let mut lines = vec![
+            "##fileformat=VCFv4.3".to_string(),
+            format!("##source=json2maf {}", env!("CARGO_PKG_VERSION")),
+        ];
+        if !header.annotator.is_empty() {
+            lines.push(format!("##annotator={}", header.annotator));
+        }
+        if !header.genome_assembly.is_empty() {
+            lines.push(format!("##reference={}", header.genome_assembly));
+        }
+        let mut declared = HashSet::new();
+        match assembly_contigs(&header.genome_assembly) {
+            Some(contigs) => {
+                for (name, length) in contigs {
+                    let name = contig_name(name, with_prefix);
+                    lines.push(format!(
+                        "##contig=<ID={},length={},assembly={}>",
+                        name, length, header.genome_assembly
+                    ));
+                    declared.insert(name);
+                }
+            }
+            None => log::warn!(
+                "Unknown genome assembly '{}'; only contigs with records get contig lines",
+                header.genome_assembly
+            ),
+        }
+        // Alt, decoy and unplaced contigs have no length in the tables above
+        for contig in &self.seen.contigs {
+            if !declared.contains(contig) {
+                lines.push(format!("##contig=<ID={}>", contig));
+            }
+        }
+        lines.push(r#"##FILTER=<ID=PASS,Description="All filters passed">"#.to_string());
+        lines.push(
+            r#"##FILTER=<ID=FAIL,Description="Sample failed a filter (Nirvana reports no filter name)">"#.to_string(),
+        );
+        for filter in &self.seen.filters {
+            if filter != "PASS" && filter != "FAIL" {
+                lines.push(format!(
+                    "##FILTER=<ID={},Description=\"FILTER value of the input VCF\">",
+                    filter
+                ));
+            }
+        }
+        for field in INFO_FIELDS {
+            lines.push(format!(
+                "##INFO=<ID={},Number={},Type={},Description=\"{}\">",
+                field.id, field.number, field.kind, field.description
+            ));
+        }
+
+        let mut columns = "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO".to_string();
+        if let Some(sample) = header.samples.first() {
+            lines.extend(FORMAT_HEADER.iter().map(|l| l.to_string()));
+            columns.push_str("\tFORMAT\t");
+            columns.push_str(sample);
+        }
+        lines.push(columns);
+
+        let out = &mut self.output;
+        for line in lines {
+            writeln!(out, "{}", line).context("Failed to write VCF header")?;
+        }
+        Ok(())
+    }
+}
+
+impl RecordSink for VcfWriter {
+    fn begin(&mut self, header: &NirvanaHeader) -> Result<()> {
+        self.header = Some(header.clone());
+        Ok(())
+    }
+
+    fn write(&mut self, record: &OutputRecord) -> Result<()> {
+        let variant = record.variant();
+        self.seen.add(&variant.chromosome, &variant.filters);
+
+        let id = if variant.dbsnp_ids.is_empty() {
+            ".".to_string()
+        } else {
+            variant.dbsnp_ids.join(";")
+        };
+        let filter = if variant.filters.is_empty() {
+            ".".to_string()
+        } else {
+            variant.filters.join(";")
+        };
+        let mut line = format!(
+            "{}\t{}\t{}\t{}\t{}\t.\t{}\t{}",
+            variant.chromosome,
+            variant.start,
+            id,
+            variant.reference_allele,
+            variant.alternate_allele,
+            filter,
+            info_column(record)
+        );
+        if self.has_sample() {
+            let (format, sample) = sample_columns(variant);
+            line.push('\t');
+            line.push_str(&format);
+            line.push('\t');
+            line.push_str(&sample);
+        }
+
+        match self.sorter.as_mut() {
+            Some(sorter) => {
+                let key = SortKey::new(
+                    &variant.chromosome,
+                    variant.start.into(),
+                    &variant.reference_allele,
+                    &variant.alternate_allele,
+                );
+                sorter.push(key, line)?;
+            }
+            None => {
+                let records = self.records.as_mut().context("VCF output already finished")?;
+                writeln!(records, "{}", line).context("Failed to write VCF record")?;
+            }
+        }
+        self.records_written += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.write_header()?;
+        let staged: Option<StagedFile> = self.records.take().map(OutputFile::into_reader).transpose()?;
+        if let Some(mut staged) = staged {
+            std::io::copy(&mut staged, &mut self.output).context("Failed to write VCF records")?;
+            self.output.commit()?;
+            return staged.remove();
+        }
+        if let Some(sorter) = self.sorter.take() {
+            let out = &mut self.output;
+            sorter.finish(|line| writeln!(out, "{}", line).context("Failed to write VCF record"))?;
+        }
+        self.output.commit()
+    }
+
+    fn records_written(&self) -> usize {
+        self.records_written
+    }
+
+    fn checkpoint(&mut self) -> Result<SinkState> {
+        let Some(records) = self.records.as_mut() else {
+            anyhow::bail!("sorted output can't be checkpointed");
+        };
+        Ok(SinkState {
+            bytes: records.checkpoint()?,
+            records: self.records_written,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::variant_to_maf;
+    use crate::filters::VariantContext;
+    use crate::types::{FilterConfig, FilterDecision};
+    use flate2::read::MultiGzDecoder;
+    use std::io::Read;
+    use tempfile::TempDir;
+
+    fn create_test_variant() -> VariantPosition {
+        VariantPosition {
+            chromosome: "chr7".to_string(),
+            start: 140753336,
+            end_pos: 140753336,
+            total_depth: Some(120),
+            variant_frequencies: Some(vec![0.25]),
+            genotype: Some("0/1".to_string()),
+            genotype_quality: Some(99.0),
+            allele_depths: Some(vec![90, 30]),
+            revel_score: Some(0.93),
+            dbsnp_ids: vec!["rs113488022".to_string()],
+            ..VariantPosition::test_default()
+        }
+    }
+
+    fn test_header() -> NirvanaHeader {
+        NirvanaHeader {
+            annotator: "Nirvana 3.18".to_string(),
+            creation_time: String::new(),
+            genome_assembly: "GRCh38".to_string(),
+            schema_version: 6,
+            data_sources: Vec::new(),
+            samples: vec!["TUMOR".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_write_vcf() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("out.vcf.gz");
+        let path = path.to_str().unwrap();
+
+        let variant = create_test_variant();
+        let decision = FilterDecision {
+            should_include: true,
+            pathogenicity_class: "Likely pathogenic".to_string(),
+            primary_evidence: "Predictive".to_string(),
+            justification: "Supported by predictive scores: REVEL (confidence: 1.00)".to_string(),
+        };
+        let rows = vec![variant_to_maf(&variant, None)];
+
+        let config = FilterConfig::default();
+        let mut writer = VcfWriter::create(path, FileOptions::default())?;
+        writer.begin(&test_header())?;
+        writer.write(&OutputRecord {
+            context: &VariantContext::new(&variant, &config),
+            decision: &decision,
+            rows: &rows,
+        })?;
+        writer.finish()?;
+        assert_eq!(writer.records_written(), 1);
+
+        let mut contents = String::new();
+        MultiGzDecoder::new(std::fs::File::open(path)?).read_to_string(&mut contents)?;
+        let lines: Vec<&str> = contents.lines().collect();
+
+        assert_eq!(lines[0], "##fileformat=VCFv4.3");
+        assert!(lines.contains(&"##contig=<ID=chr7,length=159345973,assembly=GRCh38>"));
+        assert!(lines.contains(&"##contig=<ID=chrM,length=16569,assembly=GRCh38>"));
+        assert!(lines
+            .iter()
+            .any(|l| l.starts_with("##INFO=<ID=J2M_CLASS,Number=1,Type=String")));
+        assert_eq!(
+            lines[lines.len() - 2],
+            "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tTUMOR"
+        );
+
+        let fields: Vec<&str> = lines[lines.len() - 1].split('\t').collect();
+        assert_eq!(&fields[..7], &["chr7", "140753336", "rs113488022", "A", "T", ".", "PASS"]);
+        assert_eq!(
+            fields[7],
+            "J2M_CLASS=Likely pathogenic;J2M_EVIDENCE=Predictive;\
+             J2M_JUSTIFICATION=Supported by predictive scores%3A REVEL (confidence%3A 1.00);\
+             J2M_VARIANT_CLASSIFICATION=IGR;J2M_REVEL=0.9300"
+        );
+        assert_eq!(fields[8], "GT:GQ:DP:AD:VF");
+        assert_eq!(fields[9], "0/1:99:120:90,30:0.25");
+        Ok(())
+    }
+
+    /// Writes each variant as an included record.
+    fn write_vcf(writer: &mut VcfWriter, variants: &[VariantPosition]) -> Result<()> {
+        let decision = FilterDecision {
+            should_include: true,
+            pathogenicity_class: "VUS".to_string(),
+            primary_evidence: "Quality".to_string(),
+            justification: String::new(),
+        };
+        let config = FilterConfig::default();
+        for variant in variants {
+            let rows = vec![variant_to_maf(variant, None)];
+            writer.write(&OutputRecord {
+                context: &VariantContext::new(variant, &config),
+                decision: &decision,
+                rows: &rows,
+            })?;
+        }
+        Ok(())
+    }
+
+    fn variant_at(chromosome: &str, start: i32, filters: &[&str]) -> VariantPosition {
+        VariantPosition {
+            chromosome: chromosome.to_string(),
+            start,
+            end_pos: start,
+            filters: filters.iter().map(|f| f.to_string()).collect(),
+            ..create_test_variant()
+        }
+    }
+
+    #[test]
+    fn test_multi_allelic_position_keeps_first_alt() -> Result<()> {
+        let position: crate::types::Position = serde_json::from_str(
+            r#"{
+                "chromosome": "chr7",
+                "position": 140753336,
+                "refAllele": "A",
+                "altAlleles": ["T", "G"],
+                "filters": ["PASS"],
+                "variants": [
+                    {"variantType": "SNV", "altAllele": "T"},
+                    {"variantType": "SNV", "altAllele": "G"}
+                ]
+            }"#,
+        )?;
+        let variant = crate::parser::position_to_variant(position)?.expect("position has variants");
+
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("out.vcf");
+        let mut writer = VcfWriter::create(path.to_str().unwrap(), FileOptions::default())?;
+        writer.begin(&test_header())?;
+        write_vcf(&mut writer, &[variant])?;
+        writer.finish()?;
+
+        let contents = std::fs::read_to_string(&path)?;
+        let records: Vec<&str> = contents.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(records.len(), 1);
+        let fields: Vec<&str> = records[0].split('\t').collect();
+        assert_eq!(&fields[..5], &["chr7", "140753336", ".", "A", "T"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_filters_and_contigs_are_declared() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("out.vcf");
+        let mut writer = VcfWriter::create(path.to_str().unwrap(), FileOptions::default())?;
+        writer.begin(&test_header())?;
+        write_vcf(
+            &mut writer,
+            &[
+                variant_at("chr7", 140753336, &["PASS"]),
+                variant_at("chr1_KI270706v1_random", 1000, &["LowDP"]),
+                variant_at("chrUn_JTFH01000001v1_decoy", 2000, &["LowDP", "LowGQ"]),
+                variant_at("chr7", 140753400, &[]),
+            ],
+        )?;
+        writer.finish()?;
+
+        let contents = std::fs::read_to_string(&path)?;
+        let lines: Vec<&str> = contents.lines().collect();
+        let contigs: Vec<&str> = lines.iter().copied().filter(|l| l.starts_with("##contig")).collect();
+        assert_eq!(contigs.len(), 27);
+        assert_eq!(contigs.iter().filter(|l| l.contains("ID=chr7,")).count(), 1);
+        assert_eq!(
+            &contigs[25..],
+            &["##contig=<ID=chr1_KI270706v1_random>", "##contig=<ID=chrUn_JTFH01000001v1_decoy>"]
+        );
+        let filters: Vec<&str> = lines.iter().copied().filter(|l| l.starts_with("##FILTER")).collect();
+        assert_eq!(filters.len(), 4);
+        assert_eq!(filters[2], r#"##FILTER=<ID=LowDP,Description="FILTER value of the input VCF">"#);
+        assert_eq!(filters[3], r#"##FILTER=<ID=LowGQ,Description="FILTER value of the input VCF">"#);
+
+        // Records keep their original FILTER values, in input order
+        let records: Vec<Vec<&str>> = lines
+            .iter()
+            .filter(|l| !l.starts_with('#'))
+            .map(|l| l.split('\t').collect())
+            .collect();
+        let filter_column: Vec<&str> = records.iter().map(|r| r[6]).collect();
+        assert_eq!(filter_column, ["PASS", "LowDP", "LowDP;LowGQ", "."]);
+
+        // The staged records are gone
+        assert_eq!(std::fs::read_dir(temp_dir.path())?.count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_resume_declares_values_from_staged_records() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("out.vcf.gz");
+        let path = path.to_str().unwrap();
+        let options = FileOptions {
+            checkpoint: true,
+            ..FileOptions::default()
+        };
+
+        let mut writer = VcfWriter::create(path, options)?;
+        writer.begin(&test_header())?;
+        write_vcf(&mut writer, &[variant_at("chr1_KI270706v1_random", 1000, &["LowDP"])])?;
+        let state = writer.checkpoint()?;
+        // Written after the checkpoint, so lost in the crash
+        write_vcf(&mut writer, &[variant_at("chr2", 500, &["LowGQ"])])?;
+        drop(writer);
+
+        let mut writer = VcfWriter::resume(path, state, options)?;
+        writer.begin(&test_header())?;
+        write_vcf(&mut writer, &[variant_at("chr7", 140753336, &["PASS"])])?;
+        writer.finish()?;
+        assert_eq!(writer.records_written(), 2);
+
+        let mut contents = String::new();
+        MultiGzDecoder::new(std::fs::File::open(path)?).read_to_string(&mut contents)?;
+        assert!(contents.contains("##contig=<ID=chr1_KI270706v1_random>\n"));
+        assert!(contents.contains("##FILTER=<ID=LowDP,"));
+        assert!(!contents.contains("LowGQ"));
+        let records: Vec<&str> = contents.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(records.len(), 2);
+        assert!(records[0].starts_with("chr1_KI270706v1_random\t1000\t"));
+        assert!(records[1].starts_with("chr7\t140753336\t"));
+
+        // Only the output and its .done sidecar are left
+        assert_eq!(std::fs::read_dir(temp_dir.path())?.count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_info_value() {
+        assert_eq!(encode_info_value("a;b=c,d:e%"), "a%3Bb%3Dc%2Cd%3Ae%25");
+        assert_eq!(encode_info_value("Lynch syndrome"), "Lynch syndrome");
+    }
+}
@@ -1,74 +1,480 @@
-use crate::types::MAFRecord;
+use crate::converter::variant_to_rejected;
+use crate::filters::VariantContext;
+use crate::jsonl::JsonlWriter;
+use crate::output::{FileOptions, OutputCompression, OutputFile};
+use crate::parquet_writer::ParquetWriter;
+use crate::sort::{ExternalSorter, SortKey};
+use crate::types::{FilterDecision, MAFRecord, NirvanaHeader, RejectedRecord, VariantPosition};
+use crate::vcf::VcfWriter;
 use anyhow::{Context, Result};
 use csv::Writer;
-use std::fs::File;
-use std::path::Path;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Output file format for included variants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum OutputFormat {
+    #[default]
+    #[serde(rename = "maf")]
+    Maf,
+    #[serde(rename = "vcf")]
+    Vcf,
+    /// BGZF-compressed VCF, indexable with tabix
+    #[serde(rename = "vcf.gz")]
+    VcfGz,
+    /// One JSON object per variant with every annotation and assessment
+    #[serde(rename = "jsonl")]
+    Jsonl,
+    /// Typed columns for cohort-scale analytics
+    #[serde(rename = "parquet")]
+    Parquet,
+}
+
+impl OutputFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutputFormat::Maf => "maf",
+            OutputFormat::Vcf => "vcf",
+            OutputFormat::VcfGz => "vcf.gz",
+            OutputFormat::Jsonl => "jsonl",
+            OutputFormat::Parquet => "parquet",
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "maf" => Ok(OutputFormat::Maf),
+            "vcf" => Ok(OutputFormat::Vcf),
+            "vcf.gz" | "vcfgz" => Ok(OutputFormat::VcfGz),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            "parquet" => Ok(OutputFormat::Parquet),
+            _ => anyhow::bail!(
+                "invalid output format '{}' (expected maf, vcf, vcf.gz, jsonl or parquet)",
+                s
+            ),
+        }
+    }
+}
+
+/// An included variant on its way to the output: the MAF rows built for it
+/// (one per written transcript), plus the pipeline context and decision they
+/// came from for formats that keep the original record and assessments.
+pub struct OutputRecord<'a> {
+    pub context: &'a VariantContext<'a>,
+    pub decision: &'a FilterDecision,
+    pub rows: &'a [MAFRecord],
+}
+
+impl OutputRecord<'_> {
+    pub fn variant(&self) -> &VariantPosition {
+        self.context.variant
+    }
+
+    /// The row for the selected transcript.
+    pub fn selected_row(&self) -> Option<&MAFRecord> {
+        self.rows
+            .iter()
+            .find(|r| r.is_selected_transcript == "1")
+            .or_else(|| self.rows.first())
+    }
+}
+
+/// Where a checkpointed sink stopped: its file's length and the records in
+/// it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct SinkState {
+    pub bytes: u64,
+    pub records: usize,
+}
+
+/// Destination for included variants. `begin` is called with the Nirvana
+/// header before any record, and `finish` once after the last one.
+pub trait RecordSink {
+    fn begin(&mut self, _header: &NirvanaHeader) -> Result<()> {
+        Ok(())
+    }
+
+    fn write(&mut self, record: &OutputRecord) -> Result<()>;
+
+    fn finish(&mut self) -> Result<()>;
+
+    /// Records written so far (MAF rows, VCF/JSONL lines, or Parquet rows)
+    fn records_written(&self) -> usize;
+
+    /// Makes every record written so far durable and returns the state a
+    /// resumed run continues from (see `resume_sink`).
+    fn checkpoint(&mut self) -> Result<SinkState> {
+        anyhow::bail!("this output can't be checkpointed")
+    }
+}
+
+/// Opens a sink of the given format at `output_path`. `batch_size` sets the
+/// rows per Parquet row group and, with `sort`, the records held in memory
+/// per sort chunk.
+pub fn create_sink(
+    format: OutputFormat,
+    output_path: &str,
+    batch_size: usize,
+    sort: bool,
+    options: FileOptions,
+) -> Result<Box<dyn RecordSink>> {
+    if options.checkpoint && (sort || format == OutputFormat::Parquet) {
+        anyhow::bail!("--checkpoint needs unsorted MAF, VCF or JSONL output");
+    }
+    let sort_chunk = sort.then_some(batch_size);
+    Ok(match format {
+        OutputFormat::Maf => Box::new(MAFWriter::with_options(output_path, options)?.with_sort(sort_chunk)),
+        OutputFormat::Vcf => Box::new(VcfWriter::create(output_path, options)?.with_sort(sort_chunk)),
+        OutputFormat::VcfGz => {
+            Box::new(VcfWriter::create(output_path, vcf_gz_options(options)?)?.with_sort(sort_chunk))
+        }
+        OutputFormat::Jsonl => Box::new(JsonlWriter::create(output_path, options)?.with_sort(sort_chunk)),
+        OutputFormat::Parquet => {
+            if sort {
+                anyhow::bail!("--sort is not supported for Parquet output; order rows when querying instead");
+            }
+            Box::new(ParquetWriter::create(output_path, batch_size, options)?)
+        }
+    })
+}
+
+/// Reopens a sink checkpointed with `state`, appending to its partial
+/// file. Sorted and Parquet outputs can't be checkpointed.
+pub fn resume_sink(
+    format: OutputFormat,
+    output_path: &str,
+    state: SinkState,
+    options: FileOptions,
+) -> Result<Box<dyn RecordSink>> {
+    Ok(match format {
+        OutputFormat::Maf => Box::new(MAFWriter::resume(output_path, state, options)?),
+        OutputFormat::Vcf => Box::new(VcfWriter::resume(output_path, state, options)?),
+        OutputFormat::VcfGz => Box::new(VcfWriter::resume(output_path, state, vcf_gz_options(options)?)?),
+        OutputFormat::Jsonl => Box::new(JsonlWriter::resume(output_path, state, options)?),
+        OutputFormat::Parquet => anyhow::bail!("Parquet output can't be checkpointed"),
+    })
+}
+
+/// Stands in for an output that an interrupted run already committed, when
+/// the rerun only has to commit the others.
+pub struct CommittedSink {
+    state: SinkState,
+}
+
+impl CommittedSink {
+    pub fn new(state: SinkState) -> Self {
+        Self { state }
+    }
+}
+
+impl RecordSink for CommittedSink {
+    fn write(&mut self, _record: &OutputRecord) -> Result<()> {
+        anyhow::bail!("output already committed")
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn records_written(&self) -> usize {
+        self.state.records
+    }
+
+    fn checkpoint(&mut self) -> Result<SinkState> {
+        Ok(self.state)
+    }
+}
+
+fn vcf_gz_options(options: FileOptions) -> Result<FileOptions> {
+    let compression = match options.compression {
+        OutputCompression::Auto | OutputCompression::Bgzf => OutputCompression::Bgzf,
+        other => anyhow::bail!("vcf.gz output is always BGZF-compressed, not {}", other),
+    };
+    Ok(FileOptions { compression, ..options })
+}
+
+/// Tab-separated writer with a header row from the serde field names
+/// (written by the csv crate on the first call to serialize()), unless the
+/// file is resumed past its header. Values starting with `#` are quoted, so
+/// readers skipping `#` lines keep them.
+fn tsv_writer(file: OutputFile, has_headers: bool) -> Writer<OutputFile> {
+    csv::WriterBuilder::new()
+        .delimiter(b'\t')
+        .comment(Some(b'#'))
+        .has_headers(has_headers)
+        .from_writer(file)
+}
+
+/// Flushes a TSV writer and checkpoints its file, returning its length.
+/// csv writers don't lend out their inner writer, so the writer is rebuilt
+/// around the file, as if resumed.
+fn checkpoint_tsv(writer: &mut Option<Writer<OutputFile>>) -> Result<u64> {
+    let mut file = writer
+        .take()
+        .context("Writer already finished")?
+        .into_inner()
+        .map_err(|e| e.into_error())
+        .context("Failed to flush writer")?;
+    let bytes = file.checkpoint()?;
+    *writer = Some(tsv_writer(file, bytes == 0));
+    Ok(bytes)
+}
+
+/// Flushes a TSV writer and commits its file.
+fn commit_tsv(writer: Option<Writer<OutputFile>>) -> Result<()> {
+    if let Some(writer) = writer {
+        let mut file = writer
+            .into_inner()
+            .map_err(|e| e.into_error())
+            .context("Failed to flush writer")?;
+        file.commit()?;
+    }
+    Ok(())
+}
 
 pub struct MAFWriter {
-    writer: Writer<File>,
+    writer: Option<Writer<OutputFile>>,
+    sorter: Option<ExternalSorter<MAFRecord>>,
     records_written: usize,
 }
 
 impl MAFWriter {
+    /// Writes straight to `output_path`, so flushed records are visible
+    /// without `finish` (see `FileOptions::direct`). Use `with_options` for
+    /// an output that only appears once committed.
     pub fn new(output_path: &str) -> Result<Self> {
-        let file = File::create(output_path)
-            .with_context(|| format!("Failed to create output file: {}", output_path))?;
-
-        // Create writer with headers enabled
-        // The csv crate will automatically write headers from the serde field names
-        // on the first call to serialize()
-        let writer = csv::WriterBuilder::new()
-            .delimiter(b'\t')
-            .has_headers(true)
-            .from_writer(file);
+        Self::with_options(output_path, FileOptions::direct())
+    }
 
+    pub fn with_options(output_path: &str, options: FileOptions) -> Result<Self> {
         Ok(Self {
-            writer,
+            writer: Some(tsv_writer(OutputFile::create(output_path, options)?, true)),
+            sorter: None,
             records_written: 0,
         })
     }
 
+    /// Continues a MAF checkpointed with `state`.
+    pub fn resume(output_path: &str, state: SinkState, options: FileOptions) -> Result<Self> {
+        let file = OutputFile::resume(output_path, options, state.bytes)?;
+        Ok(Self {
+            // Nothing is written before the first record, header included
+            writer: Some(tsv_writer(file, state.bytes == 0)),
+            sorter: None,
+            records_written: state.records,
+        })
+    }
+
+    /// With a chunk size, rows are written in genomic order on `finish`,
+    /// holding at most that many rows in memory.
+    pub fn with_sort(mut self, chunk_size: Option<usize>) -> Self {
+        if let (Some(chunk_size), Some(writer)) = (chunk_size, self.writer.as_ref()) {
+            self.sorter = Some(ExternalSorter::for_output(chunk_size, writer.get_ref().path()));
+        }
+        self
+    }
+
     pub fn write_record(&mut self, record: &MAFRecord) -> Result<()> {
-        self.writer
-            .serialize(record)
-            .context("Failed to write MAF record")?;
+        if let Some(sorter) = self.sorter.as_mut() {
+            let key = SortKey::new(
+                &record.chromosome,
+                record.start_position.into(),
+                &record.reference_allele,
+                &record.tumor_seq_allele2,
+            );
+            sorter.push(key, record.clone())?;
+        } else {
+            serialize_maf(self.writer.as_mut(), record)?;
+        }
         self.records_written += 1;
         Ok(())
     }
 
     pub fn flush(&mut self) -> Result<()> {
-        self.writer.flush().context("Failed to flush writer")?;
+        if let Some(writer) = self.writer.as_mut() {
+            writer.flush().context("Failed to flush writer")?;
+        }
         Ok(())
     }
 
+    /// Writes out any sorted rows, flushes the file and commits it to its
+    /// final path.
+    pub fn finish(&mut self) -> Result<()> {
+        if let Some(sorter) = self.sorter.take() {
+            let writer = &mut self.writer;
+            sorter.finish(|record| serialize_maf(writer.as_mut(), &record))?;
+        }
+        commit_tsv(self.writer.take())
+    }
+
     pub fn records_written(&self) -> usize {
         self.records_written
     }
 }
 
-pub fn merge_maf_files(input_files: &[String], output_path: &str) -> Result<usize> {
-    let mut output = MAFWriter::new(output_path)?;
-    let mut total_records = 0;
+fn serialize_maf(writer: Option<&mut Writer<OutputFile>>, record: &MAFRecord) -> Result<()> {
+    writer
+        .context("MAF writer already finished")?
+        .serialize(record)
+        .context("Failed to write MAF record")
+}
 
-    for input_file in input_files {
-        if !Path::new(input_file).exists() {
-            continue;
+impl RecordSink for MAFWriter {
+    fn write(&mut self, record: &OutputRecord) -> Result<()> {
+        for row in record.rows {
+            self.write_record(row)?;
         }
+        Ok(())
+    }
 
-        let mut reader = csv::ReaderBuilder::new()
-            .delimiter(b'\t')
-            .from_path(input_file)
-            .with_context(|| format!("Failed to open input file: {}", input_file))?;
+    fn finish(&mut self) -> Result<()> {
+        MAFWriter::finish(self)
+    }
+
+    fn records_written(&self) -> usize {
+        self.records_written
+    }
 
-        for result in reader.deserialize() {
-            let record: MAFRecord = result.context("Failed to deserialize MAF record")?;
-            output.write_record(&record)?;
-            total_records += 1;
+    fn checkpoint(&mut self) -> Result<SinkState> {
+        if self.sorter.is_some() {
+            anyhow::bail!("sorted output can't be checkpointed");
         }
+        Ok(SinkState {
+            bytes: checkpoint_tsv(&mut self.writer)?,
+            records: self.records_written,
+        })
     }
+}
 
-    output.flush()?;
-    Ok(total_records)
+/// Tab-separated writer for the optional rejected-variants report.
+pub struct RejectedWriter {
+    writer: Option<Writer<OutputFile>>,
+}
+
+impl RejectedWriter {
+    /// Writes straight to `output_path`, like `MAFWriter::new`.
+    pub fn new(output_path: &str) -> Result<Self> {
+        Self::with_options(output_path, FileOptions::direct())
+    }
+
+    pub fn with_options(output_path: &str, options: FileOptions) -> Result<Self> {
+        Ok(Self {
+            writer: Some(tsv_writer(OutputFile::create(output_path, options)?, true)),
+        })
+    }
+
+    /// Continues a report checkpointed at `bytes`.
+    pub fn resume(output_path: &str, bytes: u64, options: FileOptions) -> Result<Self> {
+        Ok(Self {
+            writer: Some(tsv_writer(OutputFile::resume(output_path, options, bytes)?, bytes == 0)),
+        })
+    }
+
+    pub fn write_record(&mut self, record: &RejectedRecord) -> Result<()> {
+        self.writer
+            .as_mut()
+            .context("Rejected writer already finished")?
+            .serialize(record)
+            .context("Failed to write rejected record")
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        if let Some(writer) = self.writer.as_mut() {
+            writer.flush().context("Failed to flush rejected writer")?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the file and returns its length (see `OutputFile::checkpoint`).
+    pub fn checkpoint(&mut self) -> Result<u64> {
+        checkpoint_tsv(&mut self.writer)
+    }
+
+    /// Flushes the file and commits it to its final path.
+    pub fn finish(&mut self) -> Result<()> {
+        commit_tsv(self.writer.take())
+    }
+}
+
+/// Destination for the rejected-variant report: TSV, or Parquet with the
+/// same columns as the included variants when the output is Parquet.
+pub enum RejectedOutput {
+    Tsv(RejectedWriter),
+    Parquet(ParquetWriter),
+}
+
+impl RejectedOutput {
+    pub fn create(
+        format: OutputFormat,
+        output_path: &str,
+        row_group_size: usize,
+        options: FileOptions,
+    ) -> Result<Self> {
+        Ok(match format {
+            OutputFormat::Parquet => {
+                RejectedOutput::Parquet(ParquetWriter::create(output_path, row_group_size, options)?)
+            }
+            _ => RejectedOutput::Tsv(RejectedWriter::with_options(output_path, options)?),
+        })
+    }
+
+    /// Continues a TSV report checkpointed at `bytes`.
+    pub fn resume(format: OutputFormat, output_path: &str, bytes: u64, options: FileOptions) -> Result<Self> {
+        match format {
+            OutputFormat::Parquet => anyhow::bail!("Parquet output can't be checkpointed"),
+            _ => Ok(RejectedOutput::Tsv(RejectedWriter::resume(output_path, bytes, options)?)),
+        }
+    }
+
+    pub fn set_barcodes(&mut self, tumor: &str, normal: &str) {
+        if let RejectedOutput::Parquet(writer) = self {
+            writer.set_barcodes(tumor, normal);
+        }
+    }
+
+    pub fn write(&mut self, context: &VariantContext, stage: &str, reason: &str) -> Result<()> {
+        match self {
+            RejectedOutput::Tsv(writer) => {
+                writer.write_record(&variant_to_rejected(context.variant, stage, reason, &context.flags))
+            }
+            RejectedOutput::Parquet(writer) => writer.write_rejected(context, stage, reason),
+        }
+    }
+
+    pub fn checkpoint(&mut self) -> Result<u64> {
+        match self {
+            RejectedOutput::Tsv(writer) => writer.checkpoint(),
+            RejectedOutput::Parquet(_) => anyhow::bail!("Parquet output can't be checkpointed"),
+        }
+    }
+
+    pub fn finish(&mut self) -> Result<()> {
+        match self {
+            RejectedOutput::Tsv(writer) => writer.finish(),
+            RejectedOutput::Parquet(writer) => writer.finish(),
+        }
+    }
+}
+
+/// Concatenates the MAF files that exist among `input_files` into
+/// `output_path` and returns the number of rows written.
+#[deprecated(note = "use merge::merge_maf_files, which also deduplicates, sorts and fills barcodes")]
+pub fn merge_maf_files(input_files: &[String], output_path: &str) -> Result<usize> {
+    let paths: Vec<PathBuf> = input_files.iter().map(PathBuf::from).filter(|p| p.exists()).collect();
+    let summary = crate::merge::merge_maf_files(&paths, output_path, &crate::merge::MergeOptions::default())?;
+    Ok(summary.rows_written)
 }
 
 #[cfg(test)]
@@ -76,6 +482,59 @@ mod tests {
     use super::*;
     use tempfile::TempDir;
 
+    fn create_test_record() -> MAFRecord {
+        MAFRecord {
+            hugo_symbol: "BRAF".to_string(),
+            chromosome: "chr7".to_string(),
+            start_position: 140453136,
+            end_position: 140453136,
+            strand: "+".to_string(),
+            variant_classification: "Missense_Mutation".to_string(),
+            variant_type: "SNP".to_string(),
+            reference_allele: "A".to_string(),
+            tumor_seq_allele1: "A".to_string(),
+            tumor_seq_allele2: "T".to_string(),
+            tumor_sample_barcode: "SAMPLE1".to_string(),
+            matched_norm_sample_barcode: String::new(),
+            hgvsc: "c.1799T>A".to_string(),
+            hgvsp: "p.Val600Glu".to_string(),
+            hgvsp_short: "p.V600E".to_string(),
+            transcript_id: "NM_004333.4".to_string(),
+            is_selected_transcript: "1".to_string(),
+            exon: "15/18".to_string(),
+            consequence: "missense_variant".to_string(),
+            impact: "MODERATE".to_string(),
+            codons: "Gtg/Gag".to_string(),
+            amino_acids: "V/E".to_string(),
+            cdna_position: "1799/2301".to_string(),
+            cds_position: "1799/2301".to_string(),
+            protein_position: "600/766".to_string(),
+            dbsnp_rs: "rs113488022".to_string(),
+            dbsnp_val_status: "".to_string(),
+            cosmic_id: "COSM476".to_string(),
+            clinvar_id: "RCV000123456".to_string(),
+            clinvar_review_status: "reviewed by expert panel".to_string(),
+            clinvar_significance: "Pathogenic".to_string(),
+            clinvar_disease: "Cancer".to_string(),
+            clinvar_all: String::new(),
+            primate_ai_score: "0.85".to_string(),
+            primate_ai_transcript: "ENST00000646891".to_string(),
+            dann_score: "0.99".to_string(),
+            revel_score: "0.92".to_string(),
+            predictive_evidence: "PP3_Moderate".to_string(),
+            gnomad_af: "0.0001".to_string(),
+            gnomad_eas_af: "0.0".to_string(),
+            depth: "100".to_string(),
+            vaf: "0.45".to_string(),
+            t_ref_count: "55".to_string(),
+            t_alt_count: "45".to_string(),
+            genotype: "0/1".to_string(),
+            genotype_quality: "99".to_string(),
+            qc_flags: "".to_string(),
+            all_effects: String::new(),
+        }
+    }
+
     #[test]
     fn test_write_maf() -> Result<()> {
         let temp_dir = TempDir::new()?;
@@ -96,10 +555,12 @@ mod tests {
             tumor_seq_allele1: "A".to_string(),
             tumor_seq_allele2: "T".to_string(),
             tumor_sample_barcode: "SAMPLE1".to_string(),
+            matched_norm_sample_barcode: String::new(),
             hgvsc: "c.1799T>A".to_string(),
             hgvsp: "p.Val600Glu".to_string(),
             hgvsp_short: "p.V600E".to_string(),
             transcript_id: "NM_004333.4".to_string(),
+            is_selected_transcript: "1".to_string(),
             exon: "15/18".to_string(),
             consequence: "missense_variant".to_string(),
             impact: "MODERATE".to_string(),
@@ -115,13 +576,22 @@ mod tests {
             clinvar_review_status: "reviewed by expert panel".to_string(),
             clinvar_significance: "Pathogenic".to_string(),
             clinvar_disease: "Cancer".to_string(),
+            clinvar_all: String::new(),
             primate_ai_score: "0.85".to_string(),
+            primate_ai_transcript: "ENST00000646891".to_string(),
             dann_score: "0.99".to_string(),
             revel_score: "0.92".to_string(),
+            predictive_evidence: "PP3_Moderate".to_string(),
             gnomad_af: "0.0001".to_string(),
             gnomad_eas_af: "0.0".to_string(),
             depth: "100".to_string(),
             vaf: "0.45".to_string(),
+            t_ref_count: "55".to_string(),
+            t_alt_count: "45".to_string(),
+            genotype: "0/1".to_string(),
+            genotype_quality: "99".to_string(),
+            qc_flags: "".to_string(),
+            all_effects: String::new(),
         };
 
         writer.write_record(&record)?;
@@ -132,4 +602,65 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_merge_maf_files_skips_missing_inputs() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let input = temp_dir.path().join("a.maf");
+        let mut writer = MAFWriter::new(input.to_str().unwrap())?;
+        writer.write_record(&create_test_record())?;
+        writer.write_record(&create_test_record())?;
+        writer.finish()?;
+
+        let inputs = [
+            input.to_str().unwrap().to_string(),
+            temp_dir.path().join("missing.maf").to_str().unwrap().to_string(),
+        ];
+        let output = temp_dir.path().join("merged.maf");
+        assert_eq!(merge_maf_files(&inputs, output.to_str().unwrap())?, 2);
+        assert!(output.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_resume_maf() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let output_path = temp_dir.path().join("test.maf");
+        let output_str = output_path.to_str().unwrap();
+        let options = FileOptions {
+            checkpoint: true,
+            ..FileOptions::default()
+        };
+        let record = create_test_record();
+
+        let mut writer = MAFWriter::with_options(output_str, options)?;
+        assert_eq!(RecordSink::checkpoint(&mut writer)?, SinkState::default());
+        writer.write_record(&record)?;
+        RecordSink::checkpoint(&mut writer)?;
+        writer.write_record(&record)?;
+        let state = RecordSink::checkpoint(&mut writer)?;
+        assert_eq!(state.records, 2);
+        // Lost with the crash
+        writer.write_record(&record)?;
+        writer.flush()?;
+        drop(writer);
+        assert!(!output_path.exists());
+
+        let mut writer = MAFWriter::resume(output_str, state, options)?;
+        writer.write_record(&record)?;
+        assert_eq!(writer.records_written(), 3);
+        writer.finish()?;
+
+        let text = std::fs::read_to_string(&output_path)?;
+        assert_eq!(text.lines().filter(|l| l.starts_with("Hugo_Symbol")).count(), 1);
+        let rows = crate::maf_reader::MafReader::open(&output_path)?.collect::<Result<Vec<_>>>()?;
+        assert_eq!(rows.len(), 3);
+        assert!(rows.iter().all(|row| row.record == record));
+
+        let parquet = temp_dir.path().join("test.parquet");
+        assert!(create_sink(OutputFormat::Parquet, parquet.to_str().unwrap(), 10, false, options).is_err());
+        assert!(create_sink(OutputFormat::Maf, output_str, 10, true, options).is_err());
+        Ok(())
+    }
 }
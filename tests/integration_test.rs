@@ -1,3 +1,4 @@
+#![allow(clippy::empty_line_after_doc_comments, clippy::useless_vec)]
 /// Integration tests for JSON2MAF
 /// Tests end-to-end parsing, filtering, and MAF conversion
 
@@ -109,7 +110,7 @@ fn test_parse_and_convert_with_new_fields() {
     assert_eq!(decision.pathogenicity_class, "Pathogenic");
 
     // Convert to MAF and verify new fields
-    let maf_record = converter::variant_to_maf(variant, &decision);
+    let maf_record = converter::variant_to_maf(variant, Some(&clinvar_result));
 
     assert_eq!(maf_record.hugo_symbol, "BRAF");
     assert_eq!(maf_record.chromosome, "chr7");
@@ -189,12 +190,9 @@ fn test_missing_annotation_fields() {
     assert!(transcript.codons.is_none());
 
     // Convert to MAF - should use empty strings for missing fields
-    let config = FilterConfig::default();
     let clinvar = filters::clinvar::assess_clinvar_pathogenicity(&variant.clinvar);
-    let predictive = filters::predictive::assess_predictive_scores(variant, &config);
-    let decision = filters::decision::make_filter_decision(variant, &clinvar, &predictive);
 
-    let maf_record = converter::variant_to_maf(variant, &decision);
+    let maf_record = converter::variant_to_maf(variant, Some(&clinvar));
 
     assert_eq!(maf_record.impact, "");
     assert_eq!(maf_record.amino_acids, "");
@@ -247,12 +245,9 @@ fn test_multiple_consequences() {
     let (_, variants) = parser::parse_nirvana_json(input_path.to_str().unwrap()).unwrap();
     let variant = &variants[0];
 
-    let config = FilterConfig::default();
     let clinvar = filters::clinvar::assess_clinvar_pathogenicity(&variant.clinvar);
-    let predictive = filters::predictive::assess_predictive_scores(variant, &config);
-    let decision = filters::decision::make_filter_decision(variant, &clinvar, &predictive);
 
-    let maf_record = converter::variant_to_maf(variant, &decision);
+    let maf_record = converter::variant_to_maf(variant, Some(&clinvar));
 
     // Multiple consequences should be joined with comma
     assert_eq!(maf_record.consequence, "missense_variant,splice_region_variant");
@@ -313,14 +308,364 @@ fn test_impact_case_conversion() {
         let (_, variants) = parser::parse_nirvana_json(input_path.to_str().unwrap()).unwrap();
         let variant = &variants[0];
 
-        let config = FilterConfig::default();
         let clinvar = filters::clinvar::assess_clinvar_pathogenicity(&variant.clinvar);
-        let predictive = filters::predictive::assess_predictive_scores(variant, &config);
-        let decision = filters::decision::make_filter_decision(variant, &clinvar, &predictive);
 
-        let maf_record = converter::variant_to_maf(variant, &decision);
+        let maf_record = converter::variant_to_maf(variant, Some(&clinvar));
 
         assert_eq!(maf_record.impact, *expected_output,
             "Impact '{}' should be converted to '{}'", input_impact, expected_output);
     }
 }
+
+#[test]
+fn test_rich_sample_fields() {
+    // Test that genotype-level sample fields are parsed and written to MAF
+    let test_json = r#"{
+        "header": {
+            "annotator": "Nirvana 3.0",
+            "creationTime": "2024-01-01",
+            "genomeAssembly": "GRCh38",
+            "schemaVersion": 6,
+            "dataSources": [],
+            "samples": ["TUMOR"]
+        },
+        "positions": [{
+            "chromosome": "chr12",
+            "position": 25245350,
+            "refAllele": "C",
+            "altAlleles": ["T"],
+            "filters": ["PASS"],
+            "samples": [{
+                "genotype": "0/1",
+                "variantFrequencies": [0.31],
+                "totalDepth": 120,
+                "genotypeQuality": 87,
+                "alleleDepths": [83, 37],
+                "strandBias": -12.4,
+                "somaticQuality": 45.2,
+                "isLossOfHeterozygosity": false,
+                "failedFilter": false
+            }],
+            "variants": [{
+                "variantType": "SNV",
+                "transcripts": [{
+                    "transcript": "NM_004985.5",
+                    "hgnc": "KRAS",
+                    "consequence": ["missense_variant"]
+                }]
+            }]
+        }]
+    }"#;
+
+    let temp_dir = TempDir::new().unwrap();
+    let input_path = temp_dir.path().join("test.json.gz");
+    let file = File::create(&input_path).unwrap();
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    encoder.write_all(test_json.as_bytes()).unwrap();
+    encoder.finish().unwrap();
+
+    let (_, variants) = parser::parse_nirvana_json(input_path.to_str().unwrap()).unwrap();
+    let variant = &variants[0];
+
+    assert_eq!(variant.genotype.as_deref(), Some("0/1"));
+    assert_eq!(variant.genotype_quality, Some(87.0));
+    assert_eq!(variant.ref_read_count(), Some(83));
+    assert_eq!(variant.alt_read_count(), Some(37));
+    assert_eq!(variant.strand_bias, Some(-12.4));
+    assert_eq!(variant.somatic_quality, Some(45.2));
+    assert_eq!(variant.is_loss_of_heterozygosity, Some(false));
+
+    let config = FilterConfig {
+        min_alt_reads: Some(40),
+        ..FilterConfig::default()
+    };
+    let quality = filters::quality::apply_quality_filters(variant, &config);
+    assert!(!quality.passes_quality);
+
+    let clinvar = filters::clinvar::assess_clinvar_pathogenicity(&variant.clinvar);
+    let maf_record = converter::variant_to_maf(variant, Some(&clinvar));
+
+    assert_eq!(maf_record.t_ref_count, "83");
+    assert_eq!(maf_record.t_alt_count, "37");
+    assert_eq!(maf_record.genotype, "0/1");
+    assert_eq!(maf_record.genotype_quality, "87");
+}
+
+#[test]
+fn test_custom_pipeline_stage() {
+    // Test that library users can extend the pipeline with their own stage
+    struct GeneBlocklist(Vec<String>);
+
+    impl FilterStage for GeneBlocklist {
+        fn name(&self) -> &'static str {
+            "gene_blocklist"
+        }
+
+        fn evaluate(&self, ctx: &mut VariantContext) -> StageOutcome {
+            let blocked = ctx
+                .variant
+                .transcripts
+                .iter()
+                .filter_map(|t| t.hgnc.as_deref())
+                .any(|gene| self.0.iter().any(|b| b == gene));
+            if blocked {
+                StageOutcome::Fail(Reason::new(
+                    ReasonCode::Custom("blocklisted_gene".to_string()),
+                    "Gene is on the lab blocklist",
+                ))
+            } else {
+                StageOutcome::Annotate(vec!["blocklist_checked".to_string()])
+            }
+        }
+    }
+
+    let test_json = r#"{
+        "header": {
+            "annotator": "Nirvana 3.0",
+            "creationTime": "2024-01-01",
+            "genomeAssembly": "GRCh38",
+            "schemaVersion": 6,
+            "dataSources": [],
+            "samples": ["TUMOR"]
+        },
+        "positions": [{
+            "chromosome": "chr2",
+            "position": 178527000,
+            "refAllele": "G",
+            "altAlleles": ["A"],
+            "filters": ["PASS"],
+            "samples": [{"variantFrequencies": [0.2], "totalDepth": 80}],
+            "variants": [{
+                "variantType": "SNV",
+                "primateAI-3D": [{"score": 0.91}],
+                "transcripts": [{
+                    "transcript": "NM_001267550.2",
+                    "hgnc": "TTN",
+                    "consequence": ["missense_variant"]
+                }]
+            }]
+        }]
+    }"#;
+
+    let temp_dir = TempDir::new().unwrap();
+    let input_path = temp_dir.path().join("test.json.gz");
+    let file = File::create(&input_path).unwrap();
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    encoder.write_all(test_json.as_bytes()).unwrap();
+    encoder.finish().unwrap();
+
+    let (_, variants) = parser::parse_nirvana_json(input_path.to_str().unwrap()).unwrap();
+    let variant = &variants[0];
+    let config = FilterConfig::default();
+
+    let mut pipeline = Pipeline::from_config(&config).unwrap();
+    pipeline
+        .insert_after(CONSEQUENCE_STAGE, Box::new(GeneBlocklist(vec!["MUC16".to_string()])))
+        .unwrap();
+    let mut stats = FilterStats::default();
+    let outcome = pipeline.run(variant, &config, &mut stats);
+    assert!(matches!(outcome.verdict, Verdict::Included(_)));
+    assert_eq!(outcome.context.flags, vec!["blocklist_checked".to_string()]);
+
+    let mut pipeline = Pipeline::from_config(&config).unwrap();
+    pipeline
+        .insert_after(CONSEQUENCE_STAGE, Box::new(GeneBlocklist(vec!["TTN".to_string()])))
+        .unwrap();
+    let outcome = pipeline.run(variant, &config, &mut stats);
+    match outcome.verdict {
+        Verdict::Rejected { stage, reason } => {
+            assert_eq!(stage, "gene_blocklist");
+            assert_eq!(reason.code, ReasonCode::Custom("blocklisted_gene".to_string()));
+        }
+        Verdict::Included(_) => panic!("blocklisted gene should be rejected"),
+    }
+    assert_eq!(stats.stages["gene_blocklist"].evaluated, 2);
+    assert_eq!(stats.stages["gene_blocklist"].failed, 1);
+    assert_eq!(stats.included, 1);
+    assert_eq!(stats.excluded, 1);
+}
+
+#[test]
+fn test_primate_ai_3d_follows_selected_transcript() {
+    // Overlapping genes: the higher PrimateAI-3D score belongs to the other gene
+    let test_json = r#"{
+        "header": {
+            "annotator": "Nirvana 3.0",
+            "creationTime": "2024-01-01",
+            "genomeAssembly": "GRCh38",
+            "schemaVersion": 6,
+            "dataSources": [],
+            "samples": ["TUMOR"]
+        },
+        "positions": [{
+            "chromosome": "chr1",
+            "position": 1000000,
+            "refAllele": "C",
+            "altAlleles": ["T"],
+            "filters": ["PASS"],
+            "samples": [{"variantFrequencies": [0.3], "totalDepth": 60}],
+            "variants": [{
+                "variantType": "SNV",
+                "primateAI-3D": [
+                    {"score": 0.95, "ensemblTranscriptId": "ENST00000000002.1"},
+                    {"score": 0.42, "ensemblTranscriptId": "ENST00000000001.4", "refSeqTranscriptId": "NM_000001.3"}
+                ],
+                "transcripts": [
+                    {
+                        "transcript": "ENST00000000002.1",
+                        "source": "Ensembl",
+                        "hgnc": "GENEB",
+                        "consequence": ["missense_variant"]
+                    },
+                    {
+                        "transcript": "NM_000001.2",
+                        "source": "RefSeq",
+                        "hgnc": "GENEA",
+                        "consequence": ["missense_variant"],
+                        "isManeSelect": true
+                    }
+                ]
+            }]
+        }]
+    }"#;
+
+    let temp_dir = TempDir::new().unwrap();
+    let input_path = temp_dir.path().join("test.json.gz");
+    let file = File::create(&input_path).unwrap();
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    encoder.write_all(test_json.as_bytes()).unwrap();
+    encoder.finish().unwrap();
+    let input_path = input_path.to_str().unwrap();
+
+    let (_, variants) = parser::parse_nirvana_json(input_path).unwrap();
+    let variant = &variants[0];
+    assert_eq!(variant.primate_ai_3d, Some(0.42));
+    assert_eq!(variant.primate_ai_3d_transcript.as_deref(), Some("NM_000001.3"));
+
+    let maf_record = converter::variant_to_maf(variant, None);
+    assert_eq!(maf_record.hugo_symbol, "GENEA");
+    assert_eq!(maf_record.primate_ai_score, "0.4200");
+    assert_eq!(maf_record.primate_ai_transcript, "NM_000001.3");
+
+    // The maximum across transcripts is still available on request
+    let options = ParseOptions {
+        score_transcripts: ScoreTranscriptPolicy::Max,
+        ..ParseOptions::default()
+    };
+    let mut max_variants = Vec::new();
+    parser::parse_nirvana_streaming(input_path, |position| {
+        max_variants.extend(parser::position_to_variant_with(position, &options)?);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(max_variants[0].primate_ai_3d, Some(0.95));
+    assert_eq!(max_variants[0].primate_ai_3d_transcript.as_deref(), Some("ENST00000000002.1"));
+
+    // A lab-preferred transcript overrides MANE Select, and the score follows it
+    let options = ParseOptions {
+        transcripts: TranscriptPolicy {
+            preferred: PreferredTranscripts::parse("GENEB\tENST00000000002\n").unwrap(),
+            ..TranscriptPolicy::default()
+        },
+        ..ParseOptions::default()
+    };
+    let mut preferred_variants = Vec::new();
+    parser::parse_nirvana_streaming(input_path, |position| {
+        preferred_variants.extend(parser::position_to_variant_with(position, &options)?);
+        Ok(())
+    })
+    .unwrap();
+    let variant = &preferred_variants[0];
+    assert_eq!(variant.selected_transcript_index, Some(0));
+    assert_eq!(variant.primate_ai_3d, Some(0.95));
+    let maf_record = converter::variant_to_maf(variant, None);
+    assert_eq!(maf_record.hugo_symbol, "GENEB");
+    assert_eq!(maf_record.transcript_id, "ENST00000000002.1");
+}
+
+#[test]
+fn test_primate_ai_3d_for_selected_transcript_without_id() {
+    // The selected transcript has no id; its gene's other transcripts still match
+    let test_json = r#"{
+        "header": {
+            "annotator": "Nirvana 3.0",
+            "creationTime": "2024-01-01",
+            "genomeAssembly": "GRCh38",
+            "schemaVersion": 6,
+            "dataSources": [],
+            "samples": ["TUMOR"]
+        },
+        "positions": [{
+            "chromosome": "chr1",
+            "position": 1000000,
+            "refAllele": "C",
+            "altAlleles": ["T"],
+            "filters": ["PASS"],
+            "samples": [{"variantFrequencies": [0.3], "totalDepth": 60}],
+            "variants": [{
+                "variantType": "SNV",
+                "primateAI-3D": [
+                    {"score": 0.95, "ensemblTranscriptId": "ENST00000000002.1"},
+                    {"score": 0.42, "ensemblTranscriptId": "ENST00000000001.4"}
+                ],
+                "transcripts": [
+                    {
+                        "transcript": "ENST00000000002.1",
+                        "source": "Ensembl",
+                        "hgnc": "GENEB",
+                        "consequence": ["missense_variant"]
+                    },
+                    {
+                        "source": "RefSeq",
+                        "hgnc": "GENEA",
+                        "consequence": ["missense_variant"],
+                        "isManeSelect": true
+                    },
+                    {
+                        "transcript": "ENST00000000001.4",
+                        "source": "Ensembl",
+                        "hgnc": "GENEA",
+                        "consequence": ["missense_variant"]
+                    }
+                ]
+            }]
+        }]
+    }"#;
+
+    let temp_dir = TempDir::new().unwrap();
+    let input_path = temp_dir.path().join("test.json.gz");
+    let file = File::create(&input_path).unwrap();
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    encoder.write_all(test_json.as_bytes()).unwrap();
+    encoder.finish().unwrap();
+
+    let (_, variants) = parser::parse_nirvana_json(input_path.to_str().unwrap()).unwrap();
+    let variant = &variants[0];
+    assert_eq!(variant.selected_transcript_index, Some(1));
+    assert_eq!(variant.primate_ai_3d, Some(0.42));
+    assert_eq!(variant.primate_ai_3d_transcript.as_deref(), Some("ENST00000000001.4"));
+}
+
+#[test]
+fn test_hgvsp_corpus_round_trip() {
+    // Nirvana hgvsp strings must parse, render back unchanged, and shorten
+    // to the expected HGVSp_Short, which in turn parses to the same change.
+    let corpus = include_str!("data/hgvsp_corpus.tsv");
+    let mut checked = 0;
+
+    for line in corpus.lines().filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let (hgvsp, expected_short) = line.split_once('\t').expect("tab-separated corpus line");
+        let notation = &hgvsp[hgvsp.find("p.").unwrap()..];
+
+        let change = parse_hgvsp(hgvsp).unwrap_or_else(|e| panic!("{}: {}", hgvsp, e));
+        assert_eq!(change.to_full(), notation, "full form of {}", hgvsp);
+        assert_eq!(change.to_short(), expected_short, "short form of {}", hgvsp);
+
+        let reparsed: ProteinChange = expected_short.parse().unwrap();
+        assert_eq!(reparsed.edit, change.edit, "short form {} of {}", expected_short, hgvsp);
+        assert_eq!(reparsed.to_short(), expected_short);
+
+        checked += 1;
+    }
+    assert!(checked > 150, "corpus only had {} entries", checked);
+}